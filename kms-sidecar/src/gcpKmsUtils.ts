@@ -7,7 +7,9 @@ import {
     SignatureScheme,
     SignatureFlag,
     messageWithIntent,
+    IntentScope,
 } from '@socialproof/mys/cryptography';
+import { bcs } from '@socialproof/mys/bcs';
 import { blake2b } from '@noble/hashes/blake2b';
 
 // Compress uncompressed public key from raw bytes
@@ -247,14 +249,18 @@ async function getSerializedSignature(
     return toB64(fullSignature);
 }
 
-export async function signAndVerify(txBytes: Uint8Array, keyPath: string): Promise<string | undefined> {
+export async function signAndVerify(
+    txBytes: Uint8Array,
+    keyPath: string,
+    intentScope: IntentScope = 'TransactionData',
+): Promise<string | undefined> {
     const startTime = Date.now();
 
     try {
         const client = createGCPKMSClient();
 
-        // Add intent message to transaction bytes
-        const intentMessage = messageWithIntent('TransactionData' as any, txBytes);
+        // Add intent message to the signed bytes
+        const intentMessage = messageWithIntent(intentScope, txBytes);
 
         // Create digest using blake2b hash
         const digest = blake2b(intentMessage, { dkLen: 32 });
@@ -331,4 +337,10 @@ export async function signAndVerify(txBytes: Uint8Array, keyPath: string): Promi
         console.error('Critical error in signAndVerify:', error instanceof Error ? error.message : error);
         return undefined;
     }
-} 
\ No newline at end of file
+} 
+
+// Sign an arbitrary message with the personal message intent, as signPersonalMessage does
+export async function signPersonalMessage(message: Uint8Array, keyPath: string): Promise<string | undefined> {
+    const messageBytes = bcs.vector(bcs.u8()).serialize(message).toBytes();
+    return signAndVerify(messageBytes, keyPath, 'PersonalMessage');
+}
@@ -1,7 +1,7 @@
 import 'dotenv/config';
 import express, { Request, Response } from 'express';
 import { fromB64 } from '@socialproof/mys/utils';
-import { getPublicKey, signAndVerify } from './gcpKmsUtils';
+import { getPublicKey, signAndVerify, signPersonalMessage } from './gcpKmsUtils';
 
 async function main() {
     const app = express();
@@ -77,6 +77,29 @@ async function main() {
         }
     });
     
+    // Sign a personal message - matches interface expected by SidecarTxSigner
+    app.post('/sign-personal-message', async (req: Request, res: Response) => {
+        try {
+            const { messageBytes } = req.body;
+
+            if (!messageBytes) {
+                return res.status(400).json({ error: 'Missing message bytes' });
+            }
+
+            const signature = await signPersonalMessage(fromB64(messageBytes), keyPath);
+
+            if (!signature) {
+                console.error('Failed to create signature for personal message');
+                return res.status(500).json({ error: 'Failed to sign personal message' });
+            }
+
+            res.json({ signature });
+        } catch (error) {
+            console.error('Error in sign-personal-message endpoint:', error instanceof Error ? error.message : error);
+            res.status(500).json({ error: 'Internal server error' });
+        }
+    });
+    
     app.listen(port, () => {
         console.log(`GCP KMS Sidecar listening on port ${port}`);
     });
@@ -0,0 +1,145 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{read_auth_env, read_auth_hmac_key_env};
+use anyhow::bail;
+use async_trait::async_trait;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::hmac::{hmac_sha3_256, HmacKey};
+use fastcrypto::traits::ToFromBytes;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+
+/// Used in place of a client id in usage metrics when the authentication backend cannot tell
+/// clients apart.
+pub const SHARED_CLIENT_ID: &str = "shared";
+
+/// Authenticates the bearer token of RPC requests.
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    /// Returns the id of the client the token was issued to, or None if the backend cannot tell
+    /// clients apart. Fails if the token is not valid.
+    async fn authenticate(&self, token: &str) -> anyhow::Result<Option<String>>;
+}
+
+/// All clients share a single secret token.
+pub struct StaticTokenAuthenticator {
+    token: String,
+}
+
+impl StaticTokenAuthenticator {
+    pub fn new(token: String) -> Arc<Self> {
+        Arc::new(Self { token })
+    }
+}
+
+#[async_trait]
+impl Authenticator for StaticTokenAuthenticator {
+    async fn authenticate(&self, token: &str) -> anyhow::Result<Option<String>> {
+        if !bool::from(token.as_bytes().ct_eq(self.token.as_bytes())) {
+            bail!("Invalid authorization token");
+        }
+        Ok(None)
+    }
+}
+
+/// Each client gets its own token of the form `<client_id>.<signature>`, where the signature is
+/// the hex encoded HMAC-SHA3-256 of the client id under a key only known to the gas pool.
+/// Tokens can be issued without any change to the running servers, and a token can only be
+/// used by the client it was issued to.
+pub struct HmacTokenAuthenticator {
+    key: HmacKey,
+}
+
+impl HmacTokenAuthenticator {
+    pub fn new(key: &str) -> Arc<Self> {
+        let key = HmacKey::from_bytes(key.as_bytes()).expect("Invalid HMAC key");
+        Arc::new(Self { key })
+    }
+
+    pub fn issue_token(&self, client_id: &str) -> String {
+        format!("{}.{}", client_id, Hex::encode(self.sign(client_id)))
+    }
+
+    fn sign(&self, client_id: &str) -> [u8; 32] {
+        hmac_sha3_256(&self.key, client_id.as_bytes()).digest
+    }
+}
+
+#[async_trait]
+impl Authenticator for HmacTokenAuthenticator {
+    async fn authenticate(&self, token: &str) -> anyhow::Result<Option<String>> {
+        let Some((client_id, signature)) = token.rsplit_once('.') else {
+            bail!("Invalid authorization token");
+        };
+        let Ok(signature) = Hex::decode(signature) else {
+            bail!("Invalid authorization token");
+        };
+        // Compared in constant time, so that the response time does not tell how much of a
+        // guessed signature is right.
+        if client_id.is_empty() || !bool::from(self.sign(client_id)[..].ct_eq(&signature)) {
+            bail!("Invalid authorization token");
+        }
+        Ok(Some(client_id.to_string()))
+    }
+}
+
+pub fn new_static_token_authenticator() -> Arc<dyn Authenticator> {
+    StaticTokenAuthenticator::new(read_auth_env())
+}
+
+pub fn new_hmac_token_authenticator() -> Arc<dyn Authenticator> {
+    HmacTokenAuthenticator::new(&read_auth_hmac_key_env())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_static_token_authenticator() {
+        let authenticator = StaticTokenAuthenticator::new("secret".to_string());
+        assert_eq!(authenticator.authenticate("secret").await.unwrap(), None);
+        assert!(authenticator.authenticate("other").await.is_err());
+        assert!(authenticator.authenticate("").await.is_err());
+        // Prefixes and extensions of the token are rejected too.
+        assert!(authenticator.authenticate("secre").await.is_err());
+        assert!(authenticator.authenticate("secrets").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_hmac_token_authenticator() {
+        let authenticator = HmacTokenAuthenticator::new("key");
+        let token = authenticator.issue_token("client.a");
+        assert_eq!(
+            authenticator.authenticate(&token).await.unwrap(),
+            Some("client.a".to_string())
+        );
+
+        // A token cannot be reused by another client.
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        assert!(authenticator
+            .authenticate(&format!("client.b.{}", signature))
+            .await
+            .is_err());
+        // Tokens issued with another key are rejected.
+        let other_token = HmacTokenAuthenticator::new("other key").issue_token("client.a");
+        assert!(authenticator.authenticate(&other_token).await.is_err());
+        assert!(authenticator.authenticate("client.a").await.is_err());
+        assert!(authenticator
+            .authenticate(&format!(".{}", signature))
+            .await
+            .is_err());
+        // Signatures that are not hex, or have the wrong length, are rejected.
+        assert!(authenticator.authenticate("client.a.zz").await.is_err());
+        assert!(authenticator
+            .authenticate(&format!("client.a.{}", &signature[..signature.len() - 2]))
+            .await
+            .is_err());
+        assert!(authenticator
+            .authenticate(&format!("client.a.{}00", signature))
+            .await
+            .is_err());
+    }
+}
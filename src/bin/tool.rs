@@ -3,12 +3,21 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use clap::*;
+use prometheus::Registry;
 use std::path::PathBuf;
+use std::str::FromStr;
 use mys_config::Config;
+use mys_gas_station::auth::HmacTokenAuthenticator;
 use mys_gas_station::benchmarks::kms_stress::run_kms_stress_test;
 use mys_gas_station::benchmarks::BenchmarkMode;
 use mys_gas_station::config::{GasPoolStorageConfig, GasStationConfig, TxSignerConfig};
+use mys_gas_station::gas_pool_drainer::drain_gas_pool;
+use mys_gas_station::metrics::StorageMetrics;
+use mys_gas_station::mys_client::{parse_gas_coin_type, MysClient};
+use mys_gas_station::read_auth_hmac_key_env;
 use mys_gas_station::rpc::client::GasPoolRpcClient;
+use mys_gas_station::storage::connect_storage;
+use mys_types::base_types::MysAddress;
 use mys_types::crypto::get_account_key_pair;
 
 #[derive(Parser)]
@@ -58,6 +67,25 @@ pub enum ToolCommand {
         #[arg(long, help = "Whether to use a sidecar service to sign transactions")]
         with_sidecar_signer: bool,
     },
+    /// Take all coins out of the gas pool, merge them and transfer them to a destination address.
+    /// This is meant for decommissioning a gas pool. All gas pool servers for the sponsor should
+    /// be stopped before running this, and it refuses to run if any coin is still reserved.
+    #[clap(name = "drain")]
+    Drain {
+        #[arg(long, help = "Path to the config file of the gas station to drain")]
+        config_path: PathBuf,
+        #[arg(long, help = "Address that receives all the coins in the pool")]
+        destination: String,
+        #[arg(long, help = "Confirm that all coins in the pool should be transferred")]
+        confirm: bool,
+    },
+    /// Issue the auth token of a client for gas stations configured with hmac-token auth.
+    /// Reads the signing key from the GAS_STATION_AUTH_HMAC_KEY environment variable.
+    #[clap(name = "generate-auth-token")]
+    GenerateAuthToken {
+        #[arg(long, help = "Id of the client the token is issued to")]
+        client_id: String,
+    },
     #[clap(name = "cli")]
     CLI {
         #[clap(subcommand)]
@@ -82,6 +110,57 @@ pub enum CliCommand {
         #[clap(long, help = "Full URL of the station RPC server")]
         station_rpc_url: String,
     },
+    /// Print the version, git revision, build date, signer type and storage backend of the station.
+    GetStationBuildInfo {
+        #[clap(long, help = "Full URL of the station RPC server")]
+        station_rpc_url: String,
+    },
+    /// Make the station pick up coins that were sent to the sponsor address without waiting for
+    /// the periodic refresh. Requires the GAS_STATION_ADMIN_AUTH environment variable.
+    ReloadCoins {
+        #[clap(long, help = "Full URL of the station RPC server")]
+        station_rpc_url: String,
+    },
+    /// Print the audit log records in a time range, one JSON record per line, oldest first.
+    /// Requires the GAS_STATION_ADMIN_AUTH environment variable.
+    QueryAuditLog {
+        #[clap(long, help = "Full URL of the station RPC server")]
+        station_rpc_url: String,
+        #[clap(long, help = "Start of the time range in milliseconds since the epoch, inclusive")]
+        from_ms: u64,
+        #[clap(long, help = "End of the time range in milliseconds since the epoch, exclusive")]
+        to_ms: u64,
+        #[clap(long, help = "Only print the records of this client")]
+        client_id: Option<String>,
+        #[clap(long, help = "Maximum number of records to print")]
+        limit: Option<usize>,
+    },
+    /// Print the active reservations and reserved budget of each client that holds a
+    /// reservation. Requires the GAS_STATION_ADMIN_AUTH environment variable.
+    ClientUsage {
+        #[clap(long, help = "Full URL of the station RPC server")]
+        station_rpc_url: String,
+        #[clap(long, help = "Only print the usage of this client")]
+        client_id: Option<String>,
+    },
+    /// Print a snapshot of the state of the pool as JSON, to attach to bug reports. It holds no
+    /// secrets. Requires the GAS_STATION_ADMIN_AUTH environment variable.
+    PoolSnapshot {
+        #[clap(long, help = "Full URL of the station RPC server")]
+        station_rpc_url: String,
+    },
+    /// Turn safe mode on or off. While it is on, background tasks of every station instance stop
+    /// changing the pool. Requires the GAS_STATION_ADMIN_AUTH environment variable.
+    SetSafeMode {
+        #[clap(long, help = "Full URL of the station RPC server")]
+        station_rpc_url: String,
+        #[clap(
+            long,
+            action = clap::ArgAction::Set,
+            help = "true to turn safe mode on, false to turn it off"
+        )]
+        enabled: bool,
+    },
 }
 
 impl ToolCommand {
@@ -126,6 +205,66 @@ impl ToolCommand {
                 };
                 config.save(config_path).unwrap();
             }
+            ToolCommand::Drain {
+                config_path,
+                destination,
+                confirm,
+            } => {
+                let destination = MysAddress::from_str(&destination).unwrap_or_else(|err| {
+                    panic!("Invalid destination address {}: {:?}", destination, err)
+                });
+                if !confirm {
+                    eprintln!(
+                        "This will transfer all coins in the gas pool to {}. Re-run with --confirm to proceed",
+                        destination
+                    );
+                    std::process::exit(1);
+                }
+                let config = GasStationConfig::load(config_path).unwrap();
+                let signer = config.signer_config.new_signer().await;
+                let storage = connect_storage(
+                    &config.gas_pool_config,
+                    signer.get_address(),
+                    config.coin_selection_strategy,
+                    StorageMetrics::new(&Registry::new()),
+                )
+                .await;
+                let mut mys_client = MysClient::new_with_client_config(
+                    &config.fullnode_url,
+                    config.fullnode_basic_auth,
+                    config.fullnode_client_config,
+                )
+                .await
+                .with_execute_retry_config(config.execute_retry_config)
+                .with_coin_denylist(config.coin_denylist);
+                if let Some(gas_coin_type) = &config.gas_coin_type {
+                    mys_client = mys_client.with_gas_coin_type(
+                        parse_gas_coin_type(gas_coin_type).unwrap_or_else(|err| {
+                            panic!("Invalid gas coin type {}: {:?}", gas_coin_type, err)
+                        }),
+                    );
+                }
+                match drain_gas_pool(mys_client, storage, signer, destination).await {
+                    Ok(total_balance) => {
+                        println!(
+                            "Transferred a total balance of {} to {}",
+                            total_balance, destination
+                        );
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to drain the gas pool: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            ToolCommand::GenerateAuthToken { client_id } => {
+                if client_id.is_empty() {
+                    eprintln!("Client id must not be empty");
+                    std::process::exit(1);
+                }
+                let authenticator = HmacTokenAuthenticator::new(&read_auth_hmac_key_env());
+                println!("{}", authenticator.issue_token(&client_id));
+            }
             ToolCommand::CLI { cli_command } => match cli_command {
                 CliCommand::CheckStationHealth { station_rpc_url } => {
                     let station_client = GasPoolRpcClient::new(station_rpc_url);
@@ -149,6 +288,100 @@ impl ToolCommand {
                     let version = station_client.version().await.unwrap();
                     println!("Station server version: {}", version);
                 }
+                CliCommand::GetStationBuildInfo { station_rpc_url } => {
+                    let station_client = GasPoolRpcClient::new(station_rpc_url);
+                    match station_client.build_info().await {
+                        Err(e) => {
+                            eprintln!("Failed to get build info: {}", e);
+                            std::process::exit(1);
+                        }
+                        Ok(build_info) => {
+                            println!("{}", serde_json::to_string_pretty(&build_info).unwrap());
+                        }
+                    }
+                }
+                CliCommand::ReloadCoins { station_rpc_url } => {
+                    let station_client = GasPoolRpcClient::new(station_rpc_url);
+                    match station_client.reload_coins().await {
+                        Err(e) => {
+                            eprintln!("Failed to reload coins: {}", e);
+                            std::process::exit(1);
+                        }
+                        Ok(result) => {
+                            println!(
+                                "Added {} coins with total balance {} out of {} coins owned by the sponsor",
+                                result.added_coin_count,
+                                result.added_balance,
+                                result.scanned_coin_count
+                            );
+                        }
+                    }
+                }
+                CliCommand::QueryAuditLog {
+                    station_rpc_url,
+                    from_ms,
+                    to_ms,
+                    client_id,
+                    limit,
+                } => {
+                    let station_client = GasPoolRpcClient::new(station_rpc_url);
+                    match station_client
+                        .query_audit_log(from_ms, to_ms, client_id, limit)
+                        .await
+                    {
+                        Err(e) => {
+                            eprintln!("Failed to query the audit log: {}", e);
+                            std::process::exit(1);
+                        }
+                        Ok(records) => {
+                            for record in records {
+                                println!("{}", serde_json::to_string(&record).unwrap());
+                            }
+                        }
+                    }
+                }
+                CliCommand::ClientUsage {
+                    station_rpc_url,
+                    client_id,
+                } => {
+                    let station_client = GasPoolRpcClient::new(station_rpc_url);
+                    match station_client.get_client_usage(client_id).await {
+                        Err(e) => {
+                            eprintln!("Failed to get the client usage: {}", e);
+                            std::process::exit(1);
+                        }
+                        Ok(usage) => {
+                            println!("{}", serde_json::to_string_pretty(&usage).unwrap());
+                        }
+                    }
+                }
+                CliCommand::PoolSnapshot { station_rpc_url } => {
+                    let station_client = GasPoolRpcClient::new(station_rpc_url);
+                    match station_client.get_pool_snapshot().await {
+                        Err(e) => {
+                            eprintln!("Failed to take a pool snapshot: {}", e);
+                            std::process::exit(1);
+                        }
+                        Ok(snapshot) => {
+                            println!("{}", serde_json::to_string_pretty(&snapshot).unwrap());
+                        }
+                    }
+                }
+                CliCommand::SetSafeMode {
+                    station_rpc_url,
+                    enabled,
+                } => {
+                    let station_client = GasPoolRpcClient::new(station_rpc_url);
+                    match station_client.set_safe_mode(enabled).await {
+                        Err(e) => {
+                            eprintln!("Failed to set safe mode: {}", e);
+                            std::process::exit(1);
+                        }
+                        Ok(safe_mode) => {
+                            println!("Safe mode is {}", if safe_mode { "on" } else { "off" });
+                        }
+                    }
+                }
             },
         }
     }
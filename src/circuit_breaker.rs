@@ -0,0 +1,243 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::CircuitBreakerConfig;
+use crate::errors::GasStationError;
+use crate::metrics::FullnodeMetrics;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CircuitState {
+    /// Requests go through normally.
+    Closed,
+    /// Requests fail fast without reaching the fullnode.
+    Open,
+    /// The open duration has passed and a single probe request is allowed through.
+    HalfOpen,
+}
+
+impl CircuitState {
+    fn as_metric_value(&self) -> i64 {
+        match self {
+            CircuitState::Closed => 0,
+            CircuitState::Open => 1,
+            CircuitState::HalfOpen => 2,
+        }
+    }
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CircuitState::Closed => write!(f, "closed"),
+            CircuitState::Open => write!(f, "open"),
+            CircuitState::HalfOpen => write!(f, "half-open"),
+        }
+    }
+}
+
+struct BreakerState {
+    state: CircuitState,
+    consecutive_failures: u64,
+    /// When the breaker last opened. Only meaningful in the open state.
+    opened_at: Instant,
+    /// When the current probe was let through. Only meaningful in the half-open state.
+    /// If the probe never reports back (e.g. the caller was dropped), another probe is
+    /// allowed after the open duration.
+    probe_started_at: Option<Instant>,
+}
+
+/// A circuit breaker that opens after a number of consecutive failures, and fails all requests
+/// fast for a cool-down period before letting a single probe request decide whether to close.
+pub struct CircuitBreaker {
+    failure_threshold: u64,
+    open_duration: Duration,
+    state: Mutex<BreakerState>,
+    metrics: Arc<FullnodeMetrics>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig, metrics: Arc<FullnodeMetrics>) -> Self {
+        metrics
+            .fullnode_circuit_breaker_state
+            .set(CircuitState::Closed.as_metric_value());
+        Self {
+            failure_threshold: config.failure_threshold.max(1),
+            open_duration: Duration::from_millis(config.open_duration_ms),
+            state: Mutex::new(BreakerState {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: Instant::now(),
+                probe_started_at: None,
+            }),
+            metrics,
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.state.lock().state
+    }
+
+    /// Checks whether a request may be sent to the fullnode.
+    /// Returns `GasStationError::FullnodeUnavailable` if the breaker is open.
+    pub fn check(&self) -> Result<(), GasStationError> {
+        let mut state = self.state.lock();
+        let now = Instant::now();
+        let allowed = match state.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => {
+                if now.duration_since(state.opened_at) >= self.open_duration {
+                    info!("Fullnode circuit breaker is half-open, probing the fullnode");
+                    self.set_state(&mut state, CircuitState::HalfOpen);
+                    state.probe_started_at = Some(now);
+                    true
+                } else {
+                    false
+                }
+            }
+            CircuitState::HalfOpen => match state.probe_started_at {
+                Some(started_at) if now.duration_since(started_at) < self.open_duration => false,
+                _ => {
+                    state.probe_started_at = Some(now);
+                    true
+                }
+            },
+        };
+        if allowed {
+            Ok(())
+        } else {
+            self.metrics.num_fullnode_requests_rejected.inc();
+            Err(GasStationError::FullnodeUnavailable)
+        }
+    }
+
+    /// Like `check`, but never lets a probe request through. This is used to fail fast before
+    /// starting work that will need the fullnode later on.
+    pub fn check_without_probe(&self) -> Result<(), GasStationError> {
+        let state = self.state.lock();
+        let now = Instant::now();
+        let rejecting = match state.state {
+            CircuitState::Closed => false,
+            CircuitState::Open => now.duration_since(state.opened_at) < self.open_duration,
+            CircuitState::HalfOpen => state
+                .probe_started_at
+                .is_some_and(|started_at| now.duration_since(started_at) < self.open_duration),
+        };
+        if rejecting {
+            self.metrics.num_fullnode_requests_rejected.inc();
+            Err(GasStationError::FullnodeUnavailable)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock();
+        state.consecutive_failures = 0;
+        state.probe_started_at = None;
+        if state.state != CircuitState::Closed {
+            info!("Fullnode circuit breaker is closed");
+            self.set_state(&mut state, CircuitState::Closed);
+        }
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock();
+        state.consecutive_failures += 1;
+        let should_open = match state.state {
+            CircuitState::Closed => state.consecutive_failures >= self.failure_threshold,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if should_open {
+            warn!(
+                "Fullnode circuit breaker is open after {} consecutive failures",
+                state.consecutive_failures
+            );
+            state.opened_at = Instant::now();
+            state.probe_started_at = None;
+            self.set_state(&mut state, CircuitState::Open);
+            self.metrics.num_fullnode_circuit_breaker_trips.inc();
+        }
+    }
+
+    fn set_state(&self, state: &mut BreakerState, new_state: CircuitState) {
+        state.state = new_state;
+        self.metrics
+            .fullnode_circuit_breaker_state
+            .set(new_state.as_metric_value());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_breaker(failure_threshold: u64, open_duration_ms: u64) -> CircuitBreaker {
+        CircuitBreaker::new(
+            CircuitBreakerConfig {
+                failure_threshold,
+                open_duration_ms,
+            },
+            FullnodeMetrics::new_for_testing(),
+        )
+    }
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breaker = new_breaker(3, 60_000);
+        breaker.record_failure();
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.check().is_ok());
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(matches!(
+            breaker.check(),
+            Err(GasStationError::FullnodeUnavailable)
+        ));
+        assert_eq!(breaker.metrics.num_fullnode_circuit_breaker_trips.get(), 1);
+        assert_eq!(breaker.metrics.fullnode_circuit_breaker_state.get(), 1);
+    }
+
+    #[test]
+    fn test_half_open_probe() {
+        let breaker = new_breaker(1, 50);
+        breaker.record_failure();
+        assert!(breaker.check().is_err());
+        std::thread::sleep(Duration::from_millis(60));
+
+        // Checking without probing does not use up the probe.
+        assert!(breaker.check_without_probe().is_ok());
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // Only a single probe is let through.
+        assert!(breaker.check().is_ok());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.check().is_err());
+
+        // A failed probe opens the breaker again.
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.check().is_err());
+        std::thread::sleep(Duration::from_millis(60));
+
+        // A successful probe closes it.
+        assert!(breaker.check().is_ok());
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.check().is_ok());
+        assert_eq!(breaker.metrics.fullnode_circuit_breaker_state.get(), 0);
+    }
+}
@@ -2,18 +2,24 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::config::GasStationConfig;
+use crate::config::{AuthConfig, GasPoolStorageConfig, GasStationConfig, TxSignerConfig};
 use crate::gas_pool::gas_pool_core::GasPoolContainer;
 use crate::gas_pool_initializer::GasPoolInitializer;
-use crate::metrics::{GasPoolCoreMetrics, GasPoolRpcMetrics, StorageMetrics};
+use crate::log_redaction::{init_log_redaction, redact_config};
+use crate::metrics::{
+    start_metrics_server, FullnodeMetrics, GasPoolCoreMetrics, GasPoolRpcMetrics, StorageMetrics,
+};
 use crate::rpc::GasPoolServer;
-use crate::storage::connect_storage;
-use crate::mys_client::MysClient;
+use crate::storage::{connect_storage, try_connect_storage, Storage};
+use crate::mys_client::{parse_gas_coin_type, MysClient};
+use crate::{AUTH_ENV_NAME, AUTH_HMAC_KEY_ENV_NAME};
 use clap::*;
+use prometheus::Registry;
+use reqwest::Url;
 use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 use mys_config::Config;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[derive(Parser)]
 #[command(
@@ -24,40 +30,149 @@ use tracing::{error, info};
 pub struct Command {
     #[arg(long, help = "Path to config file")]
     config_path: PathBuf,
+    #[arg(
+        long,
+        help = "Validate the config and its dependencies, then exit without serving"
+    )]
+    check: bool,
 }
 
 impl Command {
     pub async fn execute(self) {
-        let config: GasStationConfig = GasStationConfig::load(self.config_path).unwrap();
-        println!("Config: {:?}", config);
+        let config: GasStationConfig = match GasStationConfig::load(&self.config_path) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("Failed to load the config from {:?}: {:?}", self.config_path, err);
+                std::process::exit(1);
+            }
+        };
+        println!("Config: {}", redact_config(&config));
+        let problems = validate_config_and_dependencies(&config).await;
+        if !problems.is_empty() {
+            eprintln!(
+                "Found {} problem(s) with the config or its dependencies:",
+                problems.len()
+            );
+            for problem in &problems {
+                eprintln!("  - {}", problem);
+            }
+            std::process::exit(1);
+        }
+        if self.check {
+            println!("The config and its dependencies are valid");
+            return;
+        }
         let GasStationConfig {
             signer_config,
             gas_pool_config,
+            coin_selection_strategy,
             fullnode_url,
             fullnode_basic_auth,
+            read_fullnode_url,
             rpc_host_ip,
             rpc_port,
+            tls_config,
             metrics_port,
+            metrics_required,
             coin_init_config,
             daily_gas_usage_cap,
+            gas_pool_core_config,
+            fullnode_circuit_breaker_config,
+            fullnode_client_config,
+            log_redaction_config,
+            batch_execute_config,
+            reservation_limits_config,
+            request_deadline_config,
+            max_effects_size_bytes,
+            execute_retry_config,
+            auth_config,
+            coin_denylist,
+            fallback_gas_cost_per_object,
+            gas_coin_type,
+            safe_mode,
         } = config;
+        init_log_redaction(log_redaction_config);
 
         let metric_address = SocketAddr::new(IpAddr::V4(rpc_host_ip), metrics_port);
-        let registry_service = mysten_metrics::start_prometheus_server(metric_address);
-        let prometheus_registry = registry_service.default_registry();
+        let registry_service = match start_metrics_server(metric_address) {
+            Ok(registry_service) => Ok(registry_service),
+            Err(err) if metrics_required => {
+                panic!("Failed to start metrics server at {:?}: {:?}", metric_address, err)
+            }
+            Err(err) => Err(err),
+        };
+        let telemetry_registry = registry_service
+            .as_ref()
+            .map_or_else(|_| Registry::new(), |service| service.default_registry());
         let telemetry_config = telemetry_subscribers::TelemetryConfig::new()
             .with_log_level("off,mys_gas_station=debug")
             .with_env()
-            .with_prom_registry(&prometheus_registry);
+            .with_prom_registry(&telemetry_registry);
         let _guard = telemetry_config.init();
-        info!("Metrics server started at {:?}", metric_address);
+        // The gas pool metrics live in a registry of their own, so that registering them cannot
+        // conflict with the metrics of other components. Without a metrics server, they are still
+        // recorded but never exported.
+        let prometheus_registry = Registry::new();
+        match &registry_service {
+            Ok(registry_service) => {
+                registry_service.add(prometheus_registry.clone());
+                info!("Metrics server started at {:?}", metric_address);
+            }
+            Err(err) => warn!(
+                "Failed to start metrics server at {:?}, continuing with metrics disabled: {:?}",
+                metric_address, err
+            ),
+        }
 
         let signer = signer_config.new_signer().await;
         let storage_metrics = StorageMetrics::new(&prometheus_registry);
         let sponsor_address = signer.get_address();
         info!("Sponsor address: {:?}", sponsor_address);
-        let storage = connect_storage(&gas_pool_config, sponsor_address, storage_metrics).await;
-        let mys_client = MysClient::new(&fullnode_url, fullnode_basic_auth).await;
+        let storage = connect_storage(
+            &gas_pool_config,
+            sponsor_address,
+            coin_selection_strategy,
+            storage_metrics,
+        )
+        .await;
+        if safe_mode {
+            if let Err(err) = storage.set_safe_mode(true).await {
+                panic!("Failed to turn safe mode on: {:?}", err);
+            }
+            warn!("Safe mode is on. Background tasks do not change the pool until it is off");
+        }
+        let fullnode_metrics = FullnodeMetrics::new(&prometheus_registry);
+        if let Err(err) = fullnode_client_config.validate() {
+            panic!("Invalid fullnode client config: {:?}", err);
+        }
+        let mut mys_client = MysClient::new_with_client_config(
+            &fullnode_url,
+            fullnode_basic_auth.clone(),
+            fullnode_client_config,
+        )
+        .await
+        .with_metrics(fullnode_metrics.clone())
+        .with_execute_retry_config(execute_retry_config)
+        .with_coin_denylist(coin_denylist)
+        .with_fallback_gas_cost_per_object(fallback_gas_cost_per_object);
+        if let Some(read_fullnode_url) = read_fullnode_url {
+            info!("Sending fullnode read requests to the read fullnode");
+            mys_client = mys_client
+                .with_read_replica(&read_fullnode_url, fullnode_basic_auth)
+                .await;
+        }
+        if let Some(circuit_breaker_config) = fullnode_circuit_breaker_config {
+            mys_client = mys_client.with_circuit_breaker(circuit_breaker_config, fullnode_metrics);
+        }
+        if let Some(gas_coin_type) = gas_coin_type {
+            let gas_coin_type = parse_gas_coin_type(&gas_coin_type)
+                .unwrap_or_else(|err| panic!("Invalid gas coin type {}: {:?}", gas_coin_type, err));
+            info!("Managing gas coins of type {}", gas_coin_type);
+            mys_client = mys_client.with_gas_coin_type(gas_coin_type);
+        }
+        if let Err(err) = mys_client.validate_gas_coin_type().await {
+            panic!("Failed to validate the gas coin type: {:?}", err);
+        }
         let _coin_init_task = if let Some(coin_init_config) = coin_init_config {
             let task = GasPoolInitializer::start(
                 mys_client.clone(),
@@ -78,6 +193,7 @@ impl Command {
             mys_client,
             daily_gas_usage_cap,
             core_metrics,
+            gas_pool_core_config,
         )
         .await;
 
@@ -87,6 +203,12 @@ impl Command {
             rpc_host_ip,
             rpc_port,
             rpc_metrics,
+            batch_execute_config,
+            reservation_limits_config,
+            request_deadline_config,
+            max_effects_size_bytes,
+            auth_config,
+            tls_config,
         )
         .await;
         if let Err(err) = server.handle.await {
@@ -94,3 +216,370 @@ impl Command {
         }
     }
 }
+
+/// Checks the config and the services it depends on, and returns one actionable message per
+/// problem found. This runs before serving, so that misconfigurations are all reported at once
+/// instead of surfacing as panics or runtime errors later on. The dependencies are only probed
+/// once the config values are valid, and nothing is changed in the storage.
+pub async fn validate_config_and_dependencies(config: &GasStationConfig) -> Vec<String> {
+    let problems = validate_config(config);
+    if !problems.is_empty() {
+        return problems;
+    }
+    validate_dependencies(config).await
+}
+
+fn validate_config(config: &GasStationConfig) -> Vec<String> {
+    let mut problems = vec![];
+    if config.rpc_port == config.metrics_port {
+        problems.push(format!(
+            "rpc-port and metrics-port are both {}. Give the metrics server a port of its own",
+            config.rpc_port
+        ));
+    }
+    problems.extend(check_http_url("fullnode-url", &config.fullnode_url));
+    if let Some(read_fullnode_url) = &config.read_fullnode_url {
+        problems.extend(check_http_url("read-fullnode-url", read_fullnode_url));
+    }
+    if let TxSignerConfig::Sidecar { sidecar_url } = &config.signer_config {
+        problems.extend(check_http_url("sidecar-url", sidecar_url));
+    }
+    let GasPoolStorageConfig::Redis { redis_url } = &config.gas_pool_config;
+    if let Err(err) = redis::Client::open(redis_url.as_str()) {
+        problems.push(format!(
+            "redis-url is invalid: {}. Use the form redis://[user:password@]host:port",
+            err
+        ));
+    }
+    if let Err(err) = config.fullnode_client_config.validate() {
+        problems.push(format!("fullnode-client-config is invalid: {}", err));
+    }
+    if let Some(gas_coin_type) = &config.gas_coin_type {
+        if let Err(err) = parse_gas_coin_type(gas_coin_type) {
+            problems.push(format!(
+                "gas-coin-type {} is invalid: {}. Use a coin type such as 0x2::mys::MYS",
+                gas_coin_type, err
+            ));
+        }
+    }
+    if config.daily_gas_usage_cap == 0 {
+        problems.push(
+            "daily-gas-usage-cap is 0, so every transaction would be rejected. Set it to the most \
+            MIST the sponsor may spend per day"
+                .to_string(),
+        );
+    }
+    if let Some(coin_init_config) = &config.coin_init_config {
+        if coin_init_config.target_init_balance == 0 {
+            problems.push("coin-init-config.target-init-balance must be positive".to_string());
+        }
+        if coin_init_config.refresh_interval_sec == 0 {
+            problems.push("coin-init-config.refresh-interval-sec must be positive".to_string());
+        }
+        if coin_init_config.max_concurrent_splits == 0 {
+            problems.push("coin-init-config.max-concurrent-splits must be positive".to_string());
+        }
+        if coin_init_config.split_batch_size == 0 {
+            problems.push("coin-init-config.split-batch-size must be positive".to_string());
+        }
+    }
+    if config.batch_execute_config.max_batch_size == 0 {
+        problems.push("batch-execute-config.max-batch-size must be positive".to_string());
+    }
+    if config.batch_execute_config.max_concurrency == 0 {
+        problems.push("batch-execute-config.max-concurrency must be positive".to_string());
+    }
+    let execute_retry_config = &config.execute_retry_config;
+    if execute_retry_config.max_attempts == 0 {
+        problems.push(
+            "execute-retry-config.max-attempts must be positive, since it includes the first \
+            attempt"
+                .to_string(),
+        );
+    }
+    if execute_retry_config.initial_backoff_ms > execute_retry_config.max_backoff_ms {
+        problems.push(format!(
+            "execute-retry-config.initial-backoff-ms ({}) is larger than max-backoff-ms ({})",
+            execute_retry_config.initial_backoff_ms, execute_retry_config.max_backoff_ms
+        ));
+    }
+    if let Some(circuit_breaker_config) = &config.fullnode_circuit_breaker_config {
+        if circuit_breaker_config.failure_threshold == 0 {
+            problems.push(
+                "fullnode-circuit-breaker-config.failure-threshold must be positive".to_string(),
+            );
+        }
+    }
+    let request_deadline_config = &config.request_deadline_config;
+    if request_deadline_config.reserve_deadline_ms == Some(0) {
+        problems.push(
+            "request-deadline-config.reserve-deadline-ms must be positive, or left out for no \
+            deadline"
+                .to_string(),
+        );
+    }
+    if request_deadline_config.execute_deadline_ms == Some(0) {
+        problems.push(
+            "request-deadline-config.execute-deadline-ms must be positive, or left out for no \
+            deadline"
+                .to_string(),
+        );
+    }
+    if let Some(tls_config) = &config.tls_config {
+        for (name, path) in [
+            ("cert-path", &tls_config.cert_path),
+            ("key-path", &tls_config.key_path),
+        ] {
+            if !path.is_file() {
+                problems.push(format!("tls-config.{} {:?} is not a readable file", name, path));
+            }
+        }
+    }
+    if config.gas_pool_core_config.client_budget_limits_config.is_some()
+        && !config.auth_config.identifies_clients()
+    {
+        problems.push(
+            "client-budget-limits-config, including its caps on active reservations, requires an \
+            auth-config that identifies clients, such as hmac-token. Otherwise clients could get \
+            around their limits"
+                .to_string(),
+        );
+    }
+    let auth_env_name = match config.auth_config {
+        AuthConfig::StaticToken => AUTH_ENV_NAME,
+        AuthConfig::HmacToken => AUTH_HMAC_KEY_ENV_NAME,
+    };
+    if std::env::var(auth_env_name).map_or(true, |value| value.is_empty()) {
+        problems.push(format!(
+            "The {} environment variable must be set, since clients are authenticated with it",
+            auth_env_name
+        ));
+    }
+    problems
+}
+
+fn check_http_url(name: &str, url: &str) -> Option<String> {
+    match Url::parse(url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => None,
+        Ok(_) => Some(format!("{} must be an http or https URL", name)),
+        Err(err) => Some(format!("{} is not a valid URL: {}", name, err)),
+    }
+}
+
+async fn validate_dependencies(config: &GasStationConfig) -> Vec<String> {
+    let mut problems = vec![];
+    let signer = match config.signer_config.try_new_signer().await {
+        Ok(signer) => Some(signer),
+        Err(err) => {
+            problems.push(format!(
+                "Failed to reach the signer: {:?}. Check signer-config and that the KMS sidecar \
+                is running",
+                err
+            ));
+            None
+        }
+    };
+    let personal_message_options: Vec<_> = [
+        (
+            "sign-execution-effects",
+            config.gas_pool_core_config.sign_execution_effects,
+        ),
+        (
+            "sign-reservation-receipts",
+            config.gas_pool_core_config.sign_reservation_receipts,
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect();
+    if let Some(signer) = &signer {
+        if !personal_message_options.is_empty() {
+            if let Err(err) = signer.sign_personal_message(b"gas pool startup check").await {
+                let options = personal_message_options.join(" and ");
+                problems.push(format!(
+                    "{} requires signing personal messages, but the signer cannot: {:?}. Update \
+                    the KMS sidecar or unset {}",
+                    options, err, options
+                ));
+            }
+        }
+    }
+    let sponsor_address = signer.map(|signer| signer.get_address());
+    let expected_address = config
+        .gas_pool_core_config
+        .sponsor_address_check_config
+        .sponsor_address;
+    if let (Some(sponsor_address), Some(expected_address)) = (sponsor_address, expected_address) {
+        if sponsor_address != expected_address {
+            problems.push(format!(
+                "The signer signs for {} but sponsor-address-check-config expects {}. Point the \
+                signer at the sponsor key or update the expected address",
+                sponsor_address, expected_address
+            ));
+        }
+    }
+
+    let mys_client = match MysClient::try_new_with_client_config(
+        &config.fullnode_url,
+        config.fullnode_basic_auth.clone(),
+        config.fullnode_client_config.clone(),
+    )
+    .await
+    {
+        Ok(mys_client) => Some(mys_client),
+        Err(err) => {
+            problems.push(format!("{:?}", err));
+            None
+        }
+    };
+    // The gas coin type was already checked by validate_config.
+    let mys_client = mys_client.map(|client| {
+        match config.gas_coin_type.as_deref().map(parse_gas_coin_type) {
+            Some(Ok(gas_coin_type)) => client.with_gas_coin_type(gas_coin_type),
+            _ => client,
+        }
+    });
+    if let Some(client) = &mys_client {
+        if let Err(err) = client.try_get_reference_gas_price().await {
+            problems.push(format!(
+                "The fullnode at fullnode-url does not serve requests: {:?}",
+                err
+            ));
+        } else if let Err(err) = client.validate_gas_coin_type().await {
+            problems.push(format!("gas-coin-type cannot be used: {:?}", err));
+        }
+    }
+    if let Some(read_fullnode_url) = &config.read_fullnode_url {
+        let read_client = MysClient::try_new_with_client_config(
+            read_fullnode_url,
+            config.fullnode_basic_auth.clone(),
+            config.fullnode_client_config.clone(),
+        )
+        .await;
+        let result = match read_client {
+            Ok(read_client) => read_client.try_get_reference_gas_price().await.map(|_| ()),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            problems.push(format!(
+                "The fullnode at read-fullnode-url does not serve requests: {:?}",
+                err
+            ));
+        }
+    }
+
+    let Some(sponsor_address) = sponsor_address else {
+        return problems;
+    };
+    let storage = match try_connect_storage(
+        &config.gas_pool_config,
+        sponsor_address,
+        config.coin_selection_strategy,
+        StorageMetrics::new(&Registry::new()),
+    )
+    .await
+    {
+        Ok(storage) => storage,
+        Err(err) => {
+            problems.push(format!(
+                "Failed to connect to the storage: {:?}. Check redis-url and that Redis is running",
+                err
+            ));
+            return problems;
+        }
+    };
+    let Some(mys_client) = mys_client else {
+        return problems;
+    };
+    let pool_coin_count = match storage.get_available_coin_count().await {
+        Ok(count) => count,
+        Err(err) => {
+            problems.push(format!("Failed to read the gas pool from the storage: {:?}", err));
+            return problems;
+        }
+    };
+    if pool_coin_count > 0 {
+        return problems;
+    }
+    match mys_client.try_get_gas_coin_balance(sponsor_address).await {
+        Ok((0, _)) => problems.push(format!(
+            "The gas pool is empty and the sponsor address {} owns no gas coins. Fund the \
+            sponsor address before starting the gas pool",
+            sponsor_address
+        )),
+        Ok((coin_count, _)) if config.coin_init_config.is_none() => problems.push(format!(
+            "The gas pool is empty and coin-init-config is not set, so the {} gas coins owned by \
+            the sponsor address {} are never added to it. Set coin-init-config",
+            coin_count, sponsor_address
+        )),
+        Ok(_) => {}
+        Err(err) => problems.push(format!(
+            "Failed to query the gas coins owned by the sponsor address {}: {:?}",
+            sponsor_address, err
+        )),
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_config;
+    use crate::config::{
+        AuthConfig, ClientBudgetLimitsConfig, GasPoolCoreConfig, GasStationConfig,
+        RequestDeadlineConfig,
+    };
+    use crate::AUTH_ENV_NAME;
+
+    fn config_problems(config: &GasStationConfig) -> Vec<String> {
+        // Whether the auth token is set depends on the environment of the test run.
+        validate_config(config)
+            .into_iter()
+            .filter(|problem| !problem.contains(AUTH_ENV_NAME))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_config() {
+        assert!(config_problems(&GasStationConfig::default()).is_empty());
+
+        let config = GasStationConfig {
+            metrics_port: GasStationConfig::default().rpc_port,
+            fullnode_url: "localhost:9000".to_string(),
+            daily_gas_usage_cap: 0,
+            request_deadline_config: RequestDeadlineConfig {
+                reserve_deadline_ms: Some(0),
+                execute_deadline_ms: Some(1000),
+            },
+            ..Default::default()
+        };
+        let problems = config_problems(&config);
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].starts_with("rpc-port and metrics-port"));
+        assert!(problems[1].starts_with("fullnode-url"));
+        assert!(problems[2].starts_with("daily-gas-usage-cap"));
+        assert!(problems[3].starts_with("request-deadline-config.reserve-deadline-ms"));
+    }
+
+    #[test]
+    fn test_client_budget_limits_require_client_identity() {
+        let gas_pool_core_config = GasPoolCoreConfig {
+            client_budget_limits_config: Some(ClientBudgetLimitsConfig::default()),
+            ..Default::default()
+        };
+        let config = GasStationConfig {
+            gas_pool_core_config: gas_pool_core_config.clone(),
+            auth_config: AuthConfig::StaticToken,
+            ..Default::default()
+        };
+        let problems = config_problems(&config);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(problems[0].starts_with("client-budget-limits-config"));
+
+        let config = GasStationConfig {
+            gas_pool_core_config,
+            auth_config: AuthConfig::HmacToken,
+            ..Default::default()
+        };
+        assert!(config_problems(&config).is_empty());
+    }
+}
@@ -2,12 +2,17 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::auth::{new_hmac_token_authenticator, new_static_token_authenticator, Authenticator};
+use crate::execute_retry::ExecuteErrorClass;
 use crate::tx_signer::{SidecarTxSigner, TestTxSigner, TxSigner};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
+use std::collections::BTreeMap;
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use mys_config::Config;
+use mys_types::base_types::{MysAddress, ObjectID};
 use mys_types::crypto::{get_account_key_pair, MysKeyPair};
 use mys_types::gas_coin::MIST_PER_MYS;
 
@@ -18,6 +23,47 @@ pub const DEFAULT_INIT_COIN_BALANCE: u64 = MIST_PER_MYS / 10;
 // 24 hours.
 const DEFAULT_COIN_POOL_REFRESH_INTERVAL_SEC: u64 = 60 * 60 * 24;
 pub const DEFAULT_DAILY_GAS_USAGE_CAP: u64 = 1500 * MIST_PER_MYS;
+pub const DEFAULT_COIN_COOLDOWN_MS: u64 = 500;
+// 1 hour.
+const DEFAULT_STRANDED_COIN_GRACE_PERIOD_SEC: u64 = 60 * 60;
+const DEFAULT_EXECUTING_RESERVATION_GRACE_PERIOD_SEC: u64 = 5 * 60;
+const DEFAULT_MAX_GAS_PRICE_MULTIPLIER: u64 = 10;
+const DEFAULT_RESERVATION_CALLBACK_MAX_ATTEMPTS: usize = 3;
+const DEFAULT_MAX_RESERVATION_CALLBACKS_PER_SEC: u64 = 100;
+const DEFAULT_CIRCUIT_BREAKER_FAILURE_THRESHOLD: u64 = 10;
+const DEFAULT_CIRCUIT_BREAKER_OPEN_DURATION_MS: u64 = 10_000;
+const DEFAULT_MAX_LOGGED_CHARS: usize = 16;
+const DEFAULT_MAX_BATCH_SIZE: usize = 100;
+const DEFAULT_MAX_BATCH_CONCURRENCY: usize = 16;
+const DEFAULT_REBALANCE_TARGET_COIN_COUNT: usize = 1000;
+const DEFAULT_REBALANCE_BALANCE_TOLERANCE_FACTOR: u64 = 4;
+const DEFAULT_REBALANCE_INTERVAL_SEC: u64 = 60;
+const DEFAULT_REBALANCE_MAX_COINS_PER_ROUND: usize = 100;
+const DEFAULT_MAX_RESERVATION_PRIORITY: u8 = 10;
+const DEFAULT_PRIORITY_EXHAUSTION_COIN_THRESHOLD: usize = 100;
+const DEFAULT_PRIORITY_MAX_WAIT_MS: u64 = 2000;
+const DEFAULT_EXECUTE_MAX_ATTEMPTS: usize = 3;
+const DEFAULT_EXECUTE_INITIAL_BACKOFF_MS: u64 = 50;
+const DEFAULT_EXECUTE_MAX_BACKOFF_MS: u64 = 1000;
+const DEFAULT_COIN_BALANCE_AUDIT_INTERVAL_SEC: u64 = 60 * 60;
+const DEFAULT_MIN_RESERVE_DURATION_SECS: u64 = 2;
+const DEFAULT_SPONSOR_ADDRESS_CHECK_INTERVAL_SEC: u64 = 60;
+const DEFAULT_AUDIT_LOG_RETENTION_DAYS: u64 = 90;
+const DEFAULT_AUDIT_LOG_MAX_PENDING_RECORDS: usize = 10_000;
+const DEFAULT_AUDIT_LOG_FLUSH_INTERVAL_MS: u64 = 1000;
+const DEFAULT_MAX_WARMUP_SEC: u64 = 60;
+const DEFAULT_WARMUP_CHECK_INTERVAL_MS: u64 = 500;
+const DEFAULT_MAX_CONCURRENT_INIT_SPLITS: usize = 32;
+const DEFAULT_INIT_SPLIT_BATCH_SIZE: usize = 100;
+const DEFAULT_MAX_COIN_VERSION_MISMATCHES: u64 = 3;
+const DEFAULT_COIN_VERSION_MISMATCH_WINDOW_SEC: u64 = 10 * 60;
+const DEFAULT_COIN_QUARANTINE_SEC: u64 = 10 * 60;
+const DEFAULT_MAX_WAITING_RESERVATIONS: usize = 1000;
+const DEFAULT_MAX_RESERVATION_WAIT_SEC: u64 = 60;
+const DEFAULT_WAITLIST_RETRY_INTERVAL_MS: u64 = 500;
+const DEFAULT_EXECUTE_EXPIRY_MARGIN_MS: u64 = 1000;
+const DEFAULT_FULLNODE_MAX_CONCURRENT_REQUESTS: usize = 1000;
+const DEFAULT_FULLNODE_REQUEST_TIMEOUT_SECS: u64 = 60;
 
 // Use 127.0.0.1 for tests to avoid OS complaining about permissions.
 #[cfg(test)]
@@ -32,16 +78,73 @@ pub struct GasStationConfig {
     pub signer_config: TxSignerConfig,
     pub rpc_host_ip: Ipv4Addr,
     pub rpc_port: u16,
+    /// When specified, the RPC server serves HTTPS instead of HTTP.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_config: Option<TlsConfig>,
     pub metrics_port: u16,
+    /// Whether the gas pool should fail to start when the metrics cannot be exported. By default,
+    /// it keeps serving with metrics disabled.
+    #[serde(default)]
+    pub metrics_required: bool,
     pub gas_pool_config: GasPoolStorageConfig,
+    /// How coins are picked for reservations.
+    #[serde(default)]
+    pub coin_selection_strategy: CoinSelectionStrategy,
     pub fullnode_url: String,
     /// An optional basic auth when connecting to the fullnode. If specified, the format is
     /// (username, password).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fullnode_basic_auth: Option<(String, String)>,
+    /// An optional fullnode that serves all read requests, such as coin queries, gas price and
+    /// dev inspect, while transactions are still executed on fullnode_url. Reads fall back to
+    /// fullnode_url while the read fullnode is failing. Uses the same basic auth.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_fullnode_url: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub coin_init_config: Option<CoinInitConfig>,
     pub daily_gas_usage_cap: u64,
+    #[serde(default)]
+    pub gas_pool_core_config: GasPoolCoreConfig,
+    /// When specified, requests to the fullnode go through a circuit breaker that fails fast
+    /// while the fullnode is persistently failing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fullnode_circuit_breaker_config: Option<CircuitBreakerConfig>,
+    /// Tuning of the connections to the fullnodes, shared by fullnode_url and read_fullnode_url.
+    #[serde(default)]
+    pub fullnode_client_config: FullnodeClientConfig,
+    #[serde(default)]
+    pub log_redaction_config: LogRedactionConfig,
+    #[serde(default)]
+    pub batch_execute_config: BatchExecuteConfig,
+    #[serde(default)]
+    pub reservation_limits_config: ReservationLimitsConfig,
+    #[serde(default)]
+    pub request_deadline_config: RequestDeadlineConfig,
+    /// When specified, execute_tx responses whose effects are larger than this many bytes of
+    /// JSON only carry a summary of the effects, unless the request asks for the full effects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_effects_size_bytes: Option<usize>,
+    #[serde(default)]
+    pub execute_retry_config: ExecuteRetryConfig,
+    #[serde(default)]
+    pub auth_config: AuthConfig,
+    /// Coins owned by the sponsor that must never be added to the pool, e.g. because they are
+    /// earmarked for other uses.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub coin_denylist: Vec<ObjectID>,
+    /// The gas cost per object to use when splitting coins if it cannot be calibrated with
+    /// dev_inspect, e.g. because the fullnode disables dev_inspect. Without it, calibration is
+    /// retried until dev_inspect succeeds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_gas_cost_per_object: Option<u64>,
+    /// The type `T` of the `Coin<T>` coins managed by the pool, e.g. `0x2::mys::MYS`. Defaults to
+    /// GAS. Only useful on networks that accept another coin type for gas payment.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_coin_type: Option<String>,
+    /// Turns safe mode on at startup, for every gas pool instance of the sponsor. It stays on
+    /// until it is turned off with the admin endpoint.
+    #[serde(default)]
+    pub safe_mode: bool,
 }
 
 impl Config for GasStationConfig {}
@@ -52,12 +155,30 @@ impl Default for GasStationConfig {
             signer_config: TxSignerConfig::default(),
             rpc_host_ip: LOCALHOST,
             rpc_port: DEFAULT_RPC_PORT,
+            tls_config: None,
             metrics_port: DEFAULT_METRICS_PORT,
+            metrics_required: false,
             gas_pool_config: GasPoolStorageConfig::default(),
+            coin_selection_strategy: CoinSelectionStrategy::default(),
             fullnode_url: "http://localhost:9000".to_string(),
             fullnode_basic_auth: None,
+            read_fullnode_url: None,
             coin_init_config: Some(CoinInitConfig::default()),
             daily_gas_usage_cap: DEFAULT_DAILY_GAS_USAGE_CAP,
+            gas_pool_core_config: GasPoolCoreConfig::default(),
+            fullnode_circuit_breaker_config: Some(CircuitBreakerConfig::default()),
+            fullnode_client_config: FullnodeClientConfig::default(),
+            log_redaction_config: LogRedactionConfig::default(),
+            batch_execute_config: BatchExecuteConfig::default(),
+            reservation_limits_config: ReservationLimitsConfig::default(),
+            request_deadline_config: RequestDeadlineConfig::default(),
+            max_effects_size_bytes: None,
+            execute_retry_config: ExecuteRetryConfig::default(),
+            auth_config: AuthConfig::default(),
+            coin_denylist: vec![],
+            fallback_gas_cost_per_object: None,
+            gas_coin_type: None,
+            safe_mode: false,
         }
     }
 }
@@ -77,6 +198,48 @@ impl Default for GasPoolStorageConfig {
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CoinSelectionStrategy {
+    /// Reserve coins in the order they became available.
+    #[default]
+    Fifo,
+    /// For budgets up to `max_single_coin_balance`, assemble the budget from coins with a
+    /// balance up to `max_single_coin_balance`, and only fall back to a single larger coin when
+    /// the smaller ones cannot cover it. This keeps large coins for large reservations, at the cost
+    /// of reserving more coins per small reservation. Larger budgets are served in FIFO order.
+    #[serde(rename_all = "kebab-case")]
+    PreserveLargeCoins { max_single_coin_balance: u64 },
+    /// Reserve coins whose version has not changed for at least `min_stable_sec` first, in FIFO
+    /// order, and only fall back to coins that changed more recently when the stable ones cannot
+    /// cover the budget. This lowers the chance of handing out a coin whose version is not yet
+    /// seen by every fullnode, at the cost of looking past more coins per reservation.
+    #[serde(rename_all = "kebab-case")]
+    PreferStableCoins { min_stable_sec: u64 },
+}
+
+impl CoinSelectionStrategy {
+    /// Coins above this balance are avoided for budgets up to it. 0 if no coin is avoided.
+    pub fn max_single_coin_balance(&self) -> u64 {
+        match self {
+            CoinSelectionStrategy::PreserveLargeCoins {
+                max_single_coin_balance,
+            } => *max_single_coin_balance,
+            CoinSelectionStrategy::Fifo | CoinSelectionStrategy::PreferStableCoins { .. } => 0,
+        }
+    }
+
+    /// Coins whose version changed less than this long ago are avoided. 0 if no coin is avoided.
+    pub fn min_stable_ms(&self) -> u64 {
+        match self {
+            CoinSelectionStrategy::PreferStableCoins { min_stable_sec } => {
+                min_stable_sec.saturating_mul(1000)
+            }
+            CoinSelectionStrategy::Fifo | CoinSelectionStrategy::PreserveLargeCoins { .. } => 0,
+        }
+    }
+}
+
 #[serde_as]
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
@@ -101,11 +264,62 @@ impl TxSignerConfig {
             TxSignerConfig::Sidecar { sidecar_url } => SidecarTxSigner::new(sidecar_url).await,
         }
     }
+
+    /// Same as `new_signer`, but fails instead of panicking when the signer cannot be reached.
+    pub async fn try_new_signer(&self) -> anyhow::Result<Arc<dyn TxSigner>> {
+        Ok(match self {
+            TxSignerConfig::Local { keypair } => TestTxSigner::new(keypair.copy()),
+            TxSignerConfig::Sidecar { sidecar_url } => {
+                SidecarTxSigner::try_new(sidecar_url.clone()).await?
+            }
+        })
+    }
 }
 
+/// How the RPC server authenticates the bearer token of requests. Secrets are always read from
+/// environment variables so that they never show up in the config file.
 #[serde_as]
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
+pub enum AuthConfig {
+    /// All clients share the token in the GAS_STATION_AUTH environment variable.
+    #[default]
+    StaticToken,
+    /// Each client has its own token, signed with the key in the GAS_STATION_AUTH_HMAC_KEY
+    /// environment variable. See `HmacTokenAuthenticator`.
+    HmacToken,
+}
+
+impl AuthConfig {
+    /// Whether the authenticator tells clients apart, so that per-client limits can be enforced.
+    pub fn identifies_clients(&self) -> bool {
+        match self {
+            AuthConfig::StaticToken => false,
+            AuthConfig::HmacToken => true,
+        }
+    }
+
+    pub fn new_authenticator(self) -> Arc<dyn Authenticator> {
+        match self {
+            AuthConfig::StaticToken => new_static_token_authenticator(),
+            AuthConfig::HmacToken => new_hmac_token_authenticator(),
+        }
+    }
+}
+
+/// PEM encoded certificate and private key of the RPC server. Both files are read again on SIGHUP,
+/// so that the certificate can be rotated without a restart.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TlsConfig {
+    /// The certificate chain, starting with the certificate of the server.
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+#[serde_as]
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
 pub struct CoinInitConfig {
     /// When we split a new gas coin, what is the target balance for the new coins, in MIST.
     pub target_init_balance: u64,
@@ -113,6 +327,13 @@ pub struct CoinInitConfig {
     /// requires initialization, i.e. splitting into smaller coins and add them to the gas pool.
     /// This is in seconds.
     pub refresh_interval_sec: u64,
+    /// How many split transactions can be signed and executed at the same time, to avoid
+    /// overwhelming the fullnode or the signer.
+    pub max_concurrent_splits: usize,
+    /// How many coins are split per batch. The coins produced by a batch are added to the pool
+    /// once the batch is done, and the coins of a batch that failed to split are retried on their
+    /// own.
+    pub split_batch_size: usize,
 }
 
 impl Default for CoinInitConfig {
@@ -120,6 +341,593 @@ impl Default for CoinInitConfig {
         CoinInitConfig {
             target_init_balance: DEFAULT_INIT_COIN_BALANCE,
             refresh_interval_sec: DEFAULT_COIN_POOL_REFRESH_INTERVAL_SEC,
+            max_concurrent_splits: DEFAULT_MAX_CONCURRENT_INIT_SPLITS,
+            split_batch_size: DEFAULT_INIT_SPLIT_BATCH_SIZE,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct GasPoolCoreConfig {
+    /// Whether to return the raw effects signed by the sponsor in execute_tx responses, so that
+    /// clients can verify that the effects were obtained by the gas pool.
+    /// With the sidecar signer, this requires a sidecar that can sign personal messages.
+    pub sign_execution_effects: bool,
+    /// How long to hold back gas coins that were just used in a transaction before they can be
+    /// reserved again, in milliseconds. This gives fullnodes time to catch up with the new coin
+    /// versions. Can be set to 0 when there is only a single fullnode.
+    pub coin_cooldown_ms: u64,
+    pub reservation_callback_config: ReservationCallbackConfig,
+    /// When specified, the gas pool periodically splits large coins and merges small coins to
+    /// keep the pool close to the target coin distribution.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coin_rebalance_config: Option<CoinRebalanceConfig>,
+    /// When specified, reserve_gas requests are served in priority order while the pool is
+    /// close to running out of coins.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reservation_priority_config: Option<ReservationPriorityConfig>,
+    /// When specified, reservations are rejected when they exceed the budget limits of the client
+    /// id that made them. Requires an auth config that identifies clients.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_budget_limits_config: Option<ClientBudgetLimitsConfig>,
+    /// When specified, new reservations and executions are rejected once the gas spent by
+    /// executed transactions in the current epoch reaches this amount, in MIST.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_epoch_spend_cap: Option<u64>,
+    /// When specified, the gas pool periodically checks the balances it tracks for the available
+    /// coins against the coins themselves and against the chain.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coin_balance_audit_config: Option<CoinBalanceAuditConfig>,
+    pub sponsor_address_check_config: SponsorAddressCheckConfig,
+    /// When specified, every reservation and execution is recorded in an audit log kept in the
+    /// storage, which can be queried through the admin endpoint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audit_log_config: Option<AuditLogConfig>,
+    /// On startup, coins that are still held by a reservation that is over, e.g. because the gas
+    /// pool crashed while executing a transaction, are released back to the pool if they were
+    /// reserved more than this many seconds ago. This must be longer than a reservation can last,
+    /// plus the time it takes to execute a transaction, since other gas pool instances may still
+    /// be using more recently reserved coins.
+    pub stranded_coin_grace_period_sec: u64,
+    /// Before a transaction is submitted, its digest is recorded along with its reservation until
+    /// the coins are released. On startup, and then every this many seconds, reservations
+    /// whose transaction was submitted more than this many seconds ago are reconciled with the
+    /// fullnode: their coins are released at the versions produced by the transaction if it was
+    /// executed, and as they are otherwise. This must be longer than it takes to execute a
+    /// transaction, including retries and the coin cooldown.
+    pub executing_reservation_grace_period_sec: u64,
+    /// When specified, reservations are held back after startup until the gas pool is ready.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warmup_config: Option<WarmupConfig>,
+    /// Transactions may pay a gas price above the reference gas price, e.g. to get prioritized
+    /// under congestion, up to this multiple of the reference gas price. The sponsor pays for the
+    /// higher price.
+    pub max_gas_price_multiplier: u64,
+    /// Limits how many move calls to a package, module or function each client can get
+    /// sponsored within a time window. Transactions that would exceed any of the limits are
+    /// rejected before they are executed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub move_call_rate_limits: Vec<MoveCallRateLimitConfig>,
+    /// Whether to check that the gas coins of a transaction are still at the reserved versions
+    /// and owned by the sponsor right before executing it. This costs an extra fullnode request
+    /// per execution, but rejects transactions that would fail on a stale gas coin without
+    /// submitting them.
+    pub check_coin_freshness_before_execute: bool,
+    /// Transactions of reservations that expire within this many milliseconds are rejected
+    /// instead of executed, so that a reservation never expires while its transaction is being
+    /// prepared. The client should reserve gas again.
+    pub execute_expiry_margin_ms: u64,
+    /// Gas coins whose balance is below this many MIST after they were used in a transaction are
+    /// retired instead of being returned to the pool, since they can barely cover any budget.
+    /// Retired coins stay with the sponsor and are logged, but are no longer tracked by the pool.
+    /// With 0, every coin is returned.
+    pub retire_coin_balance_threshold: u64,
+    /// Whether to run every transaction with dev_inspect before executing it, and reject the
+    /// transactions that would fail instead of paying for them. This costs an extra fullnode
+    /// request per execution. Clients can also ask for it per execute_tx request.
+    pub preflight_transactions: bool,
+    /// Whether to return a reservation receipt signed by the sponsor in reserve_gas responses,
+    /// so that clients can prove that the gas pool committed gas to them. This adds a signing
+    /// operation to every reservation. With the sidecar signer, this requires a sidecar that can
+    /// sign personal messages.
+    pub sign_reservation_receipts: bool,
+    /// When specified, gas coins that are repeatedly found at a newer version than they were
+    /// reserved at, i.e. that are used outside of the gas pool, are held back for a while before
+    /// they can be reserved again.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coin_quarantine_config: Option<CoinQuarantineConfig>,
+    /// When specified, reserve_gas requests with wait_for_coins set that the pool cannot cover
+    /// right away are put on a waitlist instead of failing, and their result is POSTed to their
+    /// callback URL once coins free up.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reservation_waitlist_config: Option<ReservationWaitlistConfig>,
+    /// Reservation tags that get their own series in the per-tag metrics. Usage under any other
+    /// tag is counted under `other`, so that clients cannot create an unbounded number of metric
+    /// series. Tags are still recorded as given in responses and the audit log.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub metric_tags: Vec<String>,
+}
+
+impl Default for GasPoolCoreConfig {
+    fn default() -> Self {
+        GasPoolCoreConfig {
+            sign_execution_effects: false,
+            coin_cooldown_ms: DEFAULT_COIN_COOLDOWN_MS,
+            reservation_callback_config: ReservationCallbackConfig::default(),
+            coin_rebalance_config: None,
+            reservation_priority_config: None,
+            client_budget_limits_config: None,
+            per_epoch_spend_cap: None,
+            coin_balance_audit_config: None,
+            sponsor_address_check_config: SponsorAddressCheckConfig::default(),
+            audit_log_config: None,
+            stranded_coin_grace_period_sec: DEFAULT_STRANDED_COIN_GRACE_PERIOD_SEC,
+            executing_reservation_grace_period_sec: DEFAULT_EXECUTING_RESERVATION_GRACE_PERIOD_SEC,
+            warmup_config: None,
+            max_gas_price_multiplier: DEFAULT_MAX_GAS_PRICE_MULTIPLIER,
+            move_call_rate_limits: vec![],
+            check_coin_freshness_before_execute: false,
+            execute_expiry_margin_ms: DEFAULT_EXECUTE_EXPIRY_MARGIN_MS,
+            retire_coin_balance_threshold: 0,
+            preflight_transactions: false,
+            sign_reservation_receipts: false,
+            coin_quarantine_config: None,
+            reservation_waitlist_config: None,
+            metric_tags: vec![],
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ReservationCallbackConfig {
+    /// URL schemes that callback URLs in reserve_gas requests are allowed to use.
+    /// Callbacks are disabled if this is empty.
+    pub allowed_schemes: Vec<String>,
+    /// Whether callback URLs may point to localhost or private IP addresses.
+    /// This should stay disabled unless the gas pool only serves trusted callers.
+    pub allow_private_hosts: bool,
+    /// How many times to retry delivering a callback before giving up.
+    pub max_attempts: usize,
+    /// The maximum number of callbacks sent per second.
+    pub max_callbacks_per_sec: u64,
+}
+
+impl Default for ReservationCallbackConfig {
+    fn default() -> Self {
+        ReservationCallbackConfig {
+            allowed_schemes: vec!["https".to_string()],
+            allow_private_hosts: false,
+            max_attempts: DEFAULT_RESERVATION_CALLBACK_MAX_ATTEMPTS,
+            max_callbacks_per_sec: DEFAULT_MAX_RESERVATION_CALLBACKS_PER_SEC,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct CoinRebalanceConfig {
+    /// The number of available coins the pool tries to maintain.
+    pub target_coin_count: usize,
+    /// The balance the pool tries to keep each coin close to, in MIST.
+    pub target_coin_balance: u64,
+    /// Coins with balance below target_coin_balance / balance_tolerance_factor are merged, and
+    /// coins with balance above target_coin_balance * balance_tolerance_factor are split while
+    /// the pool has fewer than target_coin_count coins. Must be at least 2, so that coins
+    /// produced by rebalancing are never rebalanced again.
+    pub balance_tolerance_factor: u64,
+    /// How often the pool is rebalanced, in seconds.
+    pub rebalance_interval_sec: u64,
+    /// The maximum number of coins taken out of the pool in each rebalancing round.
+    pub max_coins_per_round: usize,
+}
+
+impl Default for CoinRebalanceConfig {
+    fn default() -> Self {
+        CoinRebalanceConfig {
+            target_coin_count: DEFAULT_REBALANCE_TARGET_COIN_COUNT,
+            target_coin_balance: DEFAULT_INIT_COIN_BALANCE,
+            balance_tolerance_factor: DEFAULT_REBALANCE_BALANCE_TOLERANCE_FACTOR,
+            rebalance_interval_sec: DEFAULT_REBALANCE_INTERVAL_SEC,
+            max_coins_per_round: DEFAULT_REBALANCE_MAX_COINS_PER_ROUND,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ReservationPriorityConfig {
+    /// The lowest priority a reservation can have.
+    pub min_priority: u8,
+    /// The highest priority a reservation can have.
+    pub max_priority: u8,
+    /// The priority of reservations that don't specify one.
+    pub default_priority: u8,
+    /// The highest priority that clients not listed in client_max_priorities can request,
+    /// including all clients when the authenticator cannot tell clients apart.
+    pub default_client_max_priority: u8,
+    /// The highest priority each client id can request, where the client id is the one of the
+    /// auth token. Requested priorities above the limit are lowered to it, so that clients can't
+    /// promote themselves above other clients.
+    pub client_max_priorities: BTreeMap<String, u8>,
+    /// Reservations are served in priority order once the number of available coins drops to
+    /// this threshold. Above it, reservations go straight to the storage as usual.
+    pub exhaustion_coin_threshold: usize,
+    /// How long a reservation waits for gas coins to be released when the pool cannot serve it,
+    /// in milliseconds. Higher priority reservations are served first once coins are available.
+    pub max_wait_ms: u64,
+}
+
+impl Default for ReservationPriorityConfig {
+    fn default() -> Self {
+        ReservationPriorityConfig {
+            min_priority: 0,
+            max_priority: DEFAULT_MAX_RESERVATION_PRIORITY,
+            default_priority: 0,
+            default_client_max_priority: 0,
+            client_max_priorities: BTreeMap::new(),
+            exhaustion_coin_threshold: DEFAULT_PRIORITY_EXHAUSTION_COIN_THRESHOLD,
+            max_wait_ms: DEFAULT_PRIORITY_MAX_WAIT_MS,
+        }
+    }
+}
+
+/// What happens when a coin balance audit finds a discrepancy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BalanceAuditStrictness {
+    /// Log the discrepancy along with the offending coins and report it in metrics.
+    #[default]
+    Warn,
+    /// Panic on any discrepancy. Meant for tests and staging deployments.
+    Panic,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct CoinBalanceAuditConfig {
+    /// How often the available coins are audited, in seconds. Each audit reads every available
+    /// coin from the storage and from the fullnode, so this should not be too frequent for
+    /// large pools.
+    pub audit_interval_sec: u64,
+    pub strictness: BalanceAuditStrictness,
+}
+
+impl Default for CoinBalanceAuditConfig {
+    fn default() -> Self {
+        CoinBalanceAuditConfig {
+            audit_interval_sec: DEFAULT_COIN_BALANCE_AUDIT_INTERVAL_SEC,
+            strictness: BalanceAuditStrictness::default(),
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct SponsorAddressCheckConfig {
+    /// The address the signer is expected to sign for. When not specified, the signer is
+    /// expected to keep the address it had when the gas pool started, since the pool only
+    /// tracks coins owned by that address.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sponsor_address: Option<MysAddress>,
+    /// How often the signer address is checked, in seconds.
+    pub check_interval_sec: u64,
+}
+
+impl Default for SponsorAddressCheckConfig {
+    fn default() -> Self {
+        SponsorAddressCheckConfig {
+            sponsor_address: None,
+            check_interval_sec: DEFAULT_SPONSOR_ADDRESS_CHECK_INTERVAL_SEC,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct AuditLogConfig {
+    /// How long audit records are kept, in days.
+    pub retention_days: u64,
+    /// How many records can wait to be written to the storage. Records are written in the
+    /// background so that requests never wait for the audit log. Records that don't fit are
+    /// dropped and counted by the `num_dropped_audit_records` metric.
+    pub max_pending_records: usize,
+    /// How often pending records are written to the storage, in milliseconds.
+    pub flush_interval_ms: u64,
+}
+
+impl Default for AuditLogConfig {
+    fn default() -> Self {
+        AuditLogConfig {
+            retention_days: DEFAULT_AUDIT_LOG_RETENTION_DAYS,
+            max_pending_records: DEFAULT_AUDIT_LOG_MAX_PENDING_RECORDS,
+            flush_interval_ms: DEFAULT_AUDIT_LOG_FLUSH_INTERVAL_MS,
+        }
+    }
+}
+
+/// Caps the budget of each reservation, and the total budget of the reservations each client id
+/// holds at once, where the client id is the one of the auth token. Requires an authentication
+/// backend that tells clients apart, since clients could otherwise get around their limits.
+#[serde_as]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ClientBudgetLimitsConfig {
+    /// The limits of clients not listed in client_limits.
+    pub default_limits: ClientBudgetLimits,
+    /// The limits of each client id.
+    pub client_limits: BTreeMap<String, ClientBudgetLimits>,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ClientBudgetLimits {
+    /// The highest budget a single reservation can have, in MIST. Unlimited if not specified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_reservation_budget: Option<u64>,
+    /// The highest total budget of the reservations the client holds at once, in MIST. The budget
+    /// of a reservation counts until its coins are released back to the pool. Unlimited if not
+    /// specified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_outstanding_budget: Option<u64>,
+    /// The highest number of reservations the client holds at once. A reservation is active
+    /// until its coins are released back to the pool. Unlimited if not specified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_active_reservations: Option<u64>,
+}
+
+/// Holds back reservations right after startup, until the gas pool is ready to serve them.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct WarmupConfig {
+    /// Warmup ends after this many seconds at the latest, even if the gas pool is not ready yet.
+    pub max_warmup_sec: u64,
+    /// How many reservations per second are let through while warming up. Reservations beyond
+    /// that are rejected. If 0, all reservations are rejected until warmup ends.
+    pub max_reservations_per_sec: u64,
+    /// How often to check whether the gas pool is ready, in milliseconds.
+    pub check_interval_ms: u64,
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        WarmupConfig {
+            max_warmup_sec: DEFAULT_MAX_WARMUP_SEC,
+            max_reservations_per_sec: 0,
+            check_interval_ms: DEFAULT_WARMUP_CHECK_INTERVAL_MS,
+        }
+    }
+}
+
+/// Quarantines gas coins whose version keeps changing outside of the gas pool, e.g. because the
+/// sponsor key is shared with another service.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct CoinQuarantineConfig {
+    /// A coin is quarantined once it was found at a newer version than it was reserved at this
+    /// many times within the window.
+    pub max_version_mismatches: u64,
+    pub window_sec: u64,
+    /// How long a quarantined coin is held back before it is released to the pool, in seconds.
+    pub quarantine_sec: u64,
+}
+
+impl Default for CoinQuarantineConfig {
+    fn default() -> Self {
+        CoinQuarantineConfig {
+            max_version_mismatches: DEFAULT_MAX_COIN_VERSION_MISMATCHES,
+            window_sec: DEFAULT_COIN_VERSION_MISMATCH_WINDOW_SEC,
+            quarantine_sec: DEFAULT_COIN_QUARANTINE_SEC,
+        }
+    }
+}
+
+/// Lets reservations wait for coins without holding the HTTP connection open. Waiting
+/// reservations are served in the order they came in.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ReservationWaitlistConfig {
+    /// The most reservations that can wait at once. Further reservations fail as if they did not
+    /// ask to wait.
+    pub max_waiting_reservations: usize,
+    /// How long a reservation waits at most, in seconds. A reservation never waits longer than
+    /// its own reserve duration.
+    pub max_wait_sec: u64,
+    /// How often to retry the waiting reservations, in milliseconds.
+    pub retry_interval_ms: u64,
+}
+
+impl Default for ReservationWaitlistConfig {
+    fn default() -> Self {
+        ReservationWaitlistConfig {
+            max_waiting_reservations: DEFAULT_MAX_WAITING_RESERVATIONS,
+            max_wait_sec: DEFAULT_MAX_RESERVATION_WAIT_SEC,
+            retry_interval_ms: DEFAULT_WAITLIST_RETRY_INTERVAL_MS,
+        }
+    }
+}
+
+/// Limits the move calls matching `package`, and `module` and `function` if specified, that each
+/// client can get sponsored to `max_calls` per `window_sec` seconds.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MoveCallRateLimitConfig {
+    pub package: ObjectID,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    pub max_calls: u64,
+    pub window_sec: u64,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CircuitBreakerConfig {
+    /// Number of consecutive failed fullnode requests after which the circuit breaker opens.
+    pub failure_threshold: u64,
+    /// How long the circuit breaker stays open, failing all fullnode requests immediately,
+    /// before letting a single probe request through. This is in milliseconds.
+    pub open_duration_ms: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            failure_threshold: DEFAULT_CIRCUIT_BREAKER_FAILURE_THRESHOLD,
+            open_duration_ms: DEFAULT_CIRCUIT_BREAKER_OPEN_DURATION_MS,
+        }
+    }
+}
+
+/// Controls the client used for all fullnode requests.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct FullnodeClientConfig {
+    /// The most requests in flight to a fullnode at once. Further requests wait for one of them
+    /// to finish, so this should stay within the connection limits of the fullnode.
+    pub max_concurrent_requests: usize,
+    /// How long a single fullnode request may take before it fails, in seconds.
+    pub request_timeout_secs: u64,
+}
+
+impl FullnodeClientConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.max_concurrent_requests == 0 {
+            anyhow::bail!("max-concurrent-requests must be positive");
+        }
+        if self.request_timeout_secs == 0 {
+            anyhow::bail!("request-timeout-secs must be positive");
+        }
+        Ok(())
+    }
+}
+
+impl Default for FullnodeClientConfig {
+    fn default() -> Self {
+        FullnodeClientConfig {
+            max_concurrent_requests: DEFAULT_FULLNODE_MAX_CONCURRENT_REQUESTS,
+            request_timeout_secs: DEFAULT_FULLNODE_REQUEST_TIMEOUT_SECS,
         }
     }
 }
+
+/// Controls how transaction executions are retried. Unlike reads, executions are only retried for
+/// errors that are known to happen before the transaction was submitted, so that a transaction is
+/// never submitted twice.
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ExecuteRetryConfig {
+    /// The maximum number of attempts, including the first one.
+    pub max_attempts: usize,
+    /// The error classes that are retried. Errors after which the transaction may have been
+    /// submitted are never retried.
+    pub retryable_errors: Vec<ExecuteErrorClass>,
+    /// The delay before the first retry, doubled for every following retry. In milliseconds.
+    pub initial_backoff_ms: u64,
+    /// The maximum delay between retries, in milliseconds.
+    pub max_backoff_ms: u64,
+}
+
+impl Default for ExecuteRetryConfig {
+    fn default() -> Self {
+        ExecuteRetryConfig {
+            max_attempts: DEFAULT_EXECUTE_MAX_ATTEMPTS,
+            retryable_errors: vec![
+                ExecuteErrorClass::ConnectionFailed,
+                ExecuteErrorClass::RateLimited,
+            ],
+            initial_backoff_ms: DEFAULT_EXECUTE_INITIAL_BACKOFF_MS,
+            max_backoff_ms: DEFAULT_EXECUTE_MAX_BACKOFF_MS,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct LogRedactionConfig {
+    /// Whether to truncate sensitive values, such as transaction bytes, signatures and sidecar
+    /// responses, in logs. Secrets in the config are never logged regardless of this setting.
+    pub enabled: bool,
+    /// How many characters of a sensitive value are logged when redaction is enabled.
+    pub max_logged_chars: usize,
+}
+
+impl Default for LogRedactionConfig {
+    fn default() -> Self {
+        LogRedactionConfig {
+            enabled: true,
+            max_logged_chars: DEFAULT_MAX_LOGGED_CHARS,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct BatchExecuteConfig {
+    /// The maximum number of transactions in a single batch_execute_tx request.
+    pub max_batch_size: usize,
+    /// How many transactions of a batch are executed at the same time.
+    pub max_concurrency: usize,
+}
+
+impl Default for BatchExecuteConfig {
+    fn default() -> Self {
+        BatchExecuteConfig {
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            max_concurrency: DEFAULT_MAX_BATCH_CONCURRENCY,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ReservationLimitsConfig {
+    /// Reservations and claimed leases shorter than this are rejected, so that clients cannot
+    /// thrash coin selection and the expiration sweeper with rapid reserve/expire cycles.
+    pub min_reserve_duration_secs: u64,
+}
+
+impl Default for ReservationLimitsConfig {
+    fn default() -> Self {
+        ReservationLimitsConfig {
+            min_reserve_duration_secs: DEFAULT_MIN_RESERVE_DURATION_SECS,
+        }
+    }
+}
+
+/// Overall deadlines of the reserve_gas and execute_tx requests, so that a slow signer or fullnode
+/// cannot hold on to a request indefinitely. Requests that miss their deadline fail with status
+/// 504. By default there is no deadline.
+#[serde_as]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct RequestDeadlineConfig {
+    /// The longest a reserve_gas request may take, in milliseconds. A reservation that is made
+    /// after its deadline passed is cancelled right away, releasing its coins.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reserve_deadline_ms: Option<u64>,
+    /// The longest an execute_tx request, or a single transaction of a batch, may take, in
+    /// milliseconds. Signing and execution are cancelled once it passes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execute_deadline_ms: Option<u64>,
+}
@@ -2,6 +2,9 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::types::ReservationID;
+use mys_types::base_types::ObjectID;
+
 #[macro_export]
 macro_rules! retry_with_max_attempts {
     ($func:expr, $max_attempts:expr) => {{
@@ -29,3 +32,45 @@ macro_rules! retry_forever {
         $func.await
     }};
 }
+
+/// Errors that callers of the gas pool may want to handle differently from a generic failure.
+/// They are carried inside `anyhow::Error` and can be recovered with `downcast_ref`.
+#[derive(Debug, thiserror::Error)]
+pub enum GasStationError {
+    #[error("Fullnode is unavailable, please retry later")]
+    FullnodeUnavailable,
+    #[error("Invalid callback URL: {0}")]
+    InvalidCallbackUrl(String),
+    #[error("Invalid sponsored transaction: {0}")]
+    InvalidSponsoredTransaction(String),
+    #[error("Unsupported transaction kind {0}, only programmable transactions can be sponsored")]
+    UnsupportedTransactionKind(String),
+    #[error("Reservation {0} does not exist, it may have expired, been executed or been cancelled")]
+    ReservationNotFound(ReservationID),
+    #[error("Unable to reserve gas coins for the given budget.")]
+    InsufficientGasCoins,
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("The gas spend cap of the current epoch has been reached, please retry in the next epoch")]
+    BudgetCapReached,
+    #[error("The gas pool is warming up, please retry later")]
+    ServiceWarming,
+    #[error("Move call rate limit reached: {0}")]
+    PolicyRateLimited(String),
+    #[error("Gas coins changed since they were reserved, please reserve gas again: {0}")]
+    StaleGasCoins(String),
+    #[error("The budget exceeds the budget limits of the client, the remaining allowance is {0}")]
+    BudgetLimitExceeded(u64),
+    #[error("The client holds {0} active reservations, the most it can, please retry later")]
+    TooManyActiveReservations(u64),
+    #[error("Reservation {0} expires in less than {1}ms, please reserve gas again")]
+    ReservationTooCloseToExpiry(ReservationID, u64),
+    #[error("Requested gas coin {0} is held by another reservation, please retry later")]
+    RequestedCoinReserved(ObjectID),
+    #[error("Requested gas coin {0} is not in the gas pool")]
+    RequestedCoinNotFound(ObjectID),
+    #[error("Transaction would fail, so it was not executed: {0}")]
+    PreflightFailed(String),
+    #[error("The request did not complete before its deadline, please retry later")]
+    RequestTimeout,
+}
@@ -0,0 +1,164 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::ExecuteRetryConfig;
+use crate::errors::GasStationError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Classes of transaction execution errors that are known to happen before the transaction was
+/// submitted, and can therefore be retried without risking to execute it twice.
+/// Errors that don't fall in any of these classes, e.g. timeouts, dropped connections or
+/// responses without effects, may happen after the transaction was submitted and are never
+/// retried, regardless of the config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExecuteErrorClass {
+    /// The request never reached the fullnode, e.g. the connection was refused or the fullnode
+    /// circuit breaker is open.
+    ConnectionFailed,
+    /// The fullnode refused the request because of rate limiting or overload.
+    RateLimited,
+    /// The fullnode rejected the transaction as invalid before submitting it.
+    Rejected,
+}
+
+/// Connection errors that happen before any byte of the request is sent. A timeout while
+/// connecting also belongs here, which is why these are checked before timeouts.
+const CONNECTION_FAILED_MARKERS: &[&str] = &[
+    "connection refused",
+    "error trying to connect",
+    "dns error",
+    "failed to lookup address",
+];
+/// Errors after which the transaction may already be on its way to the validators.
+const POSSIBLY_SUBMITTED_MARKERS: &[&str] = &[
+    "timed out",
+    "timeout",
+    "connection reset",
+    "connection closed",
+    "broken pipe",
+    "no effects",
+];
+pub(crate) const RATE_LIMITED_MARKERS: &[&str] = &[
+    "429",
+    "too many requests",
+    "rate limit",
+    "server is busy",
+];
+const REJECTED_MARKERS: &[&str] = &[
+    "invalid params",
+    "invalid user signature",
+    "invalid transaction",
+];
+
+/// Returns the class of an execution error, or None if the transaction may have been submitted.
+/// Fullnode errors only carry a message, so this matches on the error messages, and falls back
+/// to None for anything it does not recognize.
+pub fn classify_execute_error(err: &anyhow::Error) -> Option<ExecuteErrorClass> {
+    if let Some(GasStationError::FullnodeUnavailable) = err.downcast_ref::<GasStationError>() {
+        return Some(ExecuteErrorClass::ConnectionFailed);
+    }
+    let message = format!("{:#}", err).to_lowercase();
+    let matches = |markers: &[&str]| markers.iter().any(|marker| message.contains(marker));
+    if matches(CONNECTION_FAILED_MARKERS) {
+        Some(ExecuteErrorClass::ConnectionFailed)
+    } else if matches(POSSIBLY_SUBMITTED_MARKERS) {
+        None
+    } else if matches(RATE_LIMITED_MARKERS) {
+        Some(ExecuteErrorClass::RateLimited)
+    } else if matches(REJECTED_MARKERS) {
+        Some(ExecuteErrorClass::Rejected)
+    } else {
+        None
+    }
+}
+
+impl ExecuteRetryConfig {
+    /// Whether a failed attempt should be retried, given how many attempts were made so far.
+    pub fn should_retry(&self, err: &anyhow::Error, attempts: usize) -> bool {
+        if attempts >= self.max_attempts {
+            return false;
+        }
+        classify_execute_error(err).is_some_and(|class| self.retryable_errors.contains(&class))
+    }
+
+    /// How long to wait before the given retry, starting from 1. The delay doubles with every
+    /// retry, up to the max backoff.
+    pub fn backoff(&self, retry: usize) -> Duration {
+        let exponent = retry.saturating_sub(1).min(31) as u32;
+        let delay_ms = self
+            .initial_backoff_ms
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff_ms);
+        Duration::from_millis(delay_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_execute_error() {
+        assert_eq!(
+            classify_execute_error(&GasStationError::FullnodeUnavailable.into()),
+            Some(ExecuteErrorClass::ConnectionFailed)
+        );
+        assert_eq!(
+            classify_execute_error(&anyhow::anyhow!(
+                "error trying to connect: tcp connect error: Connection refused"
+            )),
+            Some(ExecuteErrorClass::ConnectionFailed)
+        );
+        assert_eq!(
+            classify_execute_error(&anyhow::anyhow!(
+                "HTTP status client error (429 Too Many Requests)"
+            )),
+            Some(ExecuteErrorClass::RateLimited)
+        );
+        assert_eq!(
+            classify_execute_error(&anyhow::anyhow!("Invalid params: invalid user signature")),
+            Some(ExecuteErrorClass::Rejected)
+        );
+        // The transaction may have been submitted in all of these cases.
+        assert_eq!(classify_execute_error(&anyhow::anyhow!("Request timeout")), None);
+        assert_eq!(classify_execute_error(&anyhow::anyhow!("No effects")), None);
+        assert_eq!(
+            classify_execute_error(&anyhow::anyhow!("connection reset by peer")),
+            None
+        );
+        assert_eq!(classify_execute_error(&anyhow::anyhow!("Something else")), None);
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let config = ExecuteRetryConfig {
+            max_attempts: 3,
+            retryable_errors: vec![ExecuteErrorClass::ConnectionFailed],
+            ..Default::default()
+        };
+        let connection_error = anyhow::anyhow!("Connection refused");
+        assert!(config.should_retry(&connection_error, 1));
+        assert!(config.should_retry(&connection_error, 2));
+        assert!(!config.should_retry(&connection_error, 3));
+        assert!(!config.should_retry(&anyhow::anyhow!("429 Too Many Requests"), 1));
+        assert!(!config.should_retry(&anyhow::anyhow!("Request timeout"), 1));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let config = ExecuteRetryConfig {
+            initial_backoff_ms: 50,
+            max_backoff_ms: 300,
+            ..Default::default()
+        };
+        assert_eq!(config.backoff(1), Duration::from_millis(50));
+        assert_eq!(config.backoff(2), Duration::from_millis(100));
+        assert_eq!(config.backoff(3), Duration::from_millis(200));
+        assert_eq!(config.backoff(4), Duration::from_millis(300));
+        assert_eq!(config.backoff(100), Duration::from_millis(300));
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::AuditLogConfig;
+use crate::metrics::GasPoolCoreMetrics;
+use crate::retry_with_max_attempts;
+use crate::storage::Storage;
+use crate::types::AuditRecord;
+use chrono::Utc;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tap::TapFallible;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+/// How often records older than the retention period are removed.
+const AUDIT_LOG_TRIM_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A durable record of every reservation and execution, for financial reconciliation.
+/// Records are handed to a background task that writes them to the storage in batches, so that
+/// requests never wait for the audit log.
+pub struct AuditLog {
+    config: AuditLogConfig,
+    gas_pool_store: Arc<dyn Storage>,
+    metrics: Arc<GasPoolCoreMetrics>,
+    sender: mpsc::Sender<AuditRecord>,
+    // Taken by the writer task when it starts.
+    receiver: Mutex<Option<mpsc::Receiver<AuditRecord>>>,
+}
+
+impl AuditLog {
+    pub fn new(
+        config: AuditLogConfig,
+        gas_pool_store: Arc<dyn Storage>,
+        metrics: Arc<GasPoolCoreMetrics>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel(config.max_pending_records.max(1));
+        Self {
+            config,
+            gas_pool_store,
+            metrics,
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+
+    /// Queues a record to be written. Never blocks: if too many records are waiting to be
+    /// written, the record is dropped.
+    pub fn record(&self, record: AuditRecord) {
+        if let Err(err) = self.sender.try_send(record) {
+            warn!("Dropping audit record: {:?}", err);
+            self.metrics.num_dropped_audit_records.inc();
+        }
+    }
+
+    /// Reads up to `limit` records with from_ms <= timestamp_ms < to_ms, oldest first,
+    /// optionally only those of a single client.
+    pub async fn query(
+        &self,
+        from_ms: u64,
+        to_ms: u64,
+        client_id: Option<String>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuditRecord>> {
+        self.gas_pool_store
+            .query_audit_records(from_ms, to_ms, client_id, limit)
+            .await
+    }
+
+    /// Starts a task that writes the queued records every `flush_interval_ms`, and removes the
+    /// records older than the retention period every hour. Pending records are written before
+    /// the task exits.
+    pub fn start_writer_task(
+        self: Arc<Self>,
+        mut cancel_receiver: tokio::sync::oneshot::Receiver<()>,
+    ) -> JoinHandle<()> {
+        let mut receiver = self
+            .receiver
+            .lock()
+            .take()
+            .expect("Audit log writer task can only be started once");
+        tokio::task::spawn(async move {
+            let flush_interval = Duration::from_millis(self.config.flush_interval_ms);
+            let mut last_trim: Option<Instant> = None;
+            loop {
+                let cancelled = tokio::select! {
+                    _ = tokio::time::sleep(flush_interval) => false,
+                    _ = &mut cancel_receiver => true,
+                };
+                self.flush(&mut receiver).await;
+                if cancelled {
+                    info!("Audit log writer task is cancelled");
+                    break;
+                }
+                if last_trim.map_or(true, |last| last.elapsed() >= AUDIT_LOG_TRIM_INTERVAL) {
+                    self.trim().await;
+                    last_trim = Some(Instant::now());
+                }
+            }
+        })
+    }
+
+    async fn flush(&self, receiver: &mut mpsc::Receiver<AuditRecord>) {
+        let mut records = vec![];
+        while let Ok(record) = receiver.try_recv() {
+            records.push(record);
+        }
+        if records.is_empty() {
+            return;
+        }
+        let count = records.len() as u64;
+        let result = retry_with_max_attempts!(
+            async {
+                self.gas_pool_store
+                    .append_audit_records(records.clone())
+                    .await
+                    .tap_err(|err| debug!("Failed to write audit records: {:?}", err))
+            },
+            3
+        );
+        match result {
+            Ok(()) => self.metrics.num_audit_records_written.inc_by(count),
+            Err(err) => {
+                error!("Failed to write {} audit records: {:?}", count, err);
+                self.metrics.num_dropped_audit_records.inc_by(count);
+            }
+        }
+    }
+
+    async fn trim(&self) {
+        let retention = Duration::from_secs(self.config.retention_days * 24 * 60 * 60);
+        let before_ms = (Utc::now().timestamp_millis() as u64)
+            .saturating_sub(retention.as_millis() as u64);
+        match self.gas_pool_store.trim_audit_records(before_ms).await {
+            Ok(removed) => debug!("Removed {} audit records past retention", removed),
+            Err(err) => warn!("Failed to remove audit records past retention: {:?}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::connect_storage_for_testing;
+    use crate::types::{AuditEvent, ReservationID};
+    use mys_types::base_types::MysAddress;
+
+    #[tokio::test]
+    async fn test_audit_log_writer() {
+        let storage = connect_storage_for_testing(MysAddress::random_for_testing_only()).await;
+        let metrics = GasPoolCoreMetrics::new_for_testing();
+        let audit_log = Arc::new(AuditLog::new(
+            AuditLogConfig {
+                max_pending_records: 2,
+                flush_interval_ms: 100,
+                ..Default::default()
+            },
+            storage.clone(),
+            metrics.clone(),
+        ));
+        let now = Utc::now().timestamp_millis() as u64;
+        let record = |reservation_id: u64| AuditRecord {
+            timestamp_ms: now,
+            client_id: None,
+            reservation_id: ReservationID::new(reservation_id).unwrap(),
+            tag: None,
+            event: AuditEvent::Reserve {
+                gas_budget: 1,
+                gas_coins: vec![],
+            },
+        };
+        // Records beyond max_pending_records are dropped instead of blocking.
+        audit_log.record(record(1));
+        audit_log.record(record(2));
+        audit_log.record(record(3));
+        assert_eq!(metrics.num_dropped_audit_records.get(), 1);
+
+        let (cancel_sender, cancel_receiver) = tokio::sync::oneshot::channel();
+        let task = audit_log.clone().start_writer_task(cancel_receiver);
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        audit_log.record(record(4));
+        // Pending records are written when the task is cancelled.
+        cancel_sender.send(()).unwrap();
+        task.await.unwrap();
+        assert_eq!(metrics.num_audit_records_written.get(), 3);
+
+        let records = audit_log.query(now, now + 1, None, 100).await.unwrap();
+        let mut ids: Vec<_> = records.iter().map(|r| r.reservation_id.value()).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2, 4]);
+    }
+}
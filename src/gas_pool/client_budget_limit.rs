@@ -0,0 +1,288 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{ClientBudgetLimits, ClientBudgetLimitsConfig};
+use crate::errors::GasStationError;
+use crate::metrics::GasPoolCoreMetrics;
+use crate::storage::Storage;
+use crate::types::ReservationID;
+use std::sync::Arc;
+
+/// Enforces the budget limits of each client id on new reservations: the budget of a single
+/// reservation, the total budget of the reservations a client holds at once, and how many
+/// reservations it holds at once. The usage of each client is kept in the storage, so that it is
+/// shared by all gas pool instances.
+pub struct ClientBudgetLimiter {
+    config: ClientBudgetLimitsConfig,
+    gas_pool_store: Arc<dyn Storage>,
+    metrics: Arc<GasPoolCoreMetrics>,
+}
+
+impl ClientBudgetLimiter {
+    pub fn new(
+        config: ClientBudgetLimitsConfig,
+        gas_pool_store: Arc<dyn Storage>,
+        metrics: Arc<GasPoolCoreMetrics>,
+    ) -> Self {
+        Self {
+            config,
+            gas_pool_store,
+            metrics,
+        }
+    }
+
+    fn limits(&self, client_id: &str) -> &ClientBudgetLimits {
+        self.config
+            .client_limits
+            .get(client_id)
+            .unwrap_or(&self.config.default_limits)
+    }
+
+    /// Fails with `GasStationError::BudgetLimitExceeded` if the budget is above the reservation
+    /// budget limit of the client, or would push its outstanding budget over its limit, and with
+    /// `GasStationError::TooManyActiveReservations` if the client already holds as many
+    /// reservations as it can.
+    /// This is checked before any coin is reserved. The usage of the client is checked again
+    /// atomically by `add_reservation`, since other reservations of the client may be counted
+    /// in between.
+    pub async fn check_budget(&self, client_id: &str, budget: u64) -> anyhow::Result<()> {
+        let limits = self.limits(client_id);
+        let mut allowance = limits.max_reservation_budget.unwrap_or(u64::MAX);
+        if limits.max_outstanding_budget.is_some() || limits.max_active_reservations.is_some() {
+            let usage = self.gas_pool_store.get_client_usage(client_id).await?;
+            if let Some(max_active_reservations) = limits.max_active_reservations {
+                if usage.active_reservations >= max_active_reservations {
+                    return Err(self.reject(
+                        client_id,
+                        GasStationError::TooManyActiveReservations(max_active_reservations),
+                    ));
+                }
+            }
+            if let Some(max_outstanding_budget) = limits.max_outstanding_budget {
+                allowance = allowance
+                    .min(max_outstanding_budget.saturating_sub(usage.reserved_budget));
+            }
+        }
+        if budget > allowance {
+            return Err(self.reject(client_id, GasStationError::BudgetLimitExceeded(allowance)));
+        }
+        Ok(())
+    }
+
+    /// Counts a new reservation and its budget against the usage of the client, until its coins
+    /// are released. Fails with `GasStationError::TooManyActiveReservations` or
+    /// `GasStationError::BudgetLimitExceeded` without counting anything if that would push the
+    /// usage of the client over its limits.
+    pub async fn add_reservation(
+        &self,
+        client_id: &str,
+        reservation_id: ReservationID,
+        budget: u64,
+    ) -> anyhow::Result<()> {
+        let limits = self.limits(client_id);
+        if limits.max_outstanding_budget.is_none() && limits.max_active_reservations.is_none() {
+            return Ok(());
+        }
+        let max_outstanding_budget = limits.max_outstanding_budget.unwrap_or(u64::MAX);
+        let max_active_reservations = limits.max_active_reservations.unwrap_or(u64::MAX);
+        let (added, usage) = self
+            .gas_pool_store
+            .add_client_reservation(
+                reservation_id,
+                client_id,
+                budget,
+                max_outstanding_budget,
+                max_active_reservations,
+            )
+            .await?;
+        if !added {
+            let err = if usage.active_reservations >= max_active_reservations {
+                GasStationError::TooManyActiveReservations(max_active_reservations)
+            } else {
+                GasStationError::BudgetLimitExceeded(
+                    max_outstanding_budget.saturating_sub(usage.reserved_budget),
+                )
+            };
+            return Err(self.reject(client_id, err));
+        }
+        Ok(())
+    }
+
+    fn reject(&self, client_id: &str, err: GasStationError) -> anyhow::Error {
+        self.metrics
+            .num_client_budget_limit_rejections
+            .with_label_values(&[client_id])
+            .inc();
+        err.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::connect_storage_for_testing;
+    use crate::types::{ClientUsage, GasCoin};
+    use mys_types::base_types::{random_object_ref, MysAddress};
+    use std::collections::BTreeMap;
+
+    fn assert_rejected(result: anyhow::Result<()>, expected_allowance: u64) {
+        match result.unwrap_err().downcast_ref::<GasStationError>() {
+            Some(GasStationError::BudgetLimitExceeded(allowance)) => {
+                assert_eq!(*allowance, expected_allowance)
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_budget_limits() {
+        let storage = connect_storage_for_testing(MysAddress::random_for_testing_only()).await;
+        storage
+            .add_new_coins(
+                (0..4)
+                    .map(|_| GasCoin {
+                        object_ref: random_object_ref(),
+                        balance: 100,
+                    })
+                    .collect(),
+            )
+            .await
+            .unwrap();
+        let limiter = ClientBudgetLimiter::new(
+            ClientBudgetLimitsConfig {
+                default_limits: ClientBudgetLimits {
+                    max_reservation_budget: Some(100),
+                    max_outstanding_budget: Some(150),
+                    max_active_reservations: None,
+                },
+                client_limits: BTreeMap::from([(
+                    "unlimited".to_string(),
+                    ClientBudgetLimits::default(),
+                )]),
+            },
+            storage.clone(),
+            GasPoolCoreMetrics::new_for_testing(),
+        );
+
+        assert_rejected(limiter.check_budget("a", 101).await, 100);
+        limiter.check_budget("a", 100).await.unwrap();
+        let (res_id1, coins1) = storage.reserve_gas_coins(100, 10000).await.unwrap();
+        limiter.add_reservation("a", res_id1, 100).await.unwrap();
+        assert_rejected(limiter.check_budget("a", 60).await, 50);
+        // Another reservation can take the rest of the allowance in between.
+        limiter.check_budget("a", 50).await.unwrap();
+        let (res_id2, _) = storage.reserve_gas_coins(50, 10000).await.unwrap();
+        limiter.add_reservation("a", res_id2, 50).await.unwrap();
+        let (res_id3, _) = storage.reserve_gas_coins(50, 10000).await.unwrap();
+        assert_rejected(limiter.add_reservation("a", res_id3, 50).await, 0);
+        // Limits are per client.
+        limiter.check_budget("b", 100).await.unwrap();
+        limiter.check_budget("unlimited", 1000).await.unwrap();
+
+        // The budget is no longer outstanding once the coins of the reservation are released.
+        storage
+            .release_reserved_coins(
+                res_id1,
+                coins1.iter().map(|c| c.object_ref.0).collect(),
+                coins1,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get_client_usage("a").await.unwrap(),
+            ClientUsage {
+                reserved_budget: 50,
+                active_reservations: 1,
+            }
+        );
+        limiter.check_budget("a", 100).await.unwrap();
+        assert_eq!(
+            limiter
+                .metrics
+                .num_client_budget_limit_rejections
+                .with_label_values(&["a"])
+                .get(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_active_reservations() {
+        let storage = connect_storage_for_testing(MysAddress::random_for_testing_only()).await;
+        storage
+            .add_new_coins(
+                (0..4)
+                    .map(|_| GasCoin {
+                        object_ref: random_object_ref(),
+                        balance: 100,
+                    })
+                    .collect(),
+            )
+            .await
+            .unwrap();
+        let limiter = ClientBudgetLimiter::new(
+            ClientBudgetLimitsConfig {
+                default_limits: ClientBudgetLimits {
+                    max_active_reservations: Some(2),
+                    ..Default::default()
+                },
+                client_limits: BTreeMap::new(),
+            },
+            storage.clone(),
+            GasPoolCoreMetrics::new_for_testing(),
+        );
+
+        let (res_id1, coins1) = storage.reserve_gas_coins(100, 10000).await.unwrap();
+        limiter.add_reservation("a", res_id1, 100).await.unwrap();
+        // Another reservation can take the last slot in between.
+        limiter.check_budget("a", 100).await.unwrap();
+        let (res_id2, _) = storage.reserve_gas_coins(100, 10000).await.unwrap();
+        limiter.add_reservation("a", res_id2, 100).await.unwrap();
+        let (res_id3, _) = storage.reserve_gas_coins(100, 10000).await.unwrap();
+        assert!(matches!(
+            limiter
+                .add_reservation("a", res_id3, 100)
+                .await
+                .unwrap_err()
+                .downcast_ref::<GasStationError>(),
+            Some(GasStationError::TooManyActiveReservations(2))
+        ));
+        assert!(matches!(
+            limiter
+                .check_budget("a", 100)
+                .await
+                .unwrap_err()
+                .downcast_ref::<GasStationError>(),
+            Some(GasStationError::TooManyActiveReservations(2))
+        ));
+        limiter.check_budget("b", 100).await.unwrap();
+        assert_eq!(
+            storage.get_all_client_usage().await.unwrap(),
+            BTreeMap::from([(
+                "a".to_string(),
+                ClientUsage {
+                    reserved_budget: 200,
+                    active_reservations: 2,
+                }
+            )])
+        );
+
+        // A slot is freed once the coins of a reservation are released.
+        storage
+            .release_reserved_coins(
+                res_id1,
+                coins1.iter().map(|c| c.object_ref.0).collect(),
+                coins1,
+            )
+            .await
+            .unwrap();
+        limiter.check_budget("a", 100).await.unwrap();
+        let (res_id4, _) = storage.reserve_gas_coins(100, 10000).await.unwrap();
+        limiter.add_reservation("a", res_id4, 100).await.unwrap();
+        assert_eq!(
+            storage.get_client_usage("a").await.unwrap().active_reservations,
+            2
+        );
+    }
+}
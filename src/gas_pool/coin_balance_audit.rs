@@ -0,0 +1,199 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::{BalanceAuditStrictness, CoinBalanceAuditConfig};
+use crate::metrics::GasPoolCoreMetrics;
+use crate::mys_client::MysClient;
+use crate::storage::Storage;
+use crate::types::GasCoin;
+use mys_types::base_types::MysAddress;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoinBalanceAuditReport {
+    pub audited_coin_count: usize,
+    /// Tracked total balance of the available coins minus the sum of their balances.
+    pub total_balance_drift: i64,
+    /// Tracked number of available coins minus the number of coins actually available.
+    pub coin_count_drift: i64,
+    /// Available coins whose balance differs from the balance on chain at the same version,
+    /// along with the balance on chain.
+    pub mismatched_coins: Vec<(GasCoin, u64)>,
+}
+
+impl CoinBalanceAuditReport {
+    pub fn is_consistent(&self) -> bool {
+        self.total_balance_drift == 0
+            && self.coin_count_drift == 0
+            && self.mismatched_coins.is_empty()
+    }
+}
+
+/// Catches accounting bugs early, by checking the tracked total balance and coin count of the
+/// available coins against the coins themselves, and the balance of each available coin against
+/// the chain. Reserved coins are not covered: the pool only keeps their object ids, and their
+/// balances are read from the chain again when they are released.
+pub struct CoinBalanceAuditor {
+    config: CoinBalanceAuditConfig,
+    gas_pool_store: Arc<dyn Storage>,
+    mys_client: MysClient,
+    sponsor_address: MysAddress,
+    metrics: Arc<GasPoolCoreMetrics>,
+}
+
+impl CoinBalanceAuditor {
+    pub fn new(
+        config: CoinBalanceAuditConfig,
+        gas_pool_store: Arc<dyn Storage>,
+        mys_client: MysClient,
+        sponsor_address: MysAddress,
+        metrics: Arc<GasPoolCoreMetrics>,
+    ) -> Self {
+        Self {
+            config,
+            gas_pool_store,
+            mys_client,
+            sponsor_address,
+            metrics,
+        }
+    }
+
+    /// Starts a task that runs an audit every `audit_interval_sec`.
+    pub fn start_audit_task(
+        self: Arc<Self>,
+        mut cancel_receiver: tokio::sync::oneshot::Receiver<()>,
+    ) -> JoinHandle<()> {
+        tokio::task::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(self.config.audit_interval_sec)) => {}
+                    _ = &mut cancel_receiver => {
+                        info!("Coin balance audit task is cancelled");
+                        break;
+                    }
+                }
+                if let Err(err) = self.run_once().await {
+                    error!("Failed to audit coin balances: {:?}", err);
+                }
+            }
+        })
+    }
+
+    /// Audits the available coins and reports any discrepancy according to the strictness.
+    pub async fn run_once(&self) -> anyhow::Result<CoinBalanceAuditReport> {
+        let report = self.audit().await?;
+        self.metrics
+            .coin_balance_audit_drift
+            .set(report.total_balance_drift);
+        self.metrics
+            .num_coin_balance_mismatches
+            .inc_by(report.mismatched_coins.len() as u64);
+        if report.is_consistent() {
+            info!(
+                "Coin balance audit found no discrepancy among {} coins",
+                report.audited_coin_count
+            );
+            return Ok(report);
+        }
+        warn!(
+            "Coin balance audit found discrepancies among {} coins. Total balance drift: {}, coin count drift: {}, coins with mismatched balance (coin, balance on chain): {:?}",
+            report.audited_coin_count,
+            report.total_balance_drift,
+            report.coin_count_drift,
+            report.mismatched_coins
+        );
+        if self.config.strictness == BalanceAuditStrictness::Panic {
+            panic!("Coin balance audit failed: {:?}", report);
+        }
+        Ok(report)
+    }
+
+    async fn audit(&self) -> anyhow::Result<CoinBalanceAuditReport> {
+        let snapshot = self.gas_pool_store.get_available_coins_snapshot().await?;
+        let total_balance: u64 = snapshot.coins.iter().map(|c| c.balance).sum();
+        let latest_coins = self
+            .mys_client
+            .get_latest_gas_objects(
+                self.sponsor_address,
+                snapshot.coins.iter().map(|c| c.object_ref.0),
+            )
+            .await;
+        // Coins can be reserved and used after the snapshot was taken, so only coins that are
+        // still at the same version on chain are compared.
+        let mismatched_coins = snapshot
+            .coins
+            .iter()
+            .filter_map(|coin| match latest_coins.get(&coin.object_ref.0) {
+                Some(Some(latest_coin))
+                    if latest_coin.object_ref == coin.object_ref
+                        && latest_coin.balance != coin.balance =>
+                {
+                    Some((coin.clone(), latest_coin.balance))
+                }
+                _ => None,
+            })
+            .collect();
+        Ok(CoinBalanceAuditReport {
+            audited_coin_count: snapshot.coins.len(),
+            total_balance_drift: snapshot.tracked_total_balance - total_balance as i64,
+            coin_count_drift: snapshot.tracked_coin_count - snapshot.coins.len() as i64,
+            mismatched_coins,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CoinInitConfig;
+    use crate::gas_pool_initializer::GasPoolInitializer;
+    use crate::storage::connect_storage_for_testing;
+    use crate::test_env::start_mys_cluster;
+    use crate::tx_signer::TxSigner;
+    use mys_types::gas_coin::MIST_PER_MYS;
+
+    #[tokio::test]
+    async fn test_coin_balance_audit() {
+        let (cluster, signer) = start_mys_cluster(vec![MIST_PER_MYS; 4]).await;
+        let fullnode_url = cluster.fullnode_handle.rpc_url.clone();
+        let storage = connect_storage_for_testing(signer.get_address()).await;
+        let mys_client = MysClient::new(&fullnode_url, None).await;
+        let _init_task = GasPoolInitializer::start(
+            mys_client.clone(),
+            storage.clone(),
+            CoinInitConfig {
+                target_init_balance: MIST_PER_MYS,
+                refresh_interval_sec: 200,
+                ..Default::default()
+            },
+            signer.clone(),
+        )
+        .await;
+        let auditor = CoinBalanceAuditor::new(
+            CoinBalanceAuditConfig::default(),
+            storage.clone(),
+            mys_client,
+            signer.get_address(),
+            GasPoolCoreMetrics::new_for_testing(),
+        );
+        let report = auditor.run_once().await.unwrap();
+        assert!(report.is_consistent());
+        assert_eq!(report.audited_coin_count, 4);
+
+        // Put a coin back with a wrong balance, as a buggy release would.
+        let (_, coins) = storage.reserve_gas_coins(1, 1000).await.unwrap();
+        let mut coin = coins[0].clone();
+        let actual_balance = coin.balance;
+        coin.balance += 1;
+        storage.add_new_coins(vec![coin.clone()]).await.unwrap();
+        let report = auditor.run_once().await.unwrap();
+        assert_eq!(report.total_balance_drift, 0);
+        assert_eq!(report.coin_count_drift, 0);
+        assert_eq!(report.mismatched_coins, vec![(coin, actual_balance)]);
+        assert_eq!(auditor.metrics.num_coin_balance_mismatches.get(), 1);
+    }
+}
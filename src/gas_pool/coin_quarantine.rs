@@ -0,0 +1,131 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::CoinQuarantineConfig;
+use crate::metrics::GasPoolCoreMetrics;
+use mys_types::base_types::ObjectID;
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Tracks gas coins whose version changed outside of the gas pool while they were reserved, e.g.
+/// because the sponsor key is also used elsewhere. A coin that does so repeatedly within the
+/// window is flagged, and held back for the quarantine duration the next time it is released.
+/// Mismatches are kept in memory, so each gas pool instance tracks them on its own.
+pub struct CoinQuarantine {
+    config: CoinQuarantineConfig,
+    metrics: Arc<GasPoolCoreMetrics>,
+    inner: Mutex<CoinQuarantineInner>,
+}
+
+#[derive(Default)]
+struct CoinQuarantineInner {
+    /// The times at which the version of each coin was found to have changed, oldest first.
+    version_mismatches: HashMap<ObjectID, VecDeque<Instant>>,
+    /// Coins to quarantine the next time they are released.
+    flagged_coins: HashSet<ObjectID>,
+}
+
+impl CoinQuarantine {
+    pub fn new(config: CoinQuarantineConfig, metrics: Arc<GasPoolCoreMetrics>) -> Self {
+        Self {
+            config,
+            metrics,
+            inner: Mutex::new(CoinQuarantineInner::default()),
+        }
+    }
+
+    /// Records that the version of the coin changed outside of the gas pool, and flags it once
+    /// this happened max_version_mismatches times within the window.
+    pub fn record_version_mismatch(&self, coin_id: ObjectID) {
+        self.metrics.num_gas_coin_version_mismatches.inc();
+        let now = Instant::now();
+        let window = Duration::from_secs(self.config.window_sec);
+        let mut inner = self.inner.lock();
+        inner.version_mismatches.retain(|_, times| {
+            while times
+                .front()
+                .is_some_and(|time| now.duration_since(*time) >= window)
+            {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        let times = inner.version_mismatches.entry(coin_id).or_default();
+        times.push_back(now);
+        if times.len() as u64 >= self.config.max_version_mismatches {
+            inner.version_mismatches.remove(&coin_id);
+            inner.flagged_coins.insert(coin_id);
+        }
+    }
+
+    /// Takes the flags of the given coins, and returns the ones that were flagged. These must be
+    /// held back for `quarantine_duration`.
+    pub fn take_flagged_coins(&self, coin_ids: &[ObjectID]) -> HashSet<ObjectID> {
+        let mut inner = self.inner.lock();
+        let flagged_coins: HashSet<_> = coin_ids
+            .iter()
+            .filter(|coin_id| inner.flagged_coins.remove(*coin_id))
+            .copied()
+            .collect();
+        self.metrics
+            .num_quarantined_gas_coins
+            .inc_by(flagged_coins.len() as u64);
+        flagged_coins
+    }
+
+    pub fn quarantine_duration(&self) -> Duration {
+        Duration::from_secs(self.config.quarantine_sec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_quarantine(max_version_mismatches: u64, window_sec: u64) -> CoinQuarantine {
+        CoinQuarantine::new(
+            CoinQuarantineConfig {
+                max_version_mismatches,
+                window_sec,
+                quarantine_sec: 60,
+            },
+            GasPoolCoreMetrics::new_for_testing(),
+        )
+    }
+
+    #[test]
+    fn test_flag_after_repeated_mismatches() {
+        let quarantine = new_quarantine(3, 60);
+        let coin = ObjectID::random();
+        let other_coin = ObjectID::random();
+        quarantine.record_version_mismatch(coin);
+        quarantine.record_version_mismatch(coin);
+        quarantine.record_version_mismatch(other_coin);
+        assert!(quarantine.take_flagged_coins(&[coin, other_coin]).is_empty());
+
+        quarantine.record_version_mismatch(coin);
+        assert_eq!(
+            quarantine.take_flagged_coins(&[coin, other_coin]),
+            HashSet::from([coin])
+        );
+        // The flag is taken once, and the count starts over.
+        assert!(quarantine.take_flagged_coins(&[coin]).is_empty());
+        quarantine.record_version_mismatch(coin);
+        assert!(quarantine.take_flagged_coins(&[coin]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mismatches_outside_window_are_forgotten() {
+        let quarantine = new_quarantine(2, 1);
+        let coin = ObjectID::random();
+        quarantine.record_version_mismatch(coin);
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        quarantine.record_version_mismatch(coin);
+        assert!(quarantine.take_flagged_coins(&[coin]).is_empty());
+        quarantine.record_version_mismatch(coin);
+        assert_eq!(quarantine.take_flagged_coins(&[coin]), HashSet::from([coin]));
+    }
+}
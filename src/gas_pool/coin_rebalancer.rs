@@ -0,0 +1,494 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::CoinRebalanceConfig;
+use crate::metrics::GasPoolCoreMetrics;
+use crate::mys_client::MysClient;
+use crate::storage::{safe_mode_is_on, Storage, MAX_GAS_PER_QUERY};
+use crate::tx_signer::TxSigner;
+use crate::types::{CoinBalanceDistribution, GasCoin};
+use anyhow::bail;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use mys_json_rpc_types::{MysTransactionBlockEffects, MysTransactionBlockEffectsAPI};
+use mys_types::gas_coin::MIST_PER_MYS;
+use mys_types::programmable_transaction_builder::ProgrammableTransactionBuilder;
+use mys_types::transaction::{Transaction, TransactionData};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+/// How long coins can stay out of the pool while being rebalanced. If the round does not finish
+/// in time, e.g. because the server crashed, the coins are released by the regular reservation
+/// expiration.
+const REBALANCE_RESERVATION_DURATION: Duration = Duration::from_secs(10 * 60);
+
+/// Rebalancing holds the init lock, so that it never runs concurrently with coin initialization
+/// or with a rebalancing round of another gas pool server for the same sponsor.
+const REBALANCE_LOCK_DURATION_SEC: u64 = 15 * 60;
+
+/// Maximum number of new coins split off a single coin in one transaction.
+const MAX_SPLIT_COUNT_PER_TX: u64 = 500;
+
+/// Gas budget of each merge transaction. Merging coins deletes them, so most of the cost is
+/// paid back through storage rebates.
+const MERGE_TX_GAS_BUDGET: u64 = MIST_PER_MYS / 10;
+
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
+pub struct CoinDistributionStats {
+    pub target_coin_count: usize,
+    pub target_coin_balance: u64,
+    /// Coins with balance below this are merged.
+    pub small_balance_threshold: u64,
+    /// Coins with balance above this are split while the pool is below the target coin count.
+    pub large_balance_threshold: u64,
+    /// The distribution observed by the last rebalancing round on this server.
+    /// None until the first round runs.
+    pub current: Option<CoinBalanceDistribution>,
+}
+
+/// Keeps the pool close to the configured coin distribution, by periodically splitting large
+/// coins while the pool has fewer coins than the target, and merging small coins into coins
+/// close to the target balance.
+/// Coins produced by either operation fall between the small and large balance thresholds, so
+/// they are never picked up again by a later round.
+pub struct CoinRebalancer {
+    config: CoinRebalanceConfig,
+    signer: Arc<dyn TxSigner>,
+    gas_pool_store: Arc<dyn Storage>,
+    mys_client: MysClient,
+    metrics: Arc<GasPoolCoreMetrics>,
+    last_distribution: Mutex<Option<CoinBalanceDistribution>>,
+}
+
+impl CoinRebalancer {
+    pub fn new(
+        config: CoinRebalanceConfig,
+        signer: Arc<dyn TxSigner>,
+        gas_pool_store: Arc<dyn Storage>,
+        mys_client: MysClient,
+        metrics: Arc<GasPoolCoreMetrics>,
+    ) -> Self {
+        Self {
+            config,
+            signer,
+            gas_pool_store,
+            mys_client,
+            metrics,
+            last_distribution: Mutex::new(None),
+        }
+    }
+
+    fn balance_tolerance_factor(&self) -> u64 {
+        self.config.balance_tolerance_factor.max(2)
+    }
+
+    pub fn small_balance_threshold(&self) -> u64 {
+        self.config.target_coin_balance / self.balance_tolerance_factor()
+    }
+
+    pub fn large_balance_threshold(&self) -> u64 {
+        self.config
+            .target_coin_balance
+            .saturating_mul(self.balance_tolerance_factor())
+    }
+
+    pub fn get_stats(&self) -> CoinDistributionStats {
+        CoinDistributionStats {
+            target_coin_count: self.config.target_coin_count,
+            target_coin_balance: self.config.target_coin_balance,
+            small_balance_threshold: self.small_balance_threshold(),
+            large_balance_threshold: self.large_balance_threshold(),
+            current: *self.last_distribution.lock(),
+        }
+    }
+
+    /// Starts a task that runs a rebalancing round every `rebalance_interval_sec`.
+    pub fn start_rebalance_task(
+        self: Arc<Self>,
+        mut cancel_receiver: tokio::sync::oneshot::Receiver<()>,
+    ) -> JoinHandle<()> {
+        tokio::task::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(self.config.rebalance_interval_sec)) => {}
+                    _ = &mut cancel_receiver => {
+                        info!("Coin rebalance task is cancelled");
+                        break;
+                    }
+                }
+                if safe_mode_is_on(self.gas_pool_store.as_ref()).await {
+                    info!("Safe mode is on. Skipping this rebalancing round");
+                    continue;
+                }
+                if let Err(err) = self.run_once().await {
+                    error!("Failed to rebalance the gas pool: {:?}", err);
+                }
+            }
+        })
+    }
+
+    pub async fn run_once(&self) -> anyhow::Result<()> {
+        if !self
+            .gas_pool_store
+            .acquire_init_lock(REBALANCE_LOCK_DURATION_SEC)
+            .await?
+        {
+            debug!("Another task is initializing or rebalancing the pool. Skipping this round");
+            return Ok(());
+        }
+        let result = self.rebalance().await;
+        if let Err(err) = self.gas_pool_store.release_init_lock().await {
+            error!("Failed to release initialization lock: {:?}", err);
+        }
+        result
+    }
+
+    async fn rebalance(&self) -> anyhow::Result<()> {
+        let coin_count = self.gas_pool_store.get_available_coin_count().await?;
+        let deficit = self.config.target_coin_count.saturating_sub(coin_count);
+        // Large coins are only worth taking out of the pool if we need more coins.
+        let max_large_coins = deficit.min(self.config.max_coins_per_round);
+        let max_small_coins = self.config.max_coins_per_round - max_large_coins;
+        let (reservation_id, reserved_coins, distribution) = self
+            .gas_pool_store
+            .reserve_coins_for_rebalance(
+                self.small_balance_threshold(),
+                self.large_balance_threshold(),
+                max_small_coins,
+                max_large_coins,
+                REBALANCE_RESERVATION_DURATION.as_millis() as u64,
+            )
+            .await?;
+        *self.last_distribution.lock() = Some(distribution);
+        let Some(reservation_id) = reservation_id else {
+            debug!(?distribution, "Gas pool does not need rebalancing");
+            return Ok(());
+        };
+        info!(
+            ?reservation_id,
+            ?distribution,
+            "Rebalancing {} coins",
+            reserved_coins.len()
+        );
+
+        // Make sure we are using the latest versions of the coins.
+        let latest_coins: Vec<GasCoin> = self
+            .mys_client
+            .get_latest_gas_objects(
+                self.signer.get_address(),
+                reserved_coins.iter().map(|c| c.object_ref.0),
+            )
+            .await
+            .into_values()
+            .flatten()
+            .collect();
+        let large_balance_threshold = self.large_balance_threshold();
+        let (large_coins, small_coins): (Vec<_>, Vec<_>) = latest_coins
+            .into_iter()
+            .partition(|c| c.balance > large_balance_threshold);
+        let rgp = self.mys_client.get_reference_gas_price().await;
+
+        // Coins from the reservation, which are put back into the pool with their latest state.
+        let mut rebalanced_coins = vec![];
+        // Coins split off large coins. These are not part of the reservation.
+        let mut new_coins = vec![];
+        if let Some(first_coin) = large_coins.first() {
+            let gas_cost_per_object = self
+                .mys_client
+                .calibrate_gas_cost_per_object(self.signer.get_address(), first_coin)
+                .await;
+            let mut remaining_deficit = deficit;
+            for coin in large_coins {
+                if remaining_deficit == 0 {
+                    rebalanced_coins.push(coin);
+                    continue;
+                }
+                match self
+                    .split_coin(&coin, remaining_deficit, gas_cost_per_object, rgp)
+                    .await
+                {
+                    Ok((updated_coin, created_coins)) => {
+                        remaining_deficit = remaining_deficit.saturating_sub(created_coins.len());
+                        rebalanced_coins.push(updated_coin);
+                        new_coins.extend(created_coins);
+                    }
+                    Err(err) => {
+                        error!("Failed to split coin {:?}: {:?}", coin, err);
+                        self.metrics.num_failed_rebalance_transactions.inc();
+                        rebalanced_coins.extend(self.get_latest_coins(&[coin]).await);
+                    }
+                }
+            }
+        }
+        for group in group_small_coins(small_coins, self.config.target_coin_balance) {
+            if group.len() < 2 {
+                rebalanced_coins.extend(group);
+                continue;
+            }
+            match self.merge_coins(&group, rgp).await {
+                Ok(merged_coin) => rebalanced_coins.push(merged_coin),
+                Err(err) => {
+                    error!("Failed to merge {} coins: {:?}", group.len(), err);
+                    self.metrics.num_failed_rebalance_transactions.inc();
+                    rebalanced_coins.extend(self.get_latest_coins(&group).await);
+                }
+            }
+        }
+
+        // Take the coins out of the reservation so that they are not released again when it expires.
+        if let Err(err) = self
+            .gas_pool_store
+            .ready_for_execution(reservation_id)
+            .await
+        {
+            warn!(
+                ?reservation_id,
+                "Rebalancing reservation expired before the round finished. The reserved coins that were already released are skipped: {:?}",
+                err
+            );
+        }
+        info!(
+            ?reservation_id,
+            "Rebalancing finished. Releasing {} coins and adding {} new coins to the pool",
+            rebalanced_coins.len(),
+            new_coins.len()
+        );
+        // The new coins are not tracked anywhere else, so they go first. Reserved coins stay
+        // recorded as held by the reservation until they are released.
+        self.gas_pool_store.add_new_coins(new_coins).await?;
+        self.gas_pool_store
+            .release_reserved_coins(
+                reservation_id,
+                reserved_coins.iter().map(|c| c.object_ref.0).collect(),
+                rebalanced_coins,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Splits coins with the target balance off the coin, keeping at least the target balance
+    /// in the original coin. Returns the updated coin and the new coins.
+    async fn split_coin(
+        &self,
+        coin: &GasCoin,
+        max_new_coins: usize,
+        gas_cost_per_object: u64,
+        rgp: u64,
+    ) -> anyhow::Result<(GasCoin, Vec<GasCoin>)> {
+        let target_balance = self.config.target_coin_balance;
+        let split_count = (coin.balance / target_balance.saturating_add(gas_cost_per_object))
+            .saturating_sub(1)
+            .min(max_new_coins as u64)
+            .min(MAX_SPLIT_COUNT_PER_TX);
+        if split_count == 0 {
+            return Ok((coin.clone(), vec![]));
+        }
+        let budget = coin.split_budget(gas_cost_per_object, split_count + 1)?;
+        let sponsor_address = self.signer.get_address();
+        let tx_data = TransactionData::new_programmable(
+            sponsor_address,
+            vec![coin.object_ref],
+            MysClient::construct_split_off_gas_coin_pt(
+                sponsor_address,
+                target_balance,
+                split_count as u16,
+            ),
+            budget,
+            rgp,
+        );
+        let effects = self.sign_and_execute(tx_data).await?;
+        let created_coins: Vec<_> = effects
+            .created()
+            .iter()
+            .map(|created| GasCoin {
+                object_ref: created.reference.to_object_ref(),
+                balance: target_balance,
+            })
+            .collect();
+        let updated_coin = GasCoin {
+            object_ref: effects.gas_object().reference.to_object_ref(),
+            balance: (coin.balance as i64
+                - (target_balance * split_count) as i64
+                - effects.gas_cost_summary().net_gas_usage()) as u64,
+        };
+        debug!(
+            "Split {} coins off coin {:?}. Tx digest: {:?}",
+            created_coins.len(),
+            coin,
+            effects.transaction_digest()
+        );
+        self.metrics
+            .num_rebalance_split_coins
+            .inc_by(created_coins.len() as u64);
+        Ok((updated_coin, created_coins))
+    }
+
+    /// Merges the coins by using all of them as gas payment of an empty transaction.
+    async fn merge_coins(&self, coins: &[GasCoin], rgp: u64) -> anyhow::Result<GasCoin> {
+        let total_balance: u64 = coins.iter().map(|c| c.balance).sum();
+        let tx_data = TransactionData::new_programmable(
+            self.signer.get_address(),
+            coins.iter().map(|c| c.object_ref).collect(),
+            ProgrammableTransactionBuilder::new().finish(),
+            MERGE_TX_GAS_BUDGET.min(total_balance),
+            rgp,
+        );
+        let effects = self.sign_and_execute(tx_data).await?;
+        debug!(
+            "Merged {} coins. Tx digest: {:?}",
+            coins.len(),
+            effects.transaction_digest()
+        );
+        self.metrics
+            .num_rebalance_merged_coins
+            .inc_by(coins.len() as u64 - 1);
+        Ok(GasCoin {
+            object_ref: effects.gas_object().reference.to_object_ref(),
+            balance: (total_balance as i64 - effects.gas_cost_summary().net_gas_usage()) as u64,
+        })
+    }
+
+    async fn sign_and_execute(
+        &self,
+        tx_data: TransactionData,
+    ) -> anyhow::Result<MysTransactionBlockEffects> {
+        let sig = self.signer.sign_transaction(&tx_data).await?;
+        let tx = Transaction::from_generic_sig_data(tx_data, vec![sig]);
+        let effects = self.mys_client.execute_transaction(tx).await?;
+        if !effects.status().is_ok() {
+            bail!("Rebalance transaction failed: {:?}", effects.status());
+        }
+        Ok(effects)
+    }
+
+    async fn get_latest_coins(&self, coins: &[GasCoin]) -> Vec<GasCoin> {
+        self.mys_client
+            .get_latest_gas_objects(self.signer.get_address(), coins.iter().map(|c| c.object_ref.0))
+            .await
+            .into_values()
+            .flatten()
+            .collect()
+    }
+}
+
+/// Groups small coins such that each group adds up to at least the target balance, except for
+/// the last group. A merged group therefore stays well below the large balance threshold.
+fn group_small_coins(coins: Vec<GasCoin>, target_balance: u64) -> Vec<Vec<GasCoin>> {
+    let mut groups = vec![];
+    let mut group = vec![];
+    let mut group_balance = 0;
+    for coin in coins {
+        group_balance += coin.balance;
+        group.push(coin);
+        if group_balance >= target_balance || group.len() == MAX_GAS_PER_QUERY {
+            groups.push(std::mem::take(&mut group));
+            group_balance = 0;
+        }
+    }
+    if !group.is_empty() {
+        groups.push(group);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CoinInitConfig;
+    use crate::gas_pool_initializer::GasPoolInitializer;
+    use crate::storage::connect_storage_for_testing;
+    use crate::test_env::start_mys_cluster;
+    use mys_types::base_types::random_object_ref;
+
+    fn new_coins(balances: &[u64]) -> Vec<GasCoin> {
+        balances
+            .iter()
+            .map(|balance| GasCoin {
+                object_ref: random_object_ref(),
+                balance: *balance,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_group_small_coins() {
+        let groups = group_small_coins(new_coins(&[10, 20, 30, 40, 50, 5]), 50);
+        let group_balances: Vec<Vec<u64>> = groups
+            .iter()
+            .map(|group| group.iter().map(|c| c.balance).collect())
+            .collect();
+        assert_eq!(group_balances, vec![vec![10, 20, 30], vec![40, 50], vec![5]]);
+
+        let groups = group_small_coins(new_coins(&[1; 300]), u64::MAX);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].len(), MAX_GAS_PER_QUERY);
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_splits_and_merges() {
+        telemetry_subscribers::init_for_testing();
+        let (cluster, signer) = start_mys_cluster(vec![
+            100 * MIST_PER_MYS,
+            MIST_PER_MYS / 100,
+            MIST_PER_MYS / 100,
+            MIST_PER_MYS / 100,
+        ])
+        .await;
+        let fullnode_url = cluster.fullnode_handle.rpc_url.clone();
+        let storage = connect_storage_for_testing(signer.get_address()).await;
+        let mys_client = MysClient::new(&fullnode_url, None).await;
+        // Use a large init balance so that the initializer leaves the coins as they are.
+        let _init_task = GasPoolInitializer::start(
+            mys_client.clone(),
+            storage.clone(),
+            CoinInitConfig {
+                target_init_balance: 100 * MIST_PER_MYS,
+                refresh_interval_sec: 200,
+                ..Default::default()
+            },
+            signer.clone(),
+        )
+        .await;
+        assert_eq!(storage.get_available_coin_count().await.unwrap(), 4);
+
+        let rebalancer = CoinRebalancer::new(
+            CoinRebalanceConfig {
+                target_coin_count: 10,
+                target_coin_balance: MIST_PER_MYS,
+                ..Default::default()
+            },
+            signer,
+            storage.clone(),
+            mys_client,
+            GasPoolCoreMetrics::new_for_testing(),
+        );
+        // The 3 small coins are merged into one, and 6 coins are split off the large coin.
+        rebalancer.run_once().await.unwrap();
+        assert_eq!(
+            rebalancer.get_stats().current,
+            Some(CoinBalanceDistribution {
+                coin_count: 4,
+                small_coin_count: 3,
+                large_coin_count: 1,
+            })
+        );
+        assert_eq!(storage.get_available_coin_count().await.unwrap(), 8);
+        assert_eq!(rebalancer.metrics.num_rebalance_split_coins.get(), 6);
+        assert_eq!(rebalancer.metrics.num_rebalance_merged_coins.get(), 2);
+
+        // The remaining deficit is made up in the next round.
+        rebalancer.run_once().await.unwrap();
+        assert_eq!(storage.get_available_coin_count().await.unwrap(), 10);
+        assert_eq!(rebalancer.metrics.num_rebalance_split_coins.get(), 8);
+
+        // Once the target coin count is reached, large coins are left alone.
+        rebalancer.run_once().await.unwrap();
+        assert_eq!(storage.get_available_coin_count().await.unwrap(), 10);
+        assert_eq!(rebalancer.metrics.num_rebalance_split_coins.get(), 8);
+        assert_eq!(rebalancer.metrics.num_failed_rebalance_transactions.get(), 0);
+    }
+}
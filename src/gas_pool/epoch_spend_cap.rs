@@ -0,0 +1,162 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::errors::GasStationError;
+use mys_types::committee::EpochId;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// How often to ask the fullnode for the current epoch while the cap is reached. Without this,
+/// the cap would never be lifted, since the epoch is otherwise learned from executed transactions.
+pub const EPOCH_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Tracks the gas spent by executed transactions within the current epoch, and rejects new
+/// reservations and executions once the spend reaches the cap.
+pub struct EpochSpendCap {
+    cap: u64,
+    inner: Mutex<EpochSpendCapInner>,
+}
+
+struct EpochSpendCapInner {
+    /// None until the first epoch is observed.
+    cur_epoch: Option<EpochId>,
+    /// Net gas usage can be negative thanks to storage rebates, so this can be negative too.
+    cur_epoch_spend: i64,
+    last_epoch_refresh: Option<Instant>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct EpochSpendStats {
+    /// None if no epoch has been observed yet.
+    pub epoch: Option<EpochId>,
+    pub spend_cap: u64,
+    pub spent: i64,
+    pub remaining_budget: u64,
+}
+
+impl EpochSpendCap {
+    pub fn new(cap: u64) -> Self {
+        Self {
+            cap,
+            inner: Mutex::new(EpochSpendCapInner {
+                cur_epoch: None,
+                cur_epoch_spend: 0,
+                last_epoch_refresh: None,
+            }),
+        }
+    }
+
+    pub fn check_spend(&self) -> Result<(), GasStationError> {
+        if self.inner.lock().cur_epoch_spend >= self.cap as i64 {
+            return Err(GasStationError::BudgetCapReached);
+        }
+        Ok(())
+    }
+
+    /// Whether the caller should fetch the current epoch from the fullnode and pass it to
+    /// `observe_epoch`. This is only the case while the cap is reached, at most once per
+    /// `EPOCH_REFRESH_INTERVAL`.
+    pub fn needs_epoch_refresh(&self) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.cur_epoch_spend < self.cap as i64 {
+            return false;
+        }
+        let now = Instant::now();
+        if inner
+            .last_epoch_refresh
+            .is_some_and(|last| now.duration_since(last) < EPOCH_REFRESH_INTERVAL)
+        {
+            return false;
+        }
+        inner.last_epoch_refresh = Some(now);
+        true
+    }
+
+    /// Resets the spend if the epoch is newer than the current one.
+    pub fn observe_epoch(&self, epoch: EpochId) {
+        self.inner.lock().observe_epoch(epoch);
+    }
+
+    /// Adds the gas used by a transaction executed in the given epoch, and returns the spend of
+    /// the current epoch. Transactions from an older epoch don't count against the current one.
+    pub fn update_spend(&self, epoch: EpochId, usage: i64) -> i64 {
+        let mut inner = self.inner.lock();
+        inner.observe_epoch(epoch);
+        if inner.cur_epoch == Some(epoch) {
+            inner.cur_epoch_spend += usage;
+        }
+        inner.cur_epoch_spend
+    }
+
+    pub fn get_stats(&self) -> EpochSpendStats {
+        let inner = self.inner.lock();
+        EpochSpendStats {
+            epoch: inner.cur_epoch,
+            spend_cap: self.cap,
+            spent: inner.cur_epoch_spend,
+            remaining_budget: (self.cap as i64)
+                .saturating_sub(inner.cur_epoch_spend)
+                .max(0) as u64,
+        }
+    }
+}
+
+impl EpochSpendCapInner {
+    fn observe_epoch(&mut self, epoch: EpochId) {
+        if self.cur_epoch.map_or(true, |cur_epoch| epoch > cur_epoch) {
+            self.cur_epoch = Some(epoch);
+            self.cur_epoch_spend = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_epoch_spend_cap() {
+        let cap = EpochSpendCap::new(100);
+        assert!(cap.check_spend().is_ok());
+        assert!(!cap.needs_epoch_refresh());
+        assert_eq!(cap.update_spend(1, 60), 60);
+        assert!(cap.check_spend().is_ok());
+        assert_eq!(cap.update_spend(1, 40), 100);
+        assert!(matches!(
+            cap.check_spend(),
+            Err(GasStationError::BudgetCapReached)
+        ));
+        assert_eq!(cap.get_stats().remaining_budget, 0);
+
+        // Only one refresh is needed per interval.
+        assert!(cap.needs_epoch_refresh());
+        assert!(!cap.needs_epoch_refresh());
+
+        // Late transactions from an older epoch are ignored.
+        assert_eq!(cap.update_spend(0, 10), 100);
+        cap.observe_epoch(1);
+        assert!(cap.check_spend().is_err());
+    }
+
+    #[test]
+    fn test_epoch_spend_cap_reset() {
+        let cap = EpochSpendCap::new(100);
+        cap.update_spend(1, 150);
+        assert!(cap.check_spend().is_err());
+        cap.observe_epoch(2);
+        assert!(cap.check_spend().is_ok());
+        assert_eq!(cap.update_spend(2, 30), 30);
+        assert_eq!(
+            cap.get_stats(),
+            EpochSpendStats {
+                epoch: Some(2),
+                spend_cap: 100,
+                spent: 30,
+                remaining_budget: 70,
+            }
+        );
+    }
+}
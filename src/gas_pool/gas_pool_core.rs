@@ -2,19 +2,39 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::auth::SHARED_CLIENT_ID;
+use crate::circuit_breaker::CircuitState;
+use crate::config::GasPoolCoreConfig;
+use crate::errors::GasStationError;
 use crate::metrics::GasPoolCoreMetrics;
-use crate::object_locks::ObjectLockManager;
-use crate::storage::Storage;
-use crate::mys_client::MysClient;
+use crate::object_locks::{MultiGetObjectOwners, ObjectLockManager, ObjectLocks};
+use crate::reservation_callback::{
+    ReservationCallbackSender, ReservationEvent, ReservationOutcome, WaitingReservationEvent,
+    WaitingReservationOutcome,
+};
+use crate::storage::{safe_mode_is_on, ExecutingReservation, PoolSnapshot, Storage};
+use crate::mys_client::{move_abort_code, GasCostCalibration, MysClient};
 use crate::tx_signer::TxSigner;
-use crate::types::{GasCoin, ReservationID};
+use crate::types::{
+    AuditEvent, AuditRecord, BudgetLeaseSnapshot, ClientUsage, GasCoin, ReservationID,
+    ReservationReceipt, ReservationSnapshot, SignedEffects, SignedReservationReceipt,
+};
 use crate::{retry_forever, retry_with_max_attempts};
 use anyhow::bail;
+use chrono::Utc;
+use fastcrypto::encoding::Base64;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::sync::Arc;
-use std::time::Duration;
-use mys_json_rpc_types::{MysTransactionBlockEffects, MysTransactionBlockEffectsAPI};
+use std::time::{Duration, Instant};
+use mys_json_rpc_types::{
+    MysExecutionStatus, MysTransactionBlockEffects, MysTransactionBlockEffectsAPI,
+};
 use mys_types::base_types::{ObjectID, ObjectRef, MysAddress};
 use mys_types::gas_coin::MIST_PER_MYS;
+use mys_types::object::Owner;
 use mys_types::programmable_transaction_builder::ProgrammableTransactionBuilder;
 use mys_types::signature::GenericSignature;
 use mys_types::transaction::{
@@ -22,17 +42,71 @@ use mys_types::transaction::{
 };
 use tap::TapFallible;
 use tokio::task::JoinHandle;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
+use super::audit_log::AuditLog;
+use super::client_budget_limit::ClientBudgetLimiter;
+use super::coin_balance_audit::CoinBalanceAuditor;
+use super::coin_quarantine::CoinQuarantine;
+use super::coin_rebalancer::{CoinDistributionStats, CoinRebalancer};
+use super::epoch_spend_cap::{EpochSpendCap, EpochSpendStats};
 use super::gas_usage_cap::GasUsageCap;
+use super::move_call_rate_limit::MoveCallRateLimiter;
+use super::reservation_queue::ReservationQueue;
+use super::reservation_waitlist::{ReservationWaitlist, WaitTicket, WaitingReservation};
+use super::sponsor_address_check::SponsorAddressChecker;
+use super::warmup::Warmup;
 
 const EXPIRATION_JOB_INTERVAL: Duration = Duration::from_secs(1);
+const COOLDOWN_JOB_INTERVAL: Duration = Duration::from_millis(100);
+/// How often a reservation waiting for its turn in priority order retries while the pool
+/// cannot serve it.
+const PRIORITY_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+/// How long a coin reload waits before adding the coins it discovered, to let coins that are
+/// briefly tracked nowhere, e.g. while being used in a transaction, settle. This must be longer
+/// than it takes to execute a transaction.
+const COIN_RELOAD_GRACE_PERIOD: Duration = Duration::from_secs(10);
+const COIN_RELOAD_LOCK_DURATION_SEC: u64 = 5 * 60;
+/// How long the reference gas price is cached for when validating gas prices. It only changes
+/// between epochs, and the fullnode rejects prices below the current one anyway.
+const REFERENCE_GAS_PRICE_CACHE_TTL: Duration = Duration::from_secs(60);
+/// How long recovering an executed transaction waits for the fullnode to serve the gas coin
+/// version it produced.
+const EXECUTED_COIN_WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How many times the gas budget candidate reservations cover, so that clients have coins to
+/// choose from.
+pub const CANDIDATE_BUDGET_MULTIPLIER: u64 = 2;
+/// The per-tag metrics label for tags that are not configured as metric tags.
+pub const OTHER_METRIC_TAG: &str = "other";
+
+/// How much of the requested budget the coins of a reservation must cover.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ReservationCoverage {
+    /// At least the whole budget.
+    Full,
+    /// As much of the budget as the pool can cover.
+    Partial,
+    /// CANDIDATE_BUDGET_MULTIPLIER times the budget, for the client to pick from.
+    Candidates,
+}
 
 pub struct GasPoolContainer {
     inner: Arc<GasPool>,
     _coin_unlocker_task: JoinHandle<()>,
     // This is always Some. It is None only after the drop method is called.
     cancel_sender: Option<tokio::sync::oneshot::Sender<()>>,
+    _coin_rebalance_task: Option<JoinHandle<()>>,
+    rebalance_cancel_sender: Option<tokio::sync::oneshot::Sender<()>>,
+    _coin_balance_audit_task: Option<JoinHandle<()>>,
+    balance_audit_cancel_sender: Option<tokio::sync::oneshot::Sender<()>>,
+    _sponsor_address_check_task: JoinHandle<()>,
+    sponsor_address_check_cancel_sender: Option<tokio::sync::oneshot::Sender<()>>,
+    _audit_log_writer_task: Option<JoinHandle<()>>,
+    audit_log_cancel_sender: Option<tokio::sync::oneshot::Sender<()>>,
+    _warmup_task: Option<JoinHandle<()>>,
+    warmup_cancel_sender: Option<tokio::sync::oneshot::Sender<()>>,
+    _reservation_waitlist_task: Option<JoinHandle<()>>,
+    reservation_waitlist_cancel_sender: Option<tokio::sync::oneshot::Sender<()>>,
 }
 
 pub struct GasPool {
@@ -42,6 +116,162 @@ pub struct GasPool {
     metrics: Arc<GasPoolCoreMetrics>,
     gas_usage_cap: Arc<GasUsageCap>,
     object_lock_manager: Arc<ObjectLockManager>,
+    config: GasPoolCoreConfig,
+    /// Gas coins that were just used in a transaction, along with the time at which
+    /// they can be released back to the pool.
+    cooldown_queue: Mutex<VecDeque<(Instant, FinishedReservation)>>,
+    /// None if coin quarantine is not enabled.
+    coin_quarantine: Option<CoinQuarantine>,
+    /// Gas coins that were quarantined, along with the time at which they can be released back
+    /// to the pool.
+    quarantine_queue: Mutex<VecDeque<(Instant, FinishedReservation)>>,
+    callback_sender: ReservationCallbackSender,
+    coin_rebalancer: Option<Arc<CoinRebalancer>>,
+    coin_balance_auditor: Option<Arc<CoinBalanceAuditor>>,
+    sponsor_address_checker: Arc<SponsorAddressChecker>,
+    reservation_queue: Option<Arc<ReservationQueue>>,
+    reservation_waitlist: Option<ReservationWaitlist>,
+    client_budget_limiter: Option<ClientBudgetLimiter>,
+    epoch_spend_cap: Option<EpochSpendCap>,
+    audit_log: Option<Arc<AuditLog>>,
+    warmup: Option<Arc<Warmup>>,
+    move_call_rate_limiter: Option<MoveCallRateLimiter>,
+    /// The reference gas price, along with the time at which it was fetched.
+    reference_gas_price: Mutex<Option<(Instant, u64)>>,
+}
+
+/// Runs the future until the deadline, if any, and fails with `GasStationError::RequestTimeout`
+/// once it passes. The future is dropped then, which cancels the work it is waiting on.
+pub async fn within_deadline<T>(
+    deadline: Option<Instant>,
+    future: impl std::future::Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline.into(), future)
+            .await
+            .unwrap_or_else(|_| Err(GasStationError::RequestTimeout.into())),
+        None => future.await,
+    }
+}
+
+/// Whether the error is a `GasStationError::RequestTimeout`.
+pub fn is_request_timeout(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<GasStationError>(),
+        Some(GasStationError::RequestTimeout)
+    )
+}
+
+/// The coins of a reservation that is over, to be released back to the pool.
+struct FinishedReservation {
+    reservation_id: ReservationID,
+    /// All coins of the reservation, including the ones that no longer exist.
+    reserved_coin_ids: Vec<ObjectID>,
+    latest_coins: Vec<GasCoin>,
+}
+
+/// Takes the reservations whose release time has passed out of the queue, or all of them if
+/// `force` is true. All entries of a queue are held back for the same duration, so the queue is
+/// ordered by release time.
+fn take_ready_reservations(
+    queue: &Mutex<VecDeque<(Instant, FinishedReservation)>>,
+    force: bool,
+) -> Vec<FinishedReservation> {
+    let now = Instant::now();
+    let mut queue = queue.lock();
+    let mut ready_reservations = vec![];
+    while let Some((release_time, _)) = queue.front() {
+        if !force && *release_time > now {
+            break;
+        }
+        ready_reservations.push(queue.pop_front().unwrap().1);
+    }
+    ready_reservations
+}
+
+fn count_queued_coins(queue: &Mutex<VecDeque<(Instant, FinishedReservation)>>) -> usize {
+    queue
+        .lock()
+        .iter()
+        .map(|(_, reservation)| reservation.latest_coins.len())
+        .sum()
+}
+
+#[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
+pub struct CoinReloadResult {
+    /// Number of coins owned by the sponsor on chain.
+    pub scanned_coin_count: usize,
+    /// Number of coins that were not in the pool and got added.
+    pub added_coin_count: usize,
+    pub added_balance: u64,
+}
+
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct GasPoolStats {
+    pub sponsor_address: MysAddress,
+    pub available_coin_count: usize,
+    pub available_coin_total_balance: u64,
+    pub coins_in_cooldown: usize,
+    pub coins_in_quarantine: usize,
+    /// Budget committed to budget leases that have not been claimed yet.
+    pub leased_budget: u64,
+    /// None if the fullnode circuit breaker is not enabled.
+    pub fullnode_circuit_state: Option<CircuitState>,
+    /// Current and target coin distribution. None if coin rebalancing is not enabled.
+    pub coin_distribution: Option<CoinDistributionStats>,
+    /// Gas spent and budget remaining in the current epoch. None if there is no per-epoch spend cap.
+    pub epoch_spend: Option<EpochSpendStats>,
+    /// How the gas cost per object used to split coins was last calibrated. None if no coin was
+    /// split yet.
+    pub gas_cost_calibration: Option<GasCostCalibration>,
+    /// Whether safe mode is on, in which case background tasks do not change the pool.
+    pub safe_mode: bool,
+    /// Number of reservations waiting for coins. None if the reservation waitlist is not enabled.
+    pub waiting_reservations: Option<usize>,
+}
+
+/// The state of the pool, for bug reports. It only holds ids, balances and settings, never
+/// secrets.
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct PoolDebugSnapshot {
+    /// When the snapshot was taken, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub stats: GasPoolStats,
+    /// The available coins, active reservations and budget leases are read from the storage at
+    /// once, so they never share a coin.
+    pub available_coin_balances: CoinBalanceSummary,
+    pub reservations: Vec<ReservationSnapshot>,
+    pub budget_leases: Vec<BudgetLeaseSnapshot>,
+    /// The cached reference gas price. None if it was not fetched yet.
+    pub reference_gas_price: Option<u64>,
+    pub background_tasks: BackgroundTaskStatus,
+}
+
+/// How the balances of the available coins are distributed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct CoinBalanceSummary {
+    pub coin_count: usize,
+    pub total_balance: u64,
+    /// The coin count and total balance tracked by the storage, which only differ from the
+    /// actual ones because of an accounting bug.
+    pub tracked_coin_count: i64,
+    pub tracked_total_balance: i64,
+    /// The balances are 0 if there is no available coin.
+    pub min_balance: u64,
+    pub median_balance: u64,
+    pub max_balance: u64,
+}
+
+/// Which optional background tasks run on this gas pool instance. While safe mode is on, they
+/// do not change the pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct BackgroundTaskStatus {
+    pub coin_rebalance: bool,
+    pub coin_balance_audit: bool,
+    pub audit_log_writer: bool,
+    pub reservation_waitlist: bool,
+    /// Whether the warmup is over. None if warmup is not enabled.
+    pub warm: Option<bool>,
 }
 
 impl GasPool {
@@ -51,8 +281,76 @@ impl GasPool {
         mys_client: MysClient,
         metrics: Arc<GasPoolCoreMetrics>,
         gas_usage_cap: Arc<GasUsageCap>,
+        config: GasPoolCoreConfig,
     ) -> Arc<Self> {
         let object_lock_manager = Arc::new(ObjectLockManager::new(Arc::new(mys_client.clone())));
+        let callback_sender = ReservationCallbackSender::new(
+            config.reservation_callback_config.clone(),
+            metrics.clone(),
+        );
+        let coin_rebalancer = config.coin_rebalance_config.clone().map(|rebalance_config| {
+            Arc::new(CoinRebalancer::new(
+                rebalance_config,
+                signer.clone(),
+                gas_pool_store.clone(),
+                mys_client.clone(),
+                metrics.clone(),
+            ))
+        });
+        let coin_balance_auditor = config.coin_balance_audit_config.clone().map(|audit_config| {
+            Arc::new(CoinBalanceAuditor::new(
+                audit_config,
+                gas_pool_store.clone(),
+                mys_client.clone(),
+                signer.get_address(),
+                metrics.clone(),
+            ))
+        });
+        let sponsor_address_checker = Arc::new(SponsorAddressChecker::new(
+            config.sponsor_address_check_config.clone(),
+            signer.get_address(),
+            signer.clone(),
+            metrics.clone(),
+        ));
+        sponsor_address_checker.check().await;
+        let reservation_queue = config
+            .reservation_priority_config
+            .clone()
+            .map(ReservationQueue::new);
+        let reservation_waitlist = config
+            .reservation_waitlist_config
+            .clone()
+            .map(|waitlist_config| ReservationWaitlist::new(waitlist_config, metrics.clone()));
+        let client_budget_limiter = config
+            .client_budget_limits_config
+            .clone()
+            .map(|limits_config| {
+                ClientBudgetLimiter::new(limits_config, gas_pool_store.clone(), metrics.clone())
+            });
+        let epoch_spend_cap = config.per_epoch_spend_cap.map(EpochSpendCap::new);
+        let audit_log = config.audit_log_config.clone().map(|audit_log_config| {
+            Arc::new(AuditLog::new(
+                audit_log_config,
+                gas_pool_store.clone(),
+                metrics.clone(),
+            ))
+        });
+        let warmup = config.warmup_config.clone().map(|warmup_config| {
+            Arc::new(Warmup::new(
+                warmup_config,
+                gas_pool_store.clone(),
+                mys_client.clone(),
+                sponsor_address_checker.clone(),
+                metrics.clone(),
+            ))
+        });
+        let move_call_rate_limiter = (!config.move_call_rate_limits.is_empty()).then(|| {
+            MoveCallRateLimiter::new(config.move_call_rate_limits.clone(), metrics.clone())
+        });
+        let coin_quarantine = config
+            .coin_quarantine_config
+            .clone()
+            .map(|quarantine_config| CoinQuarantine::new(quarantine_config, metrics.clone()));
         let pool = Self {
             signer,
             gas_pool_store,
@@ -60,6 +358,22 @@ impl GasPool {
             metrics,
             gas_usage_cap,
             object_lock_manager,
+            config,
+            cooldown_queue: Mutex::new(VecDeque::new()),
+            coin_quarantine,
+            quarantine_queue: Mutex::new(VecDeque::new()),
+            callback_sender,
+            coin_rebalancer,
+            coin_balance_auditor,
+            sponsor_address_checker,
+            reservation_queue,
+            reservation_waitlist,
+            client_budget_limiter,
+            epoch_spend_cap,
+            audit_log,
+            warmup,
+            move_call_rate_limiter,
+            reference_gas_price: Mutex::new(None),
         };
         Arc::new(pool)
     }
@@ -69,12 +383,465 @@ impl GasPool {
         gas_budget: u64,
         duration: Duration,
     ) -> anyhow::Result<(MysAddress, ReservationID, Vec<ObjectRef>)> {
+        self.reserve_gas_with_callback(gas_budget, duration, None)
+            .await
+    }
+
+    /// Same as `reserve_gas`, but the callback URL is notified once the reservation
+    /// expires or is consumed.
+    pub async fn reserve_gas_with_callback(
+        &self,
+        gas_budget: u64,
+        duration: Duration,
+        callback_url: Option<String>,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<ObjectRef>)> {
+        self.reserve_gas_with_priority(gas_budget, duration, callback_url, None, None)
+            .await
+    }
+
+    /// Same as `reserve_gas_with_callback`, but when reservation priorities are enabled and the
+    /// pool is close to running out of coins, reservations are served in priority order and may
+    /// wait for coins to be released. The requested priority is capped by the limit configured
+    /// for the client id. Without a reservation priority config, the priority is ignored.
+    /// The client id also keys the client budget limits, so it must be the authenticated one.
+    pub async fn reserve_gas_with_priority(
+        &self,
+        gas_budget: u64,
+        duration: Duration,
+        callback_url: Option<String>,
+        client_id: Option<&str>,
+        priority: Option<u8>,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<ObjectRef>)> {
+        let (sponsor, reservation_id, gas_coins) = self
+            .reserve_gas_coins(
+                gas_budget,
+                duration,
+                callback_url,
+                client_id,
+                priority,
+                ReservationCoverage::Full,
+                vec![],
+            )
+            .await?;
+        Ok((
+            sponsor,
+            reservation_id,
+            gas_coins.into_iter().map(|c| c.object_ref).collect(),
+        ))
+    }
+
+    /// Same as `reserve_gas_with_priority`, but when the pool cannot cover the whole budget,
+    /// reserves as many coins as it can instead of failing. Also returns the total balance of
+    /// the reserved coins, which is less than gas_budget if the reservation is partial, so that
+    /// the client can decide whether to proceed.
+    pub async fn reserve_partial_gas_with_priority(
+        &self,
+        gas_budget: u64,
+        duration: Duration,
+        callback_url: Option<String>,
+        client_id: Option<&str>,
+        priority: Option<u8>,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<ObjectRef>, u64)> {
+        let (sponsor, reservation_id, gas_coins) = self
+            .reserve_gas_coins(
+                gas_budget,
+                duration,
+                callback_url,
+                client_id,
+                priority,
+                ReservationCoverage::Partial,
+                vec![],
+            )
+            .await?;
+        let granted_budget = gas_coins.iter().map(|c| c.balance).sum();
+        if granted_budget < gas_budget {
+            self.metrics.num_partial_reservations.inc();
+        }
+        Ok((
+            sponsor,
+            reservation_id,
+            gas_coins.into_iter().map(|c| c.object_ref).collect(),
+            granted_budget,
+        ))
+    }
+
+    /// Reserves coins summing to at least target_balance, and returns them along with their
+    /// balances. This is the same reservation as `reserve_gas_with_priority`, for clients that
+    /// think in terms of the balance they need rather than a gas budget.
+    pub async fn reserve_balance(
+        &self,
+        target_balance: u64,
+        duration: Duration,
+        callback_url: Option<String>,
+        client_id: Option<&str>,
+        priority: Option<u8>,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<GasCoin>)> {
+        self.reserve_gas_coins(
+            target_balance,
+            duration,
+            callback_url,
+            client_id,
+            priority,
+            ReservationCoverage::Full,
+            vec![],
+        )
+        .await
+    }
+
+    /// Reserves exactly the requested coins, for clients that need particular coins as gas.
+    /// The coins must all be available in the pool, which only tracks coins owned by the
+    /// sponsor, and their total balance must cover gas_budget. Priorities do not apply, since
+    /// there is no point waiting for coins that are specific.
+    pub async fn reserve_requested_gas(
+        &self,
+        requested_coins: Vec<ObjectID>,
+        gas_budget: u64,
+        duration: Duration,
+        callback_url: Option<String>,
+        client_id: Option<&str>,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<ObjectRef>)> {
+        let (sponsor, reservation_id, gas_coins) = self
+            .reserve_gas_coins(
+                gas_budget,
+                duration,
+                callback_url,
+                client_id,
+                None,
+                ReservationCoverage::Full,
+                requested_coins,
+            )
+            .await?;
+        Ok((
+            sponsor,
+            reservation_id,
+            gas_coins.into_iter().map(|c| c.object_ref).collect(),
+        ))
+    }
+
+    /// Reserves coins covering CANDIDATE_BUDGET_MULTIPLIER times the gas budget, and returns them
+    /// along with their balances, so that the client can pick which of them to pay with, e.g. to
+    /// use coins that fit its other inputs. The reserved coins that the transaction does not pay
+    /// with are released once it is executed. Only gas_budget counts against the client budget
+    /// limits.
+    pub async fn reserve_candidate_gas(
+        &self,
+        gas_budget: u64,
+        duration: Duration,
+        callback_url: Option<String>,
+        client_id: Option<&str>,
+        priority: Option<u8>,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<GasCoin>)> {
+        self.reserve_gas_coins(
+            gas_budget,
+            duration,
+            callback_url,
+            client_id,
+            priority,
+            ReservationCoverage::Candidates,
+            vec![],
+        )
+        .await
+    }
+
+    /// Fails with `GasStationError::InvalidRequest` unless reservations can wait for coins.
+    pub fn check_reservation_waitlist_enabled(&self) -> anyhow::Result<()> {
+        self.reservation_waitlist().map(|_| ())
+    }
+
+    fn reservation_waitlist(&self) -> anyhow::Result<&ReservationWaitlist> {
+        self.reservation_waitlist.as_ref().ok_or_else(|| {
+            GasStationError::InvalidRequest("Waiting for coins is not enabled".to_string()).into()
+        })
+    }
+
+    /// Puts a reservation that the pool cannot cover right now on the waitlist. Once coins free
+    /// up, the reservation is made and a `WaitingReservationEvent` with the result is POSTed to
+    /// the callback URL, which then also receives the events of the reservation. If no coins
+    /// free up in time, the event reports that the wait expired.
+    /// Fails with `GasStationError::InsufficientGasCoins` if the waitlist is full.
+    pub fn add_waiting_reservation(
+        &self,
+        gas_budget: u64,
+        duration: Duration,
+        callback_url: String,
+        client_id: Option<String>,
+        priority: Option<u8>,
+        tag: Option<String>,
+    ) -> anyhow::Result<WaitTicket> {
+        let waitlist = self.reservation_waitlist()?;
+        self.validate_callback_url(&Some(callback_url.clone()))?;
+        let ticket = waitlist
+            .push(gas_budget, duration, callback_url, client_id, priority, tag)
+            .ok_or(GasStationError::InsufficientGasCoins)?;
+        Ok(ticket)
+    }
+
+    async fn reserve_gas_coins(
+        &self,
+        gas_budget: u64,
+        duration: Duration,
+        callback_url: Option<String>,
+        client_id: Option<&str>,
+        priority: Option<u8>,
+        coverage: ReservationCoverage,
+        requested_coins: Vec<ObjectID>,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<GasCoin>)> {
         let cur_time = std::time::Instant::now();
+        let allow_partial = coverage == ReservationCoverage::Partial;
+        // Only gas_budget counts against the client budget limits, since the client pays with no
+        // more than that, whatever it picks from the candidates.
+        let target_balance = match coverage {
+            ReservationCoverage::Candidates => {
+                gas_budget.saturating_mul(CANDIDATE_BUDGET_MULTIPLIER)
+            }
+            ReservationCoverage::Full | ReservationCoverage::Partial => gas_budget,
+        };
+        self.validate_callback_url(&callback_url)?;
+        self.check_warmup()?;
+        // There is no point handing out gas coins if the transaction cannot be executed.
+        self.mys_client.check_available()?;
         self.gas_usage_cap.check_usage().await?;
+        self.check_epoch_spend_cap().await?;
+        let budget_limit = self
+            .client_budget_limiter
+            .as_ref()
+            .map(|limiter| (limiter, client_id.unwrap_or(SHARED_CLIENT_ID)));
+        if let Some((limiter, budget_client_id)) = budget_limit {
+            limiter.check_budget(budget_client_id, gas_budget).await?;
+        }
         let sponsor = self.signer.get_address();
-        let (reservation_id, gas_coins) = self
+        let reserved_duration_ms = duration.as_millis() as u64;
+        let (reservation_id, gas_coins) = match &self.reservation_queue {
+            // Requested coins either are available or not, so there is nothing to wait for.
+            _ if !requested_coins.is_empty() => {
+                self.gas_pool_store
+                    .reserve_requested_coins(
+                        requested_coins,
+                        target_balance,
+                        reserved_duration_ms,
+                        callback_url,
+                    )
+                    .await?
+            }
+            Some(queue) => {
+                let priority = queue.resolve_priority(client_id, priority);
+                self.reserve_gas_coins_in_priority_order(
+                    queue,
+                    priority,
+                    target_balance,
+                    reserved_duration_ms,
+                    callback_url,
+                    allow_partial,
+                )
+                .await?
+            }
+            None => {
+                self.reserve_gas_coins_from_store(
+                    target_balance,
+                    reserved_duration_ms,
+                    callback_url,
+                    allow_partial,
+                )
+                .await?
+            }
+        };
+        if let Some((limiter, budget_client_id)) = budget_limit {
+            // A partial reservation only counts for the balance it got.
+            let budget = gas_budget.min(gas_coins.iter().map(|c| c.balance).sum());
+            if let Err(err) = limiter
+                .add_reservation(budget_client_id, reservation_id, budget)
+                .await
+            {
+                self.release_rejected_reservation(reservation_id, gas_coins)
+                    .await;
+                return Err(err);
+            }
+        }
+        if let Some(client_id) = client_id {
+            if let Err(err) = self.set_reservation_owner(reservation_id, client_id).await {
+                self.release_rejected_reservation(reservation_id, gas_coins)
+                    .await;
+                return Err(err);
+            }
+        }
+        let elapsed = cur_time.elapsed().as_millis();
+        self.metrics.reserve_gas_latency_ms.observe(elapsed as u64);
+        self.metrics
+            .reserved_gas_coin_count_per_request
+            .observe(gas_coins.len() as u64);
+        Ok((sponsor, reservation_id, gas_coins))
+    }
+
+    /// Releases the coins of a reservation that was rejected right after it was made, without
+    /// notifying its callback URL. If that fails, the coins are released when it expires.
+    async fn release_rejected_reservation(
+        &self,
+        reservation_id: ReservationID,
+        gas_coins: Vec<GasCoin>,
+    ) {
+        match self.gas_pool_store.cancel_reservation(reservation_id).await {
+            Ok((coin_ids, _)) => {
+                self.release_gas_coins(FinishedReservation {
+                    reservation_id,
+                    reserved_coin_ids: coin_ids,
+                    latest_coins: gas_coins,
+                })
+                .await;
+            }
+            Err(err) => error!(
+                ?reservation_id,
+                "Failed to release the coins of a rejected reservation: {:?}", err
+            ),
+        }
+    }
+
+    async fn reserve_gas_coins_from_store(
+        &self,
+        gas_budget: u64,
+        reserved_duration_ms: u64,
+        callback_url: Option<String>,
+        allow_partial: bool,
+    ) -> anyhow::Result<(ReservationID, Vec<GasCoin>)> {
+        if allow_partial {
+            self.gas_pool_store
+                .reserve_partial_gas_coins_with_callback(
+                    gas_budget,
+                    reserved_duration_ms,
+                    callback_url,
+                )
+                .await
+        } else {
+            self.gas_pool_store
+                .reserve_gas_coins_with_callback(gas_budget, reserved_duration_ms, callback_url)
+                .await
+        }
+    }
+
+    async fn reserve_gas_coins_in_priority_order(
+        &self,
+        queue: &Arc<ReservationQueue>,
+        priority: u8,
+        gas_budget: u64,
+        reserved_duration_ms: u64,
+        callback_url: Option<String>,
+        allow_partial: bool,
+    ) -> anyhow::Result<(ReservationID, Vec<GasCoin>)> {
+        // While the pool has plenty of coins and nobody is waiting, there is nothing to order.
+        if queue.is_idle()
+            && self.gas_pool_store.get_available_coin_count().await?
+                > queue.exhaustion_coin_threshold()
+        {
+            return self
+                .reserve_gas_coins_from_store(
+                    gas_budget,
+                    reserved_duration_ms,
+                    callback_url,
+                    allow_partial,
+                )
+                .await;
+        }
+        let start = Instant::now();
+        let deadline = start + queue.max_wait();
+        let ticket = queue.ticket(priority);
+        let mut turn = None;
+        loop {
+            if turn.is_none() {
+                turn = Some(
+                    tokio::time::timeout_at(deadline.into(), queue.wait_turn(ticket))
+                        .await
+                        .map_err(|_| GasStationError::InsufficientGasCoins)?,
+                );
+                self.metrics
+                    .reservation_priority_wait_ms
+                    .observe(start.elapsed().as_millis() as u64);
+            }
+            let result = self
+                .reserve_gas_coins_from_store(
+                    gas_budget,
+                    reserved_duration_ms,
+                    callback_url.clone(),
+                    allow_partial,
+                )
+                .await;
+            let insufficient_coins = matches!(
+                result
+                    .as_ref()
+                    .err()
+                    .and_then(|err| err.downcast_ref::<GasStationError>()),
+                Some(GasStationError::InsufficientGasCoins)
+            );
+            if !insufficient_coins || Instant::now() + PRIORITY_RETRY_INTERVAL >= deadline {
+                return result;
+            }
+            // Keep the turn while waiting for coins to be released, so that lower priority
+            // reservations can't take them first, unless a higher priority reservation shows up.
+            if queue.has_higher_priority_waiter(ticket) {
+                turn = None;
+            }
+            tokio::time::sleep(PRIORITY_RETRY_INTERVAL).await;
+        }
+    }
+
+    /// Commits the gas budget against the pool without picking any gas coins. The coins are only
+    /// picked when the lease is claimed with `claim_budget_lease`. This lets clients reserve
+    /// budget far ahead of execution without holding on to specific coin versions.
+    /// Returns the lease id, which becomes the reservation id once the lease is claimed.
+    pub async fn reserve_budget_lease(
+        &self,
+        gas_budget: u64,
+        duration: Duration,
+        callback_url: Option<String>,
+        client_id: Option<&str>,
+    ) -> anyhow::Result<(MysAddress, ReservationID)> {
+        self.validate_callback_url(&callback_url)?;
+        self.check_warmup()?;
+        self.gas_usage_cap.check_usage().await?;
+        self.check_epoch_spend_cap().await?;
+        // A lease counts against the client budget limits like a reservation, so that it cannot be
+        // used to get around them.
+        let budget_limit = self
+            .client_budget_limiter
+            .as_ref()
+            .map(|limiter| (limiter, client_id.unwrap_or(SHARED_CLIENT_ID)));
+        if let Some((limiter, budget_client_id)) = budget_limit {
+            limiter.check_budget(budget_client_id, gas_budget).await?;
+        }
+        let lease_id = self
+            .gas_pool_store
+            .reserve_budget_lease(gas_budget, duration.as_millis() as u64, callback_url)
+            .await?;
+        if let Some((limiter, budget_client_id)) = budget_limit {
+            if let Err(err) = limiter
+                .add_reservation(budget_client_id, lease_id, gas_budget)
+                .await
+            {
+                self.release_rejected_reservation(lease_id, vec![]).await;
+                return Err(err);
+            }
+        }
+        if let Some(client_id) = client_id {
+            if let Err(err) = self.set_reservation_owner(lease_id, client_id).await {
+                self.release_rejected_reservation(lease_id, vec![]).await;
+                return Err(err);
+            }
+        }
+        Ok((self.signer.get_address(), lease_id))
+    }
+
+    /// Picks gas coins for a budget lease, turning it into a regular reservation with the same id
+    /// that expires after `duration`.
+    pub async fn claim_budget_lease(
+        &self,
+        lease_id: ReservationID,
+        duration: Duration,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<ObjectRef>)> {
+        let cur_time = std::time::Instant::now();
+        self.mys_client.check_available()?;
+        self.gas_usage_cap.check_usage().await?;
+        self.check_epoch_spend_cap().await?;
+        let gas_coins = self
             .gas_pool_store
-            .reserve_gas_coins(gas_budget, duration.as_millis() as u64)
+            .claim_budget_lease(lease_id, duration.as_millis() as u64)
             .await?;
         let elapsed = cur_time.elapsed().as_millis();
         self.metrics.reserve_gas_latency_ms.observe(elapsed as u64);
@@ -82,23 +849,153 @@ impl GasPool {
             .reserved_gas_coin_count_per_request
             .observe(gas_coins.len() as u64);
         Ok((
-            sponsor,
-            reservation_id,
+            self.signer.get_address(),
+            lease_id,
             gas_coins.into_iter().map(|c| c.object_ref).collect(),
         ))
     }
 
+    /// Same as `cancel_reservation`, but fails with `GasStationError::ReservationNotFound` if the
+    /// reservation was made by another client, so that clients cannot cancel each other's
+    /// reservations.
+    pub async fn cancel_client_reservation(
+        &self,
+        reservation_id: ReservationID,
+        client_id: Option<&str>,
+    ) -> anyhow::Result<usize> {
+        let owner = self
+            .gas_pool_store
+            .get_reservation_owner(reservation_id)
+            .await?;
+        if owner.is_some() && owner.as_deref() != client_id {
+            return Err(GasStationError::ReservationNotFound(reservation_id).into());
+        }
+        self.cancel_reservation(reservation_id).await
+    }
+
+    /// Cancels a reservation or an unclaimed budget lease, releasing all of its coins back to the
+    /// pool right away. A cancelled reservation can no longer be executed. Cancelling a
+    /// reservation that was already cancelled succeeds without releasing anything.
+    /// Returns the number of coins that were released.
+    pub async fn cancel_reservation(
+        &self,
+        reservation_id: ReservationID,
+    ) -> anyhow::Result<usize> {
+        let (coin_ids, callback_url) = self
+            .gas_pool_store
+            .cancel_reservation(reservation_id)
+            .await?;
+        // Only the object ids of reserved coins are stored, so look up the latest coins the same
+        // way as for expired reservations.
+        let latest_coins: Vec<_> = self
+            .mys_client
+            .get_latest_gas_objects(self.signer.get_address(), coin_ids.clone())
+            .await
+            .into_values()
+            .flatten()
+            .collect();
+        let count = self
+            .release_gas_coins(FinishedReservation {
+                reservation_id,
+                reserved_coin_ids: coin_ids,
+                latest_coins,
+            })
+            .await;
+        info!(?reservation_id, "Released {:?} coins after cancellation", count);
+        if let Some(callback_url) = callback_url {
+            self.callback_sender.notify(
+                callback_url,
+                ReservationEvent {
+                    reservation_id,
+                    outcome: ReservationOutcome::Cancelled,
+                    digest: None,
+                },
+            );
+        }
+        Ok(count)
+    }
+
+    /// Fails with `GasStationError::BudgetCapReached` once the gas spent in the current epoch
+    /// reaches the per-epoch spend cap.
+    async fn check_epoch_spend_cap(&self) -> anyhow::Result<()> {
+        let Some(epoch_spend_cap) = &self.epoch_spend_cap else {
+            return Ok(());
+        };
+        if epoch_spend_cap.needs_epoch_refresh() {
+            match self.mys_client.get_current_epoch().await {
+                Ok(epoch) => epoch_spend_cap.observe_epoch(epoch),
+                Err(err) => warn!("Failed to get the current epoch: {:?}", err),
+            }
+        }
+        Ok(epoch_spend_cap.check_spend()?)
+    }
+
+    /// Fails with `GasStationError::ServiceWarming` while the gas pool is warming up, unless the
+    /// reservation fits in the reduced warmup rate.
+    fn check_warmup(&self) -> Result<(), GasStationError> {
+        match &self.warmup {
+            Some(warmup) => warmup.check_reservation(),
+            None => Ok(()),
+        }
+    }
+
+    fn validate_callback_url(&self, callback_url: &Option<String>) -> anyhow::Result<()> {
+        if let Some(callback_url) = callback_url {
+            self.callback_sender
+                .validate_callback_url(callback_url)
+                .map_err(|err| GasStationError::InvalidCallbackUrl(err.to_string()))?;
+        }
+        Ok(())
+    }
+
     pub async fn execute_transaction(
         &self,
         reservation_id: ReservationID,
         tx_data: TransactionData,
         user_sig: GenericSignature,
-    ) -> anyhow::Result<MysTransactionBlockEffects> {
+    ) -> anyhow::Result<(MysTransactionBlockEffects, Option<SignedEffects>)> {
+        self.execute_transaction_with_preflight(reservation_id, tx_data, user_sig, false)
+            .await
+    }
+
+    /// Same as `execute_transaction`, but when preflight is true, the transaction is first run
+    /// with dev_inspect and rejected with `GasStationError::PreflightFailed` if it would fail.
+    /// The preflight is always run when the pool is configured with `preflight_transactions`.
+    pub async fn execute_transaction_with_preflight(
+        &self,
+        reservation_id: ReservationID,
+        tx_data: TransactionData,
+        user_sig: GenericSignature,
+        preflight: bool,
+    ) -> anyhow::Result<(MysTransactionBlockEffects, Option<SignedEffects>)> {
+        self.execute_transaction_with_deadline(reservation_id, tx_data, user_sig, preflight, None)
+            .await
+    }
+
+    /// Same as `execute_transaction_with_preflight`, but fails with
+    /// `GasStationError::RequestTimeout` once the deadline passes, cancelling the fullnode and
+    /// signer calls that are still in flight. If the deadline passes before the coins are taken
+    /// out of the reservation, they stay reserved until it expires. If it passes before the
+    /// transaction is submitted, they are released right away, as after any failed execution.
+    /// Once the transaction may have been submitted, the coins are left to
+    /// `recover_executing_reservations`, which releases them at the versions that are on chain.
+    pub async fn execute_transaction_with_deadline(
+        &self,
+        reservation_id: ReservationID,
+        tx_data: TransactionData,
+        user_sig: GenericSignature,
+        preflight: bool,
+        deadline: Option<Instant>,
+    ) -> anyhow::Result<(MysTransactionBlockEffects, Option<SignedEffects>)> {
+        Self::check_transaction_kind(&tx_data)?;
+        self.check_sponsorship(&tx_data)?;
         let sponsor = tx_data.gas_data().owner;
-        if !self.signer.is_valid_address(&sponsor) {
-            bail!("Sponsor {:?} is not registered", sponsor);
-        };
         Self::check_transaction_validity(&tx_data)?;
+        // Fail before taking the coins out of the reservation, so that they are simply
+        // released when the reservation expires.
+        self.mys_client.check_available()?;
+        self.check_epoch_spend_cap().await?;
+        self.check_gas_price(&tx_data).await?;
         let payment: Vec<_> = tx_data
             .gas_data()
             .payment
@@ -110,25 +1007,114 @@ impl GasPool {
             ?reservation_id,
             "Payment coins in transaction: {:?}", payment
         );
-        self.gas_pool_store
-            .ready_for_execution(reservation_id)
-            .await?;
-        debug!(?reservation_id, "Reservation is ready for execution");
 
         // To avoid read-after-write inconsistency, we apply a trick here to calculate the
         // new balance of the gas coin after the transaction.
         // We first query the total balance prior to transaction execution, then execute the
         // transaction, and finally derive the new gas coin balance using the gas usage from effects.
-        let total_gas_coin_balance = self.get_total_gas_coin_balance(payment.clone()).await;
-        debug!(
-            ?reservation_id,
-            "Total gas coin balance prior to execution: {}", total_gas_coin_balance,
-        );
-        let response = self
-            .execute_transaction_impl(reservation_id, tx_data, user_sig)
-            .await;
+        // The coins are reserved, so their balance cannot change until the transaction executes.
+        let total_gas_coin_balance = within_deadline(deadline, async {
+            let latest_gas_coins = self
+                .mys_client
+                .get_latest_gas_objects(self.signer.get_address(), payment.clone())
+                .await;
+            let total_gas_coin_balance: u64 = latest_gas_coins
+                .values()
+                .flatten()
+                .map(|coin| coin.balance)
+                .sum();
+            debug!(
+                ?reservation_id,
+                "Total gas coin balance prior to execution: {}", total_gas_coin_balance,
+            );
+            self.record_version_mismatches(reservation_id, &tx_data, &latest_gas_coins);
+            Self::check_gas_budget(&tx_data, total_gas_coin_balance)?;
+            if self.config.check_coin_freshness_before_execute {
+                self.check_coin_freshness(reservation_id, &tx_data, &latest_gas_coins)
+                    .await?;
+            }
+            if preflight || self.config.preflight_transactions {
+                self.preflight_transaction(reservation_id, &tx_data).await?;
+            }
+            Ok::<_, anyhow::Error>(total_gas_coin_balance)
+        })
+        .await?;
+        // Taking the coins out of the reservation is not cancelled, so that they are either
+        // still reserved or released below.
+        let (callback_url, reserved_coin_ids) = self
+            .gas_pool_store
+            .ready_for_execution_with_payment(
+                reservation_id,
+                self.config.execute_expiry_margin_ms,
+                payment.clone(),
+            )
+            .await?;
+        debug!(?reservation_id, "Reservation is ready for execution");
+        // The client may pay with only some of the reserved coins, e.g. when it picked among
+        // candidate coins. The others are not touched by the transaction.
+        let unused_coin_ids: Vec<_> = reserved_coin_ids
+            .into_iter()
+            .filter(|id| !payment.contains(id))
+            .collect();
+        let response = match within_deadline(
+            deadline,
+            self.sign_transaction_for_execution(reservation_id, &tx_data, user_sig),
+        )
+        .await
+        {
+            Ok((_object_locks, tx)) => {
+                // Record the transaction before submitting it, so that its coins can be
+                // reconciled with the fullnode if they are not released below.
+                match self
+                    .gas_pool_store
+                    .mark_executing(reservation_id, *tx.digest(), payment.clone())
+                    .await
+                {
+                    Ok(()) => {
+                        let response =
+                            within_deadline(deadline, self.submit_transaction(&tx_data, tx)).await;
+                        if response.as_ref().is_err_and(is_request_timeout) {
+                            // The transaction may still execute, so its payment coins cannot be
+                            // released at their current versions. The reservation stays open for
+                            // recovery, and only the coins it does not pay with are released.
+                            warn!(
+                                ?reservation_id,
+                                "Transaction did not finish before the deadline, leaving its coins to be reconciled with the fullnode"
+                            );
+                            if !unused_coin_ids.is_empty() {
+                                self.release_unused_coins(reservation_id, unused_coin_ids)
+                                    .await;
+                            }
+                            return response;
+                        }
+                        response
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            Err(err) => {
+                if is_request_timeout(&err) {
+                    warn!(
+                        ?reservation_id,
+                        "Transaction was not signed before the deadline, releasing its coins"
+                    );
+                }
+                Err(err)
+            }
+        };
+        let response = match response {
+            Ok((effects, raw_effects)) => {
+                debug!(?reservation_id, "Transaction executed");
+                let signed_effects = match raw_effects {
+                    Some(raw_effects) => self.sign_effects(reservation_id, raw_effects).await,
+                    None => None,
+                };
+                Ok((effects, signed_effects))
+            }
+            Err(err) => Err(err),
+        };
         let updated_coins = match &response {
-            Ok(effects) => {
+            Ok((effects, _)) => {
                 let new_gas_coin = effects.gas_object().reference.to_object_ref();
                 let new_balance =
                     total_gas_coin_balance as i64 - effects.gas_cost_summary().net_gas_usage();
@@ -140,7 +1126,7 @@ impl GasPool {
                 {
                     self.mys_client.wait_for_object(new_gas_coin).await;
                     assert_eq!(
-                        self.get_total_gas_coin_balance(payment).await,
+                        self.get_total_gas_coin_balance(payment.clone()).await,
                         new_balance as u64
                     );
                 }
@@ -155,7 +1141,7 @@ impl GasPool {
                     "Querying latest gas state since transaction failed"
                 );
                 self.mys_client
-                    .get_latest_gas_objects(payment)
+                    .get_latest_gas_objects(self.signer.get_address(), payment.clone())
                     .await
                     .into_values()
                     .flatten()
@@ -163,10 +1149,20 @@ impl GasPool {
             }
         };
         let smashed_coin_count = payment_count - updated_coins.len();
+        let updated_coins = self.retire_low_balance_coins(reservation_id, &sponsor, updated_coins);
         // Regardless of whether the transaction succeeded, we need to release the coins.
         // Otherwise, we lose track of them. This is because `ready_for_execution` already takes
         // the coins out of the pool and will not be covered by the auto-release mechanism.
-        self.release_gas_coins(updated_coins).await;
+        self.release_gas_coins_after_cooldown(FinishedReservation {
+            reservation_id,
+            reserved_coin_ids: payment,
+            latest_coins: updated_coins,
+        })
+        .await;
+        if !unused_coin_ids.is_empty() {
+            self.release_unused_coins(reservation_id, unused_coin_ids)
+                .await;
+        }
         if smashed_coin_count > 0 {
             info!(
                 ?reservation_id,
@@ -177,30 +1173,135 @@ impl GasPool {
                 .with_label_values(&[&sponsor.to_string()])
                 .inc_by(smashed_coin_count as u64);
         }
+        if let Some(callback_url) = callback_url {
+            self.callback_sender.notify(
+                callback_url,
+                ReservationEvent {
+                    reservation_id,
+                    outcome: ReservationOutcome::Consumed,
+                    digest: response
+                        .as_ref()
+                        .ok()
+                        .map(|(effects, _)| *effects.transaction_digest()),
+                },
+            );
+        }
         info!(?reservation_id, "Transaction execution finished");
 
         response
     }
 
-    async fn execute_transaction_impl(
-        &self,
-        reservation_id: ReservationID,
-        tx_data: TransactionData,
-        user_sig: GenericSignature,
-    ) -> anyhow::Result<MysTransactionBlockEffects> {
-        let _object_locks = self
+    /// Releases the reserved coins that the transaction of the reservation did not pay with. They
+    /// were not touched by the transaction, so they go back to the pool right away. The
+    /// reservation itself is left open, so that it is still recorded as executing and counted
+    /// against its client until the payment coins are released.
+    async fn release_unused_coins(&self, reservation_id: ReservationID, coin_ids: Vec<ObjectID>) {
+        let latest_coins: Vec<_> = self
+            .mys_client
+            .get_latest_gas_objects(self.signer.get_address(), coin_ids.clone())
+            .await
+            .into_values()
+            .flatten()
+            .collect();
+        let count = match retry_forever!(async {
+            self.gas_pool_store
+                .release_unused_coins(reservation_id, coin_ids.clone(), latest_coins.clone())
+                .await
+                .tap_err(|err| error!("Failed to call release_unused_coins on storage: {:?}", err))
+        }) {
+            Ok(released_coins) => released_coins.len(),
+            Err(err) => {
+                error!("Failed to release unused gas coins after all retries: {:?}", err);
+                0
+            }
+        };
+        debug!(
+            ?reservation_id,
+            "Released {} reserved coins that the transaction did not pay with", count
+        );
+    }
+
+    /// Runs the transaction with dev_inspect and fails with `GasStationError::PreflightFailed`
+    /// if it would fail, so that the sponsor does not pay for it. Since the preflight only saves
+    /// gas, the transaction is executed anyway when dev_inspect cannot be run.
+    async fn preflight_transaction(
+        &self,
+        reservation_id: ReservationID,
+        tx_data: &TransactionData,
+    ) -> anyhow::Result<()> {
+        self.metrics.num_preflight_checks.inc();
+        match self.mys_client.dev_inspect_transaction(tx_data).await {
+            Ok(MysExecutionStatus::Success) => Ok(()),
+            Ok(MysExecutionStatus::Failure { error }) => {
+                info!(
+                    ?reservation_id,
+                    "Rejecting transaction that would fail: {}", error
+                );
+                self.metrics.num_preflight_failures.inc();
+                let error = match move_abort_code(&error) {
+                    Some(abort_code) => format!("abort code {}, {}", abort_code, error),
+                    None => error,
+                };
+                Err(GasStationError::PreflightFailed(error).into())
+            }
+            Err(err) => {
+                warn!(
+                    ?reservation_id,
+                    "Failed to run the preflight check, executing the transaction anyway: {:?}",
+                    err
+                );
+                self.metrics.num_preflight_errors.inc();
+                Ok(())
+            }
+        }
+    }
+
+    /// Drops the coins whose balance fell below `retire_coin_balance_threshold` from the coins
+    /// that are about to be returned to the pool, and returns the rest.
+    fn retire_low_balance_coins(
+        &self,
+        reservation_id: ReservationID,
+        sponsor: &MysAddress,
+        coins: Vec<GasCoin>,
+    ) -> Vec<GasCoin> {
+        let threshold = self.config.retire_coin_balance_threshold;
+        let (retired_coins, coins): (Vec<_>, Vec<_>) =
+            coins.into_iter().partition(|c| c.balance < threshold);
+        if !retired_coins.is_empty() {
+            info!(
+                ?reservation_id,
+                "Retiring gas coins with balance below {} after execution: {:?}",
+                threshold,
+                retired_coins
+            );
+            self.metrics
+                .num_retired_gas_coins
+                .with_label_values(&[&sponsor.to_string()])
+                .inc_by(retired_coins.len() as u64);
+        }
+        coins
+    }
+
+    /// Acquires the locks of the objects of the transaction and signs it with the sponsor key.
+    /// The locks must be held until the transaction is executed.
+    async fn sign_transaction_for_execution(
+        &self,
+        reservation_id: ReservationID,
+        tx_data: &TransactionData,
+        user_sig: GenericSignature,
+    ) -> anyhow::Result<(ObjectLocks, Transaction)> {
+        let object_locks = self
             .object_lock_manager
-            .try_acquire_locks(reservation_id, &tx_data)
+            .try_acquire_locks(reservation_id, tx_data)
             .await
             .tap_err(|_| {
                 self.metrics.num_equivocation_detected.inc();
             })?;
-        let sponsor = tx_data.gas_data().owner;
         let cur_time = std::time::Instant::now();
         let sponsor_sig = retry_with_max_attempts!(
             async {
                 self.signer
-                    .sign_transaction(&tx_data)
+                    .sign_transaction(tx_data)
                     .await
                     .tap_err(|err| error!("Failed to sign transaction: {:?}", err))
             },
@@ -211,11 +1312,26 @@ impl GasPool {
             .transaction_signing_latency_ms
             .observe(elapsed as u64);
         debug!(?reservation_id, "Transaction signed by sponsor");
-
         let tx = Transaction::from_generic_sig_data(tx_data.clone(), vec![sponsor_sig, user_sig]);
+        Ok((object_locks, tx))
+    }
+
+    /// Submits the signed transaction and records its gas usage. Also returns the raw effects
+    /// when they are to be signed.
+    async fn submit_transaction(
+        &self,
+        tx_data: &TransactionData,
+        tx: Transaction,
+    ) -> anyhow::Result<(MysTransactionBlockEffects, Option<Vec<u8>>)> {
+        let sponsor = tx_data.gas_data().owner;
         let cur_time = std::time::Instant::now();
-        let effects = self.mys_client.execute_transaction(tx, 3).await?;
-        debug!(?reservation_id, "Transaction executed");
+        let (effects, raw_effects) = if self.config.sign_execution_effects {
+            let (effects, raw_effects) =
+                self.mys_client.execute_transaction_with_raw_effects(tx).await?;
+            (effects, Some(raw_effects))
+        } else {
+            (self.mys_client.execute_transaction(tx).await?, None)
+        };
         let elapsed = cur_time.elapsed().as_millis();
         self.metrics
             .transaction_execution_latency_ms
@@ -226,18 +1342,84 @@ impl GasPool {
             .daily_gas_usage
             .with_label_values(&[&sponsor.to_string()])
             .set(new_daily_usage);
+        if let Some(epoch_spend_cap) = &self.epoch_spend_cap {
+            let epoch_spend = epoch_spend_cap.update_spend(effects.executed_epoch(), net_gas_usage);
+            self.metrics
+                .epoch_gas_spend
+                .with_label_values(&[&sponsor.to_string()])
+                .set(epoch_spend);
+        }
         let mutated_objects = effects
             .mutated()
             .iter()
             .map(|o| (o.object_id(), o.owner.clone(), o.version().value()))
             .collect();
         self.object_lock_manager
-            .update_cache_post_execution(&tx_data, mutated_objects);
-        Ok(effects)
+            .update_cache_post_execution(tx_data, mutated_objects);
+        Ok((effects, raw_effects))
+    }
+
+    /// Sign the raw effects with the sponsor key. Failing to sign does not fail the execution,
+    /// since the transaction has already been executed at this point.
+    async fn sign_effects(
+        &self,
+        reservation_id: ReservationID,
+        raw_effects: Vec<u8>,
+    ) -> Option<SignedEffects> {
+        if raw_effects.is_empty() {
+            error!(?reservation_id, "Fullnode did not return raw effects");
+            return None;
+        }
+        match self.signer.sign_personal_message(&raw_effects).await {
+            Ok(signature) => Some(SignedEffects {
+                raw_effects: Base64::from_bytes(&raw_effects),
+                signature: Base64::from_bytes(signature.as_ref()),
+            }),
+            Err(err) => {
+                error!(?reservation_id, "Failed to sign effects: {:?}", err);
+                None
+            }
+        }
+    }
+
+    /// Signs a receipt of the reservation with the sponsor key, if reservation receipts are
+    /// enabled. Failing to sign does not fail the reservation, since the coins are already
+    /// reserved at this point.
+    pub async fn sign_reservation_receipt(
+        &self,
+        reservation_id: ReservationID,
+        gas_coins: &[ObjectRef],
+        budget: u64,
+        duration: Duration,
+    ) -> Option<SignedReservationReceipt> {
+        if !self.config.sign_reservation_receipts {
+            return None;
+        }
+        let receipt = ReservationReceipt {
+            reservation_id,
+            sponsor_address: self.signer.get_address(),
+            gas_coins: gas_coins.to_vec(),
+            budget,
+            expiration_ms: Utc::now().timestamp_millis() as u64 + duration.as_millis() as u64,
+        };
+        let raw_receipt = bcs::to_bytes(&receipt).unwrap();
+        match self.signer.sign_personal_message(&raw_receipt).await {
+            Ok(signature) => Some(SignedReservationReceipt {
+                raw_receipt: Base64::from_bytes(&raw_receipt),
+                signature: Base64::from_bytes(signature.as_ref()),
+            }),
+            Err(err) => {
+                error!(?reservation_id, "Failed to sign reservation receipt: {:?}", err);
+                None
+            }
+        }
     }
 
     async fn get_total_gas_coin_balance(&self, gas_coins: Vec<ObjectID>) -> u64 {
-        let latest = self.mys_client.get_latest_gas_objects(gas_coins).await;
+        let latest = self
+            .mys_client
+            .get_latest_gas_objects(self.signer.get_address(), gas_coins)
+            .await;
         latest
             .into_values()
             .flatten()
@@ -245,6 +1427,191 @@ impl GasPool {
             .sum()
     }
 
+    /// Checks that the transaction is sponsored by the gas pool: the sponsor must be the gas owner,
+    /// paying for a transaction sent by someone else. The gas data is covered by the user
+    /// signature, so the client must set it this way before signing; we cannot fix it up here.
+    fn check_sponsorship(&self, tx_data: &TransactionData) -> Result<(), GasStationError> {
+        let gas_owner = tx_data.gas_data().owner;
+        if !self.signer.is_valid_address(&gas_owner) {
+            return Err(GasStationError::InvalidSponsoredTransaction(format!(
+                "Gas owner {} must be the sponsor address {}",
+                gas_owner,
+                self.signer.get_address()
+            )));
+        }
+        if tx_data.sender() == gas_owner {
+            return Err(GasStationError::InvalidSponsoredTransaction(format!(
+                "Sender must not be the sponsor address {}",
+                gas_owner
+            )));
+        }
+        if tx_data.gas_data().payment.is_empty() {
+            return Err(GasStationError::InvalidSponsoredTransaction(
+                "Gas payment must not be empty".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Counts the move calls of the transaction against the move call rate limits of the client,
+    /// and fails with `GasStationError::PolicyRateLimited` if any limit would be exceeded.
+    /// Clients that cannot be told apart share the same limits.
+    pub fn check_move_call_rate_limits(
+        &self,
+        tx_data: &TransactionData,
+        client_id: Option<&str>,
+    ) -> Result<(), GasStationError> {
+        let Some(move_call_rate_limiter) = &self.move_call_rate_limiter else {
+            return Ok(());
+        };
+        move_call_rate_limiter.check_calls(client_id.unwrap_or(SHARED_CLIENT_ID), tx_data)
+    }
+
+    /// Checks that the gas price is at least the reference gas price, and at most
+    /// `max_gas_price_multiplier` times the reference gas price.
+    async fn check_gas_price(&self, tx_data: &TransactionData) -> anyhow::Result<()> {
+        let gas_price = tx_data.gas_data().price;
+        let reference_gas_price = self.get_reference_gas_price().await?;
+        if gas_price < reference_gas_price {
+            return Err(GasStationError::InvalidSponsoredTransaction(format!(
+                "Gas price {} is below the reference gas price {}",
+                gas_price, reference_gas_price
+            ))
+            .into());
+        }
+        let max_gas_price =
+            reference_gas_price.saturating_mul(self.config.max_gas_price_multiplier);
+        if gas_price > max_gas_price {
+            return Err(GasStationError::InvalidSponsoredTransaction(format!(
+                "Gas price {} exceeds the maximum gas price {}, {} times the reference gas price {}",
+                gas_price, max_gas_price, self.config.max_gas_price_multiplier, reference_gas_price
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Checks that the reserved gas coins cover the gas budget. The sponsor pays for the gas
+    /// price set in the transaction, so a higher price does not buy more gas within the budget.
+    fn check_gas_budget(
+        tx_data: &TransactionData,
+        total_gas_coin_balance: u64,
+    ) -> Result<(), GasStationError> {
+        let gas_budget = tx_data.gas_data().budget;
+        if gas_budget > total_gas_coin_balance {
+            return Err(GasStationError::InvalidSponsoredTransaction(format!(
+                "Gas budget {} exceeds the reserved gas coin balance {}",
+                gas_budget, total_gas_coin_balance
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that the gas coins of the transaction are still at the versions they were reserved
+    /// at and owned by the sponsor, so that a transaction that would fail on a stale or locked gas
+    /// coin is rejected before it is signed. If the owners cannot be fetched, the check is skipped
+    /// and the fullnode gets to reject the transaction instead.
+    async fn check_coin_freshness(
+        &self,
+        reservation_id: ReservationID,
+        tx_data: &TransactionData,
+        latest_gas_coins: &HashMap<ObjectID, Option<GasCoin>>,
+    ) -> anyhow::Result<()> {
+        let sponsor = tx_data.gas_data().owner;
+        let existing_coin_ids = latest_gas_coins
+            .iter()
+            .filter_map(|(id, coin)| coin.as_ref().map(|_| *id))
+            .collect();
+        let owners = match self.mys_client.multi_get_object_owners(existing_coin_ids).await {
+            Ok(owners) => owners,
+            Err(err) => {
+                warn!(
+                    ?reservation_id,
+                    "Failed to get gas coin owners, skipping the freshness check: {:?}", err
+                );
+                return Ok(());
+            }
+        };
+        let stale_coins: Vec<_> = tx_data
+            .gas_data()
+            .payment
+            .iter()
+            .filter_map(|reserved_ref| {
+                let current_ref = latest_gas_coins
+                    .get(&reserved_ref.0)
+                    .cloned()
+                    .flatten()
+                    .map(|coin| coin.object_ref);
+                let owner = owners.get(&reserved_ref.0).map(|(owner, _)| owner);
+                let is_fresh = current_ref == Some(*reserved_ref)
+                    && matches!(owner, Some(Owner::AddressOwner(address)) if *address == sponsor);
+                (!is_fresh).then(|| match (current_ref, owner) {
+                    (Some(current_ref), Some(owner)) => format!(
+                        "{:?} is now {:?} owned by {}",
+                        reserved_ref, current_ref, owner
+                    ),
+                    _ => format!("{:?} no longer exists", reserved_ref),
+                })
+            })
+            .collect();
+        if stale_coins.is_empty() {
+            return Ok(());
+        }
+        warn!(?reservation_id, "Gas coins changed since they were reserved: {:?}", stale_coins);
+        self.metrics.num_stale_gas_coin_rejections.inc();
+        Err(GasStationError::StaleGasCoins(stale_coins.join(", ")).into())
+    }
+
+    /// Records the gas coins of the transaction that are at a newer version than the one they
+    /// were reserved at, which means that they were used outside of the gas pool since. Coins that
+    /// are at an older version are not counted, since that only means that the fullnode is
+    /// lagging behind.
+    fn record_version_mismatches(
+        &self,
+        reservation_id: ReservationID,
+        tx_data: &TransactionData,
+        latest_gas_coins: &HashMap<ObjectID, Option<GasCoin>>,
+    ) {
+        let Some(coin_quarantine) = &self.coin_quarantine else {
+            return;
+        };
+        for reserved_ref in &tx_data.gas_data().payment {
+            let Some(Some(coin)) = latest_gas_coins.get(&reserved_ref.0) else {
+                continue;
+            };
+            if coin.object_ref.1 > reserved_ref.1 {
+                warn!(
+                    ?reservation_id,
+                    "Gas coin {:?} is at version {} instead of {}",
+                    reserved_ref.0,
+                    coin.object_ref.1,
+                    reserved_ref.1
+                );
+                coin_quarantine.record_version_mismatch(reserved_ref.0);
+            }
+        }
+    }
+
+    async fn get_reference_gas_price(&self) -> anyhow::Result<u64> {
+        if let Some((fetched_at, reference_gas_price)) = *self.reference_gas_price.lock() {
+            if fetched_at.elapsed() < REFERENCE_GAS_PRICE_CACHE_TTL {
+                return Ok(reference_gas_price);
+            }
+        }
+        let reference_gas_price = self.mys_client.try_get_reference_gas_price().await?;
+        *self.reference_gas_price.lock() = Some((Instant::now(), reference_gas_price));
+        Ok(reference_gas_price)
+    }
+
+    /// Only programmable transactions are sponsored. Other kinds are system transactions, which
+    /// the sponsor should never pay for.
+    fn check_transaction_kind(tx_data: &TransactionData) -> Result<(), GasStationError> {
+        match tx_data.kind() {
+            TransactionKind::ProgrammableTransaction(_) => Ok(()),
+            kind => Err(GasStationError::UnsupportedTransactionKind(kind.name().to_string())),
+        }
+    }
+
     fn check_transaction_validity(tx_data: &TransactionData) -> anyhow::Result<()> {
         let mut all_args = vec![];
         for command in tx_data.kind().iter_commands() {
@@ -278,16 +1645,157 @@ impl GasPool {
         Ok(())
     }
 
-    /// Release gas coins back to the gas pool, by adding them to the storage.
-    async fn release_gas_coins(&self, gas_coins: Vec<GasCoin>) {
-        debug!("Trying to release gas coins: {:?}", gas_coins);
-        if let Err(err) = retry_forever!(async {
+    /// Release gas coins that were just used in a transaction. If a cooldown is configured,
+    /// the coins are held back and released by the background task once the cooldown passes.
+    async fn release_gas_coins_after_cooldown(&self, reservation: FinishedReservation) {
+        if self.config.coin_cooldown_ms == 0 || reservation.latest_coins.is_empty() {
+            self.release_gas_coins(reservation).await;
+            return;
+        }
+        let release_time = Instant::now() + Duration::from_millis(self.config.coin_cooldown_ms);
+        self.metrics
+            .num_gas_coins_in_cooldown
+            .add(reservation.latest_coins.len() as i64);
+        self.cooldown_queue
+            .lock()
+            .push_back((release_time, reservation));
+    }
+
+    /// Release all coins in the cooldown queue whose cooldown has passed, and all coins in the
+    /// quarantine queue whose quarantine has passed.
+    /// If `force` is true, release all coins regardless of their cooldown or quarantine.
+    async fn release_cooled_down_coins(&self, force: bool) {
+        for reservation in take_ready_reservations(&self.cooldown_queue, force) {
+            let count = reservation.latest_coins.len();
+            self.release_gas_coins(reservation).await;
+            self.metrics.num_gas_coins_in_cooldown.sub(count as i64);
+            debug!("Released {:?} coins after cooldown", count);
+        }
+        for reservation in take_ready_reservations(&self.quarantine_queue, force) {
+            let count = reservation.latest_coins.len();
+            self.release_gas_coins(reservation).await;
+            self.metrics.num_gas_coins_in_quarantine.sub(count as i64);
+            info!("Released {:?} coins after quarantine", count);
+        }
+    }
+
+    /// Holds back the coins of the reservation that are flagged for quarantine until their
+    /// quarantine passes, and returns the rest of the reservation.
+    fn quarantine_flagged_coins(&self, reservation: FinishedReservation) -> FinishedReservation {
+        let Some(coin_quarantine) = &self.coin_quarantine else {
+            return reservation;
+        };
+        let latest_coin_ids: Vec<_> = reservation
+            .latest_coins
+            .iter()
+            .map(|c| c.object_ref.0)
+            .collect();
+        let flagged_coins = coin_quarantine.take_flagged_coins(&latest_coin_ids);
+        if flagged_coins.is_empty() {
+            return reservation;
+        }
+        let FinishedReservation {
+            reservation_id,
+            reserved_coin_ids,
+            latest_coins,
+        } = reservation;
+        let (quarantined_coins, latest_coins): (Vec<_>, Vec<_>) = latest_coins
+            .into_iter()
+            .partition(|c| flagged_coins.contains(&c.object_ref.0));
+        warn!(
+            ?reservation_id,
+            "Quarantining gas coins whose version keeps changing outside of the gas pool: {:?}",
+            quarantined_coins
+        );
+        self.metrics
+            .num_gas_coins_in_quarantine
+            .add(quarantined_coins.len() as i64);
+        let release_time = Instant::now() + coin_quarantine.quarantine_duration();
+        self.quarantine_queue.lock().push_back((
+            release_time,
+            FinishedReservation {
+                reservation_id,
+                reserved_coin_ids: flagged_coins.iter().copied().collect(),
+                latest_coins: quarantined_coins,
+            },
+        ));
+        FinishedReservation {
+            reservation_id,
+            reserved_coin_ids: reserved_coin_ids
+                .into_iter()
+                .filter(|id| !flagged_coins.contains(id))
+                .collect(),
+            latest_coins,
+        }
+    }
+
+    /// Excludes the coins that are in cooldown or in quarantine.
+    fn exclude_coins_in_cooldown(&self, coins: Vec<GasCoin>) -> Vec<GasCoin> {
+        let held_back_coins: HashSet<ObjectID> = [&self.cooldown_queue, &self.quarantine_queue]
+            .into_iter()
+            .flat_map(|queue| {
+                queue
+                    .lock()
+                    .iter()
+                    .flat_map(|(_, reservation)| {
+                        reservation.latest_coins.iter().map(|c| c.object_ref.0)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        coins
+            .into_iter()
+            .filter(|c| !held_back_coins.contains(&c.object_ref.0))
+            .collect()
+    }
+
+    fn get_coins_in_cooldown_count(&self) -> usize {
+        count_queued_coins(&self.cooldown_queue)
+    }
+
+    fn get_coins_in_quarantine_count(&self) -> usize {
+        count_queued_coins(&self.quarantine_queue)
+    }
+
+    /// Release the gas coins of a reservation that is over back to the gas pool.
+    /// Returns the number of coins that were released.
+    async fn release_gas_coins(&self, reservation: FinishedReservation) -> usize {
+        let reservation = self.quarantine_flagged_coins(reservation);
+        let FinishedReservation {
+            reservation_id,
+            reserved_coin_ids,
+            latest_coins,
+        } = reservation;
+        if reserved_coin_ids.is_empty() {
+            return 0;
+        }
+        debug!(?reservation_id, "Trying to release gas coins: {:?}", latest_coins);
+        match retry_forever!(async {
             self.gas_pool_store
-                .add_new_coins(gas_coins.clone())
+                .release_reserved_coins(
+                    reservation_id,
+                    reserved_coin_ids.clone(),
+                    latest_coins.clone(),
+                )
                 .await
-                .tap_err(|err| error!("Failed to call update_gas_coins on storage: {:?}", err))
+                .tap_err(|err| {
+                    error!("Failed to call release_reserved_coins on storage: {:?}", err)
+                })
         }) {
-            error!("Failed to release gas coins after all retries: {:?}", err);
+            Ok(released_coins) => {
+                if released_coins.len() < latest_coins.len() {
+                    warn!(
+                        ?reservation_id,
+                        "Skipped releasing {} coins that are no longer held by the reservation",
+                        latest_coins.len() - released_coins.len()
+                    );
+                }
+                released_coins.len()
+            }
+            Err(err) => {
+                error!("Failed to release gas coins after all retries: {:?}", err);
+                0
+            }
         }
     }
 
@@ -311,33 +1819,369 @@ impl GasPool {
         Ok(())
     }
 
-    async fn start_coin_unlock_task(
+    async fn expire_coins(&self) {
+        let expire_results = self.gas_pool_store.expire_coins().await;
+        let expired_reservations = expire_results.unwrap_or_else(|err| {
+            error!("Failed to call expire_coins to the storage: {:?}", err);
+            vec![]
+        });
+        if !expired_reservations.is_empty() {
+            debug!("Reservations that are expired: {:?}", expired_reservations);
+            let count = self.release_reservations(expired_reservations).await;
+            info!("Released {:?} coins after expiration", count);
+        }
+        self.notify_expired_reservations().await;
+    }
+
+    /// Looks up the latest state of the coins of reservations that are over, and releases them.
+    /// Returns the number of coins that were released.
+    async fn release_reservations(
+        &self,
+        reservations: Vec<(ReservationID, Vec<ObjectID>)>,
+    ) -> usize {
+        let latest_coins = self
+            .mys_client
+            .get_latest_gas_objects(
+                self.signer.get_address(),
+                reservations.iter().flat_map(|(_, ids)| ids.iter().copied()),
+            )
+            .await;
+        let mut count = 0;
+        for (reservation_id, reserved_coin_ids) in reservations {
+            let latest_coins = reserved_coin_ids
+                .iter()
+                .filter_map(|id| latest_coins.get(id).cloned().flatten())
+                .collect();
+            count += self
+                .release_gas_coins(FinishedReservation {
+                    reservation_id,
+                    reserved_coin_ids,
+                    latest_coins,
+                })
+                .await;
+        }
+        count
+    }
+
+    /// Releases the coins that are still held by reservations that are over, but were never
+    /// released, e.g. because the gas pool crashed while executing a transaction.
+    async fn release_stranded_coins(&self) {
+        let reserved_before_ms = (Utc::now().timestamp_millis() as u64)
+            .saturating_sub(self.config.stranded_coin_grace_period_sec * 1000);
+        let stranded_coins = match self
+            .gas_pool_store
+            .get_stranded_coins(reserved_before_ms)
+            .await
+        {
+            Ok(stranded_coins) => stranded_coins,
+            Err(err) => {
+                error!("Failed to get stranded coins: {:?}", err);
+                return;
+            }
+        };
+        if stranded_coins.is_empty() {
+            return;
+        }
+        warn!("Found stranded coins: {:?}", stranded_coins);
+        let count = self.release_reservations(stranded_coins).await;
+        info!("Released {:?} stranded coins", count);
+        self.metrics
+            .num_released_stranded_gas_coins
+            .inc_by(count as u64);
+    }
+
+    /// Reconciles the reservations whose transaction was submitted more than
+    /// `executing_reservation_grace_period_sec` ago, but whose coins were never released, e.g.
+    /// because the gas pool crashed while executing it. If the fullnode knows the transaction, the
+    /// coins are released once the versions it produced are visible, so that spent versions are
+    /// never offered again. Otherwise the coins are released as they are on chain. Reservations
+    /// that cannot be looked up are left to the next attempt.
+    async fn recover_executing_reservations(&self) {
+        let started_before_ms = (Utc::now().timestamp_millis() as u64)
+            .saturating_sub(self.config.executing_reservation_grace_period_sec * 1000);
+        let reservations = match self
+            .gas_pool_store
+            .get_executing_reservations(started_before_ms)
+            .await
+        {
+            Ok(reservations) => reservations,
+            Err(err) => {
+                error!("Failed to get executing reservations: {:?}", err);
+                return;
+            }
+        };
+        for reservation in reservations {
+            let ExecutingReservation {
+                reservation_id,
+                digest,
+                reserved_coin_ids,
+            } = reservation;
+            let executed = match self.mys_client.get_transaction_effects(digest).await {
+                Ok(Some(effects)) => {
+                    let gas_object = effects.gas_object().reference.to_object_ref();
+                    if tokio::time::timeout(
+                        EXECUTED_COIN_WAIT_TIMEOUT,
+                        self.mys_client.wait_for_object(gas_object),
+                    )
+                    .await
+                    .is_err()
+                    {
+                        warn!(
+                            ?reservation_id,
+                            ?digest,
+                            "Gas coin {:?} is not visible yet, leaving the reservation to the next attempt",
+                            gas_object
+                        );
+                        continue;
+                    }
+                    true
+                }
+                Ok(None) => false,
+                Err(err) => {
+                    warn!(
+                        ?reservation_id,
+                        ?digest,
+                        "Failed to look up the transaction of an executing reservation: {:?}",
+                        err
+                    );
+                    continue;
+                }
+            };
+            let latest_coins = self
+                .mys_client
+                .get_latest_gas_objects(self.signer.get_address(), reserved_coin_ids.clone())
+                .await
+                .into_values()
+                .flatten()
+                .collect();
+            let count = self
+                .release_gas_coins(FinishedReservation {
+                    reservation_id,
+                    reserved_coin_ids,
+                    latest_coins,
+                })
+                .await;
+            warn!(
+                ?reservation_id,
+                ?digest,
+                "Recovered a reservation that was executing, transaction executed: {}. Released {} coins",
+                executed,
+                count
+            );
+            self.metrics
+                .num_recovered_executing_reservations
+                .with_label_values(&[if executed { "executed" } else { "not_executed" }])
+                .inc();
+        }
+    }
+
+    async fn notify_expired_reservations(&self) {
+        let callbacks = self
+            .gas_pool_store
+            .take_expired_reservation_callbacks()
+            .await
+            .unwrap_or_else(|err| {
+                error!("Failed to take expired reservation callbacks: {:?}", err);
+                vec![]
+            });
+        for (reservation_id, callback_url) in callbacks {
+            self.callback_sender.notify(
+                callback_url,
+                ReservationEvent {
+                    reservation_id,
+                    outcome: ReservationOutcome::Expired,
+                    digest: None,
+                },
+            );
+        }
+    }
+
+    /// Notifies the waiting reservations whose wait expired, then makes the waiting reservations
+    /// in the order they came in, until the pool cannot cover the next one.
+    async fn serve_waiting_reservations(&self, waitlist: &ReservationWaitlist) {
+        for reservation in waitlist.take_expired() {
+            debug!(
+                wait_id = reservation.wait_id,
+                "Waiting reservation expired before coins freed up"
+            );
+            self.notify_waiting_reservation(
+                reservation,
+                WaitingReservationOutcome::Expired,
+                None,
+            );
+        }
+        while let Some(reservation) = waitlist.pop_front() {
+            let result = self
+                .reserve_gas_coins(
+                    reservation.gas_budget,
+                    reservation.duration,
+                    Some(reservation.callback_url.clone()),
+                    reservation.client_id.as_deref(),
+                    reservation.priority,
+                    ReservationCoverage::Full,
+                    vec![],
+                )
+                .await;
+            match result {
+                Ok((sponsor, reservation_id, gas_coins)) => {
+                    info!(
+                        ?reservation_id,
+                        wait_id = reservation.wait_id,
+                        "Reserved gas coins for a waiting reservation with budget {}",
+                        reservation.gas_budget
+                    );
+                    self.metrics.num_served_waiting_reservations.inc();
+                    let gas_coins: Vec<ObjectRef> =
+                        gas_coins.into_iter().map(|c| c.object_ref).collect();
+                    if let Some(tag) = &reservation.tag {
+                        self.set_reservation_tag(reservation_id, tag).await;
+                    }
+                    self.record_audit_event(
+                        reservation.client_id.clone(),
+                        reservation_id,
+                        reservation.tag.clone(),
+                        AuditEvent::Reserve {
+                            gas_budget: reservation.gas_budget,
+                            gas_coins: gas_coins.iter().map(|coin| coin.0).collect(),
+                        },
+                    );
+                    let receipt = self
+                        .sign_reservation_receipt(
+                            reservation_id,
+                            &gas_coins,
+                            reservation.gas_budget,
+                            reservation.duration,
+                        )
+                        .await;
+                    self.callback_sender.notify(
+                        reservation.callback_url,
+                        WaitingReservationEvent {
+                            wait_id: reservation.wait_id,
+                            outcome: WaitingReservationOutcome::Reserved,
+                            sponsor_address: Some(sponsor),
+                            reservation_id: Some(reservation_id),
+                            gas_coins: gas_coins.into_iter().map(|c| c.into()).collect(),
+                            receipt,
+                            error: None,
+                        },
+                    );
+                }
+                Err(err)
+                    if matches!(
+                        err.downcast_ref::<GasStationError>(),
+                        Some(GasStationError::InsufficientGasCoins)
+                    ) =>
+                {
+                    waitlist.push_front(reservation);
+                    break;
+                }
+                Err(err) => {
+                    warn!(
+                        wait_id = reservation.wait_id,
+                        "Failed to reserve gas coins for a waiting reservation: {:?}", err
+                    );
+                    self.notify_waiting_reservation(
+                        reservation,
+                        WaitingReservationOutcome::Failed,
+                        Some(err.to_string()),
+                    );
+                }
+            }
+        }
+    }
+
+    fn notify_waiting_reservation(
+        &self,
+        reservation: WaitingReservation,
+        outcome: WaitingReservationOutcome,
+        error: Option<String>,
+    ) {
+        self.callback_sender.notify(
+            reservation.callback_url,
+            WaitingReservationEvent {
+                wait_id: reservation.wait_id,
+                outcome,
+                sponsor_address: None,
+                reservation_id: None,
+                gas_coins: vec![],
+                receipt: None,
+                error,
+            },
+        );
+    }
+
+    /// Starts a task that periodically retries the waiting reservations. When it is cancelled,
+    /// the reservations that are still waiting are notified that they failed.
+    fn start_reservation_waitlist_task(
         self: Arc<Self>,
         mut cancel_receiver: tokio::sync::oneshot::Receiver<()>,
     ) -> JoinHandle<()> {
         tokio::task::spawn(async move {
+            // unwrap safe because the task is only started when the waitlist is enabled.
+            let waitlist = self.reservation_waitlist.as_ref().unwrap();
+            let mut interval = tokio::time::interval(waitlist.retry_interval());
             loop {
-                let expire_results = self.gas_pool_store.expire_coins().await;
-                let unlocked_coins = expire_results.unwrap_or_else(|err| {
-                    error!("Failed to call expire_coins to the storage: {:?}", err);
-                    vec![]
-                });
-                if !unlocked_coins.is_empty() {
-                    debug!("Coins that are expired: {:?}", unlocked_coins);
-                    let latest_coins: Vec<_> = self
-                        .mys_client
-                        .get_latest_gas_objects(unlocked_coins.clone())
-                        .await
-                        .into_values()
-                        .flatten()
-                        .collect();
-                    let count = latest_coins.len();
-                    self.release_gas_coins(latest_coins).await;
-                    info!("Released {:?} coins after expiration", count);
+                tokio::select! {
+                    _ = interval.tick() => self.serve_waiting_reservations(waitlist).await,
+                    _ = &mut cancel_receiver => {
+                        while let Some(reservation) = waitlist.pop_front() {
+                            self.notify_waiting_reservation(
+                                reservation,
+                                WaitingReservationOutcome::Failed,
+                                Some("The gas pool is shutting down".to_string()),
+                            );
+                        }
+                        info!("Reservation waitlist task is cancelled");
+                        break;
+                    }
                 }
+            }
+        })
+    }
+
+    /// Starts a task that recovers executing reservations and releases stranded coins once, and
+    /// then periodically releases coins whose reservation expired, as well as coins that finished
+    /// their post-execution cooldown. Executing reservations are recovered again after every grace
+    /// period, to cover the transactions that were still executing when the gas pool stopped, and
+    /// the ones that missed their execute deadline.
+    /// While safe mode is on, only coins that finished their cooldown are released, and the rest
+    /// waits until safe mode is off.
+    async fn start_coin_unlock_task(
+        self: Arc<Self>,
+        mut cancel_receiver: tokio::sync::oneshot::Receiver<()>,
+    ) -> JoinHandle<()> {
+        tokio::task::spawn(async move {
+            let mut expiration_interval = tokio::time::interval(EXPIRATION_JOB_INTERVAL);
+            let mut cooldown_interval = tokio::time::interval(COOLDOWN_JOB_INTERVAL);
+            let recovery_period =
+                Duration::from_secs(self.config.executing_reservation_grace_period_sec.max(1));
+            let mut recovery_interval = tokio::time::interval_at(
+                tokio::time::Instant::now() + recovery_period,
+                recovery_period,
+            );
+            let mut recovery_pending = true;
+            let mut stranded_coin_release_pending = true;
+            loop {
                 tokio::select! {
-                    _ = tokio::time::sleep(EXPIRATION_JOB_INTERVAL) => {}
+                    _ = expiration_interval.tick() => {
+                        let safe_mode = safe_mode_is_on(self.gas_pool_store.as_ref()).await;
+                        self.metrics.safe_mode.set(safe_mode as i64);
+                        if safe_mode {
+                            continue;
+                        }
+                        if std::mem::take(&mut recovery_pending) {
+                            self.recover_executing_reservations().await;
+                        }
+                        if std::mem::take(&mut stranded_coin_release_pending) {
+                            self.release_stranded_coins().await;
+                        }
+                        self.expire_coins().await;
+                    }
+                    _ = cooldown_interval.tick() => self.release_cooled_down_coins(false).await,
+                    _ = recovery_interval.tick() => recovery_pending = true,
                     _ = &mut cancel_receiver => {
+                        // Do not lose track of coins that are still cooling down.
+                        self.release_cooled_down_coins(true).await;
                         info!("Coin unlocker task is cancelled");
                         break;
                     }
@@ -355,6 +2199,287 @@ impl GasPool {
                 0 // Return 0 if we can't query
             })
     }
+
+    pub async fn get_stats(&self) -> GasPoolStats {
+        GasPoolStats {
+            sponsor_address: self.signer.get_address(),
+            available_coin_count: self.query_pool_available_coin_count().await,
+            available_coin_total_balance: self
+                .gas_pool_store
+                .get_available_coin_total_balance()
+                .await,
+            coins_in_cooldown: self.get_coins_in_cooldown_count(),
+            coins_in_quarantine: self.get_coins_in_quarantine_count(),
+            leased_budget: self
+                .gas_pool_store
+                .get_leased_budget()
+                .await
+                .unwrap_or_else(|err| {
+                    error!("Failed to get leased budget: {:?}", err);
+                    0
+                }),
+            fullnode_circuit_state: self.fullnode_circuit_state(),
+            coin_distribution: self
+                .coin_rebalancer
+                .as_ref()
+                .map(|rebalancer| rebalancer.get_stats()),
+            epoch_spend: self
+                .epoch_spend_cap
+                .as_ref()
+                .map(|epoch_spend_cap| epoch_spend_cap.get_stats()),
+            gas_cost_calibration: self.mys_client.last_gas_cost_calibration(),
+            safe_mode: self.is_safe_mode().await,
+            waiting_reservations: self
+                .reservation_waitlist
+                .as_ref()
+                .map(|waitlist| waitlist.waiting_count()),
+        }
+    }
+
+    /// Turns safe mode on or off for every gas pool instance of the sponsor.
+    pub async fn set_safe_mode(&self, enabled: bool) -> anyhow::Result<()> {
+        self.gas_pool_store.set_safe_mode(enabled).await?;
+        self.metrics.safe_mode.set(enabled as i64);
+        if enabled {
+            warn!("Safe mode is on. Background tasks no longer change the pool");
+        } else {
+            info!("Safe mode is off");
+        }
+        Ok(())
+    }
+
+    pub async fn is_safe_mode(&self) -> bool {
+        safe_mode_is_on(self.gas_pool_store.as_ref()).await
+    }
+
+    /// Scans the fullnode for coins owned by the sponsor and adds the ones the pool does not know
+    /// about yet, e.g. after the sponsor was funded manually. Coins that are available, reserved,
+    /// in cooldown or in quarantine are never touched.
+    pub async fn reload_coins(&self) -> anyhow::Result<CoinReloadResult> {
+        // The init lock keeps the reload from racing with the initializer and the rebalancer,
+        // which create coins that are not in the pool yet.
+        if !self
+            .gas_pool_store
+            .acquire_init_lock(COIN_RELOAD_LOCK_DURATION_SEC)
+            .await?
+        {
+            bail!("The gas pool is being initialized or rebalanced, please retry later");
+        }
+        let result = self.reload_coins_impl().await;
+        if let Err(err) = self.gas_pool_store.release_init_lock().await {
+            error!("Failed to release init lock: {:?}", err);
+        }
+        result
+    }
+
+    async fn reload_coins_impl(&self) -> anyhow::Result<CoinReloadResult> {
+        let owned_coins = self
+            .mys_client
+            .get_all_owned_mys_coins_above_balance_threshold(self.signer.get_address(), 0)
+            .await;
+        let scanned_coin_count = owned_coins.len();
+        let candidates = self
+            .gas_pool_store
+            .filter_untracked_coins(self.exclude_coins_in_cooldown(owned_coins))
+            .await?;
+        if candidates.is_empty() {
+            info!("Coin reload found no new coins among {} coins", scanned_coin_count);
+            return Ok(CoinReloadResult {
+                scanned_coin_count,
+                ..Default::default()
+            });
+        }
+        // Coins that are being used in a transaction, or that were just released from an expired
+        // reservation, are briefly tracked nowhere. Only coins that did not change while waiting
+        // and are still untracked afterwards are new.
+        tokio::time::sleep(COIN_RELOAD_GRACE_PERIOD).await;
+        let latest_coins = self
+            .mys_client
+            .get_latest_gas_objects(
+                self.signer.get_address(),
+                candidates.iter().map(|c| c.object_ref.0),
+            )
+            .await;
+        let unchanged_coins = candidates
+            .into_iter()
+            .filter(|coin| {
+                matches!(
+                    latest_coins.get(&coin.object_ref.0),
+                    Some(Some(latest_coin)) if latest_coin.object_ref == coin.object_ref
+                )
+            })
+            .collect();
+        let added_coins = self
+            .gas_pool_store
+            .add_untracked_coins(self.exclude_coins_in_cooldown(unchanged_coins))
+            .await?;
+        let added_balance = added_coins.iter().map(|c| c.balance).sum();
+        info!(
+            "Coin reload added {} coins with total balance {} among {} coins",
+            added_coins.len(),
+            added_balance,
+            scanned_coin_count
+        );
+        self.metrics
+            .num_reloaded_gas_coins
+            .inc_by(added_coins.len() as u64);
+        Ok(CoinReloadResult {
+            scanned_coin_count,
+            added_coin_count: added_coins.len(),
+            added_balance,
+        })
+    }
+
+    pub fn fullnode_circuit_state(&self) -> Option<CircuitState> {
+        self.mys_client.circuit_state()
+    }
+
+    /// False if the signer no longer signs for the sponsor address, as of the last check.
+    pub fn sponsor_address_matches(&self) -> bool {
+        self.sponsor_address_checker.address_matches()
+    }
+
+    /// Stores the tag of a reservation, so that it can be looked up when the reservation is claimed
+    /// or executed. Failing to store it is logged but does not fail the reservation.
+    pub async fn set_reservation_tag(&self, reservation_id: ReservationID, tag: &str) {
+        if let Err(err) = self.gas_pool_store.set_reservation_tag(reservation_id, tag).await {
+            error!(?reservation_id, "Failed to store reservation tag: {:?}", err);
+        }
+    }
+
+    /// Records the client that made the reservation, so that only that client can cancel it.
+    async fn set_reservation_owner(
+        &self,
+        reservation_id: ReservationID,
+        client_id: &str,
+    ) -> anyhow::Result<()> {
+        self.gas_pool_store
+            .set_reservation_owner(reservation_id, client_id)
+            .await
+            .tap_err(|err| error!(?reservation_id, "Failed to store reservation owner: {:?}", err))
+    }
+
+    /// The label under which usage with a reservation tag is counted in the per-tag metrics:
+    /// the tag itself if it is one of the configured metric tags, and `other` otherwise.
+    pub fn metric_tag<'a>(&self, tag: &'a str) -> &'a str {
+        if self.config.metric_tags.iter().any(|metric_tag| metric_tag == tag) {
+            tag
+        } else {
+            OTHER_METRIC_TAG
+        }
+    }
+
+    /// Returns the tag of the reservation, if it has one and it could be read.
+    pub async fn get_reservation_tag(&self, reservation_id: ReservationID) -> Option<String> {
+        self.gas_pool_store
+            .get_reservation_tag(reservation_id)
+            .await
+            .unwrap_or_else(|err| {
+                error!(?reservation_id, "Failed to read reservation tag: {:?}", err);
+                None
+            })
+    }
+
+    /// Records an event of a reservation in the audit log, if it is enabled. This never waits for
+    /// the record to be written.
+    pub fn record_audit_event(
+        &self,
+        client_id: Option<String>,
+        reservation_id: ReservationID,
+        tag: Option<String>,
+        event: AuditEvent,
+    ) {
+        if let Some(audit_log) = &self.audit_log {
+            audit_log.record(AuditRecord {
+                timestamp_ms: Utc::now().timestamp_millis() as u64,
+                client_id,
+                reservation_id,
+                tag,
+                event,
+            });
+        }
+    }
+
+    /// Reads up to `limit` audit records with from_ms <= timestamp_ms < to_ms, oldest first,
+    /// optionally only those of a single client.
+    pub async fn query_audit_log(
+        &self,
+        from_ms: u64,
+        to_ms: u64,
+        client_id: Option<String>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuditRecord>> {
+        let Some(audit_log) = &self.audit_log else {
+            return Err(
+                GasStationError::InvalidRequest("Audit log is not enabled".to_string()).into(),
+            );
+        };
+        audit_log.query(from_ms, to_ms, client_id, limit).await
+    }
+
+    /// The active reservations and reserved budget of each client that holds a reservation, as
+    /// counted by the client budget limits, or only those of a single client.
+    pub async fn get_client_usage(
+        &self,
+        client_id: Option<String>,
+    ) -> anyhow::Result<BTreeMap<String, ClientUsage>> {
+        if self.client_budget_limiter.is_none() {
+            return Err(GasStationError::InvalidRequest(
+                "Client budget limits are not enabled".to_string(),
+            )
+            .into());
+        }
+        match client_id {
+            Some(client_id) => {
+                let usage = self.gas_pool_store.get_client_usage(&client_id).await?;
+                Ok(BTreeMap::from([(client_id, usage)]))
+            }
+            None => self.gas_pool_store.get_all_client_usage().await,
+        }
+    }
+
+    /// Takes a snapshot of the state of the pool for bug reports. The storage is read in a single
+    /// request, and no lock of the gas pool is held while waiting for it.
+    pub async fn get_debug_snapshot(&self) -> anyhow::Result<PoolDebugSnapshot> {
+        let PoolSnapshot {
+            available_coins,
+            reservations,
+            budget_leases,
+        } = self.gas_pool_store.get_pool_snapshot().await?;
+        let mut balances: Vec<_> = available_coins.coins.iter().map(|c| c.balance).collect();
+        balances.sort_unstable();
+        Ok(PoolDebugSnapshot {
+            timestamp_ms: Utc::now().timestamp_millis() as u64,
+            stats: self.get_stats().await,
+            available_coin_balances: CoinBalanceSummary {
+                coin_count: balances.len(),
+                total_balance: balances.iter().sum(),
+                tracked_coin_count: available_coins.tracked_coin_count,
+                tracked_total_balance: available_coins.tracked_total_balance,
+                min_balance: balances.first().copied().unwrap_or_default(),
+                median_balance: balances.get(balances.len() / 2).copied().unwrap_or_default(),
+                max_balance: balances.last().copied().unwrap_or_default(),
+            },
+            reservations,
+            budget_leases,
+            reference_gas_price: self.reference_gas_price.lock().map(|(_, price)| price),
+            background_tasks: BackgroundTaskStatus {
+                coin_rebalance: self.coin_rebalancer.is_some(),
+                coin_balance_audit: self.coin_balance_auditor.is_some(),
+                audit_log_writer: self.audit_log.is_some(),
+                reservation_waitlist: self.reservation_waitlist.is_some(),
+                warm: self.warmup.as_ref().map(|warmup| warmup.is_warm()),
+            },
+        })
+    }
+
+    pub fn signer_type(&self) -> &'static str {
+        self.signer.signer_type()
+    }
+
+    pub fn storage_backend(&self) -> &'static str {
+        self.gas_pool_store.backend_name()
+    }
 }
 
 impl GasPoolContainer {
@@ -364,6 +2489,7 @@ impl GasPoolContainer {
         mys_client: MysClient,
         gas_usage_daily_cap: u64,
         metrics: Arc<GasPoolCoreMetrics>,
+        config: GasPoolCoreConfig,
     ) -> Self {
         let inner = GasPool::new(
             signer,
@@ -371,15 +2497,82 @@ impl GasPoolContainer {
             mys_client,
             metrics,
             Arc::new(GasUsageCap::new(gas_usage_daily_cap)),
+            config,
         )
         .await;
         let (cancel_sender, cancel_receiver) = tokio::sync::oneshot::channel();
         let _coin_unlocker_task = inner.clone().start_coin_unlock_task(cancel_receiver).await;
+        let (_coin_rebalance_task, rebalance_cancel_sender) = match &inner.coin_rebalancer {
+            Some(rebalancer) => {
+                let (sender, receiver) = tokio::sync::oneshot::channel();
+                (
+                    Some(rebalancer.clone().start_rebalance_task(receiver)),
+                    Some(sender),
+                )
+            }
+            None => (None, None),
+        };
+        let (_coin_balance_audit_task, balance_audit_cancel_sender) =
+            match &inner.coin_balance_auditor {
+                Some(auditor) => {
+                    let (sender, receiver) = tokio::sync::oneshot::channel();
+                    (Some(auditor.clone().start_audit_task(receiver)), Some(sender))
+                }
+                None => (None, None),
+            };
+        let (sponsor_address_check_cancel_sender, receiver) = tokio::sync::oneshot::channel();
+        let _sponsor_address_check_task = inner
+            .sponsor_address_checker
+            .clone()
+            .start_check_task(receiver);
+        let (_audit_log_writer_task, audit_log_cancel_sender) = match &inner.audit_log {
+            Some(audit_log) => {
+                let (sender, receiver) = tokio::sync::oneshot::channel();
+                (
+                    Some(audit_log.clone().start_writer_task(receiver)),
+                    Some(sender),
+                )
+            }
+            None => (None, None),
+        };
+        let (_warmup_task, warmup_cancel_sender) = match &inner.warmup {
+            Some(warmup) => {
+                let (sender, receiver) = tokio::sync::oneshot::channel();
+                (
+                    Some(warmup.clone().start_readiness_task(receiver)),
+                    Some(sender),
+                )
+            }
+            None => (None, None),
+        };
+        let (_reservation_waitlist_task, reservation_waitlist_cancel_sender) =
+            match &inner.reservation_waitlist {
+                Some(_) => {
+                    let (sender, receiver) = tokio::sync::oneshot::channel();
+                    (
+                        Some(inner.clone().start_reservation_waitlist_task(receiver)),
+                        Some(sender),
+                    )
+                }
+                None => (None, None),
+            };
 
         Self {
             inner,
             _coin_unlocker_task,
             cancel_sender: Some(cancel_sender),
+            _coin_rebalance_task,
+            rebalance_cancel_sender,
+            _coin_balance_audit_task,
+            balance_audit_cancel_sender,
+            _sponsor_address_check_task,
+            sponsor_address_check_cancel_sender: Some(sponsor_address_check_cancel_sender),
+            _audit_log_writer_task,
+            audit_log_cancel_sender,
+            _warmup_task,
+            warmup_cancel_sender,
+            _reservation_waitlist_task,
+            reservation_waitlist_cancel_sender,
         }
     }
 
@@ -393,5 +2586,23 @@ impl Drop for GasPoolContainer {
         if let Some(sender) = self.cancel_sender.take() {
             let _ = sender.send(()); // Ignore send errors during shutdown
         }
+        if let Some(sender) = self.rebalance_cancel_sender.take() {
+            let _ = sender.send(());
+        }
+        if let Some(sender) = self.balance_audit_cancel_sender.take() {
+            let _ = sender.send(());
+        }
+        if let Some(sender) = self.sponsor_address_check_cancel_sender.take() {
+            let _ = sender.send(());
+        }
+        if let Some(sender) = self.audit_log_cancel_sender.take() {
+            let _ = sender.send(());
+        }
+        if let Some(sender) = self.warmup_cancel_sender.take() {
+            let _ = sender.send(());
+        }
+        if let Some(sender) = self.reservation_waitlist_cancel_sender.take() {
+            let _ = sender.send(());
+        }
     }
 }
@@ -2,20 +2,49 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod audit_log;
+mod client_budget_limit;
+pub mod coin_balance_audit;
+mod coin_quarantine;
+pub mod coin_rebalancer;
+pub mod epoch_spend_cap;
 pub mod gas_pool_core;
 mod gas_usage_cap;
+mod move_call_rate_limit;
+mod reservation_queue;
+pub mod reservation_waitlist;
+pub mod sponsor_address_check;
+pub mod warmup;
 
 #[cfg(test)]
 mod tests {
-    use crate::test_env::{create_test_transaction, start_gas_station};
+    use crate::config::{
+        ClientBudgetLimits, ClientBudgetLimitsConfig, CoinQuarantineConfig, GasPoolCoreConfig,
+        ReservationCallbackConfig, ReservationPriorityConfig, ReservationWaitlistConfig,
+    };
+    use crate::errors::GasStationError;
+    use crate::gas_pool::gas_pool_core::OTHER_METRIC_TAG;
+    use crate::reservation_callback::{
+        ReservationEvent, ReservationOutcome, WaitingReservationEvent, WaitingReservationOutcome,
+    };
+    use crate::test_env::{
+        create_test_transaction, start_gas_station, start_gas_station_with_config,
+    };
+    use crate::tx_signer::{TestTxSigner, TxSigner};
+    use crate::types::ReservationReceipt;
     use shared_crypto::intent::{Intent, IntentMessage};
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
     use mys_json_rpc_types::MysTransactionBlockEffectsAPI;
     use mys_types::{
+        base_types::SequenceNumber,
+        coin::{PAY_MODULE_NAME, PAY_SPLIT_N_FUNC_NAME},
         crypto::{get_account_key_pair, Signature},
-        gas_coin::MIST_PER_MYS,
+        effects::{TransactionEffects, TransactionEffectsAPI},
+        gas_coin::{GAS, MIST_PER_MYS},
+        object::Owner,
         programmable_transaction_builder::ProgrammableTransactionBuilder,
-        transaction::{TransactionData, TransactionKind},
+        transaction::{ObjectArg, TransactionData, TransactionKind},
+        MYS_FRAMEWORK_PACKAGE_ID,
     };
 
     #[tokio::test]
@@ -63,19 +92,335 @@ mod tests {
             .is_err());
 
         let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
-        let effects = station
+        let (effects, signed_effects) = station
             .execute_transaction(reservation_id, tx_data, user_sig)
             .await
             .unwrap();
         assert!(effects.status().is_ok());
+        let signed_effects = signed_effects.unwrap();
+        let raw_effects: TransactionEffects =
+            bcs::from_bytes(&signed_effects.raw_effects.to_vec().unwrap()).unwrap();
+        assert_eq!(raw_effects.transaction_digest(), effects.transaction_digest());
         assert_eq!(station.query_pool_available_coin_count().await, 1);
     }
 
+    #[tokio::test]
+    async fn test_coin_cooldown_after_execution() {
+        let (test_cluster, container) = start_gas_station_with_config(
+            vec![MIST_PER_MYS],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                coin_cooldown_ms: 3000,
+                ..Default::default()
+            },
+        )
+        .await;
+        let station = container.get_gas_pool_arc();
+        let (sponsor, reservation_id, gas_coins) = station
+            .reserve_gas(MIST_PER_MYS, Duration::from_secs(10))
+            .await
+            .unwrap();
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+        station
+            .execute_transaction(reservation_id, tx_data, user_sig)
+            .await
+            .unwrap();
+        // The coin is held back during the cooldown.
+        assert_eq!(station.query_pool_available_coin_count().await, 0);
+        assert_eq!(station.get_stats().await.coins_in_cooldown, 1);
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        assert_eq!(station.query_pool_available_coin_count().await, 1);
+        assert_eq!(station.get_stats().await.coins_in_cooldown, 0);
+    }
+
+    #[tokio::test]
+    async fn test_retire_low_balance_coins() {
+        let (test_cluster, container) = start_gas_station_with_config(
+            vec![MIST_PER_MYS; 2],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                coin_cooldown_ms: 0,
+                retire_coin_balance_threshold: MIST_PER_MYS,
+                ..Default::default()
+            },
+        )
+        .await;
+        let station = container.get_gas_pool_arc();
+        let (sponsor, reservation_id, gas_coins) = station
+            .reserve_gas(MIST_PER_MYS, Duration::from_secs(10))
+            .await
+            .unwrap();
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+        let (effects, _) = station
+            .execute_transaction(reservation_id, tx_data, user_sig)
+            .await
+            .unwrap();
+        assert!(effects.status().is_ok());
+        // The coin paid for gas, so its balance crossed the threshold and it is not returned.
+        assert_eq!(station.query_pool_available_coin_count().await, 1);
+        assert_eq!(
+            station.get_stats().await.available_coin_total_balance,
+            MIST_PER_MYS
+        );
+    }
+
+    #[tokio::test]
+    async fn test_per_epoch_spend_cap() {
+        let (test_cluster, container) = start_gas_station_with_config(
+            vec![MIST_PER_MYS; 2],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                coin_cooldown_ms: 0,
+                per_epoch_spend_cap: Some(1),
+                ..Default::default()
+            },
+        )
+        .await;
+        let station = container.get_gas_pool_arc();
+        let (sponsor, reservation_id, gas_coins) = station
+            .reserve_gas(MIST_PER_MYS, Duration::from_secs(10))
+            .await
+            .unwrap();
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+        station
+            .execute_transaction(reservation_id, tx_data, user_sig)
+            .await
+            .unwrap();
+
+        let epoch_spend = station.get_stats().await.epoch_spend.unwrap();
+        assert!(epoch_spend.spent > 0);
+        assert_eq!(epoch_spend.remaining_budget, 0);
+        let err = station
+            .reserve_gas(MIST_PER_MYS, Duration::from_secs(10))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::BudgetCapReached)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reservation_priority() {
+        let (_test_cluster, container) = start_gas_station_with_config(
+            vec![MIST_PER_MYS],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                reservation_priority_config: Some(ReservationPriorityConfig {
+                    client_max_priorities: [("critical".to_string(), 5)].into_iter().collect(),
+                    max_wait_ms: 4000,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )
+        .await;
+        let station = container.get_gas_pool_arc();
+        // Take the only coin, and let it expire while the other reservations wait.
+        station
+            .reserve_gas(MIST_PER_MYS, Duration::from_secs(1))
+            .await
+            .unwrap();
+
+        let low_station = station.clone();
+        let low = tokio::spawn(async move {
+            low_station
+                .reserve_gas_with_priority(
+                    MIST_PER_MYS,
+                    Duration::from_secs(10),
+                    None,
+                    Some("batch"),
+                    Some(5),
+                )
+                .await
+        });
+        // Make sure the low priority reservation is waiting first.
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let (_, _, gas_coins) = station
+            .reserve_gas_with_priority(
+                MIST_PER_MYS,
+                Duration::from_secs(10),
+                None,
+                Some("critical"),
+                Some(5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(gas_coins.len(), 1);
+        // The coin went to the critical client, so the other reservation eventually gives up.
+        let err = low.await.unwrap().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::InsufficientGasCoins)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reservation_callbacks() {
+        let (event_sender, mut event_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let app = axum::Router::new().route(
+            "/callback",
+            axum::routing::post(move |axum::Json(event): axum::Json<ReservationEvent>| {
+                let event_sender = event_sender.clone();
+                async move { event_sender.send(event).unwrap() }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let callback_url = format!("http://{}/callback", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (test_cluster, container) = start_gas_station_with_config(
+            vec![MIST_PER_MYS; 2],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                coin_cooldown_ms: 0,
+                reservation_callback_config: ReservationCallbackConfig {
+                    allowed_schemes: vec!["http".to_string()],
+                    allow_private_hosts: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await;
+        let station = container.get_gas_pool_arc();
+        assert!(station
+            .reserve_gas_with_callback(
+                MIST_PER_MYS,
+                Duration::from_secs(10),
+                Some("ftp://example.com".to_string())
+            )
+            .await
+            .is_err());
+
+        let (sponsor, reservation_id, gas_coins) = station
+            .reserve_gas_with_callback(
+                MIST_PER_MYS,
+                Duration::from_secs(10),
+                Some(callback_url.clone()),
+            )
+            .await
+            .unwrap();
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+        let (effects, _) = station
+            .execute_transaction(reservation_id, tx_data, user_sig)
+            .await
+            .unwrap();
+        assert_eq!(
+            event_receiver.recv().await.unwrap(),
+            ReservationEvent {
+                reservation_id,
+                outcome: ReservationOutcome::Consumed,
+                digest: Some(*effects.transaction_digest()),
+            }
+        );
+
+        let (_, reservation_id, _) = station
+            .reserve_gas_with_callback(MIST_PER_MYS, Duration::from_secs(1), Some(callback_url))
+            .await
+            .unwrap();
+        assert_eq!(
+            event_receiver.recv().await.unwrap(),
+            ReservationEvent {
+                reservation_id,
+                outcome: ReservationOutcome::Expired,
+                digest: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reservation_waitlist() {
+        let (event_sender, mut event_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let app = axum::Router::new().route(
+            "/callback",
+            axum::routing::post(move |axum::Json(event): axum::Json<WaitingReservationEvent>| {
+                let event_sender = event_sender.clone();
+                async move { event_sender.send(event).unwrap() }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let callback_url = format!("http://{}/callback", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (_test_cluster, container) = start_gas_station_with_config(
+            vec![MIST_PER_MYS],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                coin_cooldown_ms: 0,
+                reservation_callback_config: ReservationCallbackConfig {
+                    allowed_schemes: vec!["http".to_string()],
+                    allow_private_hosts: true,
+                    ..Default::default()
+                },
+                reservation_waitlist_config: Some(ReservationWaitlistConfig {
+                    max_waiting_reservations: 1,
+                    retry_interval_ms: 100,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )
+        .await;
+        let station = container.get_gas_pool_arc();
+        let (_, reservation_id, _) = station
+            .reserve_gas(MIST_PER_MYS, Duration::from_secs(10))
+            .await
+            .unwrap();
+        let ticket = station
+            .add_waiting_reservation(
+                MIST_PER_MYS,
+                Duration::from_secs(10),
+                callback_url.clone(),
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        // The waitlist is full.
+        assert!(station
+            .add_waiting_reservation(
+                MIST_PER_MYS,
+                Duration::from_secs(10),
+                callback_url.clone(),
+                None,
+                None,
+                None,
+            )
+            .is_err());
+        assert_eq!(station.get_stats().await.waiting_reservations, Some(1));
+
+        // The waiting reservation gets the coin once it is released.
+        station.cancel_reservation(reservation_id).await.unwrap();
+        let event = event_receiver.recv().await.unwrap();
+        assert_eq!(event.wait_id, ticket.wait_id);
+        assert_eq!(event.outcome, WaitingReservationOutcome::Reserved);
+        assert_eq!(event.gas_coins.len(), 1);
+        assert_eq!(station.query_pool_available_coin_count().await, 0);
+
+        // A reservation that does not get coins in time expires.
+        let ticket = station
+            .add_waiting_reservation(
+                MIST_PER_MYS,
+                Duration::from_secs(1),
+                callback_url,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let event = event_receiver.recv().await.unwrap();
+        assert_eq!(event.wait_id, ticket.wait_id);
+        assert_eq!(event.outcome, WaitingReservationOutcome::Expired);
+        assert!(event.reservation_id.is_none());
+    }
+
     #[tokio::test]
     async fn test_invalid_transaction() {
         telemetry_subscribers::init_for_testing();
-        let (_test_cluster, container) = start_gas_station(vec![MIST_PER_MYS], MIST_PER_MYS).await;
+        let (test_cluster, container) = start_gas_station(vec![MIST_PER_MYS], MIST_PER_MYS).await;
         let station = container.get_gas_pool_arc();
+        let rgp = test_cluster.get_reference_gas_price().await;
         let (sponsor, reservation_id, gas_coins) = station
             .reserve_gas(MIST_PER_MYS, Duration::from_secs(10))
             .await
@@ -83,7 +428,7 @@ mod tests {
         let (sender, keypair) = get_account_key_pair();
         let tx_kind = TransactionKind::programmable(ProgrammableTransactionBuilder::new().finish());
         let tx_data = TransactionData::new_with_gas_coins_allow_sponsor(
-            tx_kind, sender, gas_coins, 1, 1, sponsor,
+            tx_kind, sender, gas_coins, 1, rgp, sponsor,
         );
         let user_sig = Signature::new_secure(
             &IntentMessage::new(Intent::mys_transaction(), &tx_data),
@@ -97,6 +442,384 @@ mod tests {
         assert_eq!(station.query_pool_available_coin_count().await, 1);
     }
 
+    #[tokio::test]
+    async fn test_unsupported_transaction_kind() {
+        let (_test_cluster, container) = start_gas_station(vec![MIST_PER_MYS], MIST_PER_MYS).await;
+        let station = container.get_gas_pool_arc();
+        let (sponsor, reservation_id, gas_coins) = station
+            .reserve_gas(MIST_PER_MYS, Duration::from_secs(10))
+            .await
+            .unwrap();
+        let (sender, keypair) = get_account_key_pair();
+        let tx_kind = TransactionKind::EndOfEpochTransaction(vec![]);
+        let tx_data = TransactionData::new_with_gas_coins_allow_sponsor(
+            tx_kind, sender, gas_coins, 1, 1, sponsor,
+        );
+        let user_sig = Signature::new_secure(
+            &IntentMessage::new(Intent::mys_transaction(), &tx_data),
+            &keypair,
+        );
+        let err = station
+            .execute_transaction(reservation_id, tx_data, user_sig.into())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::UnsupportedTransactionKind(_))
+        ));
+        // The transaction is rejected before the reservation is consumed.
+        assert_eq!(station.query_pool_available_coin_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_sponsored_transaction() {
+        let (test_cluster, container) = start_gas_station(vec![MIST_PER_MYS], MIST_PER_MYS).await;
+        let station = container.get_gas_pool_arc();
+        let rgp = test_cluster.get_reference_gas_price().await;
+        let (sender, keypair) = get_account_key_pair();
+        let user_signer = TestTxSigner::new(keypair.into());
+        let tx_kind = TransactionKind::programmable(ProgrammableTransactionBuilder::new().finish());
+        let gas_budget = MIST_PER_MYS / 10;
+        let (sponsor, reservation_id, gas_coins) = station
+            .reserve_gas(gas_budget, Duration::from_secs(10))
+            .await
+            .unwrap();
+
+        // The gas owner must be the sponsor.
+        let tx_data = TransactionData::new_with_gas_coins_allow_sponsor(
+            tx_kind.clone(),
+            sender,
+            gas_coins.clone(),
+            gas_budget,
+            rgp,
+            sender,
+        );
+        let user_sig = user_signer.sign_transaction(&tx_data).await.unwrap();
+        let err = station
+            .execute_transaction(reservation_id, tx_data, user_sig)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::InvalidSponsoredTransaction(_))
+        ));
+
+        // The sender must not be the sponsor.
+        let tx_data = TransactionData::new_with_gas_coins(
+            tx_kind.clone(),
+            sponsor,
+            gas_coins.clone(),
+            gas_budget,
+            rgp,
+        );
+        let user_sig = user_signer.sign_transaction(&tx_data).await.unwrap();
+        let err = station
+            .execute_transaction(reservation_id, tx_data, user_sig)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::InvalidSponsoredTransaction(_))
+        ));
+
+        // Rejected transactions do not consume the reservation.
+        let tx_data = TransactionData::new_with_gas_coins_allow_sponsor(
+            tx_kind, sender, gas_coins, gas_budget, rgp, sponsor,
+        );
+        let user_sig = user_signer.sign_transaction(&tx_data).await.unwrap();
+        let (effects, _) = station
+            .execute_transaction(reservation_id, tx_data, user_sig)
+            .await
+            .unwrap();
+        assert!(effects.status().is_ok());
+        assert_eq!(effects.gas_object().owner, Owner::AddressOwner(sponsor));
+        assert_eq!(station.query_pool_available_coin_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_preflight() {
+        let (test_cluster, container) = start_gas_station(vec![MIST_PER_MYS], MIST_PER_MYS).await;
+        let station = container.get_gas_pool_arc();
+        let rgp = test_cluster.get_reference_gas_price().await;
+        let gas_budget = MIST_PER_MYS / 10;
+        let (sponsor, reservation_id, gas_coins) = station
+            .reserve_gas(gas_budget, Duration::from_secs(10))
+            .await
+            .unwrap();
+        let user = test_cluster
+            .get_addresses()
+            .into_iter()
+            .find(|a| *a != sponsor)
+            .unwrap();
+        let user_coin = test_cluster
+            .wallet
+            .get_one_gas_object_owned_by_address(user)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Splitting a coin into more coins than its balance aborts.
+        let mut builder = ProgrammableTransactionBuilder::new();
+        let coin_arg = builder
+            .obj(ObjectArg::ImmOrOwnedObject(user_coin))
+            .unwrap();
+        let count_arg = builder.pure(u64::MAX).unwrap();
+        builder.programmable_move_call(
+            MYS_FRAMEWORK_PACKAGE_ID,
+            PAY_MODULE_NAME.into(),
+            PAY_SPLIT_N_FUNC_NAME.into(),
+            vec![GAS::type_tag()],
+            vec![coin_arg, count_arg],
+        );
+        let tx_data = TransactionData::new_with_gas_coins_allow_sponsor(
+            TransactionKind::programmable(builder.finish()),
+            user,
+            gas_coins.clone(),
+            gas_budget,
+            rgp,
+            sponsor,
+        );
+        let user_sig = test_cluster
+            .sign_transaction(&tx_data)
+            .into_data()
+            .tx_signatures_mut_for_testing()
+            .pop()
+            .unwrap();
+        let err = station
+            .execute_transaction_with_preflight(reservation_id, tx_data, user_sig, true)
+            .await
+            .unwrap_err();
+        match err.downcast_ref::<GasStationError>() {
+            Some(GasStationError::PreflightFailed(error)) => {
+                assert!(error.starts_with("abort code"), "{}", error)
+            }
+            _ => panic!("Unexpected error: {:?}", err),
+        }
+        // The reservation is not consumed, and the sponsor did not pay for anything.
+        assert_eq!(station.query_pool_available_coin_count().await, 0);
+
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+        let (effects, _) = station
+            .execute_transaction_with_preflight(reservation_id, tx_data, user_sig, true)
+            .await
+            .unwrap();
+        assert!(effects.status().is_ok());
+        assert_eq!(station.query_pool_available_coin_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_execute_deadline() {
+        let (test_cluster, container) = start_gas_station(vec![MIST_PER_MYS], MIST_PER_MYS).await;
+        let station = container.get_gas_pool_arc();
+        let (sponsor, reservation_id, gas_coins) = station
+            .reserve_gas(MIST_PER_MYS, Duration::from_secs(10))
+            .await
+            .unwrap();
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+
+        // The deadline passes while the gas coins are looked up on the fullnode.
+        let err = station
+            .execute_transaction_with_deadline(
+                reservation_id,
+                tx_data.clone(),
+                user_sig.clone(),
+                false,
+                Some(Instant::now()),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::RequestTimeout)
+        ));
+        // The coins are still reserved, so the transaction can be executed again.
+        assert_eq!(station.query_pool_available_coin_count().await, 0);
+        let (effects, _) = station
+            .execute_transaction_with_deadline(
+                reservation_id,
+                tx_data,
+                user_sig,
+                false,
+                Some(Instant::now() + Duration::from_secs(30)),
+            )
+            .await
+            .unwrap();
+        assert!(effects.status().is_ok());
+        assert_eq!(station.query_pool_available_coin_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_gas_price_above_reference() {
+        let (test_cluster, container) = start_gas_station(vec![MIST_PER_MYS], MIST_PER_MYS).await;
+        let station = container.get_gas_pool_arc();
+        let rgp = test_cluster.get_reference_gas_price().await;
+        let max_gas_price = rgp * GasPoolCoreConfig::default().max_gas_price_multiplier;
+        let (sender, keypair) = get_account_key_pair();
+        let user_signer = TestTxSigner::new(keypair.into());
+        let tx_kind = TransactionKind::programmable(ProgrammableTransactionBuilder::new().finish());
+        let gas_budget = MIST_PER_MYS / 10;
+        let (sponsor, reservation_id, gas_coins) = station
+            .reserve_gas(gas_budget, Duration::from_secs(10))
+            .await
+            .unwrap();
+
+        // Gas prices outside of the allowed range, or budgets the reserved coins cannot cover,
+        // are rejected without consuming the reservation.
+        for (price, budget) in [
+            (rgp - 1, gas_budget),
+            (max_gas_price + 1, gas_budget),
+            (max_gas_price, MIST_PER_MYS + 1),
+        ] {
+            let tx_data = TransactionData::new_with_gas_coins_allow_sponsor(
+                tx_kind.clone(),
+                sender,
+                gas_coins.clone(),
+                budget,
+                price,
+                sponsor,
+            );
+            let user_sig = user_signer.sign_transaction(&tx_data).await.unwrap();
+            let err = station
+                .execute_transaction(reservation_id, tx_data, user_sig)
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<GasStationError>(),
+                Some(GasStationError::InvalidSponsoredTransaction(_))
+            ));
+        }
+
+        let tx_data = TransactionData::new_with_gas_coins_allow_sponsor(
+            tx_kind,
+            sender,
+            gas_coins,
+            gas_budget,
+            max_gas_price,
+            sponsor,
+        );
+        let user_sig = user_signer.sign_transaction(&tx_data).await.unwrap();
+        let (effects, _) = station
+            .execute_transaction(reservation_id, tx_data, user_sig)
+            .await
+            .unwrap();
+        assert!(effects.status().is_ok());
+        // The sponsor pays for the higher gas price.
+        assert_eq!(effects.gas_cost_summary().computation_cost % max_gas_price, 0);
+    }
+
+    #[tokio::test]
+    async fn test_reservation_receipt() {
+        let (_test_cluster, container) = start_gas_station_with_config(
+            vec![MIST_PER_MYS],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                sign_reservation_receipts: true,
+                ..Default::default()
+            },
+        )
+        .await;
+        let station = container.get_gas_pool_arc();
+        let (sponsor, reservation_id, gas_coins) = station
+            .reserve_gas(MIST_PER_MYS, Duration::from_secs(10))
+            .await
+            .unwrap();
+        let signed_receipt = station
+            .sign_reservation_receipt(
+                reservation_id,
+                &gas_coins,
+                MIST_PER_MYS,
+                Duration::from_secs(10),
+            )
+            .await
+            .unwrap();
+        let receipt: ReservationReceipt =
+            bcs::from_bytes(&signed_receipt.raw_receipt.to_vec().unwrap()).unwrap();
+        assert_eq!(receipt.reservation_id, reservation_id);
+        assert_eq!(receipt.sponsor_address, sponsor);
+        assert_eq!(receipt.gas_coins, gas_coins);
+        assert_eq!(receipt.budget, MIST_PER_MYS);
+        assert!(!signed_receipt.signature.to_vec().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_coin_freshness_check() {
+        let (test_cluster, container) = start_gas_station_with_config(
+            vec![MIST_PER_MYS],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                check_coin_freshness_before_execute: true,
+                ..Default::default()
+            },
+        )
+        .await;
+        let station = container.get_gas_pool_arc();
+        let (sponsor, reservation_id, gas_coins) = station
+            .reserve_gas(MIST_PER_MYS, Duration::from_secs(10))
+            .await
+            .unwrap();
+
+        // A gas coin at another version than the one on chain is rejected before execution.
+        let (id, version, digest) = gas_coins[0];
+        let stale_coins = vec![(id, version.next(), digest)];
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, stale_coins).await;
+        let err = station
+            .execute_transaction(reservation_id, tx_data, user_sig)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::StaleGasCoins(_))
+        ));
+
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+        let (effects, _) = station
+            .execute_transaction(reservation_id, tx_data, user_sig)
+            .await
+            .unwrap();
+        assert!(effects.status().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_coin_quarantine() {
+        let (test_cluster, container) = start_gas_station_with_config(
+            vec![MIST_PER_MYS],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                coin_cooldown_ms: 0,
+                coin_quarantine_config: Some(CoinQuarantineConfig {
+                    max_version_mismatches: 1,
+                    window_sec: 60,
+                    quarantine_sec: 3,
+                }),
+                ..Default::default()
+            },
+        )
+        .await;
+        let station = container.get_gas_pool_arc();
+        let (sponsor, reservation_id, gas_coins) = station
+            .reserve_gas(MIST_PER_MYS, Duration::from_secs(10))
+            .await
+            .unwrap();
+
+        // Paying with an older version of the coin looks like the coin was used outside of the
+        // gas pool since it was reserved.
+        let (id, version, digest) = gas_coins[0];
+        let outdated_coins = vec![(id, SequenceNumber::from_u64(version.value() - 1), digest)];
+        let (tx_data, user_sig) =
+            create_test_transaction(&test_cluster, sponsor, outdated_coins).await;
+        assert!(station
+            .execute_transaction(reservation_id, tx_data, user_sig)
+            .await
+            .is_err());
+
+        // The coin is held back during the quarantine.
+        assert_eq!(station.get_stats().await.coins_in_quarantine, 1);
+        assert_eq!(station.query_pool_available_coin_count().await, 0);
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        assert_eq!(station.get_stats().await.coins_in_quarantine, 0);
+        assert_eq!(station.query_pool_available_coin_count().await, 1);
+    }
+
     #[tokio::test]
     async fn test_coin_expiration() {
         telemetry_subscribers::init_for_testing();
@@ -126,7 +849,6 @@ mod tests {
             .unwrap();
     }
 
-    #[ignore]
     #[tokio::test]
     async fn test_incomplete_gas_usage() {
         let (test_cluster, container) =
@@ -137,27 +859,143 @@ mod tests {
             .await
             .unwrap();
         assert_eq!(gas_coins.len(), 3);
+        assert_eq!(station.query_pool_available_coin_count().await, 7);
 
         // Remove one gas object from the reserved list and only use the two.
         let mut incomplete_gas_coins = gas_coins.clone();
         incomplete_gas_coins.pop().unwrap();
         let (tx_data, user_sig) =
             create_test_transaction(&test_cluster, sponsor, incomplete_gas_coins).await;
-        // It should fail because it's inconsistent with the reservation.
-        assert!(station
+        let (effects, _) = station
             .execute_transaction(reservation_id, tx_data, user_sig)
             .await
-            .is_err());
+            .unwrap();
+        assert!(effects.status().is_ok());
+        // The unused coin goes back to the pool along with the payment.
+        assert_eq!(station.query_pool_available_coin_count().await, 10);
+    }
 
-        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
-        let effects = station
+    #[tokio::test]
+    async fn test_candidate_gas_coins() {
+        let (test_cluster, container) =
+            start_gas_station(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let station = container.get_gas_pool_arc();
+        let (sponsor, reservation_id, candidate_coins) = station
+            .reserve_candidate_gas(MIST_PER_MYS * 2, Duration::from_secs(10), None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(candidate_coins.len(), 4);
+        assert!(candidate_coins.iter().all(|coin| coin.balance == MIST_PER_MYS));
+        assert_eq!(station.query_pool_available_coin_count().await, 6);
+
+        // Pay with the last two candidates only.
+        let payment: Vec<_> = candidate_coins[2..]
+            .iter()
+            .map(|coin| coin.object_ref)
+            .collect();
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, payment).await;
+        let (effects, _) = station
             .execute_transaction(reservation_id, tx_data, user_sig)
             .await
             .unwrap();
         assert!(effects.status().is_ok());
+        assert_eq!(station.query_pool_available_coin_count().await, 10);
+    }
+
+    #[tokio::test]
+    async fn test_candidate_gas_client_budget_limits() {
+        let (_test_cluster, container) = start_gas_station_with_config(
+            vec![MIST_PER_MYS; 10],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                client_budget_limits_config: Some(ClientBudgetLimitsConfig {
+                    default_limits: ClientBudgetLimits {
+                        max_reservation_budget: Some(MIST_PER_MYS * 2),
+                        max_outstanding_budget: Some(MIST_PER_MYS * 3),
+                        max_active_reservations: None,
+                    },
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )
+        .await;
+        let station = container.get_gas_pool_arc();
+        // Only the budget counts against the limits, not the extra candidates.
+        let (_, _, candidate_coins) = station
+            .reserve_candidate_gas(
+                MIST_PER_MYS * 2,
+                Duration::from_secs(10),
+                None,
+                Some("client"),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(candidate_coins.len(), 4);
+        station
+            .reserve_gas_with_priority(
+                MIST_PER_MYS,
+                Duration::from_secs(10),
+                None,
+                Some("client"),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(station
+            .reserve_gas_with_priority(
+                MIST_PER_MYS,
+                Duration::from_secs(10),
+                None,
+                Some("client"),
+                None,
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_budget_lease_client_budget_limits() {
+        let (_test_cluster, container) = start_gas_station_with_config(
+            vec![MIST_PER_MYS; 10],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                client_budget_limits_config: Some(ClientBudgetLimitsConfig {
+                    default_limits: ClientBudgetLimits {
+                        max_active_reservations: Some(1),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )
+        .await;
+        let station = container.get_gas_pool_arc();
+        let (_, lease_id) = station
+            .reserve_budget_lease(MIST_PER_MYS, Duration::from_secs(10), None, Some("client"))
+            .await
+            .unwrap();
+        let reserve_gas = || {
+            station.reserve_gas_with_priority(
+                MIST_PER_MYS,
+                Duration::from_secs(10),
+                None,
+                Some("client"),
+                None,
+            )
+        };
+        // The lease takes up the only active reservation of the client.
+        assert!(reserve_gas().await.is_err());
+        assert!(station
+            .reserve_budget_lease(MIST_PER_MYS, Duration::from_secs(10), None, Some("client"))
+            .await
+            .is_err());
+        station.cancel_reservation(lease_id).await.unwrap();
+        reserve_gas().await.unwrap();
     }
 
-    #[ignore]
     #[tokio::test]
     async fn test_mixed_up_gas_coins() {
         let (test_cluster, container) =
@@ -185,10 +1023,28 @@ mod tests {
             .is_err());
 
         let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins1).await;
-        let effects = station
+        let (effects, _) = station
             .execute_transaction(reservation_id1, tx_data, user_sig)
             .await
             .unwrap();
         assert!(effects.status().is_ok());
     }
+
+    #[tokio::test]
+    async fn test_metric_tag() {
+        let (_test_cluster, container) = start_gas_station_with_config(
+            vec![MIST_PER_MYS],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                metric_tags: vec!["team-a:checkout".to_string()],
+                ..Default::default()
+            },
+        )
+        .await;
+        let station = container.get_gas_pool_arc();
+        assert_eq!(station.metric_tag("team-a:checkout"), "team-a:checkout");
+        // Tags that are not configured share a single label.
+        assert_eq!(station.metric_tag("team-b"), OTHER_METRIC_TAG);
+        assert_eq!(station.metric_tag("team-c"), OTHER_METRIC_TAG);
+    }
 }
@@ -0,0 +1,177 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::MoveCallRateLimitConfig;
+use crate::errors::GasStationError;
+use crate::metrics::GasPoolCoreMetrics;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use mys_types::transaction::{Command, ProgrammableMoveCall, TransactionData, TransactionDataAPI};
+
+/// Limits how many sponsored move calls each client can make to a package, module or function
+/// within a fixed time window, e.g. to cap the number of mints per client per hour. Calls are
+/// counted when the transaction is checked, whether or not it is executed successfully after.
+/// Counts are kept in memory, so each gas pool instance enforces the limits on its own.
+pub struct MoveCallRateLimiter {
+    limits: Vec<MoveCallRateLimitConfig>,
+    metrics: Arc<GasPoolCoreMetrics>,
+    /// The start of the current window and the number of calls made in it, by limit index and
+    /// client id.
+    windows: Mutex<HashMap<(usize, String), (Instant, u64)>>,
+}
+
+impl MoveCallRateLimiter {
+    pub fn new(limits: Vec<MoveCallRateLimitConfig>, metrics: Arc<GasPoolCoreMetrics>) -> Self {
+        Self {
+            limits,
+            metrics,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts the move calls of the transaction against the limits of the client. Fails with
+    /// `GasStationError::PolicyRateLimited` without counting any call if a limit would be
+    /// exceeded.
+    pub fn check_calls(
+        &self,
+        client_id: &str,
+        tx_data: &TransactionData,
+    ) -> Result<(), GasStationError> {
+        let calls: Vec<_> = tx_data
+            .kind()
+            .iter_commands()
+            .filter_map(|command| match command {
+                Command::MoveCall(call) => Some(call),
+                _ => None,
+            })
+            .collect();
+        let call_counts: Vec<_> = self
+            .limits
+            .iter()
+            .enumerate()
+            .filter_map(|(index, limit)| {
+                let count = calls.iter().filter(|call| covers(limit, call)).count() as u64;
+                (count > 0).then_some((index, count))
+            })
+            .collect();
+        if call_counts.is_empty() {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut windows = self.windows.lock();
+        windows.retain(|(index, _), (window_start, _)| {
+            now.duration_since(*window_start) < window(&self.limits[*index])
+        });
+        for (index, count) in &call_counts {
+            let limit = &self.limits[*index];
+            let used = windows
+                .get(&(*index, client_id.to_string()))
+                .map_or(0, |(_, used)| *used);
+            if used + count > limit.max_calls {
+                self.metrics.num_move_call_rate_limited_transactions.inc();
+                return Err(GasStationError::PolicyRateLimited(format!(
+                    "client {} can make at most {} calls to {} every {} seconds",
+                    client_id,
+                    limit.max_calls,
+                    target(limit),
+                    limit.window_sec
+                )));
+            }
+        }
+        for (index, count) in call_counts {
+            windows
+                .entry((index, client_id.to_string()))
+                .or_insert((now, 0))
+                .1 += count;
+        }
+        Ok(())
+    }
+}
+
+fn covers(limit: &MoveCallRateLimitConfig, call: &ProgrammableMoveCall) -> bool {
+    call.package == limit.package
+        && limit
+            .module
+            .as_ref()
+            .map_or(true, |module| call.module.as_str() == module)
+        && limit
+            .function
+            .as_ref()
+            .map_or(true, |function| call.function.as_str() == function)
+}
+
+fn window(limit: &MoveCallRateLimitConfig) -> Duration {
+    Duration::from_secs(limit.window_sec)
+}
+
+fn target(limit: &MoveCallRateLimitConfig) -> String {
+    format!(
+        "{}::{}::{}",
+        limit.package,
+        limit.module.as_deref().unwrap_or("*"),
+        limit.function.as_deref().unwrap_or("*")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mys_client::MysClient;
+    use mys_types::base_types::{random_object_ref, MysAddress};
+    use mys_types::gas_coin::GAS;
+    use mys_types::transaction::Argument;
+    use mys_types::MYS_FRAMEWORK_PACKAGE_ID;
+
+    fn split_coin_tx() -> TransactionData {
+        TransactionData::new_programmable(
+            MysAddress::random_for_testing_only(),
+            vec![random_object_ref()],
+            MysClient::construct_coin_split_pt(Argument::GasCoin, GAS::type_tag(), 2),
+            1000,
+            1,
+        )
+    }
+
+    #[test]
+    fn test_move_call_rate_limit() {
+        let metrics = GasPoolCoreMetrics::new_for_testing();
+        let limiter = MoveCallRateLimiter::new(
+            vec![
+                MoveCallRateLimitConfig {
+                    package: MYS_FRAMEWORK_PACKAGE_ID,
+                    module: Some("pay".to_string()),
+                    function: Some("split_n".to_string()),
+                    max_calls: 2,
+                    window_sec: 1,
+                },
+                MoveCallRateLimitConfig {
+                    package: MYS_FRAMEWORK_PACKAGE_ID,
+                    module: Some("coin".to_string()),
+                    function: None,
+                    max_calls: 0,
+                    window_sec: 3600,
+                },
+            ],
+            metrics.clone(),
+        );
+        // Only the first limit covers the call, so the second one does not reject it.
+        let tx_data = split_coin_tx();
+        assert!(limiter.check_calls("a", &tx_data).is_ok());
+        assert!(limiter.check_calls("a", &tx_data).is_ok());
+        assert!(matches!(
+            limiter.check_calls("a", &tx_data),
+            Err(GasStationError::PolicyRateLimited(_))
+        ));
+        // Each client has its own limit.
+        assert!(limiter.check_calls("b", &tx_data).is_ok());
+        assert_eq!(metrics.num_move_call_rate_limited_transactions.get(), 1);
+
+        // The window starts over once it is over.
+        std::thread::sleep(Duration::from_secs(1));
+        assert!(limiter.check_calls("a", &tx_data).is_ok());
+    }
+}
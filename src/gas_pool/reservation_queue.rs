@@ -0,0 +1,253 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::ReservationPriorityConfig;
+use parking_lot::Mutex;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Orders reservation attempts by priority while the gas pool is close to running out of coins.
+/// Only one attempt holds the turn at a time. When it is done, the turn goes to the waiting
+/// attempt with the highest priority, and to the earliest one among equal priorities.
+pub struct ReservationQueue {
+    config: ReservationPriorityConfig,
+    state: Mutex<QueueState>,
+}
+
+struct QueueState {
+    /// Whether some reservation attempt currently holds the turn.
+    busy: bool,
+    waiters: BinaryHeap<Waiter>,
+    next_seq: u64,
+}
+
+/// The position of a reservation in the queue. A reservation keeps its ticket across retries,
+/// so that it does not lose its place to reservations of the same priority that came later.
+#[derive(Clone, Copy, Debug)]
+pub struct QueueTicket {
+    priority: u8,
+    seq: u64,
+}
+
+struct Waiter {
+    ticket: QueueTicket,
+    sender: oneshot::Sender<()>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap, so earlier tickets must compare greater.
+        self.ticket
+            .priority
+            .cmp(&other.ticket.priority)
+            .then_with(|| other.ticket.seq.cmp(&self.ticket.seq))
+    }
+}
+
+/// Holding this means it is this reservation's turn. The turn is passed on when it is dropped.
+pub struct QueueTurn {
+    queue: Arc<ReservationQueue>,
+}
+
+impl Drop for QueueTurn {
+    fn drop(&mut self) {
+        self.queue.release();
+    }
+}
+
+/// Makes sure the turn is passed on if a waiting reservation is cancelled right after being
+/// handed the turn.
+struct PendingTurn {
+    queue: Arc<ReservationQueue>,
+    receiver: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for PendingTurn {
+    fn drop(&mut self) {
+        if let Some(mut receiver) = self.receiver.take() {
+            receiver.close();
+            if receiver.try_recv().is_ok() {
+                self.queue.release();
+            }
+        }
+    }
+}
+
+impl ReservationQueue {
+    pub fn new(config: ReservationPriorityConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            state: Mutex::new(QueueState {
+                busy: false,
+                waiters: BinaryHeap::new(),
+                next_seq: 0,
+            }),
+        })
+    }
+
+    /// Returns the priority to use for a reservation, given the priority it asked for.
+    /// The requested priority is capped by the limit configured for the client, which must be the
+    /// authenticated client id, or None if the client could not be identified.
+    pub fn resolve_priority(&self, client_id: Option<&str>, requested: Option<u8>) -> u8 {
+        let max_priority = self.config.max_priority.max(self.config.min_priority);
+        let client_max_priority = client_id
+            .and_then(|client_id| self.config.client_max_priorities.get(client_id))
+            .copied()
+            .unwrap_or(self.config.default_client_max_priority)
+            .clamp(self.config.min_priority, max_priority);
+        requested
+            .unwrap_or(self.config.default_priority)
+            .clamp(self.config.min_priority, client_max_priority)
+    }
+
+    pub fn exhaustion_coin_threshold(&self) -> usize {
+        self.config.exhaustion_coin_threshold
+    }
+
+    pub fn max_wait(&self) -> Duration {
+        Duration::from_millis(self.config.max_wait_ms)
+    }
+
+    /// Whether no reservation is holding or waiting for the turn.
+    pub fn is_idle(&self) -> bool {
+        let state = self.state.lock();
+        !state.busy && state.waiters.is_empty()
+    }
+
+    pub fn ticket(&self, priority: u8) -> QueueTicket {
+        let mut state = self.state.lock();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        QueueTicket { priority, seq }
+    }
+
+    /// Whether a reservation with higher priority than the ticket is waiting for the turn.
+    pub fn has_higher_priority_waiter(&self, ticket: QueueTicket) -> bool {
+        self.state
+            .lock()
+            .waiters
+            .peek()
+            .is_some_and(|waiter| waiter.ticket.priority > ticket.priority)
+    }
+
+    /// Waits until it is the ticket's turn. This is cancellation safe.
+    pub async fn wait_turn(self: &Arc<Self>, ticket: QueueTicket) -> QueueTurn {
+        let receiver = {
+            let mut state = self.state.lock();
+            if !state.busy && state.waiters.is_empty() {
+                state.busy = true;
+                return QueueTurn {
+                    queue: self.clone(),
+                };
+            }
+            let (sender, receiver) = oneshot::channel();
+            state.waiters.push(Waiter { ticket, sender });
+            receiver
+        };
+        let mut pending = PendingTurn {
+            queue: self.clone(),
+            receiver: Some(receiver),
+        };
+        // The sender is only dropped without sending once the receiver is closed, which
+        // only happens when pending is dropped, so the result can be ignored.
+        let _ = pending.receiver.as_mut().unwrap().await;
+        pending.receiver = None;
+        QueueTurn {
+            queue: self.clone(),
+        }
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock();
+        while let Some(waiter) = state.waiters.pop() {
+            // Sending fails if the waiting reservation was cancelled, in which case the turn
+            // goes to the next one.
+            if waiter.sender.send(()).is_ok() {
+                return;
+            }
+        }
+        state.busy = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_priority() {
+        let queue = ReservationQueue::new(ReservationPriorityConfig {
+            min_priority: 1,
+            max_priority: 10,
+            default_priority: 2,
+            default_client_max_priority: 3,
+            client_max_priorities: [("critical".to_string(), 8), ("admin".to_string(), 20)]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        });
+        assert_eq!(queue.resolve_priority(None, None), 2);
+        assert_eq!(queue.resolve_priority(None, Some(0)), 1);
+        assert_eq!(queue.resolve_priority(None, Some(9)), 3);
+        assert_eq!(queue.resolve_priority(Some("unknown"), Some(9)), 3);
+        assert_eq!(queue.resolve_priority(Some("critical"), Some(7)), 7);
+        assert_eq!(queue.resolve_priority(Some("critical"), Some(9)), 8);
+        assert_eq!(queue.resolve_priority(Some("admin"), Some(255)), 10);
+    }
+
+    #[tokio::test]
+    async fn test_turns_follow_priority() {
+        let queue = ReservationQueue::new(ReservationPriorityConfig::default());
+        let first_turn = queue.wait_turn(queue.ticket(0)).await;
+        assert!(!queue.is_idle());
+
+        let (order_sender, mut order_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut handles = vec![];
+        for (name, priority) in [("low", 1), ("high", 5), ("cancelled", 9), ("medium", 3)] {
+            let ticket = queue.ticket(priority);
+            let queue = queue.clone();
+            let order_sender = order_sender.clone();
+            handles.push(tokio::spawn(async move {
+                let _turn = queue.wait_turn(ticket).await;
+                order_sender.send(name).unwrap();
+            }));
+            // Make sure the waiter is queued before the next one.
+            while queue.state.lock().waiters.len() < handles.len() {
+                tokio::task::yield_now().await;
+            }
+        }
+        handles[2].abort();
+        assert!(handles.remove(2).await.unwrap_err().is_cancelled());
+        assert!(queue.has_higher_priority_waiter(queue.ticket(2)));
+
+        drop(first_turn);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        drop(order_sender);
+        let mut order = vec![];
+        while let Some(name) = order_receiver.recv().await {
+            order.push(name);
+        }
+        assert_eq!(order, vec!["high", "medium", "low"]);
+        assert!(queue.is_idle());
+    }
+}
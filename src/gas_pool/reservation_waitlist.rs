@@ -0,0 +1,193 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::ReservationWaitlistConfig;
+use crate::metrics::GasPoolCoreMetrics;
+use chrono::Utc;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Returned to a reservation that waits for coins, instead of the reserved coins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct WaitTicket {
+    /// Identifies the reservation in the event POSTed to its callback URL.
+    pub wait_id: u64,
+    /// When the reservation stops waiting at the latest, in milliseconds since the epoch.
+    pub wait_expiration_ms: u64,
+}
+
+/// A reservation waiting for coins.
+pub struct WaitingReservation {
+    pub wait_id: u64,
+    pub gas_budget: u64,
+    pub duration: Duration,
+    pub callback_url: String,
+    pub client_id: Option<String>,
+    pub priority: Option<u8>,
+    pub tag: Option<String>,
+    deadline: Instant,
+}
+
+/// Holds the reservations that the pool could not cover right away, so that they are retried as
+/// coins are released, in the order they came in. Waiting reservations are kept in memory, so
+/// each gas pool instance serves its own.
+pub struct ReservationWaitlist {
+    config: ReservationWaitlistConfig,
+    metrics: Arc<GasPoolCoreMetrics>,
+    waiting: Mutex<VecDeque<WaitingReservation>>,
+}
+
+impl ReservationWaitlist {
+    pub fn new(config: ReservationWaitlistConfig, metrics: Arc<GasPoolCoreMetrics>) -> Self {
+        Self {
+            config,
+            metrics,
+            waiting: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn retry_interval(&self) -> Duration {
+        Duration::from_millis(self.config.retry_interval_ms)
+    }
+
+    /// Adds a reservation to the end of the waitlist. It waits for max_wait_sec at most, and
+    /// never longer than its reserve duration. Returns None if the waitlist is full.
+    pub fn push(
+        &self,
+        gas_budget: u64,
+        duration: Duration,
+        callback_url: String,
+        client_id: Option<String>,
+        priority: Option<u8>,
+        tag: Option<String>,
+    ) -> Option<WaitTicket> {
+        let mut waiting = self.waiting.lock();
+        if waiting.len() >= self.config.max_waiting_reservations {
+            self.metrics.num_rejected_waiting_reservations.inc();
+            return None;
+        }
+        let max_wait = duration.min(Duration::from_secs(self.config.max_wait_sec));
+        let wait_id = rand::random();
+        waiting.push_back(WaitingReservation {
+            wait_id,
+            gas_budget,
+            duration,
+            callback_url,
+            client_id,
+            priority,
+            tag,
+            deadline: Instant::now() + max_wait,
+        });
+        self.metrics.num_waiting_reservations.set(waiting.len() as i64);
+        Some(WaitTicket {
+            wait_id,
+            wait_expiration_ms: Utc::now().timestamp_millis() as u64 + max_wait.as_millis() as u64,
+        })
+    }
+
+    /// Takes out the reservations whose wait expired.
+    pub fn take_expired(&self) -> Vec<WaitingReservation> {
+        let now = Instant::now();
+        let mut waiting = self.waiting.lock();
+        let (expired, remaining): (Vec<_>, VecDeque<_>) = std::mem::take(&mut *waiting)
+            .into_iter()
+            .partition(|reservation| reservation.deadline <= now);
+        *waiting = remaining;
+        self.metrics.num_waiting_reservations.set(waiting.len() as i64);
+        self.metrics
+            .num_expired_waiting_reservations
+            .inc_by(expired.len() as u64);
+        expired
+    }
+
+    /// Takes out the reservation that has been waiting the longest.
+    pub fn pop_front(&self) -> Option<WaitingReservation> {
+        let mut waiting = self.waiting.lock();
+        let reservation = waiting.pop_front();
+        self.metrics.num_waiting_reservations.set(waiting.len() as i64);
+        reservation
+    }
+
+    /// Puts a reservation taken out by `pop_front` back in its place, e.g. because the pool
+    /// still cannot cover it.
+    pub fn push_front(&self, reservation: WaitingReservation) {
+        let mut waiting = self.waiting.lock();
+        waiting.push_front(reservation);
+        self.metrics.num_waiting_reservations.set(waiting.len() as i64);
+    }
+
+    pub fn waiting_count(&self) -> usize {
+        self.waiting.lock().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_waitlist(max_waiting_reservations: usize, max_wait_sec: u64) -> ReservationWaitlist {
+        ReservationWaitlist::new(
+            ReservationWaitlistConfig {
+                max_waiting_reservations,
+                max_wait_sec,
+                ..Default::default()
+            },
+            GasPoolCoreMetrics::new_for_testing(),
+        )
+    }
+
+    fn push(waitlist: &ReservationWaitlist, gas_budget: u64, duration_sec: u64) -> Option<u64> {
+        waitlist
+            .push(
+                gas_budget,
+                Duration::from_secs(duration_sec),
+                "https://example.com/callback".to_string(),
+                None,
+                None,
+                None,
+            )
+            .map(|ticket| ticket.wait_id)
+    }
+
+    #[test]
+    fn test_waitlist_order_and_bound() {
+        let waitlist = new_waitlist(2, 60);
+        let first = push(&waitlist, 100, 60).unwrap();
+        let second = push(&waitlist, 200, 60).unwrap();
+        assert!(push(&waitlist, 300, 60).is_none());
+        assert_eq!(waitlist.metrics.num_rejected_waiting_reservations.get(), 1);
+
+        let reservation = waitlist.pop_front().unwrap();
+        assert_eq!(reservation.wait_id, first);
+        waitlist.push_front(reservation);
+        assert_eq!(waitlist.pop_front().unwrap().wait_id, first);
+        assert_eq!(waitlist.pop_front().unwrap().wait_id, second);
+        assert!(waitlist.pop_front().is_none());
+        assert_eq!(waitlist.metrics.num_waiting_reservations.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_waiting_reservations_expire() {
+        let waitlist = new_waitlist(10, 1);
+        // Waits for its own reserve duration at most.
+        let short = push(&waitlist, 100, 1).unwrap();
+        // Waits for max_wait_sec at most.
+        let capped = push(&waitlist, 100, 60).unwrap();
+        assert!(waitlist.take_expired().is_empty());
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let expired: Vec<_> = waitlist
+            .take_expired()
+            .into_iter()
+            .map(|reservation| reservation.wait_id)
+            .collect();
+        assert_eq!(expired, vec![short, capped]);
+        assert_eq!(waitlist.waiting_count(), 0);
+        assert_eq!(waitlist.metrics.num_expired_waiting_reservations.get(), 2);
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::SponsorAddressCheckConfig;
+use crate::metrics::GasPoolCoreMetrics;
+use crate::tx_signer::TxSigner;
+use mys_types::base_types::MysAddress;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// Detects when the signer no longer signs for the sponsor address, e.g. after a config change
+/// or a key rotation in the KMS sidecar. Every execution fails in that case, so the mismatch is
+/// reported as a critical error and fails the health check.
+pub struct SponsorAddressChecker {
+    expected_address: MysAddress,
+    check_interval: Duration,
+    signer: Arc<dyn TxSigner>,
+    metrics: Arc<GasPoolCoreMetrics>,
+    address_matches: AtomicBool,
+}
+
+impl SponsorAddressChecker {
+    /// `pool_address` is the sponsor address the gas pool tracks coins for. It is the expected
+    /// address unless one is configured.
+    pub fn new(
+        config: SponsorAddressCheckConfig,
+        pool_address: MysAddress,
+        signer: Arc<dyn TxSigner>,
+        metrics: Arc<GasPoolCoreMetrics>,
+    ) -> Self {
+        Self {
+            expected_address: config.sponsor_address.unwrap_or(pool_address),
+            check_interval: Duration::from_secs(config.check_interval_sec),
+            signer,
+            metrics,
+            address_matches: AtomicBool::new(true),
+        }
+    }
+
+    /// Starts a task that checks the signer address every `check_interval_sec`.
+    pub fn start_check_task(
+        self: Arc<Self>,
+        mut cancel_receiver: tokio::sync::oneshot::Receiver<()>,
+    ) -> JoinHandle<()> {
+        tokio::task::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(self.check_interval) => {}
+                    _ = &mut cancel_receiver => {
+                        info!("Sponsor address check task is cancelled");
+                        break;
+                    }
+                }
+                self.check().await;
+            }
+        })
+    }
+
+    /// Checks that the signer signs for the expected sponsor address and returns whether it does.
+    /// If the signer address cannot be refreshed, the result of the previous check is kept.
+    pub async fn check(&self) -> bool {
+        if let Err(err) = self.signer.refresh_address().await {
+            warn!("Failed to refresh the signer address: {:?}", err);
+            return self.address_matches();
+        }
+        let matches = self.signer.is_valid_address(&self.expected_address);
+        if !matches {
+            error!(
+                "CRITICAL: the signer signs for {:?} instead of the sponsor address {:?}. Every transaction execution will fail until this is fixed",
+                self.signer.get_address(),
+                self.expected_address
+            );
+        } else if !self.address_matches() {
+            info!(
+                "The signer signs for the sponsor address {:?} again",
+                self.expected_address
+            );
+        }
+        self.address_matches.store(matches, Ordering::Relaxed);
+        self.metrics
+            .sponsor_address_mismatch
+            .set(if matches { 0 } else { 1 });
+        matches
+    }
+
+    pub fn address_matches(&self) -> bool {
+        self.address_matches.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx_signer::TestTxSigner;
+    use mys_types::crypto::get_account_key_pair;
+
+    #[tokio::test]
+    async fn test_sponsor_address_check() {
+        let (_, keypair) = get_account_key_pair();
+        let signer: Arc<dyn TxSigner> = TestTxSigner::new(keypair.into());
+        let metrics = GasPoolCoreMetrics::new_for_testing();
+
+        let checker = SponsorAddressChecker::new(
+            SponsorAddressCheckConfig::default(),
+            signer.get_address(),
+            signer.clone(),
+            metrics.clone(),
+        );
+        assert!(checker.check().await);
+        assert_eq!(metrics.sponsor_address_mismatch.get(), 0);
+
+        let checker = SponsorAddressChecker::new(
+            SponsorAddressCheckConfig {
+                sponsor_address: Some(MysAddress::random_for_testing_only()),
+                ..Default::default()
+            },
+            signer.get_address(),
+            signer,
+            metrics.clone(),
+        );
+        assert!(!checker.check().await);
+        assert!(!checker.address_matches());
+        assert_eq!(metrics.sponsor_address_mismatch.get(), 1);
+    }
+}
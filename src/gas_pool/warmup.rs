@@ -0,0 +1,199 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::WarmupConfig;
+use crate::errors::GasStationError;
+use crate::metrics::GasPoolCoreMetrics;
+use crate::mys_client::MysClient;
+use crate::storage::Storage;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use super::sponsor_address_check::SponsorAddressChecker;
+
+/// Holds back reservations right after startup or failover, until the gas pool is ready to serve
+/// them: coins are loaded, the fullnode answers gas price queries and the signer signs for the
+/// sponsor address. Letting full traffic through before that causes a burst of failures.
+pub struct Warmup {
+    config: WarmupConfig,
+    gas_pool_store: Arc<dyn Storage>,
+    mys_client: MysClient,
+    sponsor_address_checker: Arc<SponsorAddressChecker>,
+    metrics: Arc<GasPoolCoreMetrics>,
+    started_at: Instant,
+    is_warm: AtomicBool,
+    /// The start of the current one second window, and the number of reservations let through
+    /// in it.
+    rate_window: Mutex<(Instant, u64)>,
+}
+
+impl Warmup {
+    pub fn new(
+        config: WarmupConfig,
+        gas_pool_store: Arc<dyn Storage>,
+        mys_client: MysClient,
+        sponsor_address_checker: Arc<SponsorAddressChecker>,
+        metrics: Arc<GasPoolCoreMetrics>,
+    ) -> Self {
+        metrics.warming_up.set(1);
+        let now = Instant::now();
+        Self {
+            config,
+            gas_pool_store,
+            mys_client,
+            sponsor_address_checker,
+            metrics,
+            started_at: now,
+            is_warm: AtomicBool::new(false),
+            rate_window: Mutex::new((now, 0)),
+        }
+    }
+
+    /// Starts a task that checks whether the gas pool is ready every `check_interval_ms`, until
+    /// it is or `max_warmup_sec` passed.
+    pub fn start_readiness_task(
+        self: Arc<Self>,
+        mut cancel_receiver: tokio::sync::oneshot::Receiver<()>,
+    ) -> JoinHandle<()> {
+        tokio::task::spawn(async move {
+            let max_warmup = Duration::from_secs(self.config.max_warmup_sec);
+            loop {
+                if self.is_ready().await {
+                    info!(
+                        "Gas pool is ready after warming up for {:?}",
+                        self.started_at.elapsed()
+                    );
+                    break;
+                }
+                if self.started_at.elapsed() >= max_warmup {
+                    warn!(
+                        "Gas pool is still not ready after warming up for {:?}, letting all reservations through",
+                        max_warmup
+                    );
+                    break;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_millis(self.config.check_interval_ms)) => {}
+                    _ = &mut cancel_receiver => {
+                        info!("Warmup task is cancelled");
+                        return;
+                    }
+                }
+            }
+            self.is_warm.store(true, Ordering::Relaxed);
+            self.metrics.warming_up.set(0);
+        })
+    }
+
+    async fn is_ready(&self) -> bool {
+        match self.gas_pool_store.get_available_coin_count().await {
+            Ok(count) if count > 0 => {}
+            result => {
+                debug!("Gas pool coins are not loaded yet: {:?}", result);
+                return false;
+            }
+        }
+        if let Err(err) = self.mys_client.try_get_reference_gas_price().await {
+            debug!("Gas price is not available yet: {:?}", err);
+            return false;
+        }
+        if !self.sponsor_address_checker.address_matches() {
+            debug!("Signer does not sign for the sponsor address");
+            return false;
+        }
+        true
+    }
+
+    pub fn is_warm(&self) -> bool {
+        self.is_warm.load(Ordering::Relaxed)
+    }
+
+    /// Fails with `GasStationError::ServiceWarming` while warming up, unless the reservation fits
+    /// in the reduced rate of `max_reservations_per_sec`.
+    pub fn check_reservation(&self) -> Result<(), GasStationError> {
+        if self.is_warm() {
+            return Ok(());
+        }
+        let mut rate_window = self.rate_window.lock();
+        let now = Instant::now();
+        if now.duration_since(rate_window.0) >= Duration::from_secs(1) {
+            *rate_window = (now, 0);
+        }
+        if rate_window.1 < self.config.max_reservations_per_sec {
+            rate_window.1 += 1;
+            return Ok(());
+        }
+        self.metrics.num_reservations_rejected_during_warmup.inc();
+        Err(GasStationError::ServiceWarming)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SponsorAddressCheckConfig;
+    use crate::storage::connect_storage_for_testing;
+    use crate::test_env::start_mys_cluster;
+    use crate::tx_signer::TxSigner;
+    use crate::types::GasCoin;
+    use mys_types::base_types::random_object_ref;
+    use mys_types::gas_coin::MIST_PER_MYS;
+
+    #[tokio::test]
+    async fn test_warmup() {
+        let (cluster, signer) = start_mys_cluster(vec![MIST_PER_MYS]).await;
+        let storage = connect_storage_for_testing(signer.get_address()).await;
+        let mys_client = MysClient::new(&cluster.fullnode_handle.rpc_url, None).await;
+        let metrics = GasPoolCoreMetrics::new_for_testing();
+        let checker = Arc::new(SponsorAddressChecker::new(
+            SponsorAddressCheckConfig::default(),
+            signer.get_address(),
+            signer.clone(),
+            metrics.clone(),
+        ));
+        let warmup = Arc::new(Warmup::new(
+            WarmupConfig {
+                max_reservations_per_sec: 2,
+                check_interval_ms: 100,
+                ..Default::default()
+            },
+            storage.clone(),
+            mys_client,
+            checker,
+            metrics.clone(),
+        ));
+        let (_cancel_sender, cancel_receiver) = tokio::sync::oneshot::channel();
+        let task = warmup.clone().start_readiness_task(cancel_receiver);
+
+        // Only the reduced rate is let through while the pool has no coins.
+        assert!(warmup.check_reservation().is_ok());
+        assert!(warmup.check_reservation().is_ok());
+        assert!(matches!(
+            warmup.check_reservation(),
+            Err(GasStationError::ServiceWarming)
+        ));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!warmup.is_warm());
+        assert_eq!(metrics.warming_up.get(), 1);
+
+        storage
+            .add_new_coins(vec![GasCoin {
+                object_ref: random_object_ref(),
+                balance: 1,
+            }])
+            .await
+            .unwrap();
+        task.await.unwrap();
+        assert!(warmup.is_warm());
+        assert_eq!(metrics.warming_up.get(), 0);
+        for _ in 0..10 {
+            assert!(warmup.check_reservation().is_ok());
+        }
+        assert_eq!(metrics.num_reservations_rejected_during_warmup.get(), 1);
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::mys_client::MysClient;
+use crate::storage::{Storage, MAX_GAS_PER_QUERY};
+use crate::tx_signer::TxSigner;
+use crate::types::GasCoin;
+use anyhow::bail;
+use std::sync::Arc;
+use mys_json_rpc_types::MysTransactionBlockEffectsAPI;
+use mys_types::base_types::MysAddress;
+use mys_types::gas_coin::MIST_PER_MYS;
+use mys_types::object::Owner;
+use mys_types::transaction::{Transaction, TransactionData};
+use tracing::{error, info};
+
+/// Gas budget of each drain transaction. Merging coins deletes them, so most of the cost is
+/// paid back through storage rebates.
+const DRAIN_TX_GAS_BUDGET: u64 = MIST_PER_MYS / 10;
+
+/// Takes all coins out of the gas pool, merges them and transfers them to the destination.
+/// This is used when decommissioning a gas pool, and should only be done after all gas pool
+/// servers for the sponsor are stopped.
+/// Refuses to drain if there is any active reservation.
+/// Returns the total balance received by the destination.
+pub async fn drain_gas_pool(
+    mys_client: MysClient,
+    storage: Arc<dyn Storage>,
+    signer: Arc<dyn TxSigner>,
+    destination: MysAddress,
+) -> anyhow::Result<u64> {
+    let sponsor_address = signer.get_address();
+    if destination == MysAddress::ZERO {
+        bail!("Destination address must not be the zero address");
+    }
+    if destination == sponsor_address {
+        bail!("Destination address must be different from the sponsor address");
+    }
+    let drained_coins = storage.drain_available_coins().await?;
+    info!(
+        "Drained {} coins from the pool. Transferring them to {}",
+        drained_coins.len(),
+        destination
+    );
+    // Make sure we are using the latest versions of the coins.
+    let mut remaining: Vec<GasCoin> = mys_client
+        .get_latest_gas_objects(
+            sponsor_address,
+            drained_coins.into_iter().map(|c| c.object_ref.0),
+        )
+        .await
+        .into_values()
+        .flatten()
+        .collect();
+    let rgp = mys_client.get_reference_gas_price().await;
+    let mut total_transferred = 0;
+    while !remaining.is_empty() {
+        let chunk: Vec<_> = remaining
+            .drain(..remaining.len().min(MAX_GAS_PER_QUERY))
+            .collect();
+        match transfer_coins(&mys_client, &signer, &chunk, destination, rgp).await {
+            Ok(transferred) => total_transferred += transferred,
+            Err(err) => {
+                error!("Failed to transfer coins to {}: {:?}", destination, err);
+                // Put the coins that were not transferred back to the pool so we don't lose track of them.
+                let untransferred: Vec<_> = mys_client
+                    .get_latest_gas_objects(
+                        sponsor_address,
+                        chunk.iter().chain(&remaining).map(|c| c.object_ref.0),
+                    )
+                    .await
+                    .into_values()
+                    .flatten()
+                    .collect();
+                storage.add_new_coins(untransferred).await?;
+                return Err(err);
+            }
+        }
+    }
+    info!(
+        "Transferred a total balance of {} to {}",
+        total_transferred, destination
+    );
+    Ok(total_transferred)
+}
+
+async fn transfer_coins(
+    mys_client: &MysClient,
+    signer: &Arc<dyn TxSigner>,
+    coins: &[GasCoin],
+    destination: MysAddress,
+    rgp: u64,
+) -> anyhow::Result<u64> {
+    let total_balance: u64 = coins.iter().map(|c| c.balance).sum();
+    let budget = DRAIN_TX_GAS_BUDGET.min(total_balance);
+    let tx_data = TransactionData::new_programmable(
+        signer.get_address(),
+        coins.iter().map(|c| c.object_ref).collect(),
+        MysClient::construct_transfer_gas_coin_pt(destination),
+        budget,
+        rgp,
+    );
+    let sig = signer.sign_transaction(&tx_data).await?;
+    let tx = Transaction::from_generic_sig_data(tx_data, vec![sig]);
+    let effects = mys_client.execute_transaction(tx).await?;
+    if !effects.status().is_ok() {
+        bail!("Drain transaction failed: {:?}", effects.status());
+    }
+    let gas_object = effects.gas_object();
+    if gas_object.owner != Owner::AddressOwner(destination) {
+        bail!(
+            "Drain transaction did not transfer the coins to the destination: {:?}",
+            gas_object
+        );
+    }
+    let transferred = total_balance as i64 - effects.gas_cost_summary().net_gas_usage();
+    info!(
+        "Transferred {} coins with a total balance of {} to {}. Transaction digest: {:?}",
+        coins.len(),
+        transferred,
+        destination,
+        effects.transaction_digest()
+    );
+    Ok(transferred as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::CoinInitConfig;
+    use crate::gas_pool_drainer::drain_gas_pool;
+    use crate::gas_pool_initializer::GasPoolInitializer;
+    use crate::mys_client::MysClient;
+    use crate::storage::connect_storage_for_testing;
+    use crate::test_env::start_mys_cluster;
+    use mys_types::base_types::MysAddress;
+    use mys_types::gas_coin::MIST_PER_MYS;
+
+    #[tokio::test]
+    async fn test_drain_gas_pool() {
+        telemetry_subscribers::init_for_testing();
+        let (cluster, signer) = start_mys_cluster(vec![MIST_PER_MYS; 10]).await;
+        let fullnode_url = cluster.fullnode_handle.rpc_url.clone();
+        let storage = connect_storage_for_testing(signer.get_address()).await;
+        let mys_client = MysClient::new(&fullnode_url, None).await;
+        let _init_task = GasPoolInitializer::start(
+            mys_client.clone(),
+            storage.clone(),
+            CoinInitConfig {
+                target_init_balance: MIST_PER_MYS,
+                refresh_interval_sec: 200,
+                ..Default::default()
+            },
+            signer.clone(),
+        )
+        .await;
+        assert_eq!(storage.get_available_coin_count().await.unwrap(), 10);
+        let destination = MysAddress::random_for_testing_only();
+
+        // Refuse to drain while coins are reserved.
+        storage.reserve_gas_coins(1, 60000).await.unwrap();
+        assert!(drain_gas_pool(mys_client.clone(), storage.clone(), signer.clone(), destination)
+            .await
+            .is_err());
+        storage.flush_db().await;
+        storage.init_coin_stats_at_startup().await.unwrap();
+        let coins = mys_client
+            .get_all_owned_mys_coins_above_balance_threshold(signer.get_address(), 0)
+            .await;
+        storage.add_new_coins(coins).await.unwrap();
+
+        let total_balance =
+            drain_gas_pool(mys_client.clone(), storage.clone(), signer.clone(), destination)
+                .await
+                .unwrap();
+        assert!(total_balance > 9 * MIST_PER_MYS);
+        assert_eq!(storage.get_available_coin_count().await.unwrap(), 0);
+        let received = mys_client
+            .get_all_owned_mys_coins_above_balance_threshold(destination, 0)
+            .await;
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].balance, total_balance);
+    }
+}
@@ -3,7 +3,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::config::CoinInitConfig;
-use crate::storage::Storage;
+use crate::storage::{safe_mode_is_on, Storage};
 use crate::mys_client::MysClient;
 use crate::tx_signer::TxSigner;
 use crate::types::GasCoin;
@@ -16,14 +16,14 @@ use std::time::Duration;
 use mys_json_rpc_types::MysTransactionBlockEffectsAPI;
 use mys_types::base_types::MysAddress;
 use mys_types::coin::{PAY_MODULE_NAME, PAY_SPLIT_N_FUNC_NAME};
-use mys_types::gas_coin::GAS;
 use mys_types::programmable_transaction_builder::ProgrammableTransactionBuilder;
 use mys_types::transaction::{Argument, Transaction, TransactionData};
 use mys_types::MYS_FRAMEWORK_PACKAGE_ID;
+use tokio::sync::Semaphore;
 use tokio::task::JoinHandle;
 use tokio::time::Instant;
 use tokio_retry::strategy::{jitter, ExponentialBackoff};
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 
 /// Any coin owned by the sponsor address with balance above target_init_coin_balance * NEW_COIN_BALANCE_FACTOR_THRESHOLD
 /// is considered a new coin, and we will try to split it into smaller coins with balance close to target_init_coin_balance.
@@ -35,6 +35,10 @@ const MAX_INIT_DURATION_SEC: u64 = 60 * 60 * 12;
 /// Maximum number of coins to process in one initialization cycle to prevent KMS rate limiting
 const MAX_COINS_PER_INIT_CYCLE: usize = 1000;
 
+/// How many times the coins of a batch that failed to split are tried again before they are
+/// left to the next initialization cycle.
+const MAX_SPLIT_BATCH_ATTEMPTS: usize = 3;
+
 #[derive(Clone)]
 struct CoinSplitEnv {
     target_init_coin_balance: u64,
@@ -42,14 +46,23 @@ struct CoinSplitEnv {
     signer: Arc<dyn TxSigner>,
     sponsor_address: MysAddress,
     mys_client: MysClient,
-    task_queue: Arc<Mutex<VecDeque<JoinHandle<Vec<GasCoin>>>>>,
+    /// Each task returns the coins it produced, or the coin it failed to split.
+    task_queue: Arc<Mutex<VecDeque<JoinHandle<Result<Vec<GasCoin>, GasCoin>>>>>,
+    /// Limits how many split transactions are signed and executed at the same time.
+    split_permits: Arc<Semaphore>,
     total_coin_count: Arc<AtomicUsize>,
     rgp: u64,
 }
 
 impl CoinSplitEnv {
+    /// The balance each new coin takes off the coin being split: its own balance, plus the gas
+    /// to create it.
+    fn new_coin_cost(&self) -> u64 {
+        self.gas_cost_per_object.saturating_add(self.target_init_coin_balance)
+    }
+
     fn enqueue_task(&self, coin: GasCoin) -> Option<GasCoin> {
-        if coin.balance <= (self.gas_cost_per_object + self.target_init_coin_balance) * 2 {
+        if coin.balance <= self.new_coin_cost().saturating_mul(2) {
             trace!(
                 "Skip splitting coin {:?} because it has small balance",
                 coin
@@ -71,18 +84,32 @@ impl CoinSplitEnv {
         );
     }
 
-    async fn split_one_gas_coin(self, mut coin: GasCoin) -> Vec<GasCoin> {
+    async fn split_one_gas_coin(self, mut coin: GasCoin) -> Result<Vec<GasCoin>, GasCoin> {
         let rgp = self.rgp;
         let split_count = min(
             // Max number of object mutations per transaction is 2048.
             2000,
-            coin.balance / (self.gas_cost_per_object + self.target_init_coin_balance),
+            coin.balance / self.new_coin_cost(),
         );
         trace!(
             "Evenly splitting coin {:?} into {} coins",
             coin, split_count
         );
-        let budget = self.gas_cost_per_object * split_count;
+        let budget = match coin.split_budget(self.gas_cost_per_object, split_count) {
+            Ok(budget) => budget,
+            Err(err) => {
+                error!(
+                    "Cannot split coin {:?} into {} coins, adding it as is: {:?}",
+                    coin, split_count, err
+                );
+                return Ok(vec![coin]);
+            }
+        };
+        let permit = self
+            .split_permits
+            .acquire()
+            .await
+            .expect("Split permits are never closed");
         let effects = loop {
             let mut pt_builder = ProgrammableTransactionBuilder::new();
             let pure_arg = pt_builder.pure(split_count).unwrap();
@@ -90,7 +117,7 @@ impl CoinSplitEnv {
                 MYS_FRAMEWORK_PACKAGE_ID,
                 PAY_MODULE_NAME.into(),
                 PAY_SPLIT_N_FUNC_NAME.into(),
-                vec![GAS::type_tag()],
+                vec![self.mys_client.gas_coin_type().clone()],
                 vec![Argument::GasCoin, pure_arg],
             );
             let pt = pt_builder.finish();
@@ -116,8 +143,7 @@ impl CoinSplitEnv {
                 Err(err) => {
                     // Only log final failure, not individual retry attempts
                     debug!("All signing attempts failed for coin splitting, skipping this coin. Error: {:?}", err);
-                    // Return empty vector to indicate this coin couldn't be split
-                    return vec![];
+                    return Err(coin);
                 }
             };
             let tx = Transaction::from_generic_sig_data(tx_data, vec![sig]);
@@ -125,7 +151,7 @@ impl CoinSplitEnv {
                 "Sending transaction for execution. Tx digest: {:?}",
                 tx.digest()
             );
-            let result = self.mys_client.execute_transaction(tx.clone(), 10).await;
+            let result = self.mys_client.execute_transaction(tx.clone()).await;
             match result {
                 Ok(effects) => {
                     assert!(
@@ -139,17 +165,20 @@ impl CoinSplitEnv {
                 Err(e) => {
                     error!("Failed to execute transaction: {:?}", e);
                     // Try to get the updated coin object, but don't crash if this fails
-                    let latest_objects = self.mys_client.get_latest_gas_objects([coin.object_ref.0]).await;
+                    let latest_objects = self
+                        .mys_client
+                        .get_latest_gas_objects(self.sponsor_address, [coin.object_ref.0])
+                        .await;
                     if let Some((_, Some(updated_coin))) = latest_objects.into_iter().next() {
                         coin = updated_coin;
                         continue;
                     }
                     error!("Failed to get updated coin object after transaction failure. Skipping this coin.");
-                    // Skip this coin instead of crashing - return empty result for this coin
-                    return vec![];
+                    return Err(coin);
                 }
             }
         };
+        drop(permit);
         let mut result = vec![];
         let new_coin_balance = (coin.balance - budget) / split_count;
         for created in effects.created() {
@@ -165,7 +194,7 @@ impl CoinSplitEnv {
             balance: remaining_coin_balance as u64,
         }));
         self.increment_total_coin_count_by(result.len() - 1);
-        result
+        Ok(result)
     }
 }
 
@@ -208,16 +237,22 @@ impl GasPoolInitializer {
             storage.flush_db().await;
         }
 
+        let mut init_pending = false;
         if should_run_init {
-            // If the pool has never been initialized, always run once at the beginning to make sure we have enough coins.
-            Self::run_once(
-                mys_client.clone(),
-                &storage,
-                RunMode::Init,
-                coin_init_config.target_init_balance,
-                &signer,
-            )
-            .await;
+            if safe_mode_is_on(storage.as_ref()).await {
+                info!("Safe mode is on. Deferring the first initialization until it is off");
+                init_pending = true;
+            } else {
+                // If the pool has never been initialized, always run once at the beginning to make sure we have enough coins.
+                Self::run_once(
+                    mys_client.clone(),
+                    &storage,
+                    RunMode::Init,
+                    &coin_init_config,
+                    &signer,
+                )
+                .await;
+            }
         }
         let (cancel_sender, cancel_receiver) = tokio::sync::oneshot::channel();
         let _task_handle = tokio::spawn(Self::run(
@@ -225,6 +260,7 @@ impl GasPoolInitializer {
             storage,
             coin_init_config,
             signer,
+            init_pending,
             cancel_receiver,
         ));
         Self {
@@ -238,6 +274,7 @@ impl GasPoolInitializer {
         storage: Arc<dyn Storage>,
         coin_init_config: CoinInitConfig,
         signer: Arc<dyn TxSigner>,
+        mut init_pending: bool,
         mut cancel_receiver: tokio::sync::oneshot::Receiver<()>,
     ) {
         loop {
@@ -248,12 +285,21 @@ impl GasPoolInitializer {
                     break;
                 }
             }
+            if safe_mode_is_on(storage.as_ref()).await {
+                info!("Safe mode is on. Skipping new coin initialization");
+                continue;
+            }
             info!("Coin init task waking up and looking for new coins to initialize");
+            let mode = if std::mem::take(&mut init_pending) {
+                RunMode::Init
+            } else {
+                RunMode::Refresh
+            };
             Self::run_once(
                 mys_client.clone(),
                 &storage,
-                RunMode::Refresh,
-                coin_init_config.target_init_balance,
+                mode,
+                &coin_init_config,
                 &signer,
             )
             .await;
@@ -264,9 +310,10 @@ impl GasPoolInitializer {
         mys_client: MysClient,
         storage: &Arc<dyn Storage>,
         mode: RunMode,
-        target_init_coin_balance: u64,
+        coin_init_config: &CoinInitConfig,
         signer: &Arc<dyn TxSigner>,
     ) {
+        let target_init_coin_balance = coin_init_config.target_init_balance;
         let sponsor_address = signer.get_address();
         let acquired_lock = match storage.acquire_init_lock(MAX_INIT_DURATION_SEC).await {
             Ok(acquired) => acquired,
@@ -293,6 +340,17 @@ impl GasPoolInitializer {
         let mut all_coins = mys_client
             .get_all_owned_mys_coins_above_balance_threshold(sponsor_address, balance_threshold)
             .await;
+        if matches!(mode, RunMode::Refresh) {
+            // A coin reload may have added large coins to the pool as they are, which must not
+            // be split from under it.
+            all_coins = match storage.filter_untracked_coins(all_coins).await {
+                Ok(coins) => coins,
+                Err(err) => {
+                    error!("Failed to filter out coins already in the pool: {:?}", err);
+                    vec![]
+                }
+            };
+        }
 
         if all_coins.is_empty() {
             info!(
@@ -324,8 +382,9 @@ impl GasPoolInitializer {
             .calibrate_gas_cost_per_object(sponsor_address, &coins_to_process[0])
             .await;
         info!("Calibrated gas cost per object: {:?}", gas_cost_per_object);
-        let result = Self::split_gas_coins(
+        Self::split_gas_coins(
             coins_to_process,
+            coin_init_config.split_batch_size.max(1),
             CoinSplitEnv {
                 target_init_coin_balance,
                 gas_cost_per_object,
@@ -333,17 +392,15 @@ impl GasPoolInitializer {
                 sponsor_address,
                 mys_client,
                 task_queue: Default::default(),
+                split_permits: Arc::new(Semaphore::new(
+                    coin_init_config.max_concurrent_splits.max(1),
+                )),
                 total_coin_count,
                 rgp,
             },
+            storage,
         )
         .await;
-        for chunk in result.chunks(5000) {
-            if let Err(err) = storage.add_new_coins(chunk.to_vec()).await {
-                error!("Failed to add new coins to storage: {:?}", err);
-                // Continue trying to add other chunks, but don't crash
-            }
-        }
         if let Err(err) = storage.release_init_lock().await {
             error!("Failed to release initialization lock: {:?}", err);
             // Don't crash, but this could cause issues with future initialization attempts
@@ -354,7 +411,15 @@ impl GasPoolInitializer {
         );
     }
 
-    async fn split_gas_coins(coins: Vec<GasCoin>, env: CoinSplitEnv) -> Vec<GasCoin> {
+    /// Splits the coins in batches of `batch_size`, and adds the coins produced by each batch to
+    /// the storage as soon as the batch is done, so that a failed batch is retried on its own and
+    /// never redoes the batches before it.
+    async fn split_gas_coins(
+        coins: Vec<GasCoin>,
+        batch_size: usize,
+        env: CoinSplitEnv,
+        storage: &Arc<dyn Storage>,
+    ) {
         let total_balance: u64 = coins.iter().map(|c| c.balance).sum();
         info!(
             "Splitting {} coins with total balance of {} into smaller coins with target balance of {}. This will result in close to {} coins",
@@ -364,31 +429,94 @@ impl GasPoolInitializer {
             total_balance / env.target_init_coin_balance,
         );
 
-        // Process coins in parallel for maximum speed
-        // KMS sidecar can handle concurrent requests with caching and retries
+        let batch_count = coins.len().div_ceil(batch_size);
         let mut result = vec![];
+        for (batch_index, batch) in coins.chunks(batch_size).enumerate() {
+            let mut new_coins = vec![];
+            let mut pending_coins = batch.to_vec();
+            for attempt in 1..=MAX_SPLIT_BATCH_ATTEMPTS {
+                let (split_coins, failed_coins) = Self::split_batch(pending_coins, &env).await;
+                new_coins.extend(split_coins);
+                if failed_coins.is_empty() {
+                    break;
+                }
+                warn!(
+                    "Failed to split {} coins of batch {}/{} (attempt {}/{})",
+                    failed_coins.len(),
+                    batch_index + 1,
+                    batch_count,
+                    attempt,
+                    MAX_SPLIT_BATCH_ATTEMPTS
+                );
+                pending_coins = failed_coins;
+            }
+            for chunk in new_coins.chunks(5000) {
+                if let Err(err) = storage.add_new_coins(chunk.to_vec()).await {
+                    error!("Failed to add new coins to storage: {:?}", err);
+                    // Continue trying to add other chunks, but don't crash
+                }
+            }
+            info!(
+                "Split batch {}/{} into {} coins",
+                batch_index + 1,
+                batch_count,
+                new_coins.len()
+            );
+            result.extend(new_coins);
+        }
+        let new_total_balance: u64 = result.iter().map(|c| c.balance).sum();
+        info!(
+            "Splitting finished. Got {} coins. New total balance: {}. Spent {} gas in total",
+            result.len(),
+            new_total_balance,
+            total_balance.saturating_sub(new_total_balance)
+        );
+        Self::verify_split_coins(&env.mys_client, env.sponsor_address, &result).await;
+    }
+
+    /// Splits the coins and waits for all the splits they lead to. Returns the coins produced,
+    /// and the coins that failed to split.
+    async fn split_batch(coins: Vec<GasCoin>, env: &CoinSplitEnv) -> (Vec<GasCoin>, Vec<GasCoin>) {
+        let mut split_coins = vec![];
+        let mut failed_coins = vec![];
         for coin in coins {
-            result.extend(env.enqueue_task(coin));
+            split_coins.extend(env.enqueue_task(coin));
         }
         loop {
             let Some(task) = env.task_queue.lock().pop_front() else {
                 break;
             };
             match task.await {
-                Ok(split_result) => result.extend(split_result),
+                Ok(Ok(coins)) => split_coins.extend(coins),
+                Ok(Err(coin)) => failed_coins.push(coin),
                 Err(err) => {
                     error!("Coin splitting task failed: {:?}", err);
                 }
             }
         }
-        let new_total_balance: u64 = result.iter().map(|c| c.balance).sum();
-        info!(
-            "Splitting finished. Got {} coins. New total balance: {}. Spent {} gas in total",
-            result.len(),
-            new_total_balance,
-            total_balance - new_total_balance
-        );
-        result
+        (split_coins, failed_coins)
+    }
+
+    /// Checks that the coins produced by the splits exist on chain, since the pool would
+    /// otherwise hand out coins that cannot be used.
+    async fn verify_split_coins(
+        mys_client: &MysClient,
+        sponsor_address: MysAddress,
+        coins: &[GasCoin],
+    ) {
+        let latest_coins = mys_client
+            .get_latest_gas_objects(sponsor_address, coins.iter().map(|c| c.object_ref.0))
+            .await;
+        let existing_coin_count = latest_coins.values().flatten().count();
+        if existing_coin_count < coins.len() {
+            error!(
+                "Only {} of the {} coins produced by the splits exist on chain",
+                existing_coin_count,
+                coins.len()
+            );
+        } else {
+            info!("Verified that all {} new coins exist on chain", coins.len());
+        }
     }
 }
 
@@ -416,6 +544,29 @@ mod tests {
             CoinInitConfig {
                 target_init_balance: MIST_PER_MYS,
                 refresh_interval_sec: 200,
+                ..Default::default()
+            },
+            signer,
+        )
+        .await;
+        assert!(storage.get_available_coin_count().await.unwrap() > 900);
+    }
+
+    #[tokio::test]
+    async fn test_init_in_batches() {
+        telemetry_subscribers::init_for_testing();
+        let (cluster, signer) = start_mys_cluster(vec![500 * MIST_PER_MYS; 2]).await;
+        let fullnode_url = cluster.fullnode_handle.rpc_url;
+        let storage = connect_storage_for_testing(signer.get_address()).await;
+        let mys_client = MysClient::new(&fullnode_url, None).await;
+        let _ = GasPoolInitializer::start(
+            mys_client,
+            storage.clone(),
+            CoinInitConfig {
+                target_init_balance: MIST_PER_MYS,
+                refresh_interval_sec: 200,
+                max_concurrent_splits: 1,
+                split_batch_size: 1,
             },
             signer,
         )
@@ -437,6 +588,7 @@ mod tests {
             CoinInitConfig {
                 target_init_balance,
                 refresh_interval_sec: 200,
+                ..Default::default()
             },
             signer,
         )
@@ -458,6 +610,7 @@ mod tests {
             CoinInitConfig {
                 target_init_balance: MIST_PER_MYS,
                 refresh_interval_sec: 1,
+                ..Default::default()
             },
             signer,
         )
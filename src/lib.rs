@@ -2,14 +2,21 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod auth;
 pub mod benchmarks;
+pub mod circuit_breaker;
 pub mod command;
 pub mod config;
 pub mod errors;
+pub mod execute_retry;
 pub mod gas_pool;
+pub mod gas_pool_drainer;
 pub mod gas_pool_initializer;
+pub mod log_redaction;
 pub mod metrics;
 pub mod object_locks;
+pub mod rate_limit;
+pub mod reservation_callback;
 pub mod rpc;
 pub mod storage;
 pub mod mys_client;
@@ -27,3 +34,27 @@ pub fn read_auth_env() -> String {
         .parse::<String>()
         .unwrap()
 }
+
+pub const AUTH_HMAC_KEY_ENV_NAME: &str = "GAS_STATION_AUTH_HMAC_KEY";
+
+/// Only read when the server authenticates clients with HMAC signed tokens.
+pub fn read_auth_hmac_key_env() -> String {
+    std::env::var(AUTH_HMAC_KEY_ENV_NAME)
+        .ok()
+        .filter(|key| !key.is_empty())
+        .unwrap_or_else(|| {
+            panic!(
+                "{} environment variable must be specified",
+                AUTH_HMAC_KEY_ENV_NAME
+            )
+        })
+}
+
+pub const ADMIN_AUTH_ENV_NAME: &str = "GAS_STATION_ADMIN_AUTH";
+
+/// Admin endpoints are disabled unless the admin token is specified.
+pub fn read_admin_auth_env() -> Option<String> {
+    std::env::var(ADMIN_AUTH_ENV_NAME)
+        .ok()
+        .filter(|token| !token.is_empty())
+}
@@ -3,13 +3,26 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use mysten_metrics::histogram::Histogram;
+use mysten_metrics::RegistryService;
 use prometheus::{
     register_int_counter_vec_with_registry, register_int_counter_with_registry,
-    register_int_gauge_vec_with_registry, IntCounter, IntCounterVec, IntGaugeVec, Registry,
+    register_int_gauge_vec_with_registry, register_int_gauge_with_registry, IntCounter,
+    IntCounterVec, IntGauge, IntGaugeVec, Registry,
 };
+use std::net::{SocketAddr, TcpListener};
 use std::sync::Arc;
 use tracing::error;
 
+/// Starts the prometheus server that exports the registries added to the returned service on
+/// `address`. Fails if the server cannot listen on the address, e.g. because it is already in use,
+/// so that the caller can decide whether to keep running without metrics.
+pub fn start_metrics_server(address: SocketAddr) -> anyhow::Result<RegistryService> {
+    // The server listens from a background task, where failing to bind would bring down the whole
+    // process, so check that the address is available first.
+    drop(TcpListener::bind(address)?);
+    Ok(mysten_metrics::start_prometheus_server(address))
+}
+
 pub struct GasPoolRpcMetrics {
     // === RPC Server Metrics ===
     // RPC metrics for the reserve_gas endpoint
@@ -18,6 +31,21 @@ pub struct GasPoolRpcMetrics {
     pub num_successful_reserve_gas_requests: IntCounter,
     pub num_failed_reserve_gas_requests: IntCounter,
 
+    // RPC metrics for the claim_lease endpoint
+    pub num_claim_lease_requests: IntCounter,
+    pub num_successful_claim_lease_requests: IntCounter,
+    pub num_failed_claim_lease_requests: IntCounter,
+
+    // RPC metrics for the reserve_balance endpoint
+    pub num_reserve_balance_requests: IntCounter,
+    pub num_successful_reserve_balance_requests: IntCounter,
+    pub num_failed_reserve_balance_requests: IntCounter,
+
+    // RPC metrics for the cancel_reservation endpoint
+    pub num_cancel_reservation_requests: IntCounter,
+    pub num_successful_cancel_reservation_requests: IntCounter,
+    pub num_failed_cancel_reservation_requests: IntCounter,
+
     // Statistics about the gas reservation request
     pub target_gas_budget_per_request: Histogram,
     pub reserve_duration_per_request: Histogram,
@@ -27,6 +55,26 @@ pub struct GasPoolRpcMetrics {
     pub num_authorized_execute_tx_requests: IntCounter,
     pub num_successful_execute_tx_requests: IntCounter,
     pub num_failed_execute_tx_requests: IntCounter,
+    pub num_truncated_execute_tx_effects: IntCounter,
+
+    // RPC metrics for the batch_execute_tx endpoint
+    pub num_batch_execute_tx_requests: IntCounter,
+    pub num_authorized_batch_execute_tx_requests: IntCounter,
+    pub batch_execute_tx_size: Histogram,
+    pub num_reload_coins_requests: IntCounter,
+    pub num_authorized_reload_coins_requests: IntCounter,
+
+    // Authenticated requests to any endpoint, by the client id the auth token belongs to
+    pub num_authorized_requests_per_client: IntCounterVec,
+
+    // Requests that did not complete before their deadline
+    pub num_reserve_gas_timeouts: IntCounter,
+    pub num_execute_tx_timeouts: IntCounter,
+
+    // Usage by the tag of the reservation. Untagged reservations are not counted.
+    pub num_reservations_per_tag: IntCounterVec,
+    pub num_executions_per_tag: IntCounterVec,
+    pub gas_usage_per_tag: IntCounterVec,
 }
 
 impl GasPoolRpcMetrics {
@@ -56,6 +104,60 @@ impl GasPoolRpcMetrics {
                 registry,
             )
             .unwrap(),
+            num_claim_lease_requests: register_int_counter_with_registry!(
+                "num_claim_lease_requests",
+                "Total number of claim_lease RPC requests received",
+                registry,
+            )
+            .unwrap(),
+            num_successful_claim_lease_requests: register_int_counter_with_registry!(
+                "num_successful_claim_lease_requests",
+                "Total number of claim_lease RPC requests that were successful",
+                registry,
+            )
+            .unwrap(),
+            num_failed_claim_lease_requests: register_int_counter_with_registry!(
+                "num_failed_claim_lease_requests",
+                "Total number of claim_lease RPC requests that failed",
+                registry,
+            )
+            .unwrap(),
+            num_reserve_balance_requests: register_int_counter_with_registry!(
+                "num_reserve_balance_requests",
+                "Total number of reserve_balance RPC requests received",
+                registry,
+            )
+            .unwrap(),
+            num_successful_reserve_balance_requests: register_int_counter_with_registry!(
+                "num_successful_reserve_balance_requests",
+                "Total number of reserve_balance RPC requests that were successful",
+                registry,
+            )
+            .unwrap(),
+            num_failed_reserve_balance_requests: register_int_counter_with_registry!(
+                "num_failed_reserve_balance_requests",
+                "Total number of reserve_balance RPC requests that failed",
+                registry,
+            )
+            .unwrap(),
+            num_cancel_reservation_requests: register_int_counter_with_registry!(
+                "num_cancel_reservation_requests",
+                "Total number of cancel_reservation RPC requests received",
+                registry,
+            )
+            .unwrap(),
+            num_successful_cancel_reservation_requests: register_int_counter_with_registry!(
+                "num_successful_cancel_reservation_requests",
+                "Total number of cancel_reservation RPC requests that were successful",
+                registry,
+            )
+            .unwrap(),
+            num_failed_cancel_reservation_requests: register_int_counter_with_registry!(
+                "num_failed_cancel_reservation_requests",
+                "Total number of cancel_reservation RPC requests that failed",
+                registry,
+            )
+            .unwrap(),
             target_gas_budget_per_request: Histogram::new_in_registry(
                 "target_gas_budget_per_request",
                 "Target gas budget value in the reserve_gas RPC request",
@@ -90,6 +192,81 @@ impl GasPoolRpcMetrics {
                 registry,
             )
             .unwrap(),
+            num_truncated_execute_tx_effects: register_int_counter_with_registry!(
+                "num_truncated_execute_tx_effects",
+                "Total number of executed transactions whose effects were replaced by a summary because they were too large",
+                registry,
+            )
+            .unwrap(),
+            num_batch_execute_tx_requests: register_int_counter_with_registry!(
+                "num_batch_execute_tx_requests",
+                "Total number of batch_execute_tx RPC requests received",
+                registry,
+            )
+            .unwrap(),
+            num_authorized_batch_execute_tx_requests: register_int_counter_with_registry!(
+                "num_authorized_batch_execute_tx_requests",
+                "Total number of batch_execute_tx RPC requests that provided the correct auth token",
+                registry,
+            )
+            .unwrap(),
+            batch_execute_tx_size: Histogram::new_in_registry(
+                "batch_execute_tx_size",
+                "Number of transactions in each batch_execute_tx RPC request",
+                registry,
+            ),
+            num_reload_coins_requests: register_int_counter_with_registry!(
+                "num_reload_coins_requests",
+                "Total number of reload_coins admin requests received",
+                registry,
+            )
+            .unwrap(),
+            num_authorized_reload_coins_requests: register_int_counter_with_registry!(
+                "num_authorized_reload_coins_requests",
+                "Total number of reload_coins admin requests that provided the correct admin token",
+                registry,
+            )
+            .unwrap(),
+            num_authorized_requests_per_client: register_int_counter_vec_with_registry!(
+                "num_authorized_requests_per_client",
+                "Total number of authenticated RPC requests, by the client the auth token belongs to",
+                &["client_id"],
+                registry,
+            )
+            .unwrap(),
+            num_reserve_gas_timeouts: register_int_counter_with_registry!(
+                "num_reserve_gas_timeouts",
+                "Total number of reserve_gas requests that did not complete before their deadline",
+                registry,
+            )
+            .unwrap(),
+            num_execute_tx_timeouts: register_int_counter_with_registry!(
+                "num_execute_tx_timeouts",
+                "Total number of transactions that did not execute before the deadline of their request",
+                registry,
+            )
+            .unwrap(),
+            num_reservations_per_tag: register_int_counter_vec_with_registry!(
+                "num_reservations_per_tag",
+                "Total number of successful tagged reservations, by tag",
+                &["tag"],
+                registry,
+            )
+            .unwrap(),
+            num_executions_per_tag: register_int_counter_vec_with_registry!(
+                "num_executions_per_tag",
+                "Total number of transactions executed with tagged reservations, by tag",
+                &["tag"],
+                registry,
+            )
+            .unwrap(),
+            gas_usage_per_tag: register_int_counter_vec_with_registry!(
+                "gas_usage_per_tag",
+                "Total net gas used by transactions executed with tagged reservations, by tag. Transactions with a storage rebate larger than their cost count as zero",
+                &["tag"],
+                registry,
+            )
+            .unwrap(),
         })
     }
 
@@ -101,13 +278,48 @@ impl GasPoolRpcMetrics {
 pub struct GasPoolCoreMetrics {
     pub num_expired_gas_coins: IntCounterVec,
     pub num_smashed_gas_coins: IntCounterVec,
+    pub num_retired_gas_coins: IntCounterVec,
     pub reserved_gas_coin_count_per_request: Histogram,
     pub reserve_gas_latency_ms: Histogram,
     pub transaction_signing_latency_ms: Histogram,
     pub transaction_execution_latency_ms: Histogram,
     pub num_gas_pool_invariant_violations: IntCounter,
     pub daily_gas_usage: IntGaugeVec,
+    pub epoch_gas_spend: IntGaugeVec,
     pub num_equivocation_detected: IntCounter,
+    pub num_gas_coins_in_cooldown: IntGauge,
+    pub num_reservation_callbacks_sent: IntCounter,
+    pub num_reservation_callbacks_failed: IntCounter,
+    pub num_reservation_callbacks_dropped: IntCounter,
+    pub num_rebalance_split_coins: IntCounter,
+    pub num_rebalance_merged_coins: IntCounter,
+    pub num_failed_rebalance_transactions: IntCounter,
+    pub reservation_priority_wait_ms: Histogram,
+    pub num_reloaded_gas_coins: IntCounter,
+    pub coin_balance_audit_drift: IntGauge,
+    pub num_coin_balance_mismatches: IntCounter,
+    pub num_partial_reservations: IntCounter,
+    pub sponsor_address_mismatch: IntGauge,
+    pub num_audit_records_written: IntCounter,
+    pub num_dropped_audit_records: IntCounter,
+    pub num_released_stranded_gas_coins: IntCounter,
+    pub num_recovered_executing_reservations: IntCounterVec,
+    pub warming_up: IntGauge,
+    pub num_reservations_rejected_during_warmup: IntCounter,
+    pub num_move_call_rate_limited_transactions: IntCounter,
+    pub num_stale_gas_coin_rejections: IntCounter,
+    pub num_client_budget_limit_rejections: IntCounterVec,
+    pub safe_mode: IntGauge,
+    pub num_gas_coin_version_mismatches: IntCounter,
+    pub num_quarantined_gas_coins: IntCounter,
+    pub num_gas_coins_in_quarantine: IntGauge,
+    pub num_waiting_reservations: IntGauge,
+    pub num_served_waiting_reservations: IntCounter,
+    pub num_expired_waiting_reservations: IntCounter,
+    pub num_rejected_waiting_reservations: IntCounter,
+    pub num_preflight_checks: IntCounter,
+    pub num_preflight_failures: IntCounter,
+    pub num_preflight_errors: IntCounter,
 }
 
 impl GasPoolCoreMetrics {
@@ -132,6 +344,13 @@ impl GasPoolCoreMetrics {
                 registry,
             )
                 .unwrap(),
+            num_retired_gas_coins: register_int_counter_vec_with_registry!(
+                "num_retired_gas_coins",
+                "Total number of gas coins that are retired from the pool because their balance dropped below the threshold after transaction execution",
+                &["sponsor"],
+                registry,
+            )
+                .unwrap(),
             reserve_gas_latency_ms: Histogram::new_in_registry(
                 "reserve_gas_latency",
                 "Latency of gas reservation, in milliseconds",
@@ -160,12 +379,218 @@ impl GasPoolCoreMetrics {
                 registry,
             )
                 .unwrap(),
+            epoch_gas_spend: register_int_gauge_vec_with_registry!(
+                "epoch_gas_spend",
+                "Gas spent in the current epoch, only tracked when there is a per-epoch spend cap",
+                &["sponsor"],
+                registry,
+            )
+                .unwrap(),
             num_equivocation_detected: register_int_counter_with_registry!(
                 "num_equivocation_detected",
                 "Total number of equivocation detected",
                 registry,
             )
                 .unwrap(),
+            num_gas_coins_in_cooldown: register_int_gauge_with_registry!(
+                "num_gas_coins_in_cooldown",
+                "Current number of gas coins held back after execution before being released to the pool",
+                registry,
+            )
+                .unwrap(),
+            num_reservation_callbacks_sent: register_int_counter_with_registry!(
+                "num_reservation_callbacks_sent",
+                "Total number of reservation callbacks successfully delivered",
+                registry,
+            )
+                .unwrap(),
+            num_reservation_callbacks_failed: register_int_counter_with_registry!(
+                "num_reservation_callbacks_failed",
+                "Total number of reservation callbacks that failed after all retries",
+                registry,
+            )
+                .unwrap(),
+            num_reservation_callbacks_dropped: register_int_counter_with_registry!(
+                "num_reservation_callbacks_dropped",
+                "Total number of reservation callbacks dropped because the delivery queue was full",
+                registry,
+            )
+                .unwrap(),
+            num_rebalance_split_coins: register_int_counter_with_registry!(
+                "num_rebalance_split_coins",
+                "Total number of new coins split off large coins when rebalancing the pool",
+                registry,
+            )
+                .unwrap(),
+            num_rebalance_merged_coins: register_int_counter_with_registry!(
+                "num_rebalance_merged_coins",
+                "Total number of small coins merged away when rebalancing the pool",
+                registry,
+            )
+                .unwrap(),
+            num_failed_rebalance_transactions: register_int_counter_with_registry!(
+                "num_failed_rebalance_transactions",
+                "Total number of split or merge transactions that failed when rebalancing the pool",
+                registry,
+            )
+                .unwrap(),
+            reservation_priority_wait_ms: Histogram::new_in_registry(
+                "reservation_priority_wait",
+                "Time reservations spent waiting for their turn in priority order, in milliseconds",
+                registry,
+            ),
+            num_reloaded_gas_coins: register_int_counter_with_registry!(
+                "num_reloaded_gas_coins",
+                "Total number of coins discovered on chain and added to the pool by coin reloads",
+                registry,
+            )
+                .unwrap(),
+            coin_balance_audit_drift: register_int_gauge_with_registry!(
+                "coin_balance_audit_drift",
+                "Tracked total balance of available coins minus the sum of their balances, as of the last coin balance audit",
+                registry,
+            )
+                .unwrap(),
+            num_coin_balance_mismatches: register_int_counter_with_registry!(
+                "num_coin_balance_mismatches",
+                "Total number of available coins whose balance in the pool differed from the chain in coin balance audits",
+                registry,
+            )
+                .unwrap(),
+            num_partial_reservations: register_int_counter_with_registry!(
+                "num_partial_reservations",
+                "Total number of reservations that allowed a partial budget and could not be fully covered",
+                registry,
+            )
+                .unwrap(),
+            sponsor_address_mismatch: register_int_gauge_with_registry!(
+                "sponsor_address_mismatch",
+                "1 if the signer no longer signs for the sponsor address of the gas pool, which makes every execution fail. 0 otherwise",
+                registry,
+            )
+                .unwrap(),
+            num_audit_records_written: register_int_counter_with_registry!(
+                "num_audit_records_written",
+                "Total number of records written to the audit log",
+                registry,
+            )
+                .unwrap(),
+            num_dropped_audit_records: register_int_counter_with_registry!(
+                "num_dropped_audit_records",
+                "Total number of audit records that were dropped because too many were waiting to be written, or writing them failed",
+                registry,
+            )
+                .unwrap(),
+            num_released_stranded_gas_coins: register_int_counter_with_registry!(
+                "num_released_stranded_gas_coins",
+                "Total number of coins released on startup because their reservation was over but they were never released",
+                registry,
+            )
+                .unwrap(),
+            num_recovered_executing_reservations: register_int_counter_vec_with_registry!(
+                "num_recovered_executing_reservations",
+                "Total number of reservations whose coins were released after the gas pool stopped while executing their transaction, by whether the transaction was executed",
+                &["outcome"],
+                registry,
+            )
+                .unwrap(),
+            warming_up: register_int_gauge_with_registry!(
+                "warming_up",
+                "1 while the gas pool is warming up after startup and holds back reservations. 0 otherwise",
+                registry,
+            )
+                .unwrap(),
+            num_reservations_rejected_during_warmup: register_int_counter_with_registry!(
+                "num_reservations_rejected_during_warmup",
+                "Total number of reservations rejected because the gas pool was warming up",
+                registry,
+            )
+                .unwrap(),
+            num_move_call_rate_limited_transactions: register_int_counter_with_registry!(
+                "num_move_call_rate_limited_transactions",
+                "Total number of transactions rejected because they exceeded a move call rate limit",
+                registry,
+            )
+                .unwrap(),
+            num_stale_gas_coin_rejections: register_int_counter_with_registry!(
+                "num_stale_gas_coin_rejections",
+                "Total number of transactions rejected before execution because their gas coins changed since they were reserved",
+                registry,
+            )
+                .unwrap(),
+            num_client_budget_limit_rejections: register_int_counter_vec_with_registry!(
+                "num_client_budget_limit_rejections",
+                "Total number of reservations rejected because they exceeded the budget limits of the client",
+                &["client_id"],
+                registry,
+            )
+                .unwrap(),
+            safe_mode: register_int_gauge_with_registry!(
+                "safe_mode",
+                "1 while safe mode is on and background tasks do not change the pool. 0 otherwise",
+                registry,
+            )
+                .unwrap(),
+            num_gas_coin_version_mismatches: register_int_counter_with_registry!(
+                "num_gas_coin_version_mismatches",
+                "Total number of times a reserved gas coin was found at a newer version than it was reserved at, because it was used outside of the gas pool",
+                registry,
+            )
+                .unwrap(),
+            num_quarantined_gas_coins: register_int_counter_with_registry!(
+                "num_quarantined_gas_coins",
+                "Total number of gas coins that were quarantined because their version kept changing outside of the gas pool",
+                registry,
+            )
+                .unwrap(),
+            num_gas_coins_in_quarantine: register_int_gauge_with_registry!(
+                "num_gas_coins_in_quarantine",
+                "Current number of gas coins held back in quarantine before being released to the pool",
+                registry,
+            )
+                .unwrap(),
+            num_waiting_reservations: register_int_gauge_with_registry!(
+                "num_waiting_reservations",
+                "Current number of reservations waiting for coins on the reservation waitlist",
+                registry,
+            )
+                .unwrap(),
+            num_served_waiting_reservations: register_int_counter_with_registry!(
+                "num_served_waiting_reservations",
+                "Total number of waiting reservations that got their coins",
+                registry,
+            )
+                .unwrap(),
+            num_expired_waiting_reservations: register_int_counter_with_registry!(
+                "num_expired_waiting_reservations",
+                "Total number of waiting reservations that did not get coins before their wait expired",
+                registry,
+            )
+                .unwrap(),
+            num_rejected_waiting_reservations: register_int_counter_with_registry!(
+                "num_rejected_waiting_reservations",
+                "Total number of reservations that could not wait because the reservation waitlist was full",
+                registry,
+            )
+                .unwrap(),
+            num_preflight_checks: register_int_counter_with_registry!(
+                "num_preflight_checks",
+                "Total number of transactions that were checked with dev_inspect before execution",
+                registry,
+            )
+                .unwrap(),
+            num_preflight_failures: register_int_counter_with_registry!(
+                "num_preflight_failures",
+                "Total number of transactions that were rejected because dev_inspect showed they would fail",
+                registry,
+            )
+                .unwrap(),
+            num_preflight_errors: register_int_counter_with_registry!(
+                "num_preflight_errors",
+                "Total number of preflight checks that could not be run, after which the transaction was executed anyway",
+                registry,
+            )
+                .unwrap(),
         })
     }
 
@@ -189,6 +614,8 @@ pub struct StorageMetrics {
 
     pub num_reserve_gas_coins_requests: IntCounter,
     pub num_successful_reserve_gas_coins_requests: IntCounter,
+    pub coin_selection_latency_ms: Histogram,
+    pub coin_selection_candidate_count: IntGaugeVec,
     pub num_ready_for_execution_requests: IntCounter,
     pub num_successful_ready_for_execution_requests: IntCounter,
     pub num_add_new_coins_requests: IntCounter,
@@ -226,6 +653,18 @@ impl StorageMetrics {
                 registry,
             )
             .unwrap(),
+            coin_selection_latency_ms: Histogram::new_in_registry(
+                "coin_selection_latency",
+                "Latency of selecting and reserving gas coins in the storage, in milliseconds",
+                registry,
+            ),
+            coin_selection_candidate_count: register_int_gauge_vec_with_registry!(
+                "coin_selection_candidate_count",
+                "Number of candidate coins considered by the latest coin selection. A count well above the number of reserved coins per request means the pool is fragmented",
+                &["sponsor"],
+                registry,
+            )
+            .unwrap(),
             num_ready_for_execution_requests: register_int_counter_with_registry!(
                 "num_ready_for_execution_requests",
                 "Total number of ready_for_execution requests received",
@@ -269,3 +708,57 @@ impl StorageMetrics {
         Self::new(&Registry::new())
     }
 }
+
+pub struct FullnodeMetrics {
+    pub fullnode_circuit_breaker_state: IntGauge,
+    pub num_fullnode_circuit_breaker_trips: IntCounter,
+    pub num_fullnode_requests_rejected: IntCounter,
+    pub num_fullnode_rate_limited: IntCounter,
+}
+
+impl FullnodeMetrics {
+    pub fn new(registry: &Registry) -> Arc<Self> {
+        Arc::new(Self {
+            fullnode_circuit_breaker_state: register_int_gauge_with_registry!(
+                "fullnode_circuit_breaker_state",
+                "Current state of the fullnode circuit breaker: 0 = closed, 1 = open, 2 = half-open",
+                registry,
+            )
+            .unwrap(),
+            num_fullnode_circuit_breaker_trips: register_int_counter_with_registry!(
+                "num_fullnode_circuit_breaker_trips",
+                "Total number of times the fullnode circuit breaker opened",
+                registry,
+            )
+            .unwrap(),
+            num_fullnode_requests_rejected: register_int_counter_with_registry!(
+                "num_fullnode_requests_rejected",
+                "Total number of fullnode requests failed fast by the circuit breaker",
+                registry,
+            )
+            .unwrap(),
+            num_fullnode_rate_limited: register_int_counter_with_registry!(
+                "num_fullnode_rate_limited",
+                "Total number of fullnode requests refused because of rate limiting",
+                registry,
+            )
+            .unwrap(),
+        })
+    }
+
+    pub fn new_for_testing() -> Arc<Self> {
+        Self::new(&Registry::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_start_metrics_server_on_used_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        assert!(start_metrics_server(listener.local_addr().unwrap()).is_err());
+        drop(listener);
+    }
+}
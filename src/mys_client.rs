@@ -2,54 +2,386 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::circuit_breaker::{CircuitBreaker, CircuitState};
+use crate::config::{CircuitBreakerConfig, ExecuteRetryConfig, FullnodeClientConfig};
+use crate::log_redaction::redacted;
+use crate::metrics::FullnodeMetrics;
 use crate::object_locks::MultiGetObjectOwners;
+use crate::rate_limit::{is_rate_limited, RateLimitBackoff};
 use crate::types::GasCoin;
 use crate::{retry_forever, retry_with_max_attempts};
 use futures_util::stream::FuturesUnordered;
 use futures_util::StreamExt;
 use itertools::Itertools;
-use std::collections::HashMap;
-use std::time::Duration;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use mys_json_rpc_types::MysTransactionBlockEffectsAPI;
 use mys_json_rpc_types::{
-    MysData, MysObjectDataOptions, MysObjectResponse, MysTransactionBlockEffects,
-    MysTransactionBlockResponseOptions,
+    MysData, MysExecutionStatus, MysObjectDataOptions, MysObjectResponse,
+    MysTransactionBlockEffects, MysTransactionBlockResponseOptions,
 };
 use mys_sdk::MysClientBuilder;
 use mys_types::base_types::{ObjectID, ObjectRef, MysAddress};
-use mys_types::coin::{PAY_MODULE_NAME, PAY_SPLIT_N_FUNC_NAME};
+use mys_types::coin::{Coin, PAY_MODULE_NAME, PAY_SPLIT_N_FUNC_NAME};
+use mys_types::committee::EpochId;
+use mys_types::digests::TransactionDigest;
 use mys_types::gas_coin::GAS;
 use mys_types::object::Owner;
 use mys_types::programmable_transaction_builder::ProgrammableTransactionBuilder;
 use mys_types::quorum_driver_types::ExecuteTransactionRequestType;
 use mys_types::transaction::{
-    Argument, ObjectArg, ProgrammableTransaction, Transaction, TransactionKind,
+    Argument, Command, ObjectArg, ProgrammableTransaction, Transaction, TransactionData,
+    TransactionDataAPI, TransactionKind,
 };
-use mys_types::MYS_FRAMEWORK_PACKAGE_ID;
+use mys_types::{parse_mys_type_tag, TypeTag, MYS_FRAMEWORK_PACKAGE_ID};
 use tap::TapFallible;
-use tracing::{debug, info};
+use tokio_retry::strategy::jitter;
+use tracing::{debug, info, warn};
+
+/// How long reads go to the primary fullnode after a request to the read replica failed.
+const READ_REPLICA_RECOVERY_INTERVAL: Duration = Duration::from_secs(30);
+/// How many times a failed dev_inspect is retried before falling back to the configured gas cost
+/// per object.
+const DEV_INSPECT_MAX_RETRIES: usize = 3;
+/// Errors returned by fullnodes that do not serve dev_inspect, as opposed to transient errors.
+const DEV_INSPECT_UNSUPPORTED_MARKERS: &[&str] = &[
+    "method not found",
+    "-32601",
+    "not supported",
+    "unsupported",
+    "disabled",
+];
+
+/// Parses the configured type of the coins managed by the pool, e.g. `0x2::mys::MYS`. This is the
+/// type parameter `T` of `Coin<T>`, so it must be a struct type.
+pub fn parse_gas_coin_type(coin_type: &str) -> anyhow::Result<TypeTag> {
+    let type_tag = parse_mys_type_tag(coin_type)?;
+    if !matches!(type_tag, TypeTag::Struct(_)) {
+        anyhow::bail!("Gas coin type {} is not a struct type", coin_type);
+    }
+    Ok(type_tag)
+}
+
+/// Whether the error means that the fullnode does not serve dev_inspect at all.
+fn is_dev_inspect_unsupported(err: &anyhow::Error) -> bool {
+    let message = format!("{:#}", err).to_lowercase();
+    DEV_INSPECT_UNSUPPORTED_MARKERS
+        .iter()
+        .any(|marker| message.contains(marker))
+}
+
+/// Extracts the abort code from the error of a transaction that failed with a Move abort, which
+/// is formatted as `MoveAbort(<location>, <code>) in command <index>`.
+pub fn move_abort_code(error: &str) -> Option<u64> {
+    let (_, abort) = error.split_once("MoveAbort(")?;
+    let abort = abort.split(" in command").next()?.trim_end();
+    let abort = abort.strip_suffix(')')?;
+    let (_, code) = abort.rsplit_once(", ")?;
+    code.parse().ok()
+}
 
 #[derive(Clone)]
 pub struct MysClient {
     mys_client: mys_sdk::MysClient,
+    client_config: FullnodeClientConfig,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    execute_retry_config: ExecuteRetryConfig,
+    coin_denylist: Arc<HashSet<ObjectID>>,
+    read_replica: Option<Arc<ReadReplica>>,
+    rate_limit_backoff: Arc<RateLimitBackoff>,
+    metrics: Option<Arc<FullnodeMetrics>>,
+    /// The type parameter of the coins managed by the pool. GAS unless configured otherwise.
+    gas_coin_type: TypeTag,
+    /// The gas cost per object to use when dev_inspect is not available.
+    fallback_gas_cost_per_object: Option<u64>,
+    last_gas_cost_calibration: Arc<Mutex<Option<GasCostCalibration>>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GasCostCalibrationSource {
+    DevInspect,
+    /// The configured fallback cost, because dev_inspect is not supported by the fullnode or
+    /// kept failing.
+    Fallback,
+}
+
+/// The result of the last `calibrate_gas_cost_per_object`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct GasCostCalibration {
+    pub source: GasCostCalibrationSource,
+    pub gas_cost_per_object: u64,
+}
+
+/// A fullnode that serves the read requests, so that the primary fullnode only has to handle
+/// transaction executions. After a failed request it is skipped for a while.
+struct ReadReplica {
+    mys_client: mys_sdk::MysClient,
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl ReadReplica {
+    fn is_healthy(&self) -> bool {
+        self.unhealthy_until
+            .lock()
+            .map_or(true, |until| Instant::now() >= until)
+    }
+
+    fn mark_unhealthy(&self) {
+        *self.unhealthy_until.lock() = Some(Instant::now() + READ_REPLICA_RECOVERY_INTERVAL);
+    }
+}
+
+async fn build_sdk_client(
+    fullnode_url: &str,
+    basic_auth: Option<(String, String)>,
+    client_config: &FullnodeClientConfig,
+) -> mys_sdk::MysClient {
+    try_build_sdk_client(fullnode_url, basic_auth, client_config)
+        .await
+        .unwrap_or_else(|err| panic!("{:?}", err))
+}
+
+async fn try_build_sdk_client(
+    fullnode_url: &str,
+    basic_auth: Option<(String, String)>,
+    client_config: &FullnodeClientConfig,
+) -> anyhow::Result<mys_sdk::MysClient> {
+    let mut mys_client_builder = MysClientBuilder::default()
+        .max_concurrent_requests(client_config.max_concurrent_requests)
+        .request_timeout(Duration::from_secs(client_config.request_timeout_secs));
+    if let Some((username, password)) = basic_auth {
+        mys_client_builder = mys_client_builder.basic_auth(username, password);
+    }
+    mys_client_builder.build(fullnode_url).await.map_err(|err| {
+        anyhow::anyhow!(
+            "Failed to connect to MySocial fullnode at '{}'. \
+            Please check that the FULLNODE_URL environment variable is set to a valid MySocial RPC endpoint. \
+            Error: {:?}",
+            fullnode_url,
+            err
+        )
+    })
 }
 
 impl MysClient {
     pub async fn new(fullnode_url: &str, basic_auth: Option<(String, String)>) -> Self {
-        let mut mys_client_builder = MysClientBuilder::default().max_concurrent_requests(100000);
-        if let Some((username, password)) = basic_auth {
-            mys_client_builder = mys_client_builder.basic_auth(username, password);
+        Self::new_with_client_config(fullnode_url, basic_auth, FullnodeClientConfig::default())
+            .await
+    }
+
+    /// Same as `new`, but connects to the fullnode, and to the read replica if any, with the
+    /// given client config instead of the default one.
+    pub async fn new_with_client_config(
+        fullnode_url: &str,
+        basic_auth: Option<(String, String)>,
+        client_config: FullnodeClientConfig,
+    ) -> Self {
+        let mys_client = build_sdk_client(fullnode_url, basic_auth, &client_config).await;
+        Self::from_sdk_client(mys_client, client_config)
+    }
+
+    /// Same as `new_with_client_config`, but fails instead of panicking when the fullnode cannot
+    /// be reached.
+    pub async fn try_new_with_client_config(
+        fullnode_url: &str,
+        basic_auth: Option<(String, String)>,
+        client_config: FullnodeClientConfig,
+    ) -> anyhow::Result<Self> {
+        let mys_client = try_build_sdk_client(fullnode_url, basic_auth, &client_config).await?;
+        Ok(Self::from_sdk_client(mys_client, client_config))
+    }
+
+    fn from_sdk_client(
+        mys_client: mys_sdk::MysClient,
+        client_config: FullnodeClientConfig,
+    ) -> Self {
+        Self {
+            mys_client,
+            client_config,
+            circuit_breaker: None,
+            execute_retry_config: ExecuteRetryConfig::default(),
+            coin_denylist: Arc::new(HashSet::new()),
+            read_replica: None,
+            rate_limit_backoff: Arc::new(RateLimitBackoff::new(None)),
+            metrics: None,
+            gas_coin_type: GAS::type_tag(),
+            fallback_gas_cost_per_object: None,
+            last_gas_cost_calibration: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Report fullnode metrics, such as how often the fullnode rate limits the gas pool.
+    pub fn with_metrics(mut self, metrics: Arc<FullnodeMetrics>) -> Self {
+        self.rate_limit_backoff = Arc::new(RateLimitBackoff::new(Some(metrics.clone())));
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Send all read requests to the given fullnode instead of the primary one, which is then
+    /// only used to execute transactions, and for reads while the read replica is unhealthy.
+    pub async fn with_read_replica(
+        mut self,
+        fullnode_url: &str,
+        basic_auth: Option<(String, String)>,
+    ) -> Self {
+        self.read_replica = Some(Arc::new(ReadReplica {
+            mys_client: build_sdk_client(fullnode_url, basic_auth, &self.client_config).await,
+            unhealthy_until: Mutex::new(None),
+        }));
+        self
+    }
+
+    /// Route all fullnode requests through a circuit breaker, so that requests fail fast with
+    /// `GasStationError::FullnodeUnavailable` while the fullnode is persistently failing.
+    pub fn with_circuit_breaker(
+        mut self,
+        config: CircuitBreakerConfig,
+        metrics: Arc<FullnodeMetrics>,
+    ) -> Self {
+        self.circuit_breaker = Some(Arc::new(CircuitBreaker::new(config, metrics)));
+        self
+    }
+
+    /// Retry failed transaction executions according to the given config instead of the default.
+    pub fn with_execute_retry_config(mut self, config: ExecuteRetryConfig) -> Self {
+        self.execute_retry_config = config;
+        self
+    }
+
+    /// Never return the given coins when listing the coins owned by an address, so that they are
+    /// never added to the pool.
+    pub fn with_coin_denylist(
+        mut self,
+        coin_denylist: impl IntoIterator<Item = ObjectID>,
+    ) -> Self {
+        self.coin_denylist = Arc::new(coin_denylist.into_iter().collect());
+        self
+    }
+
+    /// Manage coins of the given type instead of GAS, on networks that accept it for gas payment.
+    pub fn with_gas_coin_type(mut self, gas_coin_type: TypeTag) -> Self {
+        self.gas_coin_type = gas_coin_type;
+        self
+    }
+
+    pub fn gas_coin_type(&self) -> &TypeTag {
+        &self.gas_coin_type
+    }
+
+    /// Checks that the gas coin type is a coin type known to the fullnode. GAS always is.
+    pub async fn validate_gas_coin_type(&self) -> anyhow::Result<()> {
+        if self.gas_coin_type == GAS::type_tag() {
+            return Ok(());
+        }
+        let (client, replica) = self.read_client();
+        let metadata = self
+            .guarded_read(
+                replica,
+                client
+                    .coin_read_api()
+                    .get_coin_metadata(self.gas_coin_type.to_string()),
+            )
+            .await?;
+        if metadata.is_none() {
+            anyhow::bail!(
+                "Gas coin type {} has no coin metadata on chain",
+                self.gas_coin_type
+            );
+        }
+        Ok(())
+    }
+
+    /// Use the given gas cost per object when calibrating it with dev_inspect is not possible,
+    /// e.g. because the fullnode disables dev_inspect.
+    pub fn with_fallback_gas_cost_per_object(mut self, gas_cost_per_object: Option<u64>) -> Self {
+        self.fallback_gas_cost_per_object = gas_cost_per_object;
+        self
+    }
+
+    /// How the gas cost per object was last calibrated, or None if it never was.
+    pub fn last_gas_cost_calibration(&self) -> Option<GasCostCalibration> {
+        *self.last_gas_cost_calibration.lock()
+    }
+
+    /// The state of the circuit breaker, or None if no circuit breaker is configured.
+    pub fn circuit_state(&self) -> Option<CircuitState> {
+        self.circuit_breaker.as_ref().map(|breaker| breaker.state())
+    }
+
+    /// Fails fast if the circuit breaker is currently open.
+    pub fn check_available(&self) -> anyhow::Result<()> {
+        if let Some(breaker) = &self.circuit_breaker {
+            breaker.check_without_probe()?;
+        }
+        Ok(())
+    }
+
+    /// Sends a fullnode request through the circuit breaker, recording its outcome.
+    /// While the fullnode is rate limiting the gas pool, the request first waits for the rate
+    /// limit backoff, which makes the retry macros around fullnode requests back off for much
+    /// longer than they do for other failures.
+    async fn guarded<T, E>(&self, request: impl Future<Output = Result<T, E>>) -> anyhow::Result<T>
+    where
+        E: Into<anyhow::Error>,
+    {
+        self.rate_limit_backoff.wait().await;
+        let result = match &self.circuit_breaker {
+            Some(breaker) => {
+                breaker.check()?;
+                let result = request.await.map_err(Into::into);
+                match &result {
+                    Ok(_) => breaker.record_success(),
+                    Err(_) => breaker.record_failure(),
+                }
+                result
+            }
+            None => request.await.map_err(Into::into),
+        };
+        self.rate_limit_backoff.record(&result);
+        result
+    }
+
+    /// Picks the fullnode for a read request: the read replica while it is healthy, the primary
+    /// fullnode otherwise. The replica, if returned, must be passed to `guarded_read`.
+    fn read_client(&self) -> (&mys_sdk::MysClient, Option<&ReadReplica>) {
+        match self.read_replica.as_deref() {
+            Some(replica) if replica.is_healthy() => (&replica.mys_client, Some(replica)),
+            _ => (&self.mys_client, None),
         }
-        let mys_client = mys_client_builder.build(fullnode_url).await
-            .unwrap_or_else(|err| {
-                panic!(
-                    "Failed to connect to MySocial fullnode at '{}'. \
-                    Please check that the FULLNODE_URL environment variable is set to a valid MySocial RPC endpoint. \
-                    Error: {:?}",
-                    fullnode_url, err
-                );
-            });
-        Self { mys_client }
+    }
+
+    /// Sends a read request obtained from `read_client`. Requests to the primary fullnode go
+    /// through the circuit breaker, while a failed request to the read replica marks it unhealthy
+    /// so that the retry goes to the primary fullnode.
+    async fn guarded_read<T, E>(
+        &self,
+        replica: Option<&ReadReplica>,
+        request: impl Future<Output = Result<T, E>>,
+    ) -> anyhow::Result<T>
+    where
+        E: Into<anyhow::Error>,
+    {
+        let Some(replica) = replica else {
+            return self.guarded(request).await;
+        };
+        let result = request.await.map_err(Into::into);
+        if let Err(err) = &result {
+            if let Some(metrics) = self.metrics.as_ref().filter(|_| is_rate_limited(err)) {
+                metrics.num_fullnode_rate_limited.inc();
+            }
+            warn!(
+                "Read replica request failed, using the primary fullnode for {:?}: {:?}",
+                READ_REPLICA_RECOVERY_INTERVAL, err
+            );
+            replica.mark_unhealthy();
+        }
+        result
     }
 
     pub async fn get_all_owned_mys_coins_above_balance_threshold(
@@ -65,14 +397,28 @@ impl MysClient {
         let mut coins = Vec::new();
         loop {
             let page = retry_forever!(async {
-                self.mys_client
-                    .coin_read_api()
-                    .get_coins(address, Some(GAS::type_tag().to_string()), cursor.clone(), None)
-                    .await
-                    .tap_err(|err| debug!("Failed to get owned gas coins: {:?}", err))
+                let (client, replica) = self.read_client();
+                self.guarded_read(
+                    replica,
+                    client.coin_read_api().get_coins(
+                        address,
+                        Some(self.gas_coin_type.to_string()),
+                        cursor.clone(),
+                        None,
+                    ),
+                )
+                .await
+                .tap_err(|err| debug!("Failed to get owned gas coins: {:?}", err))
             })
             .unwrap();
             for coin in page.data {
+                if self.coin_denylist.contains(&coin.coin_object_id) {
+                    warn!(
+                        "Skipping coin {:?} with balance {} because it is in the coin denylist",
+                        coin.coin_object_id, coin.balance
+                    );
+                    continue;
+                }
                 if coin.balance >= balance_threshold {
                     coins.push(GasCoin {
                         object_ref: coin.object_ref(),
@@ -91,17 +437,51 @@ impl MysClient {
 
     pub async fn get_reference_gas_price(&self) -> u64 {
         retry_forever!(async {
-            self.mys_client
-                .governance_api()
-                .get_reference_gas_price()
+            self.try_get_reference_gas_price()
                 .await
                 .tap_err(|err| debug!("Failed to get reference gas price: {:?}", err))
         })
         .unwrap()
     }
 
+    /// Returns the number and the total balance of the gas coins owned by the address, without
+    /// retrying.
+    pub async fn try_get_gas_coin_balance(
+        &self,
+        address: MysAddress,
+    ) -> anyhow::Result<(usize, u128)> {
+        let (client, replica) = self.read_client();
+        let balance = self
+            .guarded_read(
+                replica,
+                client
+                    .coin_read_api()
+                    .get_balance(address, Some(self.gas_coin_type.to_string())),
+            )
+            .await?;
+        Ok((balance.coin_object_count, balance.total_balance))
+    }
+
+    /// Same as `get_reference_gas_price`, but fails instead of retrying.
+    pub async fn try_get_reference_gas_price(&self) -> anyhow::Result<u64> {
+        let (client, replica) = self.read_client();
+        self.guarded_read(replica, client.governance_api().get_reference_gas_price())
+            .await
+    }
+
+    pub async fn get_current_epoch(&self) -> anyhow::Result<EpochId> {
+        let (client, replica) = self.read_client();
+        let system_state = self
+            .guarded_read(replica, client.governance_api().get_latest_mys_system_state())
+            .await?;
+        Ok(system_state.epoch)
+    }
+
+    /// Gets the latest version of each gas coin. A coin maps to None if it no longer exists, or
+    /// is no longer owned by `owner`, so that it is never treated as available.
     pub async fn get_latest_gas_objects(
         &self,
+        owner: MysAddress,
         object_ids: impl IntoIterator<Item = ObjectID>,
     ) -> HashMap<ObjectID, Option<GasCoin>> {
         let tasks: FuturesUnordered<_> = object_ids
@@ -110,19 +490,20 @@ impl MysClient {
             .into_iter()
             .map(|chunk| {
                 let chunk: Vec<_> = chunk.collect();
-                let mys_client = self.mys_client.clone();
+                let client = self.clone();
                 tokio::spawn(async move {
                     retry_forever!(async {
                         let chunk = chunk.clone();
-                        let result = mys_client
-                            .clone()
-                            .read_api()
-                            .multi_get_object_with_options(
-                                chunk.clone(),
-                                MysObjectDataOptions::default().with_bcs(),
+                        let (read_client, replica) = client.read_client();
+                        let result = client
+                            .guarded_read(
+                                replica,
+                                read_client.read_api().multi_get_object_with_options(
+                                    chunk.clone(),
+                                    MysObjectDataOptions::default().with_bcs().with_owner(),
+                                ),
                             )
-                            .await
-                            .map_err(anyhow::Error::from)?;
+                            .await?;
                         if result.len() != chunk.len() {
                             anyhow::bail!(
                                 "Unable to get all gas coins, got {} out of {}",
@@ -141,13 +522,13 @@ impl MysClient {
         objects
             .into_iter()
             .map(|(id, response)| {
-                let object = match Self::try_get_mys_coin_balance(&response) {
+                let object = match self.try_get_mys_coin_balance(owner, &response) {
                     Some(coin) => {
                         debug!("Got updated gas coin info: {:?}", coin);
                         Some(coin)
                     }
                     None => {
-                        debug!("Object no longer exists: {:?}", id);
+                        debug!("Object no longer exists or changed owner: {:?}", id);
                         None
                     }
                 };
@@ -158,6 +539,7 @@ impl MysClient {
 
     pub fn construct_coin_split_pt(
         gas_coin: Argument,
+        gas_coin_type: TypeTag,
         split_count: u64,
     ) -> ProgrammableTransaction {
         let mut pt_builder = ProgrammableTransactionBuilder::new();
@@ -166,12 +548,42 @@ impl MysClient {
             MYS_FRAMEWORK_PACKAGE_ID,
             PAY_MODULE_NAME.into(),
             PAY_SPLIT_N_FUNC_NAME.into(),
-            vec![GAS::type_tag()],
+            vec![gas_coin_type],
             vec![gas_coin, pure_arg],
         );
         pt_builder.finish()
     }
 
+    /// Constructs a programmable transaction that splits `count` coins with balance `amount` off
+    /// the gas coin and transfers them to the owner.
+    pub fn construct_split_off_gas_coin_pt(
+        owner: MysAddress,
+        amount: u64,
+        count: u16,
+    ) -> ProgrammableTransaction {
+        let mut pt_builder = ProgrammableTransactionBuilder::new();
+        let amount_arg = pt_builder.pure(amount).unwrap();
+        let Argument::Result(split_result) = pt_builder.command(Command::SplitCoins(
+            Argument::GasCoin,
+            vec![amount_arg; count as usize],
+        )) else {
+            unreachable!("Commands always return Argument::Result");
+        };
+        let new_coins = (0..count)
+            .map(|i| Argument::NestedResult(split_result, i))
+            .collect();
+        pt_builder.transfer_args(owner, new_coins);
+        pt_builder.finish()
+    }
+
+    /// Constructs a programmable transaction that transfers the gas coin to the destination.
+    /// All the gas payment coins are merged into the gas coin prior to the transfer.
+    pub fn construct_transfer_gas_coin_pt(destination: MysAddress) -> ProgrammableTransaction {
+        let mut pt_builder = ProgrammableTransactionBuilder::new();
+        pt_builder.transfer_arg(destination, Argument::GasCoin);
+        pt_builder.finish()
+    }
+
     pub async fn calibrate_gas_cost_per_object(
         &self,
         sponsor_address: MysAddress,
@@ -187,63 +599,203 @@ impl MysClient {
             MYS_FRAMEWORK_PACKAGE_ID,
             PAY_MODULE_NAME.into(),
             PAY_SPLIT_N_FUNC_NAME.into(),
-            vec![GAS::type_tag()],
+            vec![self.gas_coin_type.clone()],
             vec![object_arg, pure_arg],
         );
         let pt = pt_builder.finish();
-        let response = retry_forever!(async {
-            self.mys_client
-                .read_api()
-                .dev_inspect_transaction_block(
+        let calibration = match self.fallback_gas_cost_per_object {
+            None => {
+                let gas_used = retry_forever!(self.dev_inspect_gas_used(sponsor_address, &pt))
+                    .unwrap();
+                GasCostCalibration {
+                    source: GasCostCalibrationSource::DevInspect,
+                    // Multiply by 2 to be conservative and resilient to precision loss.
+                    gas_cost_per_object: gas_used / SPLIT_COUNT * 2,
+                }
+            }
+            Some(fallback_gas_cost_per_object) => {
+                // Transient errors are retried a few times, but there is no point retrying
+                // when the fullnode does not support dev_inspect at all.
+                let retry_strategy = tokio_retry::strategy::ExponentialBackoff::from_millis(50)
+                    .max_delay(Duration::from_secs(1))
+                    .take(DEV_INSPECT_MAX_RETRIES)
+                    .map(jitter);
+                let result = tokio_retry::RetryIf::spawn(
+                    retry_strategy,
+                    || self.dev_inspect_gas_used(sponsor_address, &pt),
+                    |err: &anyhow::Error| !is_dev_inspect_unsupported(err),
+                )
+                .await;
+                match result {
+                    Ok(gas_used) => GasCostCalibration {
+                        source: GasCostCalibrationSource::DevInspect,
+                        gas_cost_per_object: gas_used / SPLIT_COUNT * 2,
+                    },
+                    Err(err) => {
+                        warn!(
+                            "Failed to calibrate the gas cost per object with dev_inspect, using the fallback cost {}: {:?}",
+                            fallback_gas_cost_per_object, err
+                        );
+                        GasCostCalibration {
+                            source: GasCostCalibrationSource::Fallback,
+                            gas_cost_per_object: fallback_gas_cost_per_object,
+                        }
+                    }
+                }
+            }
+        };
+        *self.last_gas_cost_calibration.lock() = Some(calibration);
+        calibration.gas_cost_per_object
+    }
+
+    async fn dev_inspect_gas_used(
+        &self,
+        sponsor_address: MysAddress,
+        pt: &ProgrammableTransaction,
+    ) -> anyhow::Result<u64> {
+        let (client, replica) = self.read_client();
+        let response = self
+            .guarded_read(
+                replica,
+                client.read_api().dev_inspect_transaction_block(
                     sponsor_address,
                     TransactionKind::ProgrammableTransaction(pt.clone()),
                     None,
                     None,
                     None,
-                )
-                .await
-        })
-        .unwrap();
-        let gas_used = response.effects.gas_cost_summary().gas_used();
-        // Multiply by 2 to be conservative and resilient to precision loss.
-        gas_used / SPLIT_COUNT * 2
+                ),
+            )
+            .await?;
+        Ok(response.effects.gas_cost_summary().gas_used())
     }
 
+    /// Runs the transaction with dev_inspect, without executing it, and returns whether it would
+    /// succeed. dev_inspect does not charge gas and skips some of the checks done on execution,
+    /// so a transaction that passes may still fail once executed.
+    pub async fn dev_inspect_transaction(
+        &self,
+        tx_data: &TransactionData,
+    ) -> anyhow::Result<MysExecutionStatus> {
+        let (client, replica) = self.read_client();
+        let response = self
+            .guarded_read(
+                replica,
+                client.read_api().dev_inspect_transaction_block(
+                    tx_data.sender(),
+                    tx_data.kind().clone(),
+                    None,
+                    None,
+                    None,
+                ),
+            )
+            .await?;
+        if let Some(error) = response.error {
+            return Ok(MysExecutionStatus::Failure { error });
+        }
+        Ok(response.effects.status().clone())
+    }
+
+    /// Executes the transaction, retrying according to the execute retry config. Errors after
+    /// which the transaction may have been submitted are never retried.
     pub async fn execute_transaction(
         &self,
         tx: Transaction,
-        max_attempts: usize,
     ) -> anyhow::Result<MysTransactionBlockEffects> {
+        self.execute_transaction_impl(tx, false)
+            .await
+            .map(|(effects, _)| effects)
+    }
+
+    /// Same as `execute_transaction`, but also returns the BCS bytes of the effects
+    /// as certified by the validators and returned by the fullnode.
+    pub async fn execute_transaction_with_raw_effects(
+        &self,
+        tx: Transaction,
+    ) -> anyhow::Result<(MysTransactionBlockEffects, Vec<u8>)> {
+        self.execute_transaction_impl(tx, true).await
+    }
+
+    async fn execute_transaction_impl(
+        &self,
+        tx: Transaction,
+        with_raw_effects: bool,
+    ) -> anyhow::Result<(MysTransactionBlockEffects, Vec<u8>)> {
         let digest = *tx.digest();
-        debug!(?digest, "Executing transaction: {:?}", tx);
-        let response = retry_with_max_attempts!(
-            async {
-                self.mys_client
-                    .quorum_driver_api()
-                    .execute_transaction_block(
-                        tx.clone(),
-                        MysTransactionBlockResponseOptions::new().with_effects(),
-                        Some(ExecuteTransactionRequestType::WaitForEffectsCert),
-                    )
-                    .await
-                    .tap_err(|err| debug!(?digest, "execute_transaction error: {:?}", err))
-                    .map_err(anyhow::Error::from)
-                    .and_then(|r| r.effects.ok_or_else(|| anyhow::anyhow!("No effects")))
-            },
-            max_attempts
+        debug!(?digest, "Executing transaction: {:?}", redacted(&tx));
+        let mut options = MysTransactionBlockResponseOptions::new().with_effects();
+        if with_raw_effects {
+            options = options.with_raw_effects();
+        }
+        // Do not go through the retries if we already know the fullnode is unavailable.
+        self.check_available()?;
+        let mut attempts = 0;
+        let response = loop {
+            attempts += 1;
+            let result = self
+                .guarded(self.mys_client.quorum_driver_api().execute_transaction_block(
+                    tx.clone(),
+                    options.clone(),
+                    Some(ExecuteTransactionRequestType::WaitForEffectsCert),
+                ))
+                .await
+                .tap_err(|err| debug!(?digest, "execute_transaction error: {:?}", err))
+                .and_then(|r| {
+                    let effects = r.effects.ok_or_else(|| anyhow::anyhow!("No effects"))?;
+                    Ok((effects, r.raw_effects))
+                });
+            match result {
+                Err(err) if self.execute_retry_config.should_retry(&err, attempts) => {
+                    let backoff = jitter(self.execute_retry_config.backoff(attempts));
+                    debug!(?digest, ?backoff, "Retrying transaction execution");
+                    tokio::time::sleep(backoff).await;
+                }
+                result => break result,
+            }
+        };
+        debug!(
+            ?digest,
+            "Transaction execution response: {:?}",
+            redacted(&response)
         );
-        debug!(?digest, "Transaction execution response: {:?}", response);
         response
     }
 
+    /// Returns the effects of the transaction, or None if the fullnode does not know about it,
+    /// i.e. the transaction was never executed, as far as the fullnode can tell.
+    pub async fn get_transaction_effects(
+        &self,
+        digest: TransactionDigest,
+    ) -> anyhow::Result<Option<MysTransactionBlockEffects>> {
+        let (client, replica) = self.read_client();
+        let responses = self
+            .guarded_read(
+                replica,
+                client.read_api().multi_get_transactions_with_options(
+                    vec![digest],
+                    MysTransactionBlockResponseOptions::new().with_effects(),
+                ),
+            )
+            .await?;
+        Ok(responses
+            .into_iter()
+            .find(|response| response.digest == digest)
+            .and_then(|response| response.effects))
+    }
+
     /// Wait for a known valid object version to be available on the fullnode.
+    /// This waits on the fullnode that serves reads, since that is where the object is read next.
     pub async fn wait_for_object(&self, obj_ref: ObjectRef) {
         loop {
-            let response = self
-                .mys_client
+            let (client, replica) = self.read_client();
+            let response = client
                 .read_api()
                 .get_object_with_options(obj_ref.0, MysObjectDataOptions::default())
                 .await;
+            if response.is_err() {
+                if let Some(replica) = replica {
+                    replica.mark_unhealthy();
+                }
+            }
             if let Ok(MysObjectResponse {
                 data: Some(data), ..
             }) = response
@@ -256,17 +808,25 @@ impl MysClient {
         }
     }
 
-    fn try_get_mys_coin_balance(object: &MysObjectResponse) -> Option<GasCoin> {
+    fn try_get_mys_coin_balance(
+        &self,
+        owner: MysAddress,
+        object: &MysObjectResponse,
+    ) -> Option<GasCoin> {
         let data = object.data.as_ref()?;
+        // A coin transferred away still parses as a gas coin, but can no longer pay for gas.
+        if data.owner != Some(Owner::AddressOwner(owner)) {
+            return None;
+        }
         let object_ref = data.object_ref();
         let move_obj = data.bcs.as_ref()?.try_as_move()?;
-        if move_obj.type_ != mys_types::gas_coin::GasCoin::type_() {
+        if move_obj.type_ != Coin::type_(self.gas_coin_type.clone()) {
             return None;
         }
-        let gas_coin: mys_types::gas_coin::GasCoin = bcs::from_bytes(&move_obj.bcs_bytes).ok()?;
+        let coin: Coin = bcs::from_bytes(&move_obj.bcs_bytes).ok()?;
         Some(GasCoin {
             object_ref,
-            balance: gas_coin.value(),
+            balance: coin.value(),
         })
     }
 }
@@ -279,12 +839,14 @@ impl MultiGetObjectOwners for MysClient {
     ) -> anyhow::Result<HashMap<ObjectID, (Owner, u64)>> {
         retry_with_max_attempts!(
             async {
+                let (client, replica) = self.read_client();
                 let results = self
-                    .mys_client
-                    .read_api()
-                    .multi_get_object_with_options(
-                        object_ids.clone(),
-                        MysObjectDataOptions::default().with_owner(),
+                    .guarded_read(
+                        replica,
+                        client.read_api().multi_get_object_with_options(
+                            object_ids.clone(),
+                            MysObjectDataOptions::default().with_owner(),
+                        ),
                     )
                     .await
                     .tap_err(|err| debug!("Failed to get object owners: {:?}", err))?;
@@ -309,7 +871,10 @@ impl MultiGetObjectOwners for MysClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_env::start_mys_cluster;
+    use crate::tx_signer::TxSigner;
     use mys_types::crypto::get_account_key_pair;
+    use mys_types::gas_coin::MIST_PER_MYS;
     use mys_types::object::Object;
     use test_cluster::{TestCluster, TestClusterBuilder};
 
@@ -384,4 +949,160 @@ mod tests {
         // Verify we got all objects
         assert_eq!(owner_map.len(), object_ids.len());
     }
+
+    #[tokio::test]
+    async fn test_read_replica() {
+        let (test_cluster, signer) = start_mys_cluster(vec![MIST_PER_MYS; 3]).await;
+        let sponsor = signer.get_address();
+        let mys_client = MysClient::new(&test_cluster.rpc_url(), None)
+            .await
+            .with_read_replica(&test_cluster.rpc_url(), None)
+            .await;
+        let (_, replica) = mys_client.read_client();
+        assert!(replica.is_some());
+        let coins = mys_client
+            .get_all_owned_mys_coins_above_balance_threshold(sponsor, MIST_PER_MYS)
+            .await;
+        assert_eq!(coins.len(), 3);
+
+        // Reads go to the primary fullnode while the replica is unhealthy.
+        mys_client.read_replica.as_ref().unwrap().mark_unhealthy();
+        let (_, replica) = mys_client.read_client();
+        assert!(replica.is_none());
+        let coins = mys_client
+            .get_all_owned_mys_coins_above_balance_threshold(sponsor, MIST_PER_MYS)
+            .await;
+        assert_eq!(coins.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_coin_denylist() {
+        let (test_cluster, signer) = start_mys_cluster(vec![MIST_PER_MYS; 3]).await;
+        let sponsor = signer.get_address();
+        let mys_client = MysClient::new(&test_cluster.rpc_url(), None).await;
+        let coins = mys_client
+            .get_all_owned_mys_coins_above_balance_threshold(sponsor, MIST_PER_MYS)
+            .await;
+        assert_eq!(coins.len(), 3);
+
+        // The denied coin meets the balance threshold but must still be skipped.
+        let denied_coin = coins[0].object_ref.0;
+        let mys_client = mys_client.with_coin_denylist(vec![denied_coin]);
+        let coins = mys_client
+            .get_all_owned_mys_coins_above_balance_threshold(sponsor, MIST_PER_MYS)
+            .await;
+        assert_eq!(coins.len(), 2);
+        assert!(coins.iter().all(|coin| coin.object_ref.0 != denied_coin));
+    }
+
+    #[tokio::test]
+    async fn test_get_latest_gas_objects_checks_owner() {
+        let (test_cluster, signer) = start_mys_cluster(vec![MIST_PER_MYS; 2]).await;
+        let sponsor = signer.get_address();
+        let mys_client = MysClient::new(&test_cluster.rpc_url(), None).await;
+        let coins = mys_client
+            .get_all_owned_mys_coins_above_balance_threshold(sponsor, MIST_PER_MYS)
+            .await;
+        let coin_ids: Vec<_> = coins.iter().map(|coin| coin.object_ref.0).collect();
+        let latest_coins = mys_client
+            .get_latest_gas_objects(sponsor, coin_ids.clone())
+            .await;
+        assert_eq!(latest_coins.values().flatten().count(), 2);
+
+        // The coins still parse as gas coins, but are owned by someone else.
+        let (other_owner, _) = get_account_key_pair();
+        let latest_coins = mys_client
+            .get_latest_gas_objects(other_owner, coin_ids.clone())
+            .await;
+        assert_eq!(latest_coins.len(), 2);
+        assert!(latest_coins.values().all(|coin| coin.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_calibrate_gas_cost_per_object_fallback() {
+        let (test_cluster, signer) = start_mys_cluster(vec![MIST_PER_MYS]).await;
+        let sponsor = signer.get_address();
+        let mys_client = MysClient::new(&test_cluster.rpc_url(), None)
+            .await
+            .with_fallback_gas_cost_per_object(Some(1234));
+        assert_eq!(mys_client.last_gas_cost_calibration(), None);
+        let coins = mys_client
+            .get_all_owned_mys_coins_above_balance_threshold(sponsor, MIST_PER_MYS)
+            .await;
+        let gas_cost_per_object = mys_client
+            .calibrate_gas_cost_per_object(sponsor, &coins[0])
+            .await;
+        assert_eq!(
+            mys_client.last_gas_cost_calibration(),
+            Some(GasCostCalibration {
+                source: GasCostCalibrationSource::DevInspect,
+                gas_cost_per_object,
+            })
+        );
+
+        // dev_inspect keeps failing on a coin that does not exist.
+        let missing_coin = GasCoin {
+            object_ref: mys_types::base_types::random_object_ref(),
+            balance: MIST_PER_MYS,
+        };
+        assert_eq!(
+            mys_client
+                .calibrate_gas_cost_per_object(sponsor, &missing_coin)
+                .await,
+            1234
+        );
+        assert_eq!(
+            mys_client.last_gas_cost_calibration().unwrap().source,
+            GasCostCalibrationSource::Fallback
+        );
+    }
+
+    #[test]
+    fn test_parse_gas_coin_type() {
+        assert_eq!(parse_gas_coin_type("0x2::mys::MYS").unwrap(), GAS::type_tag());
+        assert!(parse_gas_coin_type("u64").is_err());
+        assert!(parse_gas_coin_type("not a type").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_gas_coin_type() {
+        let (test_cluster, _signer) = start_mys_cluster(vec![MIST_PER_MYS]).await;
+        let mys_client = MysClient::new(&test_cluster.rpc_url(), None).await;
+        mys_client.validate_gas_coin_type().await.unwrap();
+        let mys_client =
+            mys_client.with_gas_coin_type(parse_gas_coin_type("0x2::nope::NOPE").unwrap());
+        assert!(mys_client.validate_gas_coin_type().await.is_err());
+    }
+
+    #[test]
+    fn test_move_abort_code() {
+        assert_eq!(
+            move_abort_code(
+                "MoveAbort(MoveLocation { module: ModuleId { address: 0x2, name: Identifier(\"coin\") }, function: 2, instruction: 10, function_name: Some(\"split\") }, 0) in command 0"
+            ),
+            Some(0)
+        );
+        assert_eq!(
+            move_abort_code("MoveAbort(MoveLocation { function: 0, instruction: 5 }, 42)"),
+            Some(42)
+        );
+        assert_eq!(move_abort_code("InsufficientGas"), None);
+        assert_eq!(move_abort_code("MoveAbort(garbled"), None);
+    }
+
+    #[test]
+    fn test_is_dev_inspect_unsupported() {
+        assert!(is_dev_inspect_unsupported(&anyhow::anyhow!(
+            "RPC error: Method not found"
+        )));
+        assert!(is_dev_inspect_unsupported(&anyhow::anyhow!(
+            "code: -32601, message: mys_devInspectTransactionBlock is disabled"
+        )));
+        assert!(!is_dev_inspect_unsupported(&anyhow::anyhow!(
+            "Connection refused"
+        )));
+        assert!(!is_dev_inspect_unsupported(&anyhow::anyhow!(
+            "429 Too Many Requests"
+        )));
+    }
 }
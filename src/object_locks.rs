@@ -2,6 +2,7 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::types::ReservationID;
 use moka::sync::SegmentedCache;
 use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, RwLock};
@@ -40,7 +41,7 @@ pub struct ObjectLockManager {
 /// This struct is returned by `ObjectLockManager::try_acquire_locks`.
 /// It will automatically release the locks when the guard is dropped.
 pub struct ObjectLocks {
-    reservation_id: u64,
+    reservation_id: ReservationID,
     locked_objects: Vec<ObjectID>,
     global_locked_owned_objects: Arc<RwLock<HashSet<ObjectID>>>,
 }
@@ -90,7 +91,7 @@ impl ObjectLockManager {
     /// This transaction will never succeed to execute.
     pub async fn try_acquire_locks(
         &self,
-        reservation_id: u64,
+        reservation_id: ReservationID,
         tx_data: &TransactionData,
     ) -> Result<ObjectLocks, anyhow::Error> {
         debug!(?reservation_id, "Trying to acquire object locks");
@@ -347,7 +348,10 @@ mod tests {
         let manager = Arc::new(ObjectLockManager::new(client));
         let tx_data = create_test_tx_data(vec![], vec![], vec![]);
 
-        let locks = manager.try_acquire_locks(1, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(1).unwrap(), &tx_data)
+            .await
+            .unwrap();
         // Show that it won't lock any objects, including the gas.
         assert!(locks.locked_objects.is_empty());
         drop(locks);
@@ -370,7 +374,10 @@ mod tests {
         let tx_data = create_test_tx_data(vec![(obj_id, 1)], vec![], vec![]);
 
         // Acquire locks
-        let locks = manager.try_acquire_locks(1, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(1).unwrap(), &tx_data)
+            .await
+            .unwrap();
 
         assert_eq!(locks.locked_objects.len(), 1);
         assert_eq!(locks.locked_objects[0], obj_id);
@@ -407,13 +414,22 @@ mod tests {
         let manager = Arc::new(ObjectLockManager::new(client));
         let tx_data = create_test_tx_data(vec![(obj_id, 1)], vec![], vec![]);
 
-        let locks = manager.try_acquire_locks(1, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(1).unwrap(), &tx_data)
+            .await
+            .unwrap();
         assert_eq!(locks.locked_objects.len(), 1);
 
-        assert!(manager.try_acquire_locks(2, &tx_data).await.is_err());
+        assert!(manager
+            .try_acquire_locks(ReservationID::new(2).unwrap(), &tx_data)
+            .await
+            .is_err());
         drop(locks);
 
-        let locks = manager.try_acquire_locks(2, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(2).unwrap(), &tx_data)
+            .await
+            .unwrap();
         assert_eq!(locks.locked_objects.len(), 1);
         drop(locks);
     }
@@ -435,7 +451,10 @@ mod tests {
 
         // First transaction with version 1
         let tx_data = create_test_tx_data(vec![(obj_id, 1)], vec![], vec![]);
-        let locks = manager.try_acquire_locks(1, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(1).unwrap(), &tx_data)
+            .await
+            .unwrap();
         assert_eq!(locks.locked_objects.len(), 1);
         drop(locks);
 
@@ -451,11 +470,17 @@ mod tests {
 
         // Try with version 1 - should fail since version is out of date
         let tx_data = create_test_tx_data(vec![(obj_id, 1)], vec![], vec![]);
-        assert!(manager.try_acquire_locks(2, &tx_data).await.is_err());
+        assert!(manager
+            .try_acquire_locks(ReservationID::new(2).unwrap(), &tx_data)
+            .await
+            .is_err());
 
         // Try with version 2 - should use cache and succeed
         let tx_data = create_test_tx_data(vec![(obj_id, 2)], vec![], vec![]);
-        let locks = manager.try_acquire_locks(3, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(3).unwrap(), &tx_data)
+            .await
+            .unwrap();
         assert_eq!(locks.locked_objects.len(), 1);
         drop(locks);
 
@@ -468,7 +493,10 @@ mod tests {
 
         // Try with version 3 - should re-query and succeed
         let tx_data = create_test_tx_data(vec![(obj_id, 3)], vec![], vec![]);
-        let locks = manager.try_acquire_locks(4, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(4).unwrap(), &tx_data)
+            .await
+            .unwrap();
         assert_eq!(locks.locked_objects.len(), 1);
     }
 
@@ -490,19 +518,28 @@ mod tests {
         // Try to acquire locks with version 2, but the object is only version 1.
         // We allow this to happenand it will still be locked.
         let tx_data = create_test_tx_data(vec![(obj_id, 2)], vec![], vec![]);
-        let locks = manager.try_acquire_locks(1, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(1).unwrap(), &tx_data)
+            .await
+            .unwrap();
         assert_eq!(locks.locked_objects.len(), 1);
         drop(locks);
 
         // Acquiring locks at version 1 should also succeed because it's what we cached.
         let tx_data = create_test_tx_data(vec![(obj_id, 1)], vec![], vec![]);
-        let locks = manager.try_acquire_locks(2, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(2).unwrap(), &tx_data)
+            .await
+            .unwrap();
         assert_eq!(locks.locked_objects.len(), 1);
         drop(locks);
 
         // Acquiring locks at version 0 should fail because it's out of date.
         let tx_data = create_test_tx_data(vec![(obj_id, 0)], vec![], vec![]);
-        assert!(manager.try_acquire_locks(3, &tx_data).await.is_err());
+        assert!(manager
+            .try_acquire_locks(ReservationID::new(3).unwrap(), &tx_data)
+            .await
+            .is_err());
     }
 
     #[tokio::test]
@@ -522,7 +559,10 @@ mod tests {
         let tx_data = create_test_tx_data(vec![(obj_id, 1)], vec![], vec![]);
 
         // First acquire locks - should succeed since object is address owned
-        let locks = manager.try_acquire_locks(1, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(1).unwrap(), &tx_data)
+            .await
+            .unwrap();
         assert_eq!(locks.locked_objects.len(), 1);
         drop(locks);
 
@@ -532,11 +572,17 @@ mod tests {
 
         // Try with version 1 - should fail since version is out of date
         let tx_data = create_test_tx_data(vec![(obj_id, 1)], vec![], vec![]);
-        assert!(manager.try_acquire_locks(2, &tx_data).await.is_err());
+        assert!(manager
+            .try_acquire_locks(ReservationID::new(2).unwrap(), &tx_data)
+            .await
+            .is_err());
 
         // Try with version 2 - should not lock the object since it's now immutable
         let tx_data = create_test_tx_data(vec![(obj_id, 2)], vec![], vec![]);
-        let locks = manager.try_acquire_locks(3, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(3).unwrap(), &tx_data)
+            .await
+            .unwrap();
         assert!(locks.locked_objects.is_empty());
     }
 
@@ -557,7 +603,10 @@ mod tests {
         let tx_data = create_test_tx_data(vec![(obj_id, 1)], vec![], vec![]);
 
         // First acquire locks - should succeed since object is address owned
-        let locks = manager.try_acquire_locks(1, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(1).unwrap(), &tx_data)
+            .await
+            .unwrap();
         assert_eq!(locks.locked_objects.len(), 1);
         drop(locks);
 
@@ -571,11 +620,17 @@ mod tests {
 
         // Try with version 1 - should fail since version is out of date
         let tx_data = create_test_tx_data(vec![(obj_id, 1)], vec![], vec![]);
-        assert!(manager.try_acquire_locks(2, &tx_data).await.is_err());
+        assert!(manager
+            .try_acquire_locks(ReservationID::new(2).unwrap(), &tx_data)
+            .await
+            .is_err());
 
         // Try with version 2 - should not lock shared object
         let tx_data = create_test_tx_data(vec![(obj_id, 2)], vec![], vec![]);
-        let locks = manager.try_acquire_locks(3, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(3).unwrap(), &tx_data)
+            .await
+            .unwrap();
         assert!(locks.locked_objects.is_empty());
         drop(locks);
 
@@ -585,7 +640,10 @@ mod tests {
 
         // Try with version 3 - should re-query lock the object since it's address owned again
         let tx_data = create_test_tx_data(vec![(obj_id, 3)], vec![], vec![]);
-        let locks = manager.try_acquire_locks(4, &tx_data).await.unwrap();
+        let locks = manager
+            .try_acquire_locks(ReservationID::new(4).unwrap(), &tx_data)
+            .await
+            .unwrap();
         assert_eq!(locks.locked_objects.len(), 1);
     }
 }
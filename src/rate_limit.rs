@@ -0,0 +1,167 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::execute_retry::RATE_LIMITED_MARKERS;
+use crate::metrics::FullnodeMetrics;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Backoff after the first rate limited response, doubled on every consecutive one.
+const RATE_LIMIT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound of the backoff, including the one asked for by the fullnode with Retry-After.
+const RATE_LIMIT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Whether the fullnode refused a request because of rate limiting or overload.
+pub fn is_rate_limited(err: &anyhow::Error) -> bool {
+    let message = format!("{:#}", err).to_lowercase();
+    RATE_LIMITED_MARKERS
+        .iter()
+        .any(|marker| message.contains(marker))
+}
+
+/// The delay asked for by the fullnode, if the error carries a Retry-After value in seconds.
+pub fn parse_retry_after(err: &anyhow::Error) -> Option<Duration> {
+    let message = format!("{:#}", err).to_lowercase();
+    let start = ["retry-after", "retry after"]
+        .iter()
+        .find_map(|name| message.find(name).map(|index| index + name.len()))?;
+    let value = message[start..].trim_start_matches(|c: char| c == ':' || c.is_whitespace());
+    let end = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    value[..end].parse().ok().map(Duration::from_secs)
+}
+
+struct BackoffState {
+    consecutive_rate_limits: u32,
+    backoff_until: Option<Instant>,
+}
+
+/// Holds back all requests to a fullnode that rate limits the gas pool. The regular retries are
+/// meant for transient failures and would only add to the load of a throttled fullnode, so
+/// after a rate limited response every request first waits for a longer backoff, as asked for
+/// by the fullnode with Retry-After or growing exponentially otherwise.
+pub struct RateLimitBackoff {
+    state: Mutex<BackoffState>,
+    metrics: Option<Arc<FullnodeMetrics>>,
+}
+
+impl RateLimitBackoff {
+    pub fn new(metrics: Option<Arc<FullnodeMetrics>>) -> Self {
+        Self {
+            state: Mutex::new(BackoffState {
+                consecutive_rate_limits: 0,
+                backoff_until: None,
+            }),
+            metrics,
+        }
+    }
+
+    /// Waits until the current backoff, if any, is over.
+    pub async fn wait(&self) {
+        let backoff_until = self.state.lock().backoff_until;
+        if let Some(until) = backoff_until {
+            tokio::time::sleep_until(until.into()).await;
+        }
+    }
+
+    /// Records the outcome of a request, and starts a backoff if it was rate limited.
+    pub fn record<T>(&self, result: &anyhow::Result<T>) {
+        match result {
+            Ok(_) => self.state.lock().consecutive_rate_limits = 0,
+            Err(err) if is_rate_limited(err) => {
+                let backoff = self.record_rate_limited(parse_retry_after(err));
+                warn!(
+                    "Fullnode is rate limiting requests, backing off for {:?}: {:?}",
+                    backoff, err
+                );
+            }
+            Err(_) => (),
+        }
+    }
+
+    fn record_rate_limited(&self, retry_after: Option<Duration>) -> Duration {
+        if let Some(metrics) = &self.metrics {
+            metrics.num_fullnode_rate_limited.inc();
+        }
+        let mut state = self.state.lock();
+        state.consecutive_rate_limits = state.consecutive_rate_limits.saturating_add(1);
+        let backoff = retry_after
+            .unwrap_or_else(|| {
+                RATE_LIMIT_INITIAL_BACKOFF
+                    .saturating_mul(1 << (state.consecutive_rate_limits - 1).min(16))
+            })
+            .min(RATE_LIMIT_MAX_BACKOFF);
+        let until = Instant::now() + backoff;
+        // Concurrent rate limited responses must not shorten a longer backoff.
+        if state.backoff_until.map_or(true, |current| current < until) {
+            state.backoff_until = Some(until);
+        }
+        backoff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_rate_limited() {
+        assert!(is_rate_limited(&anyhow::anyhow!("429 Too Many Requests")));
+        assert!(is_rate_limited(&anyhow::anyhow!("Server is busy")));
+        assert!(!is_rate_limited(&anyhow::anyhow!("502 Bad Gateway")));
+        assert!(!is_rate_limited(&anyhow::anyhow!("Connection refused")));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(
+            parse_retry_after(&anyhow::anyhow!("429 Too Many Requests, Retry-After: 5")),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            parse_retry_after(&anyhow::anyhow!("rate limited, retry after 12s")),
+            Some(Duration::from_secs(12))
+        );
+        assert_eq!(
+            parse_retry_after(&anyhow::anyhow!("429 Too Many Requests")),
+            None
+        );
+        assert_eq!(
+            parse_retry_after(&anyhow::anyhow!("Retry-After: Wed, 21 Oct 2015 07:28:00 GMT")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_backoff() {
+        let metrics = FullnodeMetrics::new_for_testing();
+        let backoff = RateLimitBackoff::new(Some(metrics.clone()));
+        assert_eq!(backoff.record_rate_limited(None), Duration::from_secs(1));
+        assert_eq!(backoff.record_rate_limited(None), Duration::from_secs(2));
+        assert_eq!(backoff.record_rate_limited(None), Duration::from_secs(4));
+        assert_eq!(
+            backoff.record_rate_limited(Some(Duration::from_secs(10))),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            backoff.record_rate_limited(Some(Duration::from_secs(3600))),
+            RATE_LIMIT_MAX_BACKOFF
+        );
+        for _ in 0..100 {
+            backoff.record_rate_limited(None);
+        }
+        assert_eq!(backoff.record_rate_limited(None), RATE_LIMIT_MAX_BACKOFF);
+        assert_eq!(metrics.num_fullnode_rate_limited.get(), 106);
+
+        // A successful request resets the exponential backoff.
+        backoff.record(&Ok(()));
+        assert_eq!(backoff.record_rate_limited(None), Duration::from_secs(1));
+        // Other errors are not rate limits.
+        backoff.record::<()>(&Err(anyhow::anyhow!("503 Service Unavailable")));
+        assert_eq!(metrics.num_fullnode_rate_limited.get(), 107);
+    }
+}
@@ -0,0 +1,309 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::ReservationCallbackConfig;
+use crate::metrics::GasPoolCoreMetrics;
+use crate::retry_with_max_attempts;
+use crate::types::{ReservationID, SignedReservationReceipt};
+use anyhow::bail;
+use reqwest::{Client, Url};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use mys_json_rpc_types::MysObjectRef;
+use mys_types::base_types::MysAddress;
+use mys_types::digests::TransactionDigest;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, warn};
+
+const MAX_CALLBACK_URL_LENGTH: usize = 2048;
+const CALLBACK_QUEUE_SIZE: usize = 10_000;
+const CALLBACK_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_CONCURRENT_CALLBACKS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReservationOutcome {
+    /// The reservation expired without being used.
+    Expired,
+    /// The reservation was used in an execute_tx request.
+    Consumed,
+    /// The reservation was cancelled by the client.
+    Cancelled,
+}
+
+/// The JSON body POSTed to the callback URL of a reservation.
+#[derive(Clone, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct ReservationEvent {
+    pub reservation_id: ReservationID,
+    pub outcome: ReservationOutcome,
+    /// Digest of the executed transaction. Only set when the reservation was consumed and
+    /// the transaction was executed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<TransactionDigest>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WaitingReservationOutcome {
+    /// Coins were reserved. The reservation is then tracked like any other, and its events are
+    /// POSTed to the same callback URL.
+    Reserved,
+    /// The pool could not cover the budget before the wait expired.
+    Expired,
+    /// The reservation failed for another reason than a lack of coins.
+    Failed,
+}
+
+/// The JSON body POSTed to the callback URL of a reservation that waited for coins, once it
+/// stops waiting.
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
+pub struct WaitingReservationEvent {
+    /// The wait id returned by the reserve_gas request.
+    pub wait_id: u64,
+    pub outcome: WaitingReservationOutcome,
+    /// Only set when coins were reserved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sponsor_address: Option<MysAddress>,
+    /// Only set when coins were reserved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reservation_id: Option<ReservationID>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gas_coins: Vec<MysObjectRef>,
+    /// Only set when coins were reserved and reservation receipts are enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<SignedReservationReceipt>,
+    /// Only set when the reservation failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// An event POSTed to a callback URL.
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum CallbackEvent {
+    Reservation(ReservationEvent),
+    WaitingReservation(WaitingReservationEvent),
+}
+
+impl From<ReservationEvent> for CallbackEvent {
+    fn from(event: ReservationEvent) -> Self {
+        CallbackEvent::Reservation(event)
+    }
+}
+
+impl From<WaitingReservationEvent> for CallbackEvent {
+    fn from(event: WaitingReservationEvent) -> Self {
+        CallbackEvent::WaitingReservation(event)
+    }
+}
+
+impl fmt::Display for CallbackEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallbackEvent::Reservation(event) => write!(f, "reservation {}", event.reservation_id),
+            CallbackEvent::WaitingReservation(event) => {
+                write!(f, "waiting reservation {}", event.wait_id)
+            }
+        }
+    }
+}
+
+/// Delivers reservation events to their callback URLs in a background task, so that
+/// slow or unreachable callback endpoints never hold up reservation or execution.
+pub struct ReservationCallbackSender {
+    config: ReservationCallbackConfig,
+    sender: mpsc::Sender<(String, CallbackEvent)>,
+    metrics: Arc<GasPoolCoreMetrics>,
+}
+
+impl ReservationCallbackSender {
+    /// Must be called within a tokio runtime. The delivery task stops once the sender is dropped.
+    pub fn new(config: ReservationCallbackConfig, metrics: Arc<GasPoolCoreMetrics>) -> Self {
+        let (sender, receiver) = mpsc::channel(CALLBACK_QUEUE_SIZE);
+        tokio::spawn(Self::run_delivery_loop(
+            receiver,
+            config.max_attempts,
+            config.max_callbacks_per_sec,
+            metrics.clone(),
+        ));
+        Self {
+            config,
+            sender,
+            metrics,
+        }
+    }
+
+    /// Checks that the callback URL is allowed by the config, to prevent callers from using
+    /// the gas pool to send requests to arbitrary internal services.
+    /// Note that this only looks at the URL itself; domain names resolving to private
+    /// addresses are not detected.
+    pub fn validate_callback_url(&self, callback_url: &str) -> anyhow::Result<()> {
+        validate_callback_url(&self.config, callback_url)
+    }
+
+    /// Queues the event for delivery. Never blocks; the event is dropped if the queue is full.
+    pub fn notify(&self, callback_url: String, event: impl Into<CallbackEvent>) {
+        if let Err(err) = self.sender.try_send((callback_url, event.into())) {
+            warn!("Dropping reservation callback: {:?}", err);
+            self.metrics.num_reservation_callbacks_dropped.inc();
+        }
+    }
+
+    async fn run_delivery_loop(
+        mut receiver: mpsc::Receiver<(String, CallbackEvent)>,
+        max_attempts: usize,
+        max_callbacks_per_sec: u64,
+        metrics: Arc<GasPoolCoreMetrics>,
+    ) {
+        let client = Client::builder()
+            .timeout(CALLBACK_REQUEST_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let mut rate_limiter = tokio::time::interval(Duration::from_micros(
+            1_000_000 / max_callbacks_per_sec.max(1),
+        ));
+        rate_limiter.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let concurrency = Arc::new(Semaphore::new(MAX_CONCURRENT_CALLBACKS));
+        while let Some((callback_url, event)) = receiver.recv().await {
+            rate_limiter.tick().await;
+            // unwrap safe because the semaphore is never closed.
+            let permit = concurrency.clone().acquire_owned().await.unwrap();
+            let client = client.clone();
+            let metrics = metrics.clone();
+            tokio::spawn(async move {
+                let result = retry_with_max_attempts!(
+                    async {
+                        client
+                            .post(&callback_url)
+                            .json(&event)
+                            .send()
+                            .await?
+                            .error_for_status()
+                    },
+                    max_attempts
+                );
+                match result {
+                    Ok(_) => {
+                        debug!("Delivered the callback of {} to {}", event, callback_url);
+                        metrics.num_reservation_callbacks_sent.inc();
+                    }
+                    Err(err) => {
+                        warn!(
+                            "Failed to deliver the callback of {} to {}: {:?}",
+                            event, callback_url, err
+                        );
+                        metrics.num_reservation_callbacks_failed.inc();
+                    }
+                }
+                drop(permit);
+            });
+        }
+    }
+}
+
+fn validate_callback_url(
+    config: &ReservationCallbackConfig,
+    callback_url: &str,
+) -> anyhow::Result<()> {
+    if config.allowed_schemes.is_empty() {
+        bail!("Reservation callbacks are not enabled");
+    }
+    if callback_url.len() > MAX_CALLBACK_URL_LENGTH {
+        bail!(
+            "Callback URL must be at most {} characters",
+            MAX_CALLBACK_URL_LENGTH
+        );
+    }
+    let url = Url::parse(callback_url)?;
+    if !config
+        .allowed_schemes
+        .iter()
+        .any(|scheme| scheme.eq_ignore_ascii_case(url.scheme()))
+    {
+        bail!("Callback URL scheme {} is not allowed", url.scheme());
+    }
+    let Some(host) = url.host_str() else {
+        bail!("Callback URL must have a host");
+    };
+    if config.allow_private_hosts {
+        return Ok(());
+    }
+    let host = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+    if host == "localhost" || host.ends_with(".localhost") {
+        bail!("Callback URL must not point to localhost");
+    }
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        if is_private_ip(&ip) {
+            bail!("Callback URL must not point to a private address");
+        }
+    }
+    Ok(())
+}
+
+fn is_private_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_private_ip(&IpAddr::V4(ip));
+            }
+            let first_segment = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local addresses, fc00::/7.
+                || (first_segment & 0xfe00) == 0xfc00
+                // Link local addresses, fe80::/10.
+                || (first_segment & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_callback_url() {
+        let config = ReservationCallbackConfig::default();
+        assert!(validate_callback_url(&config, "https://example.com/callback?id=1").is_ok());
+        assert!(validate_callback_url(&config, "http://example.com/callback").is_err());
+        assert!(validate_callback_url(&config, "file:///etc/passwd").is_err());
+        assert!(validate_callback_url(&config, "not a url").is_err());
+        assert!(validate_callback_url(&config, "https://localhost/callback").is_err());
+        assert!(validate_callback_url(&config, "https://127.0.0.1/callback").is_err());
+        assert!(validate_callback_url(&config, "https://10.1.2.3/callback").is_err());
+        assert!(validate_callback_url(&config, "https://169.254.169.254/latest").is_err());
+        assert!(validate_callback_url(&config, "https://[::1]/callback").is_err());
+        assert!(validate_callback_url(&config, "https://[::ffff:192.168.0.1]/callback").is_err());
+        assert!(validate_callback_url(&config, "https://8.8.8.8/callback").is_ok());
+
+        let config = ReservationCallbackConfig {
+            allowed_schemes: vec!["http".to_string(), "https".to_string()],
+            allow_private_hosts: true,
+            ..Default::default()
+        };
+        assert!(validate_callback_url(&config, "http://127.0.0.1:8080/callback").is_ok());
+
+        let config = ReservationCallbackConfig {
+            allowed_schemes: vec![],
+            ..Default::default()
+        };
+        assert!(validate_callback_url(&config, "https://example.com/callback").is_err());
+    }
+}
@@ -2,20 +2,39 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::read_auth_env;
+use crate::gas_pool::gas_pool_core::{CoinReloadResult, GasPoolStats, PoolDebugSnapshot};
+use crate::gas_pool::reservation_waitlist::WaitTicket;
+use crate::{read_admin_auth_env, read_auth_env, ADMIN_AUTH_ENV_NAME};
 use crate::rpc::rpc_types::{
-    ExecuteTxRequest, ExecuteTxResponse, ReserveGasRequest, ReserveGasResponse,
+    ApiVersion, BatchExecuteTxRequest, BatchExecuteTxResponse, BatchExecuteTxStreamEvent, BuildInfo,
+    CancelReservationRequest, CancelReservationResponse, ClaimLeaseRequest, ClientUsageRequest,
+    ClientUsageResponse, ExecuteTxRequest, ExecuteTxResponse, GasPoolStatsResponse,
+    PoolSnapshotResponse, QueryAuditLogRequest, QueryAuditLogResponse, ReloadCoinsResponse,
+    ReserveBalanceRequest, ReserveBalanceResponse, ReserveBalanceResult, ReserveGasRequest, ReserveGasResponse,
+    ReserveGasResult, SetSafeModeRequest, SetSafeModeResponse, API_VERSION_HEADER,
 };
-use crate::types::ReservationID;
+use crate::types::{AuditRecord, ClientUsage, GasCoin, ReservationID, SignedEffects};
 use anyhow::bail;
 use fastcrypto::encoding::Base64;
 use reqwest::header::{HeaderMap, AUTHORIZATION};
 use reqwest::Client;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use mys_json_rpc_types::MysTransactionBlockEffects;
-use mys_types::base_types::{ObjectRef, MysAddress};
+use mys_types::base_types::{ObjectID, ObjectRef, MysAddress};
+use mys_types::gas::GasCostSummary;
 use mys_types::signature::GenericSignature;
 use mys_types::transaction::TransactionData;
 
+/// The outcome of `GasPoolRpcClient::reserve_gas_or_wait`.
+#[derive(Debug)]
+pub enum ReserveOrWait {
+    /// The pool covered the budget right away.
+    Reserved(ReserveGasResult),
+    /// The reservation is waiting for coins.
+    Waiting(WaitTicket),
+}
+
 #[derive(Clone)]
 pub struct GasPoolRpcClient {
     client: Client,
@@ -57,6 +76,18 @@ impl GasPoolRpcClient {
             .await
     }
 
+    pub async fn build_info(&self) -> anyhow::Result<BuildInfo> {
+        let response = self
+            .client
+            .get(format!("{}/build_info", self.server_address))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            bail!("Failed to get build info: {:?}", response);
+        }
+        Ok(response.json::<BuildInfo>().await?)
+    }
+
     pub async fn debug_health_check(&self) -> anyhow::Result<()> {
         let mut headers = HeaderMap::new();
         headers.insert(
@@ -80,47 +111,539 @@ impl GasPoolRpcClient {
         }
     }
 
+    pub async fn stats(&self) -> anyhow::Result<GasPoolStats> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            format!("Bearer {}", read_auth_env()).parse().unwrap(),
+        );
+        let response = self
+            .client
+            .get(format!("{}/stats", self.server_address))
+            .headers(headers)
+            .send()
+            .await?
+            .json::<GasPoolStatsResponse>()
+            .await?;
+        response.stats.ok_or_else(|| {
+            anyhow::anyhow!(response
+                .error
+                .unwrap_or_else(|| "Unknown error".to_string()))
+        })
+    }
+
+    /// Makes the gas pool pick up coins that were sent to the sponsor address, e.g. when it was
+    /// funded manually. Requires the admin token.
+    pub async fn reload_coins(&self) -> anyhow::Result<CoinReloadResult> {
+        let Some(admin_token) = read_admin_auth_env() else {
+            bail!("{} environment variable must be specified", ADMIN_AUTH_ENV_NAME);
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            format!("Bearer {}", admin_token).parse().unwrap(),
+        );
+        let response = self
+            .client
+            .post(format!("{}/admin/reload_coins", self.server_address))
+            .headers(headers)
+            .send()
+            .await?
+            .json::<ReloadCoinsResponse>()
+            .await?;
+        response.result.ok_or_else(|| {
+            anyhow::anyhow!(response
+                .error
+                .unwrap_or_else(|| "Unknown error".to_string()))
+        })
+    }
+
+    /// Turns safe mode on or off for every gas pool instance of the sponsor, and returns the new
+    /// mode. Requires the admin token.
+    pub async fn set_safe_mode(&self, enabled: bool) -> anyhow::Result<bool> {
+        let Some(admin_token) = read_admin_auth_env() else {
+            bail!("{} environment variable must be specified", ADMIN_AUTH_ENV_NAME);
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            format!("Bearer {}", admin_token).parse().unwrap(),
+        );
+        let response = self
+            .client
+            .post(format!("{}/admin/safe_mode", self.server_address))
+            .headers(headers)
+            .json(&SetSafeModeRequest { enabled })
+            .send()
+            .await?
+            .json::<SetSafeModeResponse>()
+            .await?;
+        response.safe_mode.ok_or_else(|| {
+            anyhow::anyhow!(response
+                .error
+                .unwrap_or_else(|| "Unknown error".to_string()))
+        })
+    }
+
+    /// Reads the audit log records with from_ms <= timestamp_ms < to_ms, oldest first.
+    /// Requires the admin token.
+    pub async fn query_audit_log(
+        &self,
+        from_ms: u64,
+        to_ms: u64,
+        client_id: Option<String>,
+        limit: Option<usize>,
+    ) -> anyhow::Result<Vec<AuditRecord>> {
+        let Some(admin_token) = read_admin_auth_env() else {
+            bail!("{} environment variable must be specified", ADMIN_AUTH_ENV_NAME);
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            format!("Bearer {}", admin_token).parse().unwrap(),
+        );
+        let request = QueryAuditLogRequest {
+            from_ms,
+            to_ms,
+            client_id,
+            limit,
+        };
+        let response = self
+            .client
+            .post(format!("{}/admin/audit_log", self.server_address))
+            .headers(headers)
+            .json(&request)
+            .send()
+            .await?
+            .json::<QueryAuditLogResponse>()
+            .await?;
+        response.records.ok_or_else(|| {
+            anyhow::anyhow!(response
+                .error
+                .unwrap_or_else(|| "Unknown error".to_string()))
+        })
+    }
+
+    /// Reads the active reservations and reserved budget of each client that holds a reservation,
+    /// or only those of the given client. Requires the admin token.
+    pub async fn get_client_usage(
+        &self,
+        client_id: Option<String>,
+    ) -> anyhow::Result<BTreeMap<String, ClientUsage>> {
+        let Some(admin_token) = read_admin_auth_env() else {
+            bail!("{} environment variable must be specified", ADMIN_AUTH_ENV_NAME);
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            format!("Bearer {}", admin_token).parse().unwrap(),
+        );
+        let response = self
+            .client
+            .post(format!("{}/admin/client_usage", self.server_address))
+            .headers(headers)
+            .json(&ClientUsageRequest { client_id })
+            .send()
+            .await?
+            .json::<ClientUsageResponse>()
+            .await?;
+        response.usage.ok_or_else(|| {
+            anyhow::anyhow!(response
+                .error
+                .unwrap_or_else(|| "Unknown error".to_string()))
+        })
+    }
+
+    /// Takes a snapshot of the state of the pool for bug reports. Requires the admin token.
+    pub async fn get_pool_snapshot(&self) -> anyhow::Result<PoolDebugSnapshot> {
+        let Some(admin_token) = read_admin_auth_env() else {
+            bail!("{} environment variable must be specified", ADMIN_AUTH_ENV_NAME);
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            format!("Bearer {}", admin_token).parse().unwrap(),
+        );
+        let response = self
+            .client
+            .post(format!("{}/admin/pool_snapshot", self.server_address))
+            .headers(headers)
+            .send()
+            .await?
+            .json::<PoolSnapshotResponse>()
+            .await?;
+        response.snapshot.ok_or_else(|| {
+            anyhow::anyhow!(response
+                .error
+                .unwrap_or_else(|| "Unknown error".to_string()))
+        })
+    }
+
     pub async fn reserve_gas(
         &self,
         gas_budget: u64,
         reserve_duration_secs: u64,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<ObjectRef>)> {
+        self.reserve_gas_with_callback(gas_budget, reserve_duration_secs, None)
+            .await
+    }
+
+    /// Same as `reserve_gas`, but the gas pool POSTs a `ReservationEvent` to the callback URL
+    /// once the reservation expires or is consumed.
+    pub async fn reserve_gas_with_callback(
+        &self,
+        gas_budget: u64,
+        reserve_duration_secs: u64,
+        callback_url: Option<String>,
     ) -> anyhow::Result<(MysAddress, ReservationID, Vec<ObjectRef>)> {
         let request = ReserveGasRequest {
             gas_budget,
             reserve_duration_secs,
+            callback_url,
+            budget_lease: false,
+            client_id: None,
+            priority: None,
+            allow_partial: false,
+            wait_for_coins: false,
+            requested_coins: vec![],
+            tag: None,
+            candidates: false,
         };
+        self.send_reserve_request("v1/reserve_gas", &request).await
+    }
+
+    /// Same as `reserve_gas_with_callback`, but if the gas pool cannot cover the budget right now,
+    /// the reservation waits for coins instead of failing. The gas pool then POSTs a
+    /// `WaitingReservationEvent` to the callback URL once coins free up or the wait expires.
+    pub async fn reserve_gas_or_wait(
+        &self,
+        gas_budget: u64,
+        reserve_duration_secs: u64,
+        callback_url: String,
+    ) -> anyhow::Result<ReserveOrWait> {
+        let request = ReserveGasRequest {
+            gas_budget,
+            reserve_duration_secs,
+            callback_url: Some(callback_url),
+            budget_lease: false,
+            client_id: None,
+            priority: None,
+            allow_partial: false,
+            wait_for_coins: true,
+            requested_coins: vec![],
+            tag: None,
+            candidates: false,
+        };
+        let response = self.send_reserve_request_for_response("v1/reserve_gas", &request).await?;
+        if let Some(wait_ticket) = response.wait_ticket {
+            return Ok(ReserveOrWait::Waiting(wait_ticket));
+        }
+        response.result.map(ReserveOrWait::Reserved).ok_or_else(|| {
+            anyhow::anyhow!(response
+                .error
+                .unwrap_or_else(|| "Unknown error".to_string()))
+        })
+    }
+
+    /// Same as `reserve_gas`, but asks for the given priority, which the gas pool caps by the
+    /// limit configured for the client of the auth token.
+    pub async fn reserve_gas_with_priority(
+        &self,
+        gas_budget: u64,
+        reserve_duration_secs: u64,
+        priority: u8,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<ObjectRef>)> {
+        let request = ReserveGasRequest {
+            gas_budget,
+            reserve_duration_secs,
+            callback_url: None,
+            budget_lease: false,
+            client_id: None,
+            priority: Some(priority),
+            allow_partial: false,
+            wait_for_coins: false,
+            requested_coins: vec![],
+            tag: None,
+            candidates: false,
+        };
+        self.send_reserve_request("v1/reserve_gas", &request).await
+    }
+
+    /// Same as `reserve_gas`, but stores the given tag with the reservation for downstream
+    /// accounting. The tag is echoed in the returned result.
+    pub async fn reserve_gas_with_tag(
+        &self,
+        gas_budget: u64,
+        reserve_duration_secs: u64,
+        tag: String,
+    ) -> anyhow::Result<ReserveGasResult> {
+        let request = ReserveGasRequest {
+            gas_budget,
+            reserve_duration_secs,
+            callback_url: None,
+            budget_lease: false,
+            client_id: None,
+            priority: None,
+            allow_partial: false,
+            wait_for_coins: false,
+            requested_coins: vec![],
+            tag: Some(tag),
+            candidates: false,
+        };
+        self.send_reserve_request_for_result("v1/reserve_gas", &request)
+            .await
+    }
+
+    /// Same as `reserve_gas`, but reserves coins covering a multiple of the budget and returns
+    /// them with their balances in `candidate_coins`, so that the caller can pick which of them
+    /// to pay with. The coins that the transaction does not pay with are released on execution.
+    pub async fn reserve_candidate_gas(
+        &self,
+        gas_budget: u64,
+        reserve_duration_secs: u64,
+    ) -> anyhow::Result<ReserveGasResult> {
+        let request = ReserveGasRequest {
+            gas_budget,
+            reserve_duration_secs,
+            callback_url: None,
+            budget_lease: false,
+            client_id: None,
+            priority: None,
+            allow_partial: false,
+            wait_for_coins: false,
+            requested_coins: vec![],
+            tag: None,
+            candidates: true,
+        };
+        self.send_reserve_request_for_result("v1/reserve_gas", &request)
+            .await
+    }
+
+    /// Same as `reserve_gas`, but reserves exactly the given coins. Fails if any of them is
+    /// reserved or not in the gas pool, or if they do not cover the budget.
+    pub async fn reserve_requested_gas(
+        &self,
+        requested_coins: Vec<ObjectID>,
+        gas_budget: u64,
+        reserve_duration_secs: u64,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<ObjectRef>)> {
+        let request = ReserveGasRequest {
+            gas_budget,
+            reserve_duration_secs,
+            callback_url: None,
+            budget_lease: false,
+            client_id: None,
+            priority: None,
+            allow_partial: false,
+            wait_for_coins: false,
+            requested_coins,
+            tag: None,
+            candidates: false,
+        };
+        self.send_reserve_request("v1/reserve_gas", &request).await
+    }
+
+    /// Same as `reserve_gas`, but when the gas pool cannot cover the whole budget, it reserves
+    /// the coins it can instead of failing. Also returns the budget covered by the reserved
+    /// coins, which is less than gas_budget if the reservation is partial.
+    pub async fn reserve_partial_gas(
+        &self,
+        gas_budget: u64,
+        reserve_duration_secs: u64,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<ObjectRef>, u64)> {
+        let request = ReserveGasRequest {
+            gas_budget,
+            reserve_duration_secs,
+            callback_url: None,
+            budget_lease: false,
+            client_id: None,
+            priority: None,
+            allow_partial: true,
+            wait_for_coins: false,
+            requested_coins: vec![],
+            tag: None,
+            candidates: false,
+        };
+        let result = self.send_reserve_request_for_result("v1/reserve_gas", &request).await?;
+        let granted_budget = result
+            .granted_budget
+            .ok_or_else(|| anyhow::anyhow!("Missing granted budget in partial reservation"))?;
+        Ok((
+            result.sponsor_address,
+            result.reservation_id,
+            result
+                .gas_coins
+                .into_iter()
+                .map(|c| c.to_object_ref())
+                .collect(),
+            granted_budget,
+        ))
+    }
+
+    /// Reserves coins summing to at least target_balance. Unlike `reserve_gas`, the balance of
+    /// each reserved coin is returned as well.
+    pub async fn reserve_balance(
+        &self,
+        target_balance: u64,
+        reserve_duration_secs: u64,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<GasCoin>)> {
         let mut headers = HeaderMap::new();
         headers.insert(
             AUTHORIZATION,
             format!("Bearer {}", read_auth_env()).parse().unwrap(),
         );
+        let request = ReserveBalanceRequest {
+            target_balance,
+            reserve_duration_secs,
+            callback_url: None,
+            client_id: None,
+            priority: None,
+        };
         let response = self
             .client
-            .post(format!("{}/v1/reserve_gas", self.server_address))
+            .post(format!("{}/v1/reserve_balance", self.server_address))
             .headers(headers)
             .json(&request)
             .send()
             .await?
-            .json::<ReserveGasResponse>()
+            .json::<ReserveBalanceResponse>()
+            .await?;
+        let ReserveBalanceResult {
+            sponsor_address,
+            reservation_id,
+            coins,
+            ..
+        } = response.result.ok_or_else(|| {
+            anyhow::anyhow!(response
+                .error
+                .unwrap_or_else(|| "Unknown error".to_string()))
+        })?;
+        let coins = coins
+            .into_iter()
+            .map(|coin| GasCoin {
+                object_ref: coin.object_ref.to_object_ref(),
+                balance: coin.balance,
+            })
+            .collect();
+        Ok((sponsor_address, reservation_id, coins))
+    }
+
+    /// Reserves a budget without getting any gas coins. Returns the sponsor address and the
+    /// reservation ID, which must be passed to `claim_budget_lease` to get the gas coins.
+    pub async fn reserve_budget_lease(
+        &self,
+        gas_budget: u64,
+        lease_duration_secs: u64,
+    ) -> anyhow::Result<(MysAddress, ReservationID)> {
+        let request = ReserveGasRequest {
+            gas_budget,
+            reserve_duration_secs: lease_duration_secs,
+            callback_url: None,
+            budget_lease: true,
+            client_id: None,
+            priority: None,
+            allow_partial: false,
+            wait_for_coins: false,
+            requested_coins: vec![],
+            tag: None,
+            candidates: false,
+        };
+        let (sponsor, reservation_id, _) =
+            self.send_reserve_request("v1/reserve_gas", &request).await?;
+        Ok((sponsor, reservation_id))
+    }
+
+    pub async fn claim_budget_lease(
+        &self,
+        reservation_id: ReservationID,
+        reserve_duration_secs: u64,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<ObjectRef>)> {
+        let request = ClaimLeaseRequest {
+            reservation_id,
+            reserve_duration_secs,
+        };
+        self.send_reserve_request("v1/claim_lease", &request).await
+    }
+
+    /// Cancels a reservation or an unclaimed budget lease, releasing all of its gas coins.
+    /// Returns the number of coins released. Cancelling the same reservation again succeeds
+    /// and releases nothing.
+    pub async fn cancel_reservation(&self, reservation_id: ReservationID) -> anyhow::Result<usize> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            format!("Bearer {}", read_auth_env()).parse().unwrap(),
+        );
+        let response = self
+            .client
+            .post(format!("{}/v1/cancel_reservation", self.server_address))
+            .headers(headers)
+            .json(&CancelReservationRequest { reservation_id })
+            .send()
+            .await?
+            .json::<CancelReservationResponse>()
             .await?;
         response
             .result
+            .map(|result| result.freed_coin_count)
             .ok_or_else(|| {
                 anyhow::anyhow!(response
                     .error
                     .unwrap_or_else(|| "Unknown error".to_string()))
             })
-            .map(|result| {
-                (
-                    result.sponsor_address,
-                    result.reservation_id,
-                    result
-                        .gas_coins
-                        .into_iter()
-                        .map(|c| c.to_object_ref())
-                        .collect(),
-                )
-            })
+    }
+
+    async fn send_reserve_request<T: Serialize>(
+        &self,
+        path: &str,
+        request: &T,
+    ) -> anyhow::Result<(MysAddress, ReservationID, Vec<ObjectRef>)> {
+        let result = self.send_reserve_request_for_result(path, request).await?;
+        Ok((
+            result.sponsor_address,
+            result.reservation_id,
+            result
+                .gas_coins
+                .into_iter()
+                .map(|c| c.to_object_ref())
+                .collect(),
+        ))
+    }
+
+    async fn send_reserve_request_for_result<T: Serialize>(
+        &self,
+        path: &str,
+        request: &T,
+    ) -> anyhow::Result<ReserveGasResult> {
+        let response = self.send_reserve_request_for_response(path, request).await?;
+        response.result.ok_or_else(|| {
+            anyhow::anyhow!(response
+                .error
+                .unwrap_or_else(|| "Unknown error".to_string()))
+        })
+    }
+
+    async fn send_reserve_request_for_response<T: Serialize>(
+        &self,
+        path: &str,
+        request: &T,
+    ) -> anyhow::Result<ReserveGasResponse> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            format!("Bearer {}", read_auth_env()).parse().unwrap(),
+        );
+        headers.insert(API_VERSION_HEADER, ApiVersion::LATEST.as_str().parse().unwrap());
+        let response = self
+            .client
+            .post(format!("{}/{}", self.server_address, path))
+            .headers(headers)
+            .json(request)
+            .send()
+            .await?
+            .json::<ReserveGasResponse>()
+            .await?;
+        Ok(response)
     }
 
     pub async fn execute_tx(
@@ -129,15 +652,91 @@ impl GasPoolRpcClient {
         tx_data: &TransactionData,
         user_sig: &GenericSignature,
     ) -> anyhow::Result<MysTransactionBlockEffects> {
+        self.execute_tx_with_signed_effects(reservation_id, tx_data, user_sig)
+            .await
+            .map(|(effects, _)| effects)
+    }
+
+    /// Same as `execute_tx`, but also returns the sponsor signed effects if the gas pool
+    /// is configured to sign them.
+    pub async fn execute_tx_with_signed_effects(
+        &self,
+        reservation_id: ReservationID,
+        tx_data: &TransactionData,
+        user_sig: &GenericSignature,
+    ) -> anyhow::Result<(MysTransactionBlockEffects, Option<SignedEffects>)> {
+        let response = self
+            .send_execute_tx_request(reservation_id, tx_data, user_sig, false)
+            .await?;
+        let signed_effects = response.signed_effects;
+        response
+            .effects
+            .ok_or_else(|| {
+                anyhow::anyhow!(response
+                    .error
+                    .unwrap_or_else(|| "Unknown error".to_string()))
+            })
+            .map(|effects| (effects, signed_effects))
+    }
+
+    /// Same as `execute_tx`, but also returns the gas price the sponsor signed the transaction
+    /// with and the gas it spent, for clients that reconcile costs.
+    pub async fn execute_tx_with_gas_cost(
+        &self,
+        reservation_id: ReservationID,
+        tx_data: &TransactionData,
+        user_sig: &GenericSignature,
+    ) -> anyhow::Result<(MysTransactionBlockEffects, u64, GasCostSummary)> {
+        let response = self
+            .send_execute_tx_request(reservation_id, tx_data, user_sig, false)
+            .await?;
+        match (response.effects, response.gas_price, response.gas_used) {
+            (Some(effects), Some(gas_price), Some(gas_used)) => Ok((effects, gas_price, gas_used)),
+            (None, _, _) => Err(anyhow::anyhow!(response
+                .error
+                .unwrap_or_else(|| "Unknown error".to_string()))),
+            _ => bail!("Missing gas cost in execute_tx response"),
+        }
+    }
+
+    /// Same as `execute_tx`, but the gas pool first runs the transaction with dev_inspect and
+    /// rejects it without paying for gas if it would fail.
+    pub async fn execute_tx_with_preflight(
+        &self,
+        reservation_id: ReservationID,
+        tx_data: &TransactionData,
+        user_sig: &GenericSignature,
+    ) -> anyhow::Result<MysTransactionBlockEffects> {
+        let response = self
+            .send_execute_tx_request(reservation_id, tx_data, user_sig, true)
+            .await?;
+        response.effects.ok_or_else(|| {
+            anyhow::anyhow!(response
+                .error
+                .unwrap_or_else(|| "Unknown error".to_string()))
+        })
+    }
+
+    async fn send_execute_tx_request(
+        &self,
+        reservation_id: ReservationID,
+        tx_data: &TransactionData,
+        user_sig: &GenericSignature,
+        preflight: bool,
+    ) -> anyhow::Result<ExecuteTxResponse> {
         let mut headers = HeaderMap::new();
         headers.insert(
             AUTHORIZATION,
             format!("Bearer {}", read_auth_env()).parse().unwrap(),
         );
+        headers.insert(API_VERSION_HEADER, ApiVersion::LATEST.as_str().parse().unwrap());
         let request = ExecuteTxRequest {
             reservation_id,
             tx_bytes: Base64::from_bytes(&bcs::to_bytes(&tx_data).unwrap()),
             user_sig: Base64::from_bytes(user_sig.as_ref()),
+            // This client always returns the full effects.
+            full_effects: true,
+            preflight,
         };
         let response = self
             .client
@@ -148,10 +747,119 @@ impl GasPoolRpcClient {
             .await?
             .json::<ExecuteTxResponse>()
             .await?;
-        response.effects.ok_or_else(|| {
+        Ok(response)
+    }
+
+    /// Executes multiple sponsored transactions in a single request.
+    /// The outer result fails only if the whole batch is rejected. Otherwise there is one result
+    /// per transaction, in the same order as the input, and a failed transaction does not affect
+    /// the others.
+    pub async fn batch_execute_tx(
+        &self,
+        transactions: &[(ReservationID, TransactionData, GenericSignature)],
+    ) -> anyhow::Result<Vec<anyhow::Result<(MysTransactionBlockEffects, Option<SignedEffects>)>>>
+    {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            format!("Bearer {}", read_auth_env()).parse().unwrap(),
+        );
+        let request = batch_execute_tx_request(transactions);
+        let response = self
+            .client
+            .post(format!("{}/v1/batch_execute_tx", self.server_address))
+            .headers(headers)
+            .json(&request)
+            .send()
+            .await?
+            .json::<BatchExecuteTxResponse>()
+            .await?;
+        if let Some(err) = response.error {
+            bail!(err);
+        }
+        Ok(response
+            .results
+            .into_iter()
+            .map(execute_tx_result)
+            .collect())
+    }
+
+    /// Like batch_execute_tx, but `on_result` is called with the index and result of each
+    /// transaction as soon as it completes, instead of waiting for the whole batch.
+    /// Returns the indexes of the transactions that failed once every transaction completed.
+    pub async fn batch_execute_tx_stream(
+        &self,
+        transactions: &[(ReservationID, TransactionData, GenericSignature)],
+        mut on_result: impl FnMut(
+            usize,
+            anyhow::Result<(MysTransactionBlockEffects, Option<SignedEffects>)>,
+        ),
+    ) -> anyhow::Result<Vec<usize>> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            format!("Bearer {}", read_auth_env()).parse().unwrap(),
+        );
+        let request = batch_execute_tx_request(transactions);
+        let mut response = self
+            .client
+            .post(format!("{}/v1/batch_execute_tx_stream", self.server_address))
+            .headers(headers)
+            .json(&request)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let response = response.json::<BatchExecuteTxResponse>().await?;
+            bail!(response
+                .error
+                .unwrap_or_else(|| "Unknown error".to_string()));
+        }
+        let mut buffer = vec![];
+        while let Some(chunk) = response.chunk().await? {
+            buffer.extend_from_slice(&chunk);
+            while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                match serde_json::from_slice::<BatchExecuteTxStreamEvent>(&line)? {
+                    BatchExecuteTxStreamEvent::Result { index, response } => {
+                        on_result(index, execute_tx_result(response))
+                    }
+                    BatchExecuteTxStreamEvent::Done { failed_indexes } => {
+                        return Ok(failed_indexes)
+                    }
+                }
+            }
+        }
+        bail!("Stream ended before all transactions of the batch completed")
+    }
+}
+
+fn batch_execute_tx_request(
+    transactions: &[(ReservationID, TransactionData, GenericSignature)],
+) -> BatchExecuteTxRequest {
+    BatchExecuteTxRequest {
+        requests: transactions
+            .iter()
+            .map(|(reservation_id, tx_data, user_sig)| ExecuteTxRequest {
+                reservation_id: *reservation_id,
+                tx_bytes: Base64::from_bytes(&bcs::to_bytes(tx_data).unwrap()),
+                user_sig: Base64::from_bytes(user_sig.as_ref()),
+                full_effects: true,
+                preflight: false,
+            })
+            .collect(),
+    }
+}
+
+fn execute_tx_result(
+    response: ExecuteTxResponse,
+) -> anyhow::Result<(MysTransactionBlockEffects, Option<SignedEffects>)> {
+    let signed_effects = response.signed_effects;
+    response
+        .effects
+        .ok_or_else(|| {
             anyhow::anyhow!(response
                 .error
                 .unwrap_or_else(|| "Unknown error".to_string()))
         })
-    }
+        .map(|effects| (effects, signed_effects))
 }
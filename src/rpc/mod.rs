@@ -5,15 +5,37 @@
 pub mod client;
 mod rpc_types;
 mod server;
+mod tls;
 
 pub use server::GasPoolServer;
 
 #[cfg(test)]
 mod tests {
-    use crate::test_env::{create_test_transaction, start_rpc_server_for_testing};
-    use crate::AUTH_ENV_NAME;
+    use crate::auth::HmacTokenAuthenticator;
+    use crate::config::{
+        AuditLogConfig, AuthConfig, ClientBudgetLimits, ClientBudgetLimitsConfig,
+        GasPoolCoreConfig, ReservationLimitsConfig,
+    };
+    use crate::rpc::rpc_types::{
+        BatchExecuteTxRequest, BatchExecuteTxResponse, ExecuteTxRequest, API_VERSION_HEADER,
+    };
+    use crate::test_env::{
+        create_test_transaction, start_rpc_server_for_testing,
+        start_rpc_server_for_testing_with_auth_config, start_rpc_server_for_testing_with_config,
+    };
+    use crate::mys_client::MysClient;
+    use crate::tx_signer::{TestTxSigner, TxSigner};
+    use crate::types::{AuditEvent, ReservationID};
+    use crate::{read_auth_env, AUTH_ENV_NAME, AUTH_HMAC_KEY_ENV_NAME};
+    use fastcrypto::encoding::Base64;
     use mys_json_rpc_types::MysTransactionBlockEffectsAPI;
+    use mys_types::crypto::get_account_key_pair;
     use mys_types::gas_coin::MIST_PER_MYS;
+    use mys_types::object::Owner;
+    use mys_types::programmable_transaction_builder::ProgrammableTransactionBuilder;
+    use mys_types::transaction::{TransactionData, TransactionDataAPI, TransactionKind};
+    use std::collections::BTreeSet;
+    use std::time::Duration;
 
     #[tokio::test]
     async fn test_basic_rpc_flow() {
@@ -37,6 +59,675 @@ mod tests {
         assert!(effects.status().is_ok());
     }
 
+    #[tokio::test]
+    async fn test_execute_tx_gas_cost() {
+        let (test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+        let (sponsor, reservation_id, gas_coins) =
+            client.reserve_gas(MIST_PER_MYS, 10).await.unwrap();
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+        let (effects, gas_price, gas_used) = client
+            .execute_tx_with_gas_cost(reservation_id, &tx_data, &user_sig)
+            .await
+            .unwrap();
+        assert_eq!(gas_price, tx_data.gas_data().price);
+        assert_eq!(&gas_used, effects.gas_cost_summary());
+    }
+
+    /// Walks a sponsored transaction through the whole pipeline: the pool is initialized from a
+    /// funded sponsor address, the transaction is signed by a user key and by the sponsor, and
+    /// the used coin is put back into the pool with its balance as of the effects.
+    #[tokio::test]
+    async fn test_sponsored_transaction_pipeline() {
+        let (test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+        let stats = client.stats().await.unwrap();
+        assert_eq!(stats.available_coin_count, 10);
+        assert_eq!(stats.available_coin_total_balance, MIST_PER_MYS * 10);
+
+        let gas_budget = MIST_PER_MYS / 10;
+        let (sponsor, reservation_id, gas_coins) =
+            client.reserve_gas(gas_budget, 10).await.unwrap();
+        assert_eq!(sponsor, stats.sponsor_address);
+        assert_eq!(gas_coins.len(), 1);
+        let gas_coin_id = gas_coins[0].0;
+
+        let (sender, keypair) = get_account_key_pair();
+        let user_signer = TestTxSigner::new(keypair.into());
+        let tx_kind = TransactionKind::programmable(ProgrammableTransactionBuilder::new().finish());
+        let tx_data = TransactionData::new_with_gas_coins_allow_sponsor(
+            tx_kind,
+            sender,
+            gas_coins,
+            gas_budget,
+            test_cluster.get_reference_gas_price().await,
+            sponsor,
+        );
+        let user_sig = user_signer.sign_transaction(&tx_data).await.unwrap();
+        let effects = client
+            .execute_tx(reservation_id, &tx_data, &user_sig)
+            .await
+            .unwrap();
+        assert!(effects.status().is_ok());
+        let gas_object = effects.gas_object();
+        assert_eq!(gas_object.reference.object_id, gas_coin_id);
+        assert_eq!(gas_object.owner, Owner::AddressOwner(sponsor));
+        let gas_used = effects.gas_cost_summary().net_gas_usage();
+        assert!(gas_used > 0);
+
+        // The coin is back in the pool, with the balance it has on chain.
+        let snapshot = client.get_pool_snapshot().await.unwrap();
+        assert!(snapshot.reservations.is_empty());
+        assert_eq!(snapshot.stats.available_coin_count, 10);
+        assert_eq!(
+            snapshot.stats.available_coin_total_balance,
+            MIST_PER_MYS * 10 - gas_used as u64
+        );
+        let mys_client = MysClient::new(&test_cluster.fullnode_handle.rpc_url, None).await;
+        let latest_coin = mys_client
+            .get_latest_gas_objects(sponsor, [gas_coin_id])
+            .await
+            .remove(&gas_coin_id)
+            .flatten()
+            .unwrap();
+        assert_eq!(latest_coin.object_ref, gas_object.reference.to_object_ref());
+        assert_eq!(latest_coin.balance, MIST_PER_MYS - gas_used as u64);
+
+        // The reservation cannot be executed twice.
+        assert!(client
+            .execute_tx(reservation_id, &tx_data, &user_sig)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_min_reserve_duration() {
+        let (_test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+        let min_duration = ReservationLimitsConfig::default().min_reserve_duration_secs;
+
+        assert!(client
+            .reserve_gas(MIST_PER_MYS, min_duration - 1)
+            .await
+            .is_err());
+        let (_sponsor, reservation_id) = client
+            .reserve_budget_lease(MIST_PER_MYS, 3600)
+            .await
+            .unwrap();
+        assert!(client
+            .claim_budget_lease(reservation_id, min_duration - 1)
+            .await
+            .is_err());
+        client.reserve_gas(MIST_PER_MYS, min_duration).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_partial_reservation() {
+        let (_test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+        for _ in 0..9 {
+            client.reserve_gas(MIST_PER_MYS, 10).await.unwrap();
+        }
+        // Only one coin is left, which cannot cover the budget by default.
+        assert!(client.reserve_gas(MIST_PER_MYS * 2, 10).await.is_err());
+        let (_sponsor, _reservation_id, gas_coins, granted_budget) = client
+            .reserve_partial_gas(MIST_PER_MYS * 2, 10)
+            .await
+            .unwrap();
+        assert_eq!(gas_coins.len(), 1);
+        assert_eq!(granted_budget, MIST_PER_MYS);
+        // Partial reservations still fail when there is nothing left to reserve.
+        assert!(client.reserve_partial_gas(1, 10).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reserve_balance() {
+        let (test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+
+        let (sponsor, reservation_id, coins) = client
+            .reserve_balance(MIST_PER_MYS * 5 / 2, 10)
+            .await
+            .unwrap();
+        assert_eq!(coins.len(), 3);
+        assert!(coins.iter().all(|coin| coin.balance == MIST_PER_MYS));
+        assert_eq!(client.stats().await.unwrap().available_coin_count, 7);
+        assert!(client.reserve_balance(MIST_PER_MYS * 8, 10).await.is_err());
+        assert!(client.reserve_balance(0, 10).await.is_err());
+
+        // The reservation is executed like any other.
+        let gas_coins = coins.into_iter().map(|coin| coin.object_ref).collect();
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+        let effects = client
+            .execute_tx(reservation_id, &tx_data, &user_sig)
+            .await
+            .unwrap();
+        assert!(effects.status().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_budget_lease_rpc_flow() {
+        let (test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+
+        let (sponsor, reservation_id) = client
+            .reserve_budget_lease(MIST_PER_MYS * 3, 3600)
+            .await
+            .unwrap();
+        let stats = client.stats().await.unwrap();
+        assert_eq!(stats.available_coin_count, 10);
+        assert_eq!(stats.leased_budget, MIST_PER_MYS * 3);
+        // The leased budget is not available to other reservations.
+        assert!(client.reserve_gas(MIST_PER_MYS * 8, 10).await.is_err());
+
+        let (claimed_sponsor, claimed_reservation_id, gas_coins) = client
+            .claim_budget_lease(reservation_id, 10)
+            .await
+            .unwrap();
+        assert_eq!(claimed_sponsor, sponsor);
+        assert_eq!(claimed_reservation_id, reservation_id);
+        assert_eq!(gas_coins.len(), 3);
+        assert!(client.claim_budget_lease(reservation_id, 10).await.is_err());
+
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+        let effects = client
+            .execute_tx(reservation_id, &tx_data, &user_sig)
+            .await
+            .unwrap();
+        assert!(effects.status().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_reservation() {
+        let (test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+
+        let (sponsor, reservation_id, gas_coins) =
+            client.reserve_gas(MIST_PER_MYS * 3, 60).await.unwrap();
+        assert_eq!(gas_coins.len(), 3);
+        assert_eq!(client.stats().await.unwrap().available_coin_count, 7);
+
+        assert_eq!(client.cancel_reservation(reservation_id).await.unwrap(), 3);
+        assert_eq!(client.stats().await.unwrap().available_coin_count, 10);
+        // Cancelling is idempotent.
+        assert_eq!(client.cancel_reservation(reservation_id).await.unwrap(), 0);
+
+        // The cancelled reservation can no longer be executed.
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+        assert!(client
+            .execute_tx(reservation_id, &tx_data, &user_sig)
+            .await
+            .is_err());
+        // Unknown reservations cannot be cancelled.
+        assert!(client
+            .cancel_reservation(ReservationID::new(reservation_id.value() + 100).unwrap())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_batch_execute_tx() {
+        let (test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+
+        let mut transactions = vec![];
+        for _ in 0..2 {
+            let (sponsor, reservation_id, gas_coins) =
+                client.reserve_gas(MIST_PER_MYS, 10).await.unwrap();
+            let (tx_data, user_sig) =
+                create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+            transactions.push((reservation_id, tx_data, user_sig));
+        }
+        // Use an unknown reservation for the second transaction so that it fails.
+        transactions[1].0 = ReservationID::new(transactions[1].0.value() + 100).unwrap();
+        let results = client.batch_execute_tx(&transactions).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].as_ref().unwrap().0.status().is_ok());
+        assert!(results[1].is_err());
+
+        assert!(client.batch_execute_tx(&[]).await.is_err());
+
+        // Each executed transaction reports its gas cost, like execute_tx does.
+        let (sponsor, reservation_id, gas_coins) =
+            client.reserve_gas(MIST_PER_MYS, 10).await.unwrap();
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+        let request = BatchExecuteTxRequest {
+            requests: vec![ExecuteTxRequest {
+                reservation_id,
+                tx_bytes: Base64::from_bytes(&bcs::to_bytes(&tx_data).unwrap()),
+                user_sig: Base64::from_bytes(user_sig.as_ref()),
+                full_effects: true,
+                preflight: false,
+            }],
+        };
+        let response: BatchExecuteTxResponse = reqwest::Client::new()
+            .post(format!("http://localhost:{}/v1/batch_execute_tx", server.rpc_port))
+            .bearer_auth(read_auth_env())
+            .json(&request)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let result = &response.results[0];
+        assert_eq!(result.gas_price, Some(tx_data.gas_data().price));
+        assert_eq!(
+            result.gas_used.as_ref(),
+            Some(result.effects.as_ref().unwrap().gas_cost_summary())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_execute_tx_stream() {
+        let (test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+
+        let mut transactions = vec![];
+        for _ in 0..3 {
+            let (sponsor, reservation_id, gas_coins) =
+                client.reserve_gas(MIST_PER_MYS, 10).await.unwrap();
+            let (tx_data, user_sig) =
+                create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+            transactions.push((reservation_id, tx_data, user_sig));
+        }
+        // Use an unknown reservation for the second transaction so that it fails.
+        transactions[1].0 = ReservationID::new(transactions[1].0.value() + 100).unwrap();
+        let mut results = vec![];
+        let failed_indexes = client
+            .batch_execute_tx_stream(&transactions, |index, result| results.push((index, result)))
+            .await
+            .unwrap();
+        assert_eq!(failed_indexes, vec![1]);
+        assert_eq!(results.len(), 3);
+        results.sort_by_key(|(index, _)| *index);
+        assert!(results[0].1.as_ref().unwrap().0.status().is_ok());
+        assert!(results[1].1.is_err());
+        assert!(results[2].1.as_ref().unwrap().0.status().is_ok());
+
+        assert!(client
+            .batch_execute_tx_stream(&[], |_, _| panic!("Empty batch must be rejected"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reload_coins() {
+        let (test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+        let (sponsor, _reservation_id, gas_coins) =
+            client.reserve_gas(MIST_PER_MYS, 60).await.unwrap();
+        assert_eq!(gas_coins.len(), 1);
+
+        // Nothing changed on chain, so the reserved coin must not be added back.
+        let result = client.reload_coins().await.unwrap();
+        assert_eq!(result.scanned_coin_count, 10);
+        assert_eq!(result.added_coin_count, 0);
+
+        // Fund the sponsor manually.
+        let funder = *test_cluster
+            .get_addresses()
+            .iter()
+            .find(|addr| **addr != sponsor)
+            .unwrap();
+        let tx_data = test_cluster
+            .test_transaction_builder_with_sender(funder)
+            .await
+            .transfer_mys(Some(MIST_PER_MYS), sponsor)
+            .build();
+        test_cluster.sign_and_execute_transaction(&tx_data).await;
+
+        let result = client.reload_coins().await.unwrap();
+        assert_eq!(result.added_coin_count, 1);
+        assert_eq!(result.added_balance, MIST_PER_MYS);
+        assert_eq!(client.stats().await.unwrap().available_coin_count, 10);
+    }
+
+    #[tokio::test]
+    async fn test_safe_mode() {
+        let (test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+        assert!(!client.stats().await.unwrap().safe_mode);
+        assert!(client.set_safe_mode(true).await.unwrap());
+        assert!(client.stats().await.unwrap().safe_mode);
+        client.health().await.unwrap();
+
+        // Reservations and executions are still served.
+        let (sponsor, reservation_id, gas_coins) =
+            client.reserve_gas(MIST_PER_MYS, 10).await.unwrap();
+        let (tx_data, user_sig) = create_test_transaction(&test_cluster, sponsor, gas_coins).await;
+        let effects = client
+            .execute_tx(reservation_id, &tx_data, &user_sig)
+            .await
+            .unwrap();
+        assert!(effects.status().is_ok());
+
+        // Expired reservations are not released while in safe mode.
+        client.reserve_gas(MIST_PER_MYS, 1).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        assert_eq!(client.stats().await.unwrap().available_coin_count, 9);
+
+        assert!(!client.set_safe_mode(false).await.unwrap());
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        assert_eq!(client.stats().await.unwrap().available_coin_count, 10);
+    }
+
+    #[tokio::test]
+    async fn test_pool_snapshot() {
+        let (_test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+        let (_sponsor, reservation_id, gas_coins) =
+            client.reserve_gas(MIST_PER_MYS * 2, 10).await.unwrap();
+
+        let snapshot = client.get_pool_snapshot().await.unwrap();
+        assert_eq!(snapshot.available_coin_balances.coin_count, 8);
+        assert_eq!(snapshot.available_coin_balances.total_balance, MIST_PER_MYS * 8);
+        assert_eq!(snapshot.available_coin_balances.tracked_coin_count, 8);
+        assert_eq!(snapshot.available_coin_balances.median_balance, MIST_PER_MYS);
+        assert_eq!(snapshot.reservations.len(), 1);
+        let reservation = &snapshot.reservations[0];
+        assert_eq!(reservation.reservation_id, reservation_id);
+        assert_eq!(
+            reservation.coin_ids.iter().collect::<BTreeSet<_>>(),
+            gas_coins.iter().map(|coin| &coin.0).collect::<BTreeSet<_>>()
+        );
+        assert!(reservation.expiration_ms > snapshot.timestamp_ms);
+        assert!(snapshot.budget_leases.is_empty());
+        assert!(!snapshot.background_tasks.coin_rebalance);
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let (test_cluster, _container, server) = start_rpc_server_for_testing_with_config(
+            vec![MIST_PER_MYS; 10],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                sign_execution_effects: true,
+                coin_cooldown_ms: 0,
+                audit_log_config: Some(AuditLogConfig {
+                    flush_interval_ms: 100,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )
+        .await;
+        let client = server.get_local_client();
+        let from_ms = chrono::Utc::now().timestamp_millis() as u64;
+        let (sponsor, reservation_id, gas_coins) =
+            client.reserve_gas(MIST_PER_MYS, 10).await.unwrap();
+        let (tx_data, user_sig) =
+            create_test_transaction(&test_cluster, sponsor, gas_coins.clone()).await;
+        let effects = client
+            .execute_tx(reservation_id, &tx_data, &user_sig)
+            .await
+            .unwrap();
+        // Records are written in the background.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let to_ms = chrono::Utc::now().timestamp_millis() as u64;
+
+        let records = client
+            .query_audit_log(from_ms, to_ms, None, None)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|r| r.reservation_id == reservation_id));
+        assert!(matches!(
+            &records[0].event,
+            AuditEvent::Reserve { gas_budget, gas_coins: coins }
+                if *gas_budget == MIST_PER_MYS && coins[0] == gas_coins[0].0
+        ));
+        assert!(matches!(
+            &records[1].event,
+            AuditEvent::Execute { digest: Some(digest), error: None, .. }
+                if digest == effects.transaction_digest()
+        ));
+
+        let records = client
+            .query_audit_log(from_ms, to_ms, Some("other".to_string()), None)
+            .await
+            .unwrap();
+        assert!(records.is_empty());
+        assert!(client
+            .query_audit_log(to_ms, from_ms, None, None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reservation_tag() {
+        let (test_cluster, _container, server) = start_rpc_server_for_testing_with_config(
+            vec![MIST_PER_MYS; 10],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                coin_cooldown_ms: 0,
+                audit_log_config: Some(AuditLogConfig {
+                    flush_interval_ms: 100,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )
+        .await;
+        let client = server.get_local_client();
+        let from_ms = chrono::Utc::now().timestamp_millis() as u64;
+        let result = client
+            .reserve_gas_with_tag(MIST_PER_MYS, 10, "team-a:checkout".to_string())
+            .await
+            .unwrap();
+        assert_eq!(result.tag.as_deref(), Some("team-a:checkout"));
+        let gas_coins = result
+            .gas_coins
+            .into_iter()
+            .map(|c| c.to_object_ref())
+            .collect();
+        let (tx_data, user_sig) =
+            create_test_transaction(&test_cluster, result.sponsor_address, gas_coins).await;
+        client
+            .execute_tx(result.reservation_id, &tx_data, &user_sig)
+            .await
+            .unwrap();
+        // Records are written in the background.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let to_ms = chrono::Utc::now().timestamp_millis() as u64;
+        let records = client
+            .query_audit_log(from_ms, to_ms, None, None)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records
+            .iter()
+            .all(|r| r.tag.as_deref() == Some("team-a:checkout")));
+
+        // Tags are length-limited and may not contain characters that are unsafe to log.
+        assert!(client
+            .reserve_gas_with_tag(MIST_PER_MYS, 10, "a".repeat(65))
+            .await
+            .is_err());
+        assert!(client
+            .reserve_gas_with_tag(MIST_PER_MYS, 10, "team a\n".to_string())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_api_version() {
+        let (_test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let reserve_gas = |version: Option<&str>| {
+            let mut request = reqwest::Client::new()
+                .post(format!("http://localhost:{}/v1/reserve_gas", server.rpc_port))
+                .bearer_auth(read_auth_env())
+                .json(&serde_json::json!({
+                    "gas_budget": MIST_PER_MYS,
+                    "reserve_duration_secs": 10,
+                }));
+            if let Some(version) = version {
+                request = request.header(API_VERSION_HEADER, version);
+            }
+            request.send()
+        };
+
+        // Requests without the header get the v1 shape.
+        let response = reserve_gas(None).await.unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.headers()[API_VERSION_HEADER], "1");
+
+        let response = reserve_gas(Some("v1")).await.unwrap();
+        assert!(response.status().is_success());
+        let body: serde_json::Value = response.json().await.unwrap();
+        let mut fields: Vec<_> = body["result"].as_object().unwrap().keys().collect();
+        fields.sort();
+        assert_eq!(fields, ["gas_coins", "reservation_id", "sponsor_address"]);
+
+        let response = reserve_gas(Some("99")).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_reserve_gas_request() {
+        let (_test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let reserve_gas = |body: &'static str| {
+            reqwest::Client::new()
+                .post(format!("http://localhost:{}/v1/reserve_gas", server.rpc_port))
+                .bearer_auth(read_auth_env())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body)
+                .send()
+        };
+
+        for body in ["{", r#"{"gas_budget": "lots", "reserve_duration_secs": 10}"#] {
+            let response = reserve_gas(body).await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+            let body: serde_json::Value = response.json().await.unwrap();
+            assert!(body["error"].is_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_active_reservation_cap_uses_token_client_id() {
+        std::env::set_var(AUTH_HMAC_KEY_ENV_NAME, "some hmac key");
+        let (_test_cluster, _container, server) = start_rpc_server_for_testing_with_auth_config(
+            vec![MIST_PER_MYS; 10],
+            MIST_PER_MYS,
+            GasPoolCoreConfig {
+                client_budget_limits_config: Some(ClientBudgetLimitsConfig {
+                    default_limits: ClientBudgetLimits {
+                        max_active_reservations: Some(1),
+                        ..Default::default()
+                    },
+                    client_limits: [(
+                        "privileged".to_string(),
+                        ClientBudgetLimits {
+                            max_active_reservations: Some(5),
+                            ..Default::default()
+                        },
+                    )]
+                    .into(),
+                }),
+                ..Default::default()
+            },
+            AuthConfig::HmacToken,
+        )
+        .await;
+        let token = HmacTokenAuthenticator::new("some hmac key").issue_token("capped-client");
+        let reserve_gas = |client_id: &str| {
+            reqwest::Client::new()
+                .post(format!("http://localhost:{}/v1/reserve_gas", server.rpc_port))
+                .bearer_auth(&token)
+                .json(&serde_json::json!({
+                    "gas_budget": MIST_PER_MYS,
+                    "reserve_duration_secs": 10,
+                    "client_id": client_id,
+                }))
+                .send()
+        };
+
+        let response = reserve_gas("first").await.unwrap();
+        assert!(response.status().is_success());
+        // Neither a new client id nor the id of a client with a higher cap gets around the cap
+        // of the token's client.
+        for client_id in ["second", "privileged"] {
+            let response = reserve_gas(client_id).await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_reservation_of_another_client() {
+        std::env::set_var(AUTH_HMAC_KEY_ENV_NAME, "some hmac key");
+        let (_test_cluster, _container, server) = start_rpc_server_for_testing_with_auth_config(
+            vec![MIST_PER_MYS; 10],
+            MIST_PER_MYS,
+            GasPoolCoreConfig::default(),
+            AuthConfig::HmacToken,
+        )
+        .await;
+        let authenticator = HmacTokenAuthenticator::new("some hmac key");
+        let owner_token = authenticator.issue_token("owner");
+        let other_token = authenticator.issue_token("other");
+        let response: serde_json::Value = reqwest::Client::new()
+            .post(format!("http://localhost:{}/v1/reserve_gas", server.rpc_port))
+            .bearer_auth(&owner_token)
+            .json(&serde_json::json!({
+                "gas_budget": MIST_PER_MYS,
+                "reserve_duration_secs": 10,
+            }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let reservation_id = response["result"]["reservation_id"].clone();
+        let cancel_reservation = |token: &str| {
+            reqwest::Client::new()
+                .post(format!("http://localhost:{}/v1/cancel_reservation", server.rpc_port))
+                .bearer_auth(token)
+                .json(&serde_json::json!({ "reservation_id": reservation_id }))
+                .send()
+        };
+
+        // Another client cannot tell the reservation apart from one that does not exist.
+        let response = cancel_reservation(&other_token).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        let response = cancel_reservation(&owner_token).await.unwrap();
+        assert!(response.status().is_success());
+    }
+
+    #[tokio::test]
+    async fn test_pool_exhaustion_status() {
+        let (_test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS], MIST_PER_MYS).await;
+        let response = reqwest::Client::new()
+            .post(format!("http://localhost:{}/v1/reserve_gas", server.rpc_port))
+            .bearer_auth(read_auth_env())
+            .json(&serde_json::json!({
+                "gas_budget": MIST_PER_MYS * 2,
+                "reserve_duration_secs": 10,
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[reqwest::header::RETRY_AFTER], "1");
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert!(body["error"].is_string());
+    }
+
     #[tokio::test]
     async fn test_invalid_auth() {
         let (_test_cluster, _container, server) =
@@ -53,6 +744,34 @@ mod tests {
         assert!(client.reserve_gas(MIST_PER_MYS, 10).await.is_err());
     }
 
+    #[tokio::test]
+    async fn test_build_info() {
+        let (_test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+        let build_info = client.build_info().await.unwrap();
+        assert_eq!(
+            client.version().await.unwrap(),
+            format!("{}-{}", build_info.version, build_info.git_revision)
+        );
+        assert_eq!(build_info.signer_type, "local");
+        assert_eq!(build_info.storage_backend, "redis");
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let (_test_cluster, _container, server) =
+            start_rpc_server_for_testing(vec![MIST_PER_MYS; 10], MIST_PER_MYS).await;
+        let client = server.get_local_client();
+        let stats = client.stats().await.unwrap();
+        assert_eq!(stats.available_coin_count, 10);
+        assert_eq!(stats.coins_in_cooldown, 0);
+
+        client.reserve_gas(MIST_PER_MYS, 10).await.unwrap();
+        let stats = client.stats().await.unwrap();
+        assert_eq!(stats.available_coin_count, 9);
+    }
+
     #[tokio::test]
     async fn test_debug_health_check() {
         let (_test_cluster, _container, server) =
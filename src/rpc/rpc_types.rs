@@ -2,27 +2,175 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::types::ReservationID;
+use crate::config::ReservationLimitsConfig;
+use crate::errors::GasStationError;
+use crate::gas_pool::gas_pool_core::{CoinReloadResult, GasPoolStats, PoolDebugSnapshot};
+use crate::gas_pool::reservation_waitlist::WaitTicket;
+use crate::storage::MAX_GAS_PER_QUERY;
+use crate::types::{
+    AuditRecord, ClientUsage, GasCoin, ReservationID, SignedEffects, SignedReservationReceipt,
+};
+use anyhow::bail;
 use fastcrypto::encoding::Base64;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use mys_json_rpc_types::{MysObjectRef, MysTransactionBlockEffects};
-use mys_types::base_types::{ObjectRef, MysAddress};
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+use mys_json_rpc_types::{
+    MysExecutionStatus, MysObjectRef, MysTransactionBlockEffects, MysTransactionBlockEffectsAPI,
+};
+use mys_types::base_types::{ObjectID, ObjectRef, MysAddress};
+use mys_types::digests::TransactionDigest;
+use mys_types::gas::GasCostSummary;
 
 // 2 MYS.
 pub const MAX_BUDGET: u64 = 2_000_000_000;
 
+// Same cap as gas budgets, since the coins come from the same pool.
+pub const MAX_TARGET_BALANCE: u64 = MAX_BUDGET;
+
 // 10 mins.
 pub const MAX_DURATION_S: u64 = 10 * 60;
 
+// 24 hours.
+pub const MAX_LEASE_DURATION_S: u64 = 24 * 60 * 60;
+
+// Maximum number of audit records returned by a single query.
+pub const MAX_AUDIT_LOG_QUERY_LIMIT: usize = 10_000;
+
+// Maximum length of a reservation tag. Tags end up in logs and metric labels, so they are kept
+// short and restricted to a small set of characters.
+pub const MAX_RESERVATION_TAG_LEN: usize = 64;
+
+/// Request header that picks the API version of the response. Requests without it get v1.
+pub const API_VERSION_HEADER: &str = "x-gas-station-api-version";
+
+/// Version of the shape of the responses. Each version serializes a fixed set of fields, so that
+/// fields added to the responses later don't break clients that reject unknown fields. Fields
+/// added to a response must only be serialized for the versions introduced after them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiVersion {
+    #[default]
+    V1,
+    /// Adds the gas price and the gas used to execute_tx responses.
+    V2,
+    /// Adds the wait ticket to reserve_gas responses, for reservations that wait for coins.
+    V3,
+}
+
+impl ApiVersion {
+    pub const LATEST: ApiVersion = ApiVersion::V3;
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "1",
+            ApiVersion::V2 => "2",
+            ApiVersion::V3 => "3",
+        }
+    }
+}
+
+impl FromStr for ApiVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().trim_start_matches(['v', 'V']) {
+            "1" => Ok(ApiVersion::V1),
+            "2" => Ok(ApiVersion::V2),
+            "3" => Ok(ApiVersion::V3),
+            _ => bail!("Unsupported API version: {}", s),
+        }
+    }
+}
+
+/// A response whose shape is stable per API version.
+pub trait VersionedResponse: Serialize {
+    /// Serializes the response with only the fields of the given version.
+    fn to_versioned_json(&self, version: ApiVersion) -> serde_json::Value;
+}
+
+const RESERVE_GAS_RESPONSE_V1_FIELDS: &[&str] = &["result", "error"];
+const RESERVE_GAS_RESPONSE_V3_FIELDS: &[&str] = &["result", "error", "wait_ticket"];
+const RESERVE_GAS_RESULT_V1_FIELDS: &[&str] = &[
+    "sponsor_address",
+    "reservation_id",
+    "gas_coins",
+    "requested_budget",
+    "granted_budget",
+    "receipt",
+    "tag",
+    "candidate_coins",
+];
+const EXECUTE_TX_RESPONSE_V1_FIELDS: &[&str] =
+    &["effects", "error", "signed_effects", "effects_summary"];
+const EXECUTE_TX_RESPONSE_V2_FIELDS: &[&str] = &[
+    "effects",
+    "error",
+    "signed_effects",
+    "effects_summary",
+    "gas_price",
+    "gas_used",
+];
+
+fn retain_fields(value: &mut serde_json::Value, fields: &[&str]) {
+    if let serde_json::Value::Object(map) = value {
+        map.retain(|field, _| fields.contains(&field.as_str()));
+    }
+}
+
 #[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
 pub struct ReserveGasRequest {
     pub gas_budget: u64,
     pub reserve_duration_secs: u64,
+    /// When specified, a `ReservationEvent` is POSTed to this URL once the reservation
+    /// expires or is consumed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+    /// When true, only the budget is reserved and no gas coins are returned. The coins are
+    /// picked later with a `ClaimLeaseRequest`. In this case reserve_duration_secs is how long
+    /// the lease stays valid before it is claimed, up to MAX_LEASE_DURATION_S.
+    #[serde(default)]
+    pub budget_lease: bool,
+    /// Ignored, since the client is only identified by its auth token. Kept so that requests
+    /// of older clients are still accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Higher priority reservations are served first while the pool is close to running out
+    /// of coins. Capped by the limit configured for the client. Ignored for budget leases.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+    /// When true and the pool cannot cover the whole gas budget, the available coins are
+    /// reserved instead of failing the request. The response then reports the granted budget,
+    /// which may be less than gas_budget. Ignored for budget leases.
+    #[serde(default)]
+    pub allow_partial: bool,
+    /// When true and the pool cannot cover the gas budget right now, the reservation waits for
+    /// coins instead of failing. The response then only carries a wait ticket, and the result is
+    /// POSTed to callback_url as a `WaitingReservationEvent` once coins free up or the wait
+    /// expires. Requires a callback URL and API version 3, and cannot be combined with
+    /// budget_lease or allow_partial.
+    #[serde(default)]
+    pub wait_for_coins: bool,
+    /// When not empty, exactly these gas coins are reserved, as long as they are all available
+    /// in the pool and their total balance covers gas_budget. Fails if any of them is reserved
+    /// or not in the pool. Cannot be combined with budget_lease, allow_partial or wait_for_coins.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requested_coins: Vec<ObjectID>,
+    /// An opaque label stored with the reservation for downstream accounting. It is echoed in the
+    /// response and in the audit log, and breaks down the usage metrics. At most
+    /// MAX_RESERVATION_TAG_LEN characters out of ASCII letters, digits, '_', '-', '.' and ':'.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// When true, coins covering a multiple of gas_budget are reserved and returned with their
+    /// balances in candidate_coins, so that the client can pick which of them to pay with. The
+    /// reserved coins that the transaction does not pay with are released once it is executed.
+    /// Cannot be combined with budget_lease, allow_partial, wait_for_coins or requested_coins.
+    #[serde(default)]
+    pub candidates: bool,
 }
 
 impl ReserveGasRequest {
-    pub fn check_validity(&self) -> anyhow::Result<()> {
+    pub fn check_validity(&self, limits: &ReservationLimitsConfig) -> anyhow::Result<()> {
         if self.gas_budget == 0 {
             anyhow::bail!("Gas budget must be positive");
         }
@@ -32,6 +180,113 @@ impl ReserveGasRequest {
         if self.reserve_duration_secs == 0 {
             anyhow::bail!("Reserve duration must be positive");
         }
+        check_min_reserve_duration(self.reserve_duration_secs, limits)?;
+        if let Some(tag) = &self.tag {
+            check_reservation_tag(tag)?;
+        }
+        if self.wait_for_coins {
+            if self.callback_url.is_none() {
+                anyhow::bail!("Waiting for coins requires a callback URL");
+            }
+            if self.budget_lease || self.allow_partial {
+                anyhow::bail!("Budget leases and partial reservations cannot wait for coins");
+            }
+        }
+        if !self.requested_coins.is_empty() {
+            if self.budget_lease || self.allow_partial || self.wait_for_coins {
+                anyhow::bail!(
+                    "Requested coins cannot be combined with budget leases, partial reservations or waiting for coins"
+                );
+            }
+            if self.requested_coins.len() > MAX_GAS_PER_QUERY {
+                anyhow::bail!("At most {} coins can be requested", MAX_GAS_PER_QUERY);
+            }
+            let unique_coins: BTreeSet<_> = self.requested_coins.iter().collect();
+            if unique_coins.len() != self.requested_coins.len() {
+                anyhow::bail!("Requested coins must not contain duplicates");
+            }
+        }
+        if self.candidates
+            && (self.budget_lease
+                || self.allow_partial
+                || self.wait_for_coins
+                || !self.requested_coins.is_empty())
+        {
+            bail!(
+                "Candidate coins cannot be combined with budget leases, partial reservations, waiting for coins or requested coins"
+            );
+        }
+        let max_duration_secs = if self.budget_lease {
+            MAX_LEASE_DURATION_S
+        } else {
+            MAX_DURATION_S
+        };
+        if self.reserve_duration_secs > max_duration_secs {
+            anyhow::bail!(
+                "Reserve duration must be less than {} seconds",
+                max_duration_secs
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Reserves coins summing to at least a target balance. The coins are picked and tracked exactly
+/// like for a `ReserveGasRequest`, but the response carries the balance of each coin.
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
+pub struct ReserveBalanceRequest {
+    pub target_balance: u64,
+    pub reserve_duration_secs: u64,
+    /// When specified, a `ReservationEvent` is POSTed to this URL once the reservation
+    /// expires or is consumed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+    /// Ignored, since the client is only identified by its auth token. Kept so that requests
+    /// of older clients are still accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Higher priority reservations are served first while the pool is close to running out
+    /// of coins. Capped by the limit configured for the client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+}
+
+impl ReserveBalanceRequest {
+    pub fn check_validity(&self, limits: &ReservationLimitsConfig) -> anyhow::Result<()> {
+        if self.target_balance == 0 {
+            anyhow::bail!("Target balance must be positive");
+        }
+        if self.target_balance > MAX_TARGET_BALANCE {
+            anyhow::bail!("Target balance must be less than {}", MAX_TARGET_BALANCE);
+        }
+        if self.reserve_duration_secs == 0 {
+            anyhow::bail!("Reserve duration must be positive");
+        }
+        check_min_reserve_duration(self.reserve_duration_secs, limits)?;
+        if self.reserve_duration_secs > MAX_DURATION_S {
+            anyhow::bail!(
+                "Reserve duration must be less than {} seconds",
+                MAX_DURATION_S
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
+pub struct ClaimLeaseRequest {
+    /// The reservation ID returned when reserving the budget lease.
+    pub reservation_id: ReservationID,
+    /// The claimed gas coins will be released back to the pool after this duration expires.
+    pub reserve_duration_secs: u64,
+}
+
+impl ClaimLeaseRequest {
+    pub fn check_validity(&self, limits: &ReservationLimitsConfig) -> anyhow::Result<()> {
+        if self.reserve_duration_secs == 0 {
+            anyhow::bail!("Reserve duration must be positive");
+        }
+        check_min_reserve_duration(self.reserve_duration_secs, limits)?;
         if self.reserve_duration_secs > MAX_DURATION_S {
             anyhow::bail!(
                 "Reserve duration must be less than {} seconds",
@@ -42,10 +297,78 @@ impl ReserveGasRequest {
     }
 }
 
+fn check_reservation_tag(tag: &str) -> anyhow::Result<()> {
+    if tag.is_empty() || tag.len() > MAX_RESERVATION_TAG_LEN {
+        bail!(
+            "Reservation tag must be between 1 and {} characters",
+            MAX_RESERVATION_TAG_LEN
+        );
+    }
+    if !tag
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ':'))
+    {
+        bail!("Reservation tag may only contain ASCII letters, digits, '_', '-', '.' and ':'");
+    }
+    Ok(())
+}
+
+fn check_min_reserve_duration(
+    reserve_duration_secs: u64,
+    limits: &ReservationLimitsConfig,
+) -> anyhow::Result<()> {
+    if reserve_duration_secs < limits.min_reserve_duration_secs {
+        return Err(GasStationError::InvalidRequest(format!(
+            "Reserve duration must be at least {} seconds",
+            limits.min_reserve_duration_secs
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
+pub struct CancelReservationRequest {
+    /// The reservation or budget lease to cancel.
+    pub reservation_id: ReservationID,
+}
+
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct CancelReservationResponse {
+    pub result: Option<CancelReservationResult>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct CancelReservationResult {
+    /// Number of coins released back to the pool. 0 if the reservation was already cancelled,
+    /// or if it was an unclaimed budget lease.
+    pub freed_coin_count: usize,
+}
+
+impl CancelReservationResponse {
+    pub fn new_ok(freed_coin_count: usize) -> Self {
+        Self {
+            result: Some(CancelReservationResult { freed_coin_count }),
+            error: None,
+        }
+    }
+
+    pub fn new_err(error: anyhow::Error) -> Self {
+        Self {
+            result: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
 #[derive(Debug, JsonSchema, Serialize, Deserialize)]
 pub struct ReserveGasResponse {
     pub result: Option<ReserveGasResult>,
     pub error: Option<String>,
+    /// Only set when the reservation waits for coins, in which case there is no result yet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wait_ticket: Option<WaitTicket>,
 }
 
 #[derive(Debug, JsonSchema, Serialize, Deserialize)]
@@ -53,6 +376,24 @@ pub struct ReserveGasResult {
     pub sponsor_address: MysAddress,
     pub reservation_id: ReservationID,
     pub gas_coins: Vec<MysObjectRef>,
+    /// The gas budget of the request. Only set when a partial reservation was allowed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_budget: Option<u64>,
+    /// Total balance of the reserved gas coins, which is less than requested_budget when the
+    /// pool could only partially cover it. Only set when a partial reservation was allowed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub granted_budget: Option<u64>,
+    /// A receipt of the reservation signed by the sponsor. Only set when reservation receipts are
+    /// enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<SignedReservationReceipt>,
+    /// The tag of the request, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// All reserved coins with their balances. Only set when candidates were requested, in which
+    /// case gas_coins holds the same coins.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub candidate_coins: Vec<ReservedCoin>,
 }
 
 impl ReserveGasResponse {
@@ -66,6 +407,142 @@ impl ReserveGasResponse {
                 sponsor_address,
                 reservation_id,
                 gas_coins: gas_coins.into_iter().map(|c| c.into()).collect(),
+                requested_budget: None,
+                granted_budget: None,
+                receipt: None,
+                tag: None,
+                candidate_coins: vec![],
+            }),
+            error: None,
+            wait_ticket: None,
+        }
+    }
+
+    pub fn new_partial_ok(
+        sponsor_address: MysAddress,
+        reservation_id: ReservationID,
+        gas_coins: Vec<ObjectRef>,
+        requested_budget: u64,
+        granted_budget: u64,
+    ) -> Self {
+        Self {
+            result: Some(ReserveGasResult {
+                sponsor_address,
+                reservation_id,
+                gas_coins: gas_coins.into_iter().map(|c| c.into()).collect(),
+                requested_budget: Some(requested_budget),
+                granted_budget: Some(granted_budget),
+                receipt: None,
+                tag: None,
+                candidate_coins: vec![],
+            }),
+            error: None,
+            wait_ticket: None,
+        }
+    }
+
+    pub fn with_receipt(mut self, receipt: Option<SignedReservationReceipt>) -> Self {
+        if let Some(result) = &mut self.result {
+            result.receipt = receipt;
+        }
+        self
+    }
+
+    pub fn new_candidates_ok(
+        sponsor_address: MysAddress,
+        reservation_id: ReservationID,
+        coins: Vec<GasCoin>,
+    ) -> Self {
+        let gas_coins = coins.iter().map(|coin| coin.object_ref).collect();
+        let mut response = Self::new_ok(sponsor_address, reservation_id, gas_coins);
+        if let Some(result) = &mut response.result {
+            result.candidate_coins = coins.into_iter().map(ReservedCoin::from).collect();
+        }
+        response
+    }
+
+    pub fn with_tag(mut self, tag: Option<String>) -> Self {
+        if let Some(result) = &mut self.result {
+            result.tag = tag;
+        }
+        self
+    }
+
+    pub fn new_waiting(wait_ticket: WaitTicket) -> Self {
+        Self {
+            result: None,
+            error: None,
+            wait_ticket: Some(wait_ticket),
+        }
+    }
+
+    pub fn new_err(error: anyhow::Error) -> Self {
+        Self {
+            result: None,
+            error: Some(error.to_string()),
+            wait_ticket: None,
+        }
+    }
+}
+
+impl VersionedResponse for ReserveGasResponse {
+    fn to_versioned_json(&self, version: ApiVersion) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).expect("ReserveGasResponse is serializable");
+        match version {
+            ApiVersion::V1 | ApiVersion::V2 => {
+                retain_fields(&mut value, RESERVE_GAS_RESPONSE_V1_FIELDS)
+            }
+            ApiVersion::V3 => retain_fields(&mut value, RESERVE_GAS_RESPONSE_V3_FIELDS),
+        }
+        if let Some(result) = value.get_mut("result") {
+            retain_fields(result, RESERVE_GAS_RESULT_V1_FIELDS);
+        }
+        value
+    }
+}
+
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct ReserveBalanceResponse {
+    pub result: Option<ReserveBalanceResult>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct ReserveBalanceResult {
+    pub sponsor_address: MysAddress,
+    pub reservation_id: ReservationID,
+    pub coins: Vec<ReservedCoin>,
+    /// Total balance of the reserved coins, which is at least the target balance.
+    pub total_balance: u64,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
+pub struct ReservedCoin {
+    pub object_ref: MysObjectRef,
+    pub balance: u64,
+}
+
+impl From<GasCoin> for ReservedCoin {
+    fn from(coin: GasCoin) -> Self {
+        Self {
+            object_ref: coin.object_ref.into(),
+            balance: coin.balance,
+        }
+    }
+}
+
+impl ReserveBalanceResponse {
+    pub fn new_ok(
+        sponsor_address: MysAddress,
+        reservation_id: ReservationID,
+        coins: Vec<GasCoin>,
+    ) -> Self {
+        Self {
+            result: Some(ReserveBalanceResult {
+                sponsor_address,
+                reservation_id,
+                total_balance: coins.iter().map(|c| c.balance).sum(),
+                coins: coins.into_iter().map(ReservedCoin::from).collect(),
             }),
             error: None,
         }
@@ -84,19 +561,63 @@ pub struct ExecuteTxRequest {
     pub reservation_id: ReservationID,
     pub tx_bytes: Base64,
     pub user_sig: Base64,
+    /// When true, the full effects are returned even if they are larger than the configured
+    /// max-effects-size-bytes.
+    #[serde(default)]
+    pub full_effects: bool,
+    /// When true, the transaction is first run with dev_inspect, and rejected without paying
+    /// for gas if it would fail. This adds a fullnode round-trip. It is always done when the gas
+    /// pool is configured with preflight-transactions.
+    #[serde(default)]
+    pub preflight: bool,
 }
 
 #[derive(Debug, JsonSchema, Serialize, Deserialize)]
 pub struct ExecuteTxResponse {
     pub effects: Option<MysTransactionBlockEffects>,
     pub error: Option<String>,
+    /// Only set when the gas pool is configured to sign execution effects.
+    #[serde(default)]
+    pub signed_effects: Option<SignedEffects>,
+    /// Set in place of the effects when they were larger than the configured limit. The client
+    /// can fetch the full effects from a fullnode using the transaction digest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effects_summary: Option<EffectsSummary>,
+    /// The gas price the sponsor signed the transaction with. Only set by execute_tx once the
+    /// transaction was executed, from API version 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas_price: Option<u64>,
+    /// The gas spent by the transaction, as reported in its effects. Set along with gas_price.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas_used: Option<GasCostSummary>,
 }
 
 impl ExecuteTxResponse {
-    pub fn new_ok(effects: MysTransactionBlockEffects) -> Self {
+    pub fn new_ok(
+        effects: MysTransactionBlockEffects,
+        signed_effects: Option<SignedEffects>,
+    ) -> Self {
         Self {
             effects: Some(effects),
             error: None,
+            signed_effects,
+            effects_summary: None,
+            gas_price: None,
+            gas_used: None,
+        }
+    }
+
+    pub fn new_truncated(
+        effects_summary: EffectsSummary,
+        signed_effects: Option<SignedEffects>,
+    ) -> Self {
+        Self {
+            effects: None,
+            error: None,
+            signed_effects,
+            effects_summary: Some(effects_summary),
+            gas_price: None,
+            gas_used: None,
         }
     }
 
@@ -104,6 +625,302 @@ impl ExecuteTxResponse {
         Self {
             effects: None,
             error: Some(error.to_string()),
+            signed_effects: None,
+            effects_summary: None,
+            gas_price: None,
+            gas_used: None,
+        }
+    }
+
+    /// Sets the gas price the transaction was signed with, along with the gas it spent. Does
+    /// nothing if the transaction was not executed.
+    pub fn with_gas_cost(mut self, gas_price: u64) -> Self {
+        let gas_used = match (&self.effects, &self.effects_summary) {
+            (Some(effects), _) => Some(effects.gas_cost_summary().clone()),
+            (None, Some(effects_summary)) => Some(effects_summary.gas_used.clone()),
+            (None, None) => None,
+        };
+        if gas_used.is_some() {
+            self.gas_price = Some(gas_price);
+            self.gas_used = gas_used;
+        }
+        self
+    }
+}
+
+impl VersionedResponse for ExecuteTxResponse {
+    fn to_versioned_json(&self, version: ApiVersion) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).expect("ExecuteTxResponse is serializable");
+        match version {
+            ApiVersion::V1 => retain_fields(&mut value, EXECUTE_TX_RESPONSE_V1_FIELDS),
+            ApiVersion::V2 | ApiVersion::V3 => {
+                retain_fields(&mut value, EXECUTE_TX_RESPONSE_V2_FIELDS)
+            }
+        }
+        value
+    }
+}
+
+/// The parts of the effects that most clients need, returned when the full effects are too large.
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
+pub struct EffectsSummary {
+    pub transaction_digest: TransactionDigest,
+    pub status: MysExecutionStatus,
+    pub gas_used: GasCostSummary,
+}
+
+impl EffectsSummary {
+    pub fn new(effects: &MysTransactionBlockEffects) -> Self {
+        Self {
+            transaction_digest: *effects.transaction_digest(),
+            status: effects.status().clone(),
+            gas_used: effects.gas_cost_summary().clone(),
+        }
+    }
+}
+
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct BatchExecuteTxRequest {
+    pub requests: Vec<ExecuteTxRequest>,
+}
+
+impl BatchExecuteTxRequest {
+    pub fn check_validity(&self, max_batch_size: usize) -> anyhow::Result<()> {
+        if self.requests.is_empty() {
+            return Err(anyhow::anyhow!("Batch must not be empty"));
+        }
+        if self.requests.len() > max_batch_size {
+            return Err(anyhow::anyhow!(
+                "Batch size {} is greater than the maximum of {}",
+                self.requests.len(),
+                max_batch_size
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct BatchExecuteTxResponse {
+    /// One response for each request in the batch, in the same order.
+    /// Empty if the batch as a whole was rejected.
+    pub results: Vec<ExecuteTxResponse>,
+    pub error: Option<String>,
+}
+
+impl BatchExecuteTxResponse {
+    pub fn new_ok(results: Vec<ExecuteTxResponse>) -> Self {
+        Self {
+            results,
+            error: None,
+        }
+    }
+
+    pub fn new_err(error: anyhow::Error) -> Self {
+        Self {
+            results: vec![],
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// One line of the newline-delimited JSON stream returned by batch_execute_tx_stream.
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BatchExecuteTxStreamEvent {
+    /// Sent as soon as the transaction at `index` in the request completes, so results arrive in
+    /// completion order rather than request order.
+    Result {
+        index: usize,
+        response: ExecuteTxResponse,
+    },
+    /// Always the last event of the stream, listing the indexes of the transactions that failed.
+    /// A stream that ends without it was interrupted.
+    Done { failed_indexes: Vec<usize> },
+}
+
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct GasPoolStatsResponse {
+    pub stats: Option<GasPoolStats>,
+    pub error: Option<String>,
+}
+
+/// Describes the running build. This is served without authentication, so it must never
+/// contain secrets such as the sidecar URL.
+#[derive(Clone, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub version: String,
+    pub git_revision: String,
+    /// Set from the BUILD_DATE environment variable at compile time, "unknown" if it was not set.
+    pub build_date: String,
+    /// Either "local" or "sidecar".
+    pub signer_type: String,
+    pub storage_backend: String,
+}
+
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct ReloadCoinsResponse {
+    pub result: Option<CoinReloadResult>,
+    pub error: Option<String>,
+}
+
+impl ReloadCoinsResponse {
+    pub fn new_ok(result: CoinReloadResult) -> Self {
+        Self {
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn new_err(error: anyhow::Error) -> Self {
+        Self {
+            result: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Turns safe mode on or off for every gas pool instance of the sponsor.
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
+pub struct SetSafeModeRequest {
+    pub enabled: bool,
+}
+
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct SetSafeModeResponse {
+    pub safe_mode: Option<bool>,
+    pub error: Option<String>,
+}
+
+impl SetSafeModeResponse {
+    pub fn new_ok(safe_mode: bool) -> Self {
+        Self {
+            safe_mode: Some(safe_mode),
+            error: None,
+        }
+    }
+
+    pub fn new_err(error: anyhow::Error) -> Self {
+        Self {
+            safe_mode: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Reads the audit records with from_ms <= timestamp_ms < to_ms, oldest first.
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
+pub struct QueryAuditLogRequest {
+    pub from_ms: u64,
+    pub to_ms: u64,
+    /// Only return the records of this client.
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// The maximum number of records to return. Defaults to MAX_AUDIT_LOG_QUERY_LIMIT.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl QueryAuditLogRequest {
+    pub fn check_validity(&self) -> anyhow::Result<()> {
+        if self.from_ms >= self.to_ms {
+            anyhow::bail!("from_ms must be less than to_ms");
+        }
+        if let Some(limit) = self.limit {
+            if limit == 0 || limit > MAX_AUDIT_LOG_QUERY_LIMIT {
+                anyhow::bail!("Limit must be between 1 and {}", MAX_AUDIT_LOG_QUERY_LIMIT);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct QueryAuditLogResponse {
+    pub records: Option<Vec<AuditRecord>>,
+    pub error: Option<String>,
+}
+
+impl QueryAuditLogResponse {
+    pub fn new_ok(records: Vec<AuditRecord>) -> Self {
+        Self {
+            records: Some(records),
+            error: None,
+        }
+    }
+
+    pub fn new_err(error: anyhow::Error) -> Self {
+        Self {
+            records: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Reads the active reservations and reserved budget of each client that holds a reservation.
+#[derive(Clone, Debug, Default, JsonSchema, Serialize, Deserialize)]
+pub struct ClientUsageRequest {
+    /// Only return the usage of this client, even if it holds no reservation.
+    #[serde(default)]
+    pub client_id: Option<String>,
+}
+
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct ClientUsageResponse {
+    pub usage: Option<BTreeMap<String, ClientUsage>>,
+    pub error: Option<String>,
+}
+
+impl ClientUsageResponse {
+    pub fn new_ok(usage: BTreeMap<String, ClientUsage>) -> Self {
+        Self {
+            usage: Some(usage),
+            error: None,
+        }
+    }
+
+    pub fn new_err(error: anyhow::Error) -> Self {
+        Self {
+            usage: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, JsonSchema, Serialize, Deserialize)]
+pub struct PoolSnapshotResponse {
+    pub snapshot: Option<PoolDebugSnapshot>,
+    pub error: Option<String>,
+}
+
+impl PoolSnapshotResponse {
+    pub fn new_ok(snapshot: PoolDebugSnapshot) -> Self {
+        Self {
+            snapshot: Some(snapshot),
+            error: None,
+        }
+    }
+
+    pub fn new_err(error: anyhow::Error) -> Self {
+        Self {
+            snapshot: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+impl GasPoolStatsResponse {
+    pub fn new_ok(stats: GasPoolStats) -> Self {
+        Self {
+            stats: Some(stats),
+            error: None,
+        }
+    }
+
+    pub fn new_err(error: anyhow::Error) -> Self {
+        Self {
+            stats: None,
+            error: Some(error.to_string()),
         }
     }
 }
@@ -2,28 +2,51 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::gas_pool::gas_pool_core::GasPool;
+use crate::auth::{Authenticator, SHARED_CLIENT_ID};
+use crate::circuit_breaker::CircuitState;
+use crate::config::{
+    AuthConfig, BatchExecuteConfig, RequestDeadlineConfig, ReservationLimitsConfig, TlsConfig,
+};
+use crate::errors::GasStationError;
+use crate::gas_pool::gas_pool_core::{is_request_timeout, GasPool};
+use crate::log_redaction::redacted;
 use crate::metrics::GasPoolRpcMetrics;
-use crate::read_auth_env;
+use crate::read_admin_auth_env;
 use crate::rpc::client::GasPoolRpcClient;
 use crate::rpc::rpc_types::{
-    ExecuteTxRequest, ExecuteTxResponse, ReserveGasRequest, ReserveGasResponse,
+    ApiVersion, BatchExecuteTxRequest, BatchExecuteTxResponse, BatchExecuteTxStreamEvent, BuildInfo,
+    CancelReservationRequest, CancelReservationResponse, ClaimLeaseRequest, ClientUsageRequest,
+    ClientUsageResponse, EffectsSummary, ExecuteTxRequest, ExecuteTxResponse, GasPoolStatsResponse,
+    PoolSnapshotResponse, QueryAuditLogRequest, QueryAuditLogResponse, ReloadCoinsResponse, ReserveBalanceRequest,
+    ReserveBalanceResponse, ReserveGasRequest, ReserveGasResponse, SetSafeModeRequest,
+    SetSafeModeResponse, VersionedResponse, API_VERSION_HEADER, MAX_AUDIT_LOG_QUERY_LIMIT,
 };
+use crate::rpc::tls::{load_tls_config, start_tls_reload_task};
+use crate::types::{AuditEvent, GasCoin, ReservationID};
 use axum_extra::typed_header::TypedHeader;
 use axum_extra::headers::authorization::Bearer;
 use axum_extra::headers::Authorization;
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use axum::body::Body;
+use axum::extract::rejection::JsonRejection;
+use axum::http::header::{CONTENT_TYPE, RETRY_AFTER};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::middleware::map_response;
 use axum::routing::{get, post};
 use axum::{Extension, Json, Router};
 use fastcrypto::encoding::Base64;
+use futures_util::StreamExt;
+use std::convert::Infallible;
+use std::future::Future;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use mys_json_rpc_types::MysTransactionBlockEffectsAPI;
+use mys_types::base_types::{MysAddress, ObjectID, ObjectRef};
 use mys_types::crypto::ToFromBytes;
 use mys_types::signature::GenericSignature;
-use mys_types::transaction::TransactionData;
+use mys_types::transaction::{TransactionData, TransactionDataAPI};
+use subtle::ConstantTimeEq;
 use tokio::task::JoinHandle;
 use tracing::{debug, error, info};
 
@@ -43,6 +66,14 @@ const GIT_REVISION: &str = {
     }
 };
 const VERSION: &str = const_str::concat!(env!("CARGO_PKG_VERSION"), "-", GIT_REVISION);
+const BUILD_DATE: &str = match option_env!("BUILD_DATE") {
+    Some(build_date) if !build_date.is_empty() => build_date,
+    _ => "unknown",
+};
+/// How long clients are told to wait before retrying a request that failed with status 503,
+/// e.g. because the pool ran out of coins. Reservations expire and release their coins within
+/// seconds, so retrying soon is reasonable.
+const RETRY_AFTER_SECS: u64 = 1;
 
 pub struct GasPoolServer {
     pub handle: JoinHandle<()>,
@@ -55,24 +86,72 @@ impl GasPoolServer {
         host_ip: Ipv4Addr,
         rpc_port: u16,
         metrics: Arc<GasPoolRpcMetrics>,
+        batch_execute_config: BatchExecuteConfig,
+        reservation_limits_config: ReservationLimitsConfig,
+        request_deadline_config: RequestDeadlineConfig,
+        max_effects_size_bytes: Option<usize>,
+        auth_config: AuthConfig,
+        tls_config: Option<TlsConfig>,
     ) -> Self {
-        let state = ServerState::new(station, metrics);
+        // Bad certificate material should stop the server from starting, rather than fail
+        // every handshake.
+        let tls = match tls_config {
+            Some(tls_config) => {
+                let rustls_config = load_tls_config(&tls_config)
+                    .await
+                    .unwrap_or_else(|err| panic!("Invalid TLS config: {:?}", err));
+                Some((tls_config, rustls_config))
+            }
+            None => None,
+        };
+        let state = ServerState::new(
+            station,
+            metrics,
+            batch_execute_config,
+            reservation_limits_config,
+            request_deadline_config,
+            max_effects_size_bytes,
+            auth_config,
+        );
         let app = Router::new()
             .route("/", get(health))
             .route("/version", get(version))
+            .route("/build_info", get(build_info))
             .route("/debug_health_check", post(debug_health_check))
+            .route("/stats", get(stats))
             .route("/v1/reserve_gas", post(reserve_gas))
+            .route("/v1/reserve_balance", post(reserve_balance))
+            .route("/v1/claim_lease", post(claim_lease))
+            .route("/v1/cancel_reservation", post(cancel_reservation))
             .route("/v1/execute_tx", post(execute_tx))
+            .route("/v1/batch_execute_tx", post(batch_execute_tx))
+            .route("/v1/batch_execute_tx_stream", post(batch_execute_tx_stream))
+            .route("/admin/reload_coins", post(reload_coins))
+            .route("/admin/audit_log", post(query_audit_log))
+            .route("/admin/safe_mode", post(set_safe_mode))
+            .route("/admin/client_usage", post(get_client_usage))
+            .route("/admin/pool_snapshot", post(get_pool_snapshot))
+            .layer(map_response(add_retry_after))
             .layer(Extension(state));
         let address = SocketAddr::new(IpAddr::V4(host_ip), rpc_port);
         let handle = tokio::spawn(async move {
-            info!("listening on {}", address);
-            let listener = tokio::net::TcpListener::bind(&address)
-                .await
-                .unwrap();
-            axum::serve(listener, app.into_make_service())
+            let Some((tls_config, rustls_config)) = tls else {
+                info!("listening on {}", address);
+                let listener = tokio::net::TcpListener::bind(&address)
+                    .await
+                    .unwrap();
+                axum::serve(listener, app.into_make_service())
+                    .await
+                    .unwrap();
+                return;
+            };
+            info!("listening on {} with TLS", address);
+            let reload_task = start_tls_reload_task(tls_config, rustls_config.clone());
+            axum_server::bind_rustls(address, rustls_config)
+                .serve(app.into_make_service())
                 .await
                 .unwrap();
+            reload_task.abort();
         });
         Self { handle, rpc_port }
     }
@@ -85,24 +164,103 @@ impl GasPoolServer {
 #[derive(Clone)]
 struct ServerState {
     gas_station: Arc<GasPool>,
-    secret: Arc<String>,
+    authenticator: Arc<dyn Authenticator>,
+    /// None if admin endpoints are disabled.
+    admin_secret: Arc<Option<String>>,
     metrics: Arc<GasPoolRpcMetrics>,
+    batch_execute_config: Arc<BatchExecuteConfig>,
+    reservation_limits_config: Arc<ReservationLimitsConfig>,
+    /// How long reserve_gas requests may take. None if there is no deadline.
+    reserve_deadline: Option<Duration>,
+    /// How long each transaction of execute_tx requests may take. None if there is no deadline.
+    execute_deadline: Option<Duration>,
+    /// None if effects are never truncated.
+    max_effects_size_bytes: Option<usize>,
 }
 
 impl ServerState {
-    fn new(gas_station: Arc<GasPool>, metrics: Arc<GasPoolRpcMetrics>) -> Self {
-        let secret = Arc::new(read_auth_env());
+    fn new(
+        gas_station: Arc<GasPool>,
+        metrics: Arc<GasPoolRpcMetrics>,
+        batch_execute_config: BatchExecuteConfig,
+        reservation_limits_config: ReservationLimitsConfig,
+        request_deadline_config: RequestDeadlineConfig,
+        max_effects_size_bytes: Option<usize>,
+        auth_config: AuthConfig,
+    ) -> Self {
+        let authenticator = auth_config.new_authenticator();
+        let admin_secret = Arc::new(read_admin_auth_env());
         Self {
             gas_station,
-            secret,
+            authenticator,
+            admin_secret,
             metrics,
+            batch_execute_config: Arc::new(batch_execute_config),
+            reservation_limits_config: Arc::new(reservation_limits_config),
+            reserve_deadline: request_deadline_config
+                .reserve_deadline_ms
+                .map(Duration::from_millis),
+            execute_deadline: request_deadline_config
+                .execute_deadline_ms
+                .map(Duration::from_millis),
+            max_effects_size_bytes,
         }
     }
+
+    /// Returns the id of the client the bearer token belongs to, or None if the authentication
+    /// backend cannot tell clients apart.
+    async fn authenticate(
+        &self,
+        authorization: &Authorization<Bearer>,
+    ) -> anyhow::Result<Option<String>> {
+        let client_id = self.authenticator.authenticate(authorization.token()).await?;
+        self.metrics
+            .num_authorized_requests_per_client
+            .with_label_values(&[client_id.as_deref().unwrap_or(SHARED_CLIENT_ID)])
+            .inc();
+        Ok(client_id)
+    }
+
+    /// Checks that the bearer token is the admin token.
+    fn authorize_admin(
+        &self,
+        authorization: &Authorization<Bearer>,
+    ) -> Result<(), (StatusCode, anyhow::Error)> {
+        let Some(admin_secret) = self.admin_secret.as_ref() else {
+            return Err((
+                StatusCode::FORBIDDEN,
+                anyhow::anyhow!("Admin endpoints are disabled"),
+            ));
+        };
+        if !bool::from(authorization.token().as_bytes().ct_eq(admin_secret.as_bytes())) {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                anyhow::anyhow!("Invalid admin authorization token"),
+            ));
+        }
+        Ok(())
+    }
 }
 
-async fn health() -> &'static str {
+async fn health(Extension(server): Extension<ServerState>) -> impl IntoResponse {
     info!("Received health request");
-    "OK"
+    if !server.gas_station.sponsor_address_matches() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Signer address does not match the sponsor address".to_string(),
+        );
+    }
+    let safe_mode = server.gas_station.is_safe_mode().await;
+    match server.gas_station.fullnode_circuit_state() {
+        None | Some(CircuitState::Closed) if safe_mode => {
+            (StatusCode::OK, "OK, safe mode is on".to_string())
+        }
+        None | Some(CircuitState::Closed) => (StatusCode::OK, "OK".to_string()),
+        Some(state) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("Fullnode circuit breaker is {}", state),
+        ),
+    }
 }
 
 async fn version() -> &'static str {
@@ -110,12 +268,23 @@ async fn version() -> &'static str {
     VERSION
 }
 
+async fn build_info(Extension(server): Extension<ServerState>) -> Json<BuildInfo> {
+    info!("Received build_info request");
+    Json(BuildInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_revision: GIT_REVISION.to_string(),
+        build_date: BUILD_DATE.to_string(),
+        signer_type: server.gas_station.signer_type().to_string(),
+        storage_backend: server.gas_station.storage_backend().to_string(),
+    })
+}
+
 async fn debug_health_check(
     TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
     Extension(server): Extension<ServerState>,
 ) -> String {
     info!("Received debug_health_check request");
-    if authorization.token() != server.secret.as_str() {
+    if server.authenticate(&authorization).await.is_err() {
         return "Unauthorized".to_string();
     }
     if let Err(err) = server.gas_station.debug_check_health().await {
@@ -124,23 +293,236 @@ async fn debug_health_check(
     "OK".to_string()
 }
 
+async fn stats(
+    TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
+    Extension(server): Extension<ServerState>,
+) -> impl IntoResponse {
+    info!("Received stats request");
+    if server.authenticate(&authorization).await.is_err() {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(GasPoolStatsResponse::new_err(anyhow::anyhow!(
+                "Invalid authorization token"
+            ))),
+        );
+    }
+    let stats = server.gas_station.get_stats().await;
+    (StatusCode::OK, Json(GasPoolStatsResponse::new_ok(stats)))
+}
+
+async fn reload_coins(
+    TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
+    Extension(server): Extension<ServerState>,
+) -> impl IntoResponse {
+    info!("Received reload_coins request");
+    server.metrics.num_reload_coins_requests.inc();
+    if let Err((status, err)) = server.authorize_admin(&authorization) {
+        return (status, Json(ReloadCoinsResponse::new_err(err)));
+    }
+    server.metrics.num_authorized_reload_coins_requests.inc();
+    let gas_station = server.gas_station.clone();
+    // Spawn a thread to process the request so that it will finish even when client drops the connection.
+    tokio::task::spawn(async move { gas_station.reload_coins().await })
+        .await
+        .map(|result| match result {
+            Ok(result) => (StatusCode::OK, Json(ReloadCoinsResponse::new_ok(result))),
+            Err(err) => {
+                error!("Failed to reload coins: {:?}", err);
+                (
+                    error_status_code(&err),
+                    Json(ReloadCoinsResponse::new_err(err)),
+                )
+            }
+        })
+        .unwrap_or_else(|err| {
+            error!("Failed to spawn reload_coins task: {:?}", err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ReloadCoinsResponse::new_err(anyhow::anyhow!(
+                    "Failed to spawn reload_coins task"
+                ))),
+            )
+        })
+}
+
+async fn set_safe_mode(
+    TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
+    Extension(server): Extension<ServerState>,
+    payload: Result<Json<SetSafeModeRequest>, JsonRejection>,
+) -> impl IntoResponse {
+    info!("Received safe_mode request");
+    if let Err((status, err)) = server.authorize_admin(&authorization) {
+        return (status, Json(SetSafeModeResponse::new_err(err)));
+    }
+    let SetSafeModeRequest { enabled } = match parse_payload(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            debug!("Malformed request: {:?}", err);
+            return (StatusCode::BAD_REQUEST, Json(SetSafeModeResponse::new_err(err)));
+        }
+    };
+    match server.gas_station.set_safe_mode(enabled).await {
+        Ok(()) => (StatusCode::OK, Json(SetSafeModeResponse::new_ok(enabled))),
+        Err(err) => {
+            error!("Failed to set safe mode: {:?}", err);
+            (
+                error_status_code(&err),
+                Json(SetSafeModeResponse::new_err(err)),
+            )
+        }
+    }
+}
+
+async fn query_audit_log(
+    TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
+    Extension(server): Extension<ServerState>,
+    payload: Result<Json<QueryAuditLogRequest>, JsonRejection>,
+) -> impl IntoResponse {
+    info!("Received audit_log request");
+    if let Err((status, err)) = server.authorize_admin(&authorization) {
+        return (status, Json(QueryAuditLogResponse::new_err(err)));
+    }
+    let payload = match parse_payload(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            debug!("Malformed request: {:?}", err);
+            return (StatusCode::BAD_REQUEST, Json(QueryAuditLogResponse::new_err(err)));
+        }
+    };
+    if let Err(err) = payload.check_validity() {
+        debug!("Invalid audit_log request: {:?}", err);
+        return (StatusCode::BAD_REQUEST, Json(QueryAuditLogResponse::new_err(err)));
+    }
+    let QueryAuditLogRequest {
+        from_ms,
+        to_ms,
+        client_id,
+        limit,
+    } = payload;
+    match server
+        .gas_station
+        .query_audit_log(
+            from_ms,
+            to_ms,
+            client_id,
+            limit.unwrap_or(MAX_AUDIT_LOG_QUERY_LIMIT),
+        )
+        .await
+    {
+        Ok(records) => (StatusCode::OK, Json(QueryAuditLogResponse::new_ok(records))),
+        Err(err) => {
+            error!("Failed to query the audit log: {:?}", err);
+            (
+                error_status_code(&err),
+                Json(QueryAuditLogResponse::new_err(err)),
+            )
+        }
+    }
+}
+
+async fn get_client_usage(
+    TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
+    Extension(server): Extension<ServerState>,
+    payload: Result<Json<ClientUsageRequest>, JsonRejection>,
+) -> impl IntoResponse {
+    info!("Received client_usage request");
+    if let Err((status, err)) = server.authorize_admin(&authorization) {
+        return (status, Json(ClientUsageResponse::new_err(err)));
+    }
+    let ClientUsageRequest { client_id } = match parse_payload(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            debug!("Malformed request: {:?}", err);
+            return (StatusCode::BAD_REQUEST, Json(ClientUsageResponse::new_err(err)));
+        }
+    };
+    match server.gas_station.get_client_usage(client_id).await {
+        Ok(usage) => (StatusCode::OK, Json(ClientUsageResponse::new_ok(usage))),
+        Err(err) => {
+            error!("Failed to get the client usage: {:?}", err);
+            (
+                error_status_code(&err),
+                Json(ClientUsageResponse::new_err(err)),
+            )
+        }
+    }
+}
+
+async fn get_pool_snapshot(
+    TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
+    Extension(server): Extension<ServerState>,
+) -> impl IntoResponse {
+    info!("Received pool_snapshot request");
+    if let Err((status, err)) = server.authorize_admin(&authorization) {
+        return (status, Json(PoolSnapshotResponse::new_err(err)));
+    }
+    match server.gas_station.get_debug_snapshot().await {
+        Ok(snapshot) => (StatusCode::OK, Json(PoolSnapshotResponse::new_ok(snapshot))),
+        Err(err) => {
+            error!("Failed to take a pool snapshot: {:?}", err);
+            (
+                error_status_code(&err),
+                Json(PoolSnapshotResponse::new_err(err)),
+            )
+        }
+    }
+}
+
 async fn reserve_gas(
+    headers: HeaderMap,
     TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
     Extension(server): Extension<ServerState>,
-    Json(payload): Json<ReserveGasRequest>,
+    payload: Result<Json<ReserveGasRequest>, JsonRejection>,
 ) -> impl IntoResponse {
+    let version = match api_version(&headers) {
+        Ok(version) => version,
+        Err(err) => {
+            let response = ReserveGasResponse::new_err(err);
+            return (StatusCode::BAD_REQUEST, Versioned(ApiVersion::V1, response));
+        }
+    };
+    let (status, Json(response)) =
+        handle_reserve_gas(authorization, server, payload, version).await;
+    (status, Versioned(version, response))
+}
+
+async fn handle_reserve_gas(
+    authorization: Authorization<Bearer>,
+    server: ServerState,
+    payload: Result<Json<ReserveGasRequest>, JsonRejection>,
+    version: ApiVersion,
+) -> (StatusCode, Json<ReserveGasResponse>) {
     server.metrics.num_reserve_gas_requests.inc();
-    if authorization.token() != server.secret.as_str() {
+    let Ok(authenticated_client_id) = server.authenticate(&authorization).await else {
         return (
             StatusCode::UNAUTHORIZED,
             Json(ReserveGasResponse::new_err(anyhow::anyhow!(
                 "Invalid authorization token"
             ))),
         );
+    };
+    let payload = match parse_payload(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            debug!("Malformed request: {:?}", err);
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ReserveGasResponse::new_err(err)),
+            );
+        }
+    };
+    // Older versions don't have the wait ticket, so the response would carry nothing at all.
+    if payload.wait_for_coins && version < ApiVersion::V3 {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ReserveGasResponse::new_err(anyhow::anyhow!(
+                "Waiting for coins requires API version 3 or later"
+            ))),
+        );
     }
     server.metrics.num_authorized_reserve_gas_requests.inc();
     debug!("Received v1 reserve_gas request: {:?}", payload);
-    if let Err(err) = payload.check_validity() {
+    if let Err(err) = payload.check_validity(&server.reservation_limits_config) {
         debug!("Invalid reserve_gas request: {:?}", err);
         return (
             StatusCode::BAD_REQUEST,
@@ -150,7 +532,27 @@ async fn reserve_gas(
     let ReserveGasRequest {
         gas_budget,
         reserve_duration_secs,
+        callback_url,
+        budget_lease,
+        client_id: _,
+        priority,
+        allow_partial,
+        wait_for_coins,
+        requested_coins,
+        tag,
+        candidates,
     } = payload;
+    if wait_for_coins {
+        if let Err(err) = server.gas_station.check_reservation_waitlist_enabled() {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ReserveGasResponse::new_err(err)),
+            );
+        }
+    }
+    // Only the token identifies the client, so that clients can't claim the priority of another
+    // client. Clients that the authenticator cannot tell apart get the default priority limit.
+    let client_id = authenticated_client_id;
     server
         .metrics
         .target_gas_budget_per_request
@@ -165,6 +567,16 @@ async fn reserve_gas(
         server.metrics.clone(),
         gas_budget,
         reserve_duration_secs,
+        callback_url,
+        budget_lease,
+        client_id,
+        priority,
+        allow_partial,
+        wait_for_coins,
+        requested_coins,
+        tag,
+        candidates,
+        server.reserve_deadline,
     ))
     .await
     .unwrap_or_else(|err| {
@@ -183,22 +595,187 @@ async fn reserve_gas_impl(
     metrics: Arc<GasPoolRpcMetrics>,
     gas_budget: u64,
     reserve_duration_secs: u64,
+    callback_url: Option<String>,
+    budget_lease: bool,
+    client_id: Option<String>,
+    priority: Option<u8>,
+    allow_partial: bool,
+    wait_for_coins: bool,
+    requested_coins: Vec<ObjectID>,
+    tag: Option<String>,
+    candidates: bool,
+    reserve_deadline: Option<Duration>,
 ) -> (StatusCode, Json<ReserveGasResponse>) {
-    match gas_station
-        .reserve_gas(gas_budget, Duration::from_secs(reserve_duration_secs))
-        .await
-    {
-        Ok((sponsor, reservation_id, gas_coins)) => {
+    let duration = Duration::from_secs(reserve_duration_secs);
+    // Kept to put the reservation on the waitlist if the pool cannot cover it right now.
+    let wait_request =
+        wait_for_coins.then(|| (callback_url.clone(), client_id.clone(), tag.clone()));
+    let reservation = {
+        let gas_station = gas_station.clone();
+        let client_id = client_id.clone();
+        async move {
+            if budget_lease {
+                gas_station
+                    .reserve_budget_lease(
+                        gas_budget,
+                        duration,
+                        callback_url,
+                        client_id.as_deref(),
+                    )
+                    .await
+                    .map(|(sponsor, lease_id)| (sponsor, lease_id, vec![], None, vec![]))
+            } else if !requested_coins.is_empty() {
+                gas_station
+                    .reserve_requested_gas(
+                        requested_coins,
+                        gas_budget,
+                        duration,
+                        callback_url,
+                        client_id.as_deref(),
+                    )
+                    .await
+                    .map(|(sponsor, reservation_id, gas_coins)| {
+                        (sponsor, reservation_id, gas_coins, None, vec![])
+                    })
+            } else if candidates {
+                gas_station
+                    .reserve_candidate_gas(
+                        gas_budget,
+                        duration,
+                        callback_url,
+                        client_id.as_deref(),
+                        priority,
+                    )
+                    .await
+                    .map(|(sponsor, reservation_id, coins)| {
+                        let gas_coins = coins.iter().map(|coin| coin.object_ref).collect();
+                        (sponsor, reservation_id, gas_coins, None, coins)
+                    })
+            } else if allow_partial {
+                gas_station
+                    .reserve_partial_gas_with_priority(
+                        gas_budget,
+                        duration,
+                        callback_url,
+                        client_id.as_deref(),
+                        priority,
+                    )
+                    .await
+                    .map(|(sponsor, reservation_id, gas_coins, granted_budget)| {
+                        (sponsor, reservation_id, gas_coins, Some(granted_budget), vec![])
+                    })
+            } else {
+                gas_station
+                    .reserve_gas_with_priority(
+                        gas_budget,
+                        duration,
+                        callback_url,
+                        client_id.as_deref(),
+                        priority,
+                    )
+                    .await
+                    .map(|(sponsor, reservation_id, gas_coins)| {
+                        (sponsor, reservation_id, gas_coins, None, vec![])
+                    })
+            }
+        }
+    };
+    let result = match reserve_deadline {
+        Some(reserve_deadline) => {
+            reserve_within_deadline(gas_station.clone(), reserve_deadline, reservation).await
+        }
+        None => reservation.await,
+    };
+    if result.as_ref().is_err_and(is_request_timeout) {
+        metrics.num_reserve_gas_timeouts.inc();
+    }
+    if let (Err(err), Some((Some(callback_url), client_id, tag))) = (&result, wait_request) {
+        if matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::InsufficientGasCoins)
+        ) {
+            return match gas_station.add_waiting_reservation(
+                gas_budget,
+                duration,
+                callback_url,
+                client_id,
+                priority,
+                tag,
+            ) {
+                Ok(wait_ticket) => {
+                    info!(
+                        wait_id = wait_ticket.wait_id,
+                        "Reservation with budget {} is waiting for coins", gas_budget
+                    );
+                    (
+                        StatusCode::ACCEPTED,
+                        Json(ReserveGasResponse::new_waiting(wait_ticket)),
+                    )
+                }
+                Err(err) => {
+                    error!("Failed to reserve gas: {:?}", err);
+                    (
+                        error_status_code(&err),
+                        Json(ReserveGasResponse::new_err(err)),
+                    )
+                }
+            };
+        }
+    }
+    match result {
+        Ok((sponsor, reservation_id, gas_coins, granted_budget, candidate_coins)) => {
             info!(
                 ?reservation_id,
-                "Reserved gas coins with sponsor={:?}, budget={:?} and duration={:?}: {:?}",
+                ?tag,
+                "Reserved gas coins with sponsor={:?}, budget={:?}, granted budget={:?} and duration={:?}: {:?}",
                 sponsor,
                 gas_budget,
+                granted_budget,
                 reserve_duration_secs,
                 gas_coins
             );
             metrics.num_successful_reserve_gas_requests.inc();
-            let response = ReserveGasResponse::new_ok(sponsor, reservation_id, gas_coins);
+            if let Some(tag) = &tag {
+                gas_station.set_reservation_tag(reservation_id, tag).await;
+                metrics
+                    .num_reservations_per_tag
+                    .with_label_values(&[gas_station.metric_tag(tag)])
+                    .inc();
+            }
+            gas_station.record_audit_event(
+                client_id,
+                reservation_id,
+                tag.clone(),
+                AuditEvent::Reserve {
+                    gas_budget,
+                    gas_coins: gas_coins.iter().map(|coin| coin.0).collect(),
+                },
+            );
+            let receipt = gas_station
+                .sign_reservation_receipt(
+                    reservation_id,
+                    &gas_coins,
+                    granted_budget.map_or(gas_budget, |granted| granted.min(gas_budget)),
+                    duration,
+                )
+                .await;
+            let response = match granted_budget {
+                Some(granted_budget) => ReserveGasResponse::new_partial_ok(
+                    sponsor,
+                    reservation_id,
+                    gas_coins,
+                    gas_budget,
+                    granted_budget,
+                ),
+                None if candidates => ReserveGasResponse::new_candidates_ok(
+                    sponsor,
+                    reservation_id,
+                    candidate_coins,
+                ),
+                None => ReserveGasResponse::new_ok(sponsor, reservation_id, gas_coins),
+            }
+            .with_receipt(receipt)
+            .with_tag(tag);
             
             // Debug: Log the serialized JSON response to verify gas_coins are included
             if let Ok(json_str) = serde_json::to_string_pretty(&response) {
@@ -211,34 +788,412 @@ async fn reserve_gas_impl(
             error!("Failed to reserve gas: {:?}", err);
             metrics.num_failed_reserve_gas_requests.inc();
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                error_status_code(&err),
                 Json(ReserveGasResponse::new_err(err)),
             )
         }
     }
 }
 
+/// A reservation as returned by reserve_gas_impl: the sponsor, the reservation id, the gas coins,
+/// the granted budget of partial reservations and the coins with their balances of candidate
+/// reservations.
+type Reservation = (
+    MysAddress,
+    ReservationID,
+    Vec<ObjectRef>,
+    Option<u64>,
+    Vec<GasCoin>,
+);
+
+/// Waits for the reservation until the deadline passes. The reservation is not interrupted then,
+/// since it may already have taken coins out of the pool. Instead, it is left to finish in the
+/// background and cancelled right away, so that its coins go back to the pool.
+async fn reserve_within_deadline(
+    gas_station: Arc<GasPool>,
+    reserve_deadline: Duration,
+    reservation: impl Future<Output = anyhow::Result<Reservation>> + Send + 'static,
+) -> anyhow::Result<Reservation> {
+    let mut handle = tokio::task::spawn(reservation);
+    match tokio::time::timeout(reserve_deadline, &mut handle).await {
+        Ok(result) => result.unwrap_or_else(|err| {
+            Err(anyhow::anyhow!("Failed to run reserve_gas task: {:?}", err))
+        }),
+        Err(_) => {
+            tokio::task::spawn(async move {
+                let Ok(Ok((_, reservation_id, ..))) = handle.await else {
+                    return;
+                };
+                match gas_station.cancel_reservation(reservation_id).await {
+                    Ok(count) => info!(
+                        ?reservation_id,
+                        "Cancelled reservation that missed its deadline, releasing {} coins", count
+                    ),
+                    Err(err) => error!(
+                        ?reservation_id,
+                        "Failed to cancel reservation that missed its deadline: {:?}", err
+                    ),
+                }
+            });
+            Err(GasStationError::RequestTimeout.into())
+        }
+    }
+}
+
+async fn reserve_balance(
+    TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
+    Extension(server): Extension<ServerState>,
+    payload: Result<Json<ReserveBalanceRequest>, JsonRejection>,
+) -> impl IntoResponse {
+    server.metrics.num_reserve_balance_requests.inc();
+    let Ok(authenticated_client_id) = server.authenticate(&authorization).await else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(ReserveBalanceResponse::new_err(anyhow::anyhow!(
+                "Invalid authorization token"
+            ))),
+        );
+    };
+    let payload = match parse_payload(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            debug!("Malformed request: {:?}", err);
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ReserveBalanceResponse::new_err(err)),
+            );
+        }
+    };
+    debug!("Received v1 reserve_balance request: {:?}", payload);
+    if let Err(err) = payload.check_validity(&server.reservation_limits_config) {
+        debug!("Invalid reserve_balance request: {:?}", err);
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ReserveBalanceResponse::new_err(err)),
+        );
+    }
+    let ReserveBalanceRequest {
+        target_balance,
+        reserve_duration_secs,
+        callback_url,
+        client_id: _,
+        priority,
+    } = payload;
+    // Only the token identifies the client, so that clients can't claim the priority of another
+    // client. Clients that the authenticator cannot tell apart get the default priority limit.
+    let client_id = authenticated_client_id;
+    server
+        .metrics
+        .reserve_duration_per_request
+        .observe(reserve_duration_secs);
+    // Spawn a thread to process the request so that it will finish even when client drops the connection.
+    tokio::task::spawn(reserve_balance_impl(
+        server.gas_station.clone(),
+        server.metrics.clone(),
+        target_balance,
+        reserve_duration_secs,
+        callback_url,
+        client_id,
+        priority,
+    ))
+    .await
+    .unwrap_or_else(|err| {
+        error!("Failed to spawn reserve_balance task: {:?}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ReserveBalanceResponse::new_err(anyhow::anyhow!(
+                "Failed to spawn reserve_balance task"
+            ))),
+        )
+    })
+}
+
+async fn reserve_balance_impl(
+    gas_station: Arc<GasPool>,
+    metrics: Arc<GasPoolRpcMetrics>,
+    target_balance: u64,
+    reserve_duration_secs: u64,
+    callback_url: Option<String>,
+    client_id: Option<String>,
+    priority: Option<u8>,
+) -> (StatusCode, Json<ReserveBalanceResponse>) {
+    match gas_station
+        .reserve_balance(
+            target_balance,
+            Duration::from_secs(reserve_duration_secs),
+            callback_url,
+            client_id.as_deref(),
+            priority,
+        )
+        .await
+    {
+        Ok((sponsor, reservation_id, coins)) => {
+            info!(
+                ?reservation_id,
+                "Reserved coins with sponsor={:?}, target balance={:?} and duration={:?}: {:?}",
+                sponsor,
+                target_balance,
+                reserve_duration_secs,
+                coins
+            );
+            metrics.num_successful_reserve_balance_requests.inc();
+            gas_station.record_audit_event(
+                client_id,
+                reservation_id,
+                None,
+                AuditEvent::Reserve {
+                    gas_budget: target_balance,
+                    gas_coins: coins.iter().map(|coin| coin.object_ref.0).collect(),
+                },
+            );
+            (
+                StatusCode::OK,
+                Json(ReserveBalanceResponse::new_ok(sponsor, reservation_id, coins)),
+            )
+        }
+        Err(err) => {
+            error!("Failed to reserve balance: {:?}", err);
+            metrics.num_failed_reserve_balance_requests.inc();
+            (
+                error_status_code(&err),
+                Json(ReserveBalanceResponse::new_err(err)),
+            )
+        }
+    }
+}
+
+async fn claim_lease(
+    headers: HeaderMap,
+    TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
+    Extension(server): Extension<ServerState>,
+    payload: Result<Json<ClaimLeaseRequest>, JsonRejection>,
+) -> impl IntoResponse {
+    let version = match api_version(&headers) {
+        Ok(version) => version,
+        Err(err) => {
+            let response = ReserveGasResponse::new_err(err);
+            return (StatusCode::BAD_REQUEST, Versioned(ApiVersion::V1, response));
+        }
+    };
+    let (status, Json(response)) = handle_claim_lease(authorization, server, payload).await;
+    (status, Versioned(version, response))
+}
+
+async fn handle_claim_lease(
+    authorization: Authorization<Bearer>,
+    server: ServerState,
+    payload: Result<Json<ClaimLeaseRequest>, JsonRejection>,
+) -> (StatusCode, Json<ReserveGasResponse>) {
+    server.metrics.num_claim_lease_requests.inc();
+    let Ok(client_id) = server.authenticate(&authorization).await else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(ReserveGasResponse::new_err(anyhow::anyhow!(
+                "Invalid authorization token"
+            ))),
+        );
+    };
+    let payload = match parse_payload(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            debug!("Malformed request: {:?}", err);
+            return (StatusCode::BAD_REQUEST, Json(ReserveGasResponse::new_err(err)));
+        }
+    };
+    debug!("Received v1 claim_lease request: {:?}", payload);
+    if let Err(err) = payload.check_validity(&server.reservation_limits_config) {
+        debug!("Invalid claim_lease request: {:?}", err);
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ReserveGasResponse::new_err(err)),
+        );
+    }
+    let ClaimLeaseRequest {
+        reservation_id,
+        reserve_duration_secs,
+    } = payload;
+    // Spawn a thread to process the request so that it will finish even when client drops the connection.
+    tokio::task::spawn(claim_lease_impl(
+        server.gas_station.clone(),
+        server.metrics.clone(),
+        reservation_id,
+        reserve_duration_secs,
+        client_id,
+    ))
+    .await
+    .unwrap_or_else(|err| {
+        error!("Failed to spawn claim_lease task: {:?}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ReserveGasResponse::new_err(anyhow::anyhow!(
+                "Failed to spawn claim_lease task"
+            ))),
+        )
+    })
+}
+
+async fn claim_lease_impl(
+    gas_station: Arc<GasPool>,
+    metrics: Arc<GasPoolRpcMetrics>,
+    reservation_id: ReservationID,
+    reserve_duration_secs: u64,
+    client_id: Option<String>,
+) -> (StatusCode, Json<ReserveGasResponse>) {
+    match gas_station
+        .claim_budget_lease(reservation_id, Duration::from_secs(reserve_duration_secs))
+        .await
+    {
+        Ok((sponsor, reservation_id, gas_coins)) => {
+            info!(
+                ?reservation_id,
+                "Claimed budget lease with duration={:?}: {:?}", reserve_duration_secs, gas_coins
+            );
+            metrics.num_successful_claim_lease_requests.inc();
+            let tag = gas_station.get_reservation_tag(reservation_id).await;
+            gas_station.record_audit_event(
+                client_id,
+                reservation_id,
+                tag.clone(),
+                AuditEvent::ClaimLease {
+                    gas_coins: gas_coins.iter().map(|coin| coin.0).collect(),
+                },
+            );
+            (
+                StatusCode::OK,
+                Json(
+                    ReserveGasResponse::new_ok(sponsor, reservation_id, gas_coins).with_tag(tag),
+                ),
+            )
+        }
+        Err(err) => {
+            error!(?reservation_id, "Failed to claim budget lease: {:?}", err);
+            metrics.num_failed_claim_lease_requests.inc();
+            (
+                error_status_code(&err),
+                Json(ReserveGasResponse::new_err(err)),
+            )
+        }
+    }
+}
+
+async fn cancel_reservation(
+    TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
+    Extension(server): Extension<ServerState>,
+    payload: Result<Json<CancelReservationRequest>, JsonRejection>,
+) -> impl IntoResponse {
+    server.metrics.num_cancel_reservation_requests.inc();
+    let Ok(client_id) = server.authenticate(&authorization).await else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(CancelReservationResponse::new_err(anyhow::anyhow!(
+                "Invalid authorization token"
+            ))),
+        );
+    };
+    let CancelReservationRequest { reservation_id } = match parse_payload(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            debug!("Malformed request: {:?}", err);
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(CancelReservationResponse::new_err(err)),
+            );
+        }
+    };
+    debug!(?reservation_id, "Received v1 cancel_reservation request");
+    let gas_station = server.gas_station.clone();
+    let metrics = server.metrics.clone();
+    // Spawn a thread to process the request so that it will finish even when client drops the connection.
+    tokio::task::spawn(async move {
+        match gas_station
+            .cancel_client_reservation(reservation_id, client_id.as_deref())
+            .await
+        {
+            Ok(freed_coin_count) => {
+                info!(
+                    ?reservation_id,
+                    "Cancelled reservation, freeing {} coins", freed_coin_count
+                );
+                metrics.num_successful_cancel_reservation_requests.inc();
+                (
+                    StatusCode::OK,
+                    Json(CancelReservationResponse::new_ok(freed_coin_count)),
+                )
+            }
+            Err(err) => {
+                error!(?reservation_id, "Failed to cancel reservation: {:?}", err);
+                metrics.num_failed_cancel_reservation_requests.inc();
+                (
+                    error_status_code(&err),
+                    Json(CancelReservationResponse::new_err(err)),
+                )
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|err| {
+        error!("Failed to spawn cancel_reservation task: {:?}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(CancelReservationResponse::new_err(anyhow::anyhow!(
+                "Failed to spawn cancel_reservation task"
+            ))),
+        )
+    })
+}
+
 async fn execute_tx(
+    headers: HeaderMap,
     TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
     Extension(server): Extension<ServerState>,
-    Json(payload): Json<ExecuteTxRequest>,
+    payload: Result<Json<ExecuteTxRequest>, JsonRejection>,
 ) -> impl IntoResponse {
+    let version = match api_version(&headers) {
+        Ok(version) => version,
+        Err(err) => {
+            let response = ExecuteTxResponse::new_err(err);
+            return (StatusCode::BAD_REQUEST, Versioned(ApiVersion::V1, response));
+        }
+    };
+    let (status, Json(response)) = handle_execute_tx(authorization, server, payload).await;
+    (status, Versioned(version, response))
+}
+
+async fn handle_execute_tx(
+    authorization: Authorization<Bearer>,
+    server: ServerState,
+    payload: Result<Json<ExecuteTxRequest>, JsonRejection>,
+) -> (StatusCode, Json<ExecuteTxResponse>) {
     server.metrics.num_execute_tx_requests.inc();
-    if authorization.token() != server.secret.as_str() {
+    let Ok(client_id) = server.authenticate(&authorization).await else {
         return (
             StatusCode::UNAUTHORIZED,
             Json(ExecuteTxResponse::new_err(anyhow::anyhow!(
                 "Invalid authorization token"
             ))),
         );
-    }
+    };
     server.metrics.num_authorized_execute_tx_requests.inc();
-    debug!("Received v1 execute_tx request: {:?}", payload);
+    let payload = match parse_payload(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            debug!("Malformed request: {:?}", err);
+            return (StatusCode::BAD_REQUEST, Json(ExecuteTxResponse::new_err(err)));
+        }
+    };
     let ExecuteTxRequest {
         reservation_id,
         tx_bytes,
         user_sig,
+        full_effects,
+        preflight,
     } = payload;
+    debug!(
+        ?reservation_id,
+        "Received v1 execute_tx request. tx_bytes: {}, user_sig: {}",
+        redacted(&tx_bytes.encoded()),
+        redacted(&user_sig.encoded())
+    );
     let Ok((tx_data, user_sig)) = convert_tx_and_sig(tx_bytes, user_sig) else {
         return (
             StatusCode::BAD_REQUEST,
@@ -247,13 +1202,23 @@ async fn execute_tx(
             ))),
         );
     };
+    let gas_price = tx_data.gas_data().price;
     // Spawn a thread to process the request so that it will finish even when client drops the connection.
-    tokio::task::spawn(execute_tx_impl(
+    let max_effects_size_bytes = if full_effects {
+        None
+    } else {
+        server.max_effects_size_bytes
+    };
+    let (status, Json(response)) = tokio::task::spawn(execute_tx_impl(
         server.gas_station.clone(),
         server.metrics.clone(),
         reservation_id,
         tx_data,
         user_sig,
+        max_effects_size_bytes,
+        client_id,
+        preflight,
+        server.execute_deadline,
     ))
     .await
     .unwrap_or_else(|err| {
@@ -264,21 +1229,263 @@ async fn execute_tx(
                 "Failed to spawn execute_tx task"
             ))),
         )
+    });
+    (status, Json(response.with_gas_cost(gas_price)))
+}
+
+async fn batch_execute_tx(
+    TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
+    Extension(server): Extension<ServerState>,
+    payload: Result<Json<BatchExecuteTxRequest>, JsonRejection>,
+) -> impl IntoResponse {
+    let (client_id, payload) =
+        match check_batch_execute_tx_request(&server, &authorization, payload).await {
+            Ok(result) => result,
+            Err(rejection) => return rejection,
+        };
+    let max_concurrency = server.batch_execute_config.max_concurrency.max(1);
+    let max_effects_size_bytes = server.max_effects_size_bytes;
+    let execute_deadline = server.execute_deadline;
+    let gas_station = server.gas_station.clone();
+    let metrics = server.metrics.clone();
+    // Spawn a thread to process the request so that it will finish even when client drops the connection.
+    // Each transaction is executed independently, so a failed transaction does not affect the rest of the batch.
+    tokio::task::spawn(async move {
+        futures_util::stream::iter(payload.requests)
+            .map(|request| {
+                execute_batch_item(
+                    gas_station.clone(),
+                    metrics.clone(),
+                    request,
+                    max_effects_size_bytes,
+                    client_id.clone(),
+                    execute_deadline,
+                )
+            })
+            .buffered(max_concurrency)
+            .collect::<Vec<_>>()
+            .await
     })
+    .await
+    .map(|results| {
+        (
+            StatusCode::OK,
+            Json(BatchExecuteTxResponse::new_ok(results)),
+        )
+    })
+    .unwrap_or_else(|err| {
+        error!("Failed to spawn batch_execute_tx task: {:?}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(BatchExecuteTxResponse::new_err(anyhow::anyhow!(
+                "Failed to spawn batch_execute_tx task"
+            ))),
+        )
+    })
+}
+
+async fn batch_execute_tx_stream(
+    TypedHeader(authorization): TypedHeader<Authorization<Bearer>>,
+    Extension(server): Extension<ServerState>,
+    payload: Result<Json<BatchExecuteTxRequest>, JsonRejection>,
+) -> Response {
+    let (client_id, payload) =
+        match check_batch_execute_tx_request(&server, &authorization, payload).await {
+            Ok(result) => result,
+            Err(rejection) => return rejection.into_response(),
+        };
+    let max_concurrency = server.batch_execute_config.max_concurrency.max(1);
+    let max_effects_size_bytes = server.max_effects_size_bytes;
+    let execute_deadline = server.execute_deadline;
+    let gas_station = server.gas_station.clone();
+    let metrics = server.metrics.clone();
+    let (event_sender, event_receiver) = tokio::sync::mpsc::unbounded_channel();
+    // Spawn a thread to process the request so that it will finish even when client drops the connection.
+    tokio::task::spawn(async move {
+        let mut results = futures_util::stream::iter(payload.requests.into_iter().enumerate())
+            .map(|(index, request)| {
+                let response = execute_batch_item(
+                    gas_station.clone(),
+                    metrics.clone(),
+                    request,
+                    max_effects_size_bytes,
+                    client_id.clone(),
+                    execute_deadline,
+                );
+                async move { (index, response.await) }
+            })
+            .buffer_unordered(max_concurrency);
+        let mut failed_indexes = vec![];
+        while let Some((index, response)) = results.next().await {
+            if response.error.is_some() {
+                failed_indexes.push(index);
+            }
+            // Sending only fails once the client dropped the connection, in which case the rest of the
+            // batch is still executed.
+            let _ = event_sender.send(BatchExecuteTxStreamEvent::Result { index, response });
+        }
+        failed_indexes.sort_unstable();
+        let _ = event_sender.send(BatchExecuteTxStreamEvent::Done { failed_indexes });
+    });
+    let body = futures_util::stream::unfold(event_receiver, |mut event_receiver| async move {
+        let event = event_receiver.recv().await?;
+        let mut line =
+            serde_json::to_vec(&event).expect("BatchExecuteTxStreamEvent is always serializable");
+        line.push(b'\n');
+        Some((Ok::<_, Infallible>(line), event_receiver))
+    });
+    (
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
+/// Authenticates and validates a batch_execute_tx request. Shared by the buffered and the streaming
+/// variants of the endpoint. Returns the authenticated client id along with the request.
+async fn check_batch_execute_tx_request(
+    server: &ServerState,
+    authorization: &Authorization<Bearer>,
+    payload: Result<Json<BatchExecuteTxRequest>, JsonRejection>,
+) -> Result<(Option<String>, BatchExecuteTxRequest), (StatusCode, Json<BatchExecuteTxResponse>)> {
+    server.metrics.num_batch_execute_tx_requests.inc();
+    let Ok(client_id) = server.authenticate(authorization).await else {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(BatchExecuteTxResponse::new_err(anyhow::anyhow!(
+                "Invalid authorization token"
+            ))),
+        ));
+    };
+    server.metrics.num_authorized_batch_execute_tx_requests.inc();
+    let payload = match parse_payload(payload) {
+        Ok(payload) => payload,
+        Err(err) => {
+            debug!("Malformed request: {:?}", err);
+            return Err((StatusCode::BAD_REQUEST, Json(BatchExecuteTxResponse::new_err(err))));
+        }
+    };
+    if let Err(err) = payload.check_validity(server.batch_execute_config.max_batch_size) {
+        debug!("Invalid batch_execute_tx request: {:?}", err);
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(BatchExecuteTxResponse::new_err(err)),
+        ));
+    }
+    let batch_size = payload.requests.len();
+    debug!(
+        "Received v1 batch_execute_tx request with {} transactions",
+        batch_size
+    );
+    server
+        .metrics
+        .batch_execute_tx_size
+        .observe(batch_size as u64);
+    Ok((client_id, payload))
+}
+
+async fn execute_batch_item(
+    gas_station: Arc<GasPool>,
+    metrics: Arc<GasPoolRpcMetrics>,
+    request: ExecuteTxRequest,
+    max_effects_size_bytes: Option<usize>,
+    client_id: Option<String>,
+    execute_deadline: Option<Duration>,
+) -> ExecuteTxResponse {
+    let ExecuteTxRequest {
+        reservation_id,
+        tx_bytes,
+        user_sig,
+        full_effects,
+        preflight,
+    } = request;
+    let max_effects_size_bytes = if full_effects {
+        None
+    } else {
+        max_effects_size_bytes
+    };
+    let Ok((tx_data, user_sig)) = convert_tx_and_sig(tx_bytes, user_sig) else {
+        return ExecuteTxResponse::new_err(anyhow::anyhow!(
+            "Invalid bcs bytes for TransactionData"
+        ));
+    };
+    let gas_price = tx_data.gas_data().price;
+    let (_, Json(response)) = execute_tx_impl(
+        gas_station,
+        metrics,
+        reservation_id,
+        tx_data,
+        user_sig,
+        max_effects_size_bytes,
+        client_id,
+        preflight,
+        execute_deadline,
+    )
+    .await;
+    response.with_gas_cost(gas_price)
 }
 
 async fn execute_tx_impl(
     gas_station: Arc<GasPool>,
     metrics: Arc<GasPoolRpcMetrics>,
-    reservation_id: u64,
+    reservation_id: ReservationID,
     tx_data: TransactionData,
     user_sig: GenericSignature,
+    max_effects_size_bytes: Option<usize>,
+    client_id: Option<String>,
+    preflight: bool,
+    execute_deadline: Option<Duration>,
 ) -> (StatusCode, Json<ExecuteTxResponse>) {
-    match gas_station
-        .execute_transaction(reservation_id, tx_data, user_sig)
-        .await
-    {
-        Ok(effects) => {
+    let deadline = execute_deadline.map(|deadline| Instant::now() + deadline);
+    let gas_coins = tx_data.gas_data().payment.iter().map(|coin| coin.0).collect();
+    let result = match gas_station.check_move_call_rate_limits(&tx_data, client_id.as_deref()) {
+        Ok(()) => {
+            gas_station
+                .execute_transaction_with_deadline(
+                    reservation_id,
+                    tx_data,
+                    user_sig,
+                    preflight,
+                    deadline,
+                )
+                .await
+        }
+        Err(err) => Err(err.into()),
+    };
+    if result.as_ref().is_err_and(is_request_timeout) {
+        metrics.num_execute_tx_timeouts.inc();
+    }
+    let audit_event = match &result {
+        Ok((effects, _)) => AuditEvent::Execute {
+            gas_coins,
+            digest: Some(*effects.transaction_digest()),
+            status: Some(effects.status().clone()),
+            net_gas_usage: Some(effects.gas_cost_summary().net_gas_usage()),
+            error: None,
+        },
+        Err(err) => AuditEvent::Execute {
+            gas_coins,
+            digest: None,
+            status: None,
+            net_gas_usage: None,
+            error: Some(err.to_string()),
+        },
+    };
+    let tag = gas_station.get_reservation_tag(reservation_id).await;
+    if let (Some(tag), Ok((effects, _))) = (&tag, &result) {
+        let metric_tag = gas_station.metric_tag(tag);
+        metrics
+            .num_executions_per_tag
+            .with_label_values(&[metric_tag])
+            .inc();
+        metrics
+            .gas_usage_per_tag
+            .with_label_values(&[metric_tag])
+            .inc_by(effects.gas_cost_summary().net_gas_usage().max(0) as u64);
+    }
+    gas_station.record_audit_event(client_id, reservation_id, tag, audit_event);
+    match result {
+        Ok((effects, signed_effects)) => {
             info!(
                 ?reservation_id,
                 "Successfully executed transaction {:?} with status: {:?}",
@@ -286,19 +1493,115 @@ async fn execute_tx_impl(
                 effects.status()
             );
             metrics.num_successful_execute_tx_requests.inc();
-            (StatusCode::OK, Json(ExecuteTxResponse::new_ok(effects)))
+            let effects_size = max_effects_size_bytes.and_then(|max_size| {
+                serde_json::to_vec(&effects)
+                    .ok()
+                    .map(|bytes| bytes.len())
+                    .filter(|size| *size > max_size)
+            });
+            if let Some(effects_size) = effects_size {
+                debug!(
+                    ?reservation_id,
+                    "Returning a summary of the effects, which are {} bytes",
+                    effects_size
+                );
+                metrics.num_truncated_execute_tx_effects.inc();
+                return (
+                    StatusCode::OK,
+                    Json(ExecuteTxResponse::new_truncated(
+                        EffectsSummary::new(&effects),
+                        signed_effects,
+                    )),
+                );
+            }
+            (
+                StatusCode::OK,
+                Json(ExecuteTxResponse::new_ok(effects, signed_effects)),
+            )
         }
         Err(err) => {
             error!("Failed to execute transaction: {:?}", err);
             metrics.num_failed_execute_tx_requests.inc();
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                error_status_code(&err),
                 Json(ExecuteTxResponse::new_err(err)),
             )
         }
     }
 }
 
+/// Reads the API version the client asked for, defaulting to v1 when the header is missing.
+fn api_version(headers: &HeaderMap) -> anyhow::Result<ApiVersion> {
+    let Some(value) = headers.get(API_VERSION_HEADER) else {
+        return Ok(ApiVersion::V1);
+    };
+    let version = value
+        .to_str()
+        .map_err(|_| GasStationError::InvalidRequest("Invalid API version header".to_string()))?
+        .parse::<ApiVersion>()
+        .map_err(|err| GasStationError::InvalidRequest(err.to_string()))?;
+    Ok(version)
+}
+
+/// A JSON response serialized in the shape of an API version.
+struct Versioned<T>(ApiVersion, T);
+
+impl<T: VersionedResponse> IntoResponse for Versioned<T> {
+    fn into_response(self) -> Response {
+        let Versioned(version, response) = self;
+        let mut response = Json(response.to_versioned_json(version)).into_response();
+        response.headers_mut().insert(
+            API_VERSION_HEADER,
+            HeaderValue::from_static(version.as_str()),
+        );
+        response
+    }
+}
+
+/// Turns a request body that cannot be deserialized, e.g. because it carries an invalid
+/// reservation id, into an InvalidRequest error so that the client gets the usual JSON response.
+fn parse_payload<T>(payload: Result<Json<T>, JsonRejection>) -> anyhow::Result<T> {
+    payload
+        .map(|Json(payload)| payload)
+        .map_err(|rejection| GasStationError::InvalidRequest(rejection.body_text()).into())
+}
+
+/// Lets clients and load balancers back off from an exhausted or busy gas pool.
+async fn add_retry_after(mut response: Response) -> Response {
+    if response.status() == StatusCode::SERVICE_UNAVAILABLE {
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+    }
+    response
+}
+
+/// Errors caused by the request are reported as 4xx, errors that clients should back off from
+/// as 503, requests that missed their deadline as 504, and everything else as 500.
+fn error_status_code(err: &anyhow::Error) -> StatusCode {
+    match err.downcast_ref::<GasStationError>() {
+        Some(GasStationError::FullnodeUnavailable)
+        | Some(GasStationError::ServiceWarming)
+        | Some(GasStationError::InsufficientGasCoins) => StatusCode::SERVICE_UNAVAILABLE,
+        Some(GasStationError::InvalidCallbackUrl(_))
+        | Some(GasStationError::InvalidSponsoredTransaction(_))
+        | Some(GasStationError::UnsupportedTransactionKind(_))
+        | Some(GasStationError::InvalidRequest(_))
+        | Some(GasStationError::PreflightFailed(_)) => StatusCode::BAD_REQUEST,
+        Some(GasStationError::ReservationNotFound(_))
+        | Some(GasStationError::RequestedCoinNotFound(_)) => StatusCode::NOT_FOUND,
+        Some(GasStationError::StaleGasCoins(_))
+        | Some(GasStationError::ReservationTooCloseToExpiry(..))
+        | Some(GasStationError::RequestedCoinReserved(_)) => StatusCode::CONFLICT,
+        Some(GasStationError::BudgetCapReached)
+        | Some(GasStationError::PolicyRateLimited(_))
+        | Some(GasStationError::BudgetLimitExceeded(_))
+        | Some(GasStationError::TooManyActiveReservations(_)) => StatusCode::TOO_MANY_REQUESTS,
+        Some(GasStationError::RequestTimeout) => StatusCode::GATEWAY_TIMEOUT,
+        None => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
 fn convert_tx_and_sig(
     tx_bytes: Base64,
     user_sig: Base64,
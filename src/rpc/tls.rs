@@ -0,0 +1,72 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::TlsConfig;
+use anyhow::anyhow;
+use axum_server::tls_rustls::RustlsConfig;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// Loads the certificate and private key of the RPC server. Fails if either of them cannot be
+/// read or parsed.
+pub async fn load_tls_config(config: &TlsConfig) -> anyhow::Result<RustlsConfig> {
+    RustlsConfig::from_pem_file(&config.cert_path, &config.key_path)
+        .await
+        .map_err(|err| {
+            anyhow!(
+                "Failed to load TLS certificate {:?} and key {:?}: {}",
+                config.cert_path,
+                config.key_path,
+                err
+            )
+        })
+}
+
+/// Starts a task that loads the certificate and private key again on every SIGHUP. New
+/// connections use the new certificate, while existing connections are kept. If the new files
+/// are invalid, the current certificate keeps being served.
+pub fn start_tls_reload_task(config: TlsConfig, rustls_config: RustlsConfig) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut hangup = signal(SignalKind::hangup()).expect("Failed to listen for SIGHUP");
+        while hangup.recv().await.is_some() {
+            match rustls_config
+                .reload_from_pem_file(&config.cert_path, &config.key_path)
+                .await
+            {
+                Ok(()) => info!("Reloaded TLS certificate from {:?}", config.cert_path),
+                Err(err) => error!(
+                    "Failed to reload TLS certificate from {:?}, keeping the current one: {:?}",
+                    config.cert_path, err
+                ),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[tokio::test]
+    async fn test_invalid_tls_material() {
+        let mut cert = NamedTempFile::new().unwrap();
+        cert.write_all(b"not a certificate").unwrap();
+        let mut key = NamedTempFile::new().unwrap();
+        key.write_all(b"not a key").unwrap();
+        let config = TlsConfig {
+            cert_path: cert.path().to_path_buf(),
+            key_path: key.path().to_path_buf(),
+        };
+        assert!(load_tls_config(&config).await.is_err());
+
+        let config = TlsConfig {
+            cert_path: "/nonexistent/cert.pem".into(),
+            key_path: "/nonexistent/key.pem".into(),
+        };
+        assert!(load_tls_config(&config).await.is_err());
+    }
+}
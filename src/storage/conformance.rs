@@ -0,0 +1,183 @@
+// Copyright (c) Mysten Labs, Inc.
+// Copyright (c) The Social Proof Foundation, LLC.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Checks of the contract documented on `Storage`, which every backend must pass. Each check
+//! connects to the backend with a fresh sponsor address, so that the checks do not interfere with
+//! each other or with other tests.
+
+use crate::errors::GasStationError;
+use crate::storage::Storage;
+use crate::types::GasCoin;
+use futures_util::future::join_all;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use mys_types::base_types::{random_object_ref, ObjectID, MysAddress};
+
+/// Runs every check against the backend returned by `connect`.
+pub async fn run_conformance_suite<F, Fut>(connect: F)
+where
+    F: Fn(MysAddress) -> Fut,
+    Fut: Future<Output = Arc<dyn Storage>>,
+{
+    check_reservation_atomicity(&connect).await;
+    check_no_double_hand_out(&connect).await;
+    check_expiry(&connect).await;
+    check_recovery(&connect).await;
+}
+
+async fn setup<F, Fut>(connect: &F, coin_count: usize) -> (Arc<dyn Storage>, Vec<GasCoin>)
+where
+    F: Fn(MysAddress) -> Fut,
+    Fut: Future<Output = Arc<dyn Storage>>,
+{
+    let storage = connect(MysAddress::random_for_testing_only()).await;
+    let coins: Vec<_> = (0..coin_count)
+        .map(|_| GasCoin {
+            object_ref: random_object_ref(),
+            balance: 1,
+        })
+        .collect();
+    storage.add_new_coins(coins.clone()).await.unwrap();
+    (storage, coins)
+}
+
+fn coin_ids(coins: &[GasCoin]) -> Vec<ObjectID> {
+    coins.iter().map(|c| c.object_ref.0).collect()
+}
+
+/// Concurrent reservations split the coins between them, and a reservation that cannot be
+/// covered takes nothing.
+async fn check_reservation_atomicity<F, Fut>(connect: &F)
+where
+    F: Fn(MysAddress) -> Fut,
+    Fut: Future<Output = Arc<dyn Storage>>,
+{
+    let (storage, _) = setup(connect, 100).await;
+    let results = join_all((0..20).map(|_| storage.reserve_gas_coins(5, 60_000))).await;
+    let mut reserved_coin_ids = BTreeSet::new();
+    for result in results {
+        let (_, coins) = result.unwrap();
+        assert_eq!(coins.len(), 5);
+        for coin_id in coin_ids(&coins) {
+            assert!(reserved_coin_ids.insert(coin_id), "coin handed out twice");
+        }
+    }
+    assert_eq!(storage.get_available_coin_count().await.unwrap(), 0);
+
+    let (storage, _) = setup(connect, 10).await;
+    let err = storage.reserve_gas_coins(11, 60_000).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<GasStationError>(),
+        Some(GasStationError::InsufficientGasCoins)
+    ));
+    assert_eq!(storage.get_available_coin_count().await.unwrap(), 10);
+    assert_eq!(storage.get_available_coin_total_balance().await, 10);
+}
+
+/// Coins are only put back by the reservation that holds them, however often they are released.
+async fn check_no_double_hand_out<F, Fut>(connect: &F)
+where
+    F: Fn(MysAddress) -> Fut,
+    Fut: Future<Output = Arc<dyn Storage>>,
+{
+    let (storage, _) = setup(connect, 10).await;
+    let (res_id, coins) = storage.reserve_gas_coins(10, 60_000).await.unwrap();
+    storage.ready_for_execution(res_id).await.unwrap();
+    let released = storage
+        .release_reserved_coins(res_id, coin_ids(&coins), coins.clone())
+        .await
+        .unwrap();
+    assert_eq!(released.len(), 10);
+    assert!(storage
+        .release_reserved_coins(res_id, coin_ids(&coins), coins.clone())
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(storage.get_available_coin_count().await.unwrap(), 10);
+
+    // A stale release of coins that were reserved again since must not free them.
+    let (new_res_id, new_coins) = storage.reserve_gas_coins(10, 60_000).await.unwrap();
+    assert!(storage
+        .release_reserved_coins(res_id, coin_ids(&coins), coins.clone())
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(storage.get_available_coin_count().await.unwrap(), 0);
+    let err = storage.ready_for_execution(res_id).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<GasStationError>(),
+        Some(GasStationError::ReservationNotFound(_))
+    ));
+    storage.ready_for_execution(new_res_id).await.unwrap();
+    assert_eq!(
+        coin_ids(&new_coins).into_iter().collect::<BTreeSet<_>>(),
+        coin_ids(&coins).into_iter().collect::<BTreeSet<_>>()
+    );
+}
+
+/// Reservations expire once, not before their expiration time, and never after they were taken
+/// out for execution or cancelled.
+async fn check_expiry<F, Fut>(connect: &F)
+where
+    F: Fn(MysAddress) -> Fut,
+    Fut: Future<Output = Arc<dyn Storage>>,
+{
+    let (storage, _) = setup(connect, 30).await;
+    let (expiring_id, expiring_coins) = storage.reserve_gas_coins(10, 500).await.unwrap();
+    let (executing_id, _) = storage.reserve_gas_coins(10, 500).await.unwrap();
+    let (cancelled_id, _) = storage.reserve_gas_coins(10, 500).await.unwrap();
+    assert!(storage.expire_coins().await.unwrap().is_empty());
+    storage.ready_for_execution(executing_id).await.unwrap();
+    storage.cancel_reservation(cancelled_id).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    let expired = storage.expire_coins().await.unwrap();
+    assert_eq!(expired.len(), 1);
+    let (res_id, expired_coin_ids) = &expired[0];
+    assert_eq!(*res_id, expiring_id);
+    assert_eq!(
+        expired_coin_ids.iter().collect::<BTreeSet<_>>(),
+        coin_ids(&expiring_coins).iter().collect::<BTreeSet<_>>()
+    );
+    assert!(storage.expire_coins().await.unwrap().is_empty());
+
+    // The coins of the expired reservation are held until they are released.
+    assert_eq!(storage.get_available_coin_count().await.unwrap(), 0);
+    storage
+        .release_reserved_coins(expiring_id, expired_coin_ids.clone(), expiring_coins)
+        .await
+        .unwrap();
+    assert_eq!(storage.get_available_coin_count().await.unwrap(), 10);
+}
+
+/// Coins of reservations that were never released can be found and released.
+async fn check_recovery<F, Fut>(connect: &F)
+where
+    F: Fn(MysAddress) -> Fut,
+    Fut: Future<Output = Arc<dyn Storage>>,
+{
+    let (storage, coins) = setup(connect, 10).await;
+    let (res_id, reserved_coins) = storage.reserve_gas_coins(10, 500).await.unwrap();
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert_eq!(storage.expire_coins().await.unwrap().len(), 1);
+
+    // The gas pool crashed before releasing the expired coins.
+    let stranded_coins = storage.get_stranded_coins(now + 1).await.unwrap();
+    assert_eq!(stranded_coins.len(), 1);
+    let (stranded_id, stranded_coin_ids) = stranded_coins.into_iter().next().unwrap();
+    assert_eq!(stranded_id, res_id);
+    assert_eq!(
+        stranded_coin_ids.iter().collect::<BTreeSet<_>>(),
+        coin_ids(&reserved_coins).iter().collect::<BTreeSet<_>>()
+    );
+    storage
+        .release_reserved_coins(stranded_id, stranded_coin_ids, coins)
+        .await
+        .unwrap();
+    assert_eq!(storage.get_available_coin_count().await.unwrap(), 10);
+    assert!(storage.get_stranded_coins(now + 1).await.unwrap().is_empty());
+}
@@ -2,13 +2,21 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::config::GasPoolStorageConfig;
+use crate::config::{CoinSelectionStrategy, GasPoolStorageConfig};
 use crate::metrics::StorageMetrics;
 use crate::storage::redis::RedisStorage;
-use crate::types::{GasCoin, ReservationID};
+use crate::types::{
+    AuditRecord, BudgetLeaseSnapshot, ClientUsage, CoinBalanceDistribution, GasCoin,
+    ReservationID, ReservationSnapshot,
+};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use mys_types::base_types::{ObjectID, MysAddress};
+use mys_types::digests::TransactionDigest;
+use tracing::error;
 
+#[cfg(test)]
+mod conformance;
 mod redis;
 
 pub const MAX_GAS_PER_QUERY: usize = 256;
@@ -16,6 +24,21 @@ pub const MAX_GAS_PER_QUERY: usize = 256;
 /// Defines the trait for a storage that manages gas coins.
 /// It is expected to support concurrent access and manage atomicity internally.
 /// It supports multiple addresses each with its own gas coin queue.
+///
+/// A storage may be shared by several gas pool instances of the same sponsor, so every backend
+/// must uphold the following contract, which is checked by the conformance suite in
+/// `conformance.rs`:
+/// 1. Each method is atomic: concurrent callers, on any instance, never observe or leave behind a
+///    partially applied change.
+/// 2. A coin is either available, or held by exactly one reservation. A coin is never handed out
+///    to two reservations at once, and a failed reservation takes no coin.
+/// 3. A reservation expires exactly once, no earlier than its expiration time, unless it was
+///    taken out with `ready_for_execution` or cancelled before. The coins of an expired
+///    reservation stay held until they are released.
+/// 4. Releasing coins is idempotent: a coin is only put back if it is still held by the
+///    reservation releasing it.
+/// 5. Coins that were never released, e.g. because the gas pool crashed, can be recovered with
+///    `get_stranded_coins`.
 #[async_trait::async_trait]
 pub trait Storage: Sync + Send {
     /// Reserve gas coins with total coin balance >= target_budget.
@@ -29,13 +52,218 @@ pub trait Storage: Sync + Send {
         &self,
         target_budget: u64,
         reserved_duration_ms: u64,
+    ) -> anyhow::Result<(ReservationID, Vec<GasCoin>)> {
+        self.reserve_gas_coins_with_callback(target_budget, reserved_duration_ms, None)
+            .await
+    }
+
+    /// Same as `reserve_gas_coins`, but also records a callback URL for the reservation, which
+    /// is handed back once the reservation is either consumed or expired.
+    async fn reserve_gas_coins_with_callback(
+        &self,
+        target_budget: u64,
+        reserved_duration_ms: u64,
+        callback_url: Option<String>,
     ) -> anyhow::Result<(ReservationID, Vec<GasCoin>)>;
 
-    async fn ready_for_execution(&self, reservation_id: ReservationID) -> anyhow::Result<()>;
+    /// Same as `reserve_gas_coins_with_callback`, but when there is not enough balance to cover
+    /// target_budget, reserves as many coins as it can instead of failing, so the total balance
+    /// of the returned coins may be less than target_budget. Fails only if no coin is available.
+    async fn reserve_partial_gas_coins_with_callback(
+        &self,
+        target_budget: u64,
+        reserved_duration_ms: u64,
+        callback_url: Option<String>,
+    ) -> anyhow::Result<(ReservationID, Vec<GasCoin>)>;
+
+    /// Reserve exactly the given coins, as long as they are all available and their total balance
+    /// covers target_budget. Either all of the coins are reserved or none of them is.
+    /// Fails with `GasStationError::RequestedCoinReserved` if a coin is held by a reservation,
+    /// `GasStationError::RequestedCoinNotFound` if a coin is not in the pool, and
+    /// `GasStationError::InvalidRequest` if the coins do not cover target_budget.
+    /// Budget committed to budget leases is never reserved.
+    async fn reserve_requested_coins(
+        &self,
+        coin_ids: Vec<ObjectID>,
+        target_budget: u64,
+        reserved_duration_ms: u64,
+        callback_url: Option<String>,
+    ) -> anyhow::Result<(ReservationID, Vec<GasCoin>)>;
+
+    /// Commit a budget against the pool without taking out any coins. Coins are only picked when
+    /// the lease is claimed with `claim_budget_lease`. Until then, the leased budget is not
+    /// available to other reservations or leases. The lease is dropped when it expires.
+    /// Returns the lease id, which becomes the reservation id once the lease is claimed.
+    async fn reserve_budget_lease(
+        &self,
+        budget: u64,
+        lease_duration_ms: u64,
+        callback_url: Option<String>,
+    ) -> anyhow::Result<ReservationID>;
+
+    /// Turn a budget lease into a regular reservation with the same id, by reserving gas coins
+    /// for the leased budget. Returns error if the lease does not exist or has expired.
+    async fn claim_budget_lease(
+        &self,
+        lease_id: ReservationID,
+        reserved_duration_ms: u64,
+    ) -> anyhow::Result<Vec<GasCoin>>;
+
+    /// Take the reservation out so that it can no longer expire. Its coins stay recorded as held
+    /// by the reservation until they are released with `release_reserved_coins`.
+    /// Returns the callback URL of the reservation, if any.
+    /// Fails with `GasStationError::ReservationNotFound` if the reservation does not exist.
+    async fn ready_for_execution(
+        &self,
+        reservation_id: ReservationID,
+    ) -> anyhow::Result<Option<String>> {
+        self.ready_for_execution_with_expiry_margin(reservation_id, 0)
+            .await
+    }
+
+    /// Same as `ready_for_execution`, but fails with
+    /// `GasStationError::ReservationTooCloseToExpiry` if the reservation expires within
+    /// `expiry_margin_ms`, leaving the reservation untouched so that it expires as usual.
+    async fn ready_for_execution_with_expiry_margin(
+        &self,
+        reservation_id: ReservationID,
+        expiry_margin_ms: u64,
+    ) -> anyhow::Result<Option<String>> {
+        self.ready_for_execution_with_payment(reservation_id, expiry_margin_ms, vec![])
+            .await
+            .map(|(callback_url, _)| callback_url)
+    }
+
+    /// Same as `ready_for_execution_with_expiry_margin`, but first checks that every payment coin
+    /// is a coin of the reservation, failing with `GasStationError::InvalidSponsoredTransaction`
+    /// and leaving the reservation untouched otherwise. Also returns the object ids of all coins of
+    /// the reservation, so that the ones the transaction does not pay with can be released.
+    async fn ready_for_execution_with_payment(
+        &self,
+        reservation_id: ReservationID,
+        expiry_margin_ms: u64,
+        payment: Vec<ObjectID>,
+    ) -> anyhow::Result<(Option<String>, Vec<ObjectID>)>;
+
+    /// Take the reservation or unclaimed budget lease out and return the object ids of its coins,
+    /// so that they can be released right away instead of when it expires, along with its
+    /// callback URL, if any. Cancelling a reservation that was already cancelled returns no coins.
+    /// Fails with `GasStationError::ReservationNotFound` if the reservation does not exist, e.g.
+    /// because it expired or was executed.
+    async fn cancel_reservation(
+        &self,
+        reservation_id: ReservationID,
+    ) -> anyhow::Result<(Vec<ObjectID>, Option<String>)>;
 
     async fn add_new_coins(&self, new_coins: Vec<GasCoin>) -> anyhow::Result<()>;
 
-    async fn expire_coins(&self) -> anyhow::Result<Vec<ObjectID>>;
+    /// Returns the coins whose object ids are neither available nor reserved in the pool.
+    async fn filter_untracked_coins(&self, coins: Vec<GasCoin>) -> anyhow::Result<Vec<GasCoin>>;
+
+    /// Atomically adds the coins whose object ids are neither available nor reserved in the
+    /// pool, leaving the others untouched. Returns the coins that were added.
+    async fn add_untracked_coins(&self, coins: Vec<GasCoin>) -> anyhow::Result<Vec<GasCoin>>;
+
+    /// Take out the reservations that expired and return the object ids of their coins, which
+    /// stay recorded as held by the reservation until they are released with
+    /// `release_reserved_coins`.
+    async fn expire_coins(&self) -> anyhow::Result<Vec<(ReservationID, Vec<ObjectID>)>>;
+
+    /// Put the latest state of the coins of a reservation that is over back into the pool.
+    /// `reserved_coin_ids` are all coins of the reservation, and coins among them that are not in
+    /// `latest_coins` are considered deleted. Only coins that are recorded as held by the
+    /// reservation are released, so releasing the same coins twice, or releasing coins that were
+    /// reserved again since, never adds a coin to the pool twice.
+    /// Returns the coins that were released.
+    async fn release_reserved_coins(
+        &self,
+        reservation_id: ReservationID,
+        reserved_coin_ids: Vec<ObjectID>,
+        latest_coins: Vec<GasCoin>,
+    ) -> anyhow::Result<Vec<GasCoin>>;
+
+    /// Same as `release_reserved_coins`, but only for some coins of a reservation that is not over,
+    /// e.g. the coins that its transaction does not pay with. The reservation stays recorded as
+    /// executing by `mark_executing` and counted by `add_client_reservation` until the rest of its
+    /// coins are released with `release_reserved_coins`.
+    async fn release_unused_coins(
+        &self,
+        reservation_id: ReservationID,
+        unused_coin_ids: Vec<ObjectID>,
+        latest_coins: Vec<GasCoin>,
+    ) -> anyhow::Result<Vec<GasCoin>>;
+
+    /// Find the coins that were reserved before `reserved_before_ms`, and are still recorded as
+    /// held by a reservation that is over, e.g. because the gas pool crashed before releasing
+    /// them. Returns the object ids of the coins grouped by reservation.
+    async fn get_stranded_coins(
+        &self,
+        reserved_before_ms: u64,
+    ) -> anyhow::Result<Vec<(ReservationID, Vec<ObjectID>)>>;
+
+    /// Record that the transaction of a reservation taken out with `ready_for_execution` is about
+    /// to be submitted for execution. The record is kept until the coins of the reservation are
+    /// released with `release_reserved_coins`.
+    async fn mark_executing(
+        &self,
+        reservation_id: ReservationID,
+        digest: TransactionDigest,
+        reserved_coin_ids: Vec<ObjectID>,
+    ) -> anyhow::Result<()>;
+
+    /// Find the reservations recorded by `mark_executing` before `started_before_ms`, whose coins
+    /// were never released, e.g. because the gas pool crashed while executing the transaction.
+    async fn get_executing_reservations(
+        &self,
+        started_before_ms: u64,
+    ) -> anyhow::Result<Vec<ExecutingReservation>>;
+
+    /// Count a reservation and its budget against the usage of the client that made it, until the
+    /// coins of the reservation are released with `release_reserved_coins`, or, for a budget lease
+    /// that is never claimed, until it is cancelled or expires. Nothing is counted if
+    /// that would push the outstanding budget of the client over `max_outstanding_budget`, or its
+    /// active reservations over `max_active_reservations`.
+    /// Returns whether the reservation was counted, along with the usage of the client.
+    async fn add_client_reservation(
+        &self,
+        reservation_id: ReservationID,
+        client_id: &str,
+        budget: u64,
+        max_outstanding_budget: u64,
+        max_active_reservations: u64,
+    ) -> anyhow::Result<(bool, ClientUsage)>;
+
+    /// The usage of the client, as counted by `add_client_reservation`.
+    async fn get_client_usage(&self, client_id: &str) -> anyhow::Result<ClientUsage>;
+
+    /// The usage of every client that holds a reservation counted by `add_client_reservation`.
+    async fn get_all_client_usage(&self) -> anyhow::Result<BTreeMap<String, ClientUsage>>;
+
+    /// Take out the callback URLs of reservations that were expired by `expire_coins`.
+    /// Each callback is only returned once, even with multiple gas pool instances.
+    async fn take_expired_reservation_callbacks(
+        &self,
+    ) -> anyhow::Result<Vec<(ReservationID, String)>>;
+
+    /// Reserve up to `max_small_coins` available coins with balance below `small_balance_threshold`
+    /// and up to `max_large_coins` available coins with balance above `large_balance_threshold`,
+    /// so that they can be merged or split. The coins are released like any other reservation if
+    /// they are not taken out with `ready_for_execution` before the reservation expires.
+    /// Budget committed to budget leases is never reserved.
+    /// Returns the reservation id (None if no coin was reserved), the reserved coins, and the
+    /// distribution of the available coins prior to the reservation.
+    async fn reserve_coins_for_rebalance(
+        &self,
+        small_balance_threshold: u64,
+        large_balance_threshold: u64,
+        max_small_coins: usize,
+        max_large_coins: usize,
+        reserved_duration_ms: u64,
+    ) -> anyhow::Result<(Option<ReservationID>, Vec<GasCoin>, CoinBalanceDistribution)>;
+
+    /// Take all available coins out of the pool and return them.
+    /// Returns error without changing anything if there is any active reservation.
+    async fn drain_available_coins(&self) -> anyhow::Result<Vec<GasCoin>>;
 
     /// Initialize some of the gas pool statistics at the startup.
     /// Such as the total number of gas coins and the total balance.
@@ -58,47 +286,163 @@ pub trait Storage: Sync + Send {
 
     async fn release_init_lock(&self) -> anyhow::Result<()>;
 
+    /// Turn safe mode on or off for the sponsor address. While it is on, background tasks do not
+    /// submit transactions or change the coins of the pool, on every gas pool instance.
+    async fn set_safe_mode(&self, enabled: bool) -> anyhow::Result<()>;
+
+    async fn is_safe_mode(&self) -> anyhow::Result<bool>;
+
+    /// Store an opaque tag with the reservation, for downstream accounting. The tag outlives the
+    /// reservation, so that it can still be looked up once the reservation is executed.
+    async fn set_reservation_tag(
+        &self,
+        reservation_id: ReservationID,
+        tag: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Returns the tag of the reservation, if it has one.
+    async fn get_reservation_tag(
+        &self,
+        reservation_id: ReservationID,
+    ) -> anyhow::Result<Option<String>>;
+
+    /// Store the id of the client that made the reservation or budget lease. Like the tag, it
+    /// outlives the reservation.
+    async fn set_reservation_owner(
+        &self,
+        reservation_id: ReservationID,
+        client_id: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Returns the id of the client that made the reservation, if one was stored.
+    async fn get_reservation_owner(
+        &self,
+        reservation_id: ReservationID,
+    ) -> anyhow::Result<Option<String>>;
+
     async fn check_health(&self) -> anyhow::Result<()>;
 
+    /// A short name of the storage backend, e.g. for build info.
+    fn backend_name(&self) -> &'static str;
+
     async fn flush_db(&self);
 
     async fn get_available_coin_count(&self) -> anyhow::Result<usize>;
 
     async fn get_available_coin_total_balance(&self) -> u64;
 
+    /// Read all available coins along with the tracked total balance and coin count at once.
+    /// This is expensive with a large pool, and is only meant for periodic audits.
+    async fn get_available_coins_snapshot(&self) -> anyhow::Result<AvailableCoinsSnapshot>;
+
+    /// Read the available coins, active reservations and unclaimed budget leases at once, so that
+    /// no coin shows up twice or goes missing. Like `get_available_coins_snapshot`, this is
+    /// expensive with a large pool, and is only meant for debugging.
+    async fn get_pool_snapshot(&self) -> anyhow::Result<PoolSnapshot>;
+
+    /// The total budget committed to budget leases that have not been claimed yet.
+    async fn get_leased_budget(&self) -> anyhow::Result<u64>;
+
+    /// Append records to the audit log. They are kept until removed by `trim_audit_records`.
+    async fn append_audit_records(&self, records: Vec<AuditRecord>) -> anyhow::Result<()>;
+
+    /// Read up to `limit` audit records with from_ms <= timestamp_ms < to_ms, oldest first.
+    /// If a client id is given, only the records of that client are returned.
+    async fn query_audit_records(
+        &self,
+        from_ms: u64,
+        to_ms: u64,
+        client_id: Option<String>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuditRecord>>;
+
+    /// Remove the audit records with timestamp_ms < before_ms.
+    /// Returns the number of removed records.
+    async fn trim_audit_records(&self, before_ms: u64) -> anyhow::Result<u64>;
+
     #[cfg(test)]
     async fn get_reserved_coin_count(&self) -> usize;
+
+    /// The number of coins whose version change time is recorded for the prefer-stable-coins
+    /// strategy.
+    #[cfg(test)]
+    async fn get_coin_version_time_count(&self) -> usize;
+}
+
+/// The available coins in the pool, as read by `Storage::get_available_coins_snapshot`.
+/// The tracked totals are signed, so that an accounting bug that drives them negative shows up.
+#[derive(Clone, Debug)]
+pub struct AvailableCoinsSnapshot {
+    pub coins: Vec<GasCoin>,
+    pub tracked_total_balance: i64,
+    pub tracked_coin_count: i64,
+}
+
+/// The state of the pool, as read by `Storage::get_pool_snapshot`.
+#[derive(Clone, Debug)]
+pub struct PoolSnapshot {
+    pub available_coins: AvailableCoinsSnapshot,
+    pub reservations: Vec<ReservationSnapshot>,
+    pub budget_leases: Vec<BudgetLeaseSnapshot>,
+}
+
+/// A reservation whose transaction was submitted for execution, as recorded by
+/// `Storage::mark_executing`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExecutingReservation {
+    pub reservation_id: ReservationID,
+    pub digest: TransactionDigest,
+    pub reserved_coin_ids: Vec<ObjectID>,
 }
 
 pub async fn connect_storage(
     config: &GasPoolStorageConfig,
     sponsor_address: MysAddress,
+    coin_selection_strategy: CoinSelectionStrategy,
     metrics: Arc<StorageMetrics>,
 ) -> Arc<dyn Storage> {
-    let storage: Arc<dyn Storage> = match config {
-        GasPoolStorageConfig::Redis { redis_url } => {
-            Arc::new(RedisStorage::new(redis_url, sponsor_address, metrics).await)
-        }
-    };
-    storage
-        .check_health()
+    let storage = try_connect_storage(config, sponsor_address, coin_selection_strategy, metrics)
         .await
         .expect("Unable to connect to the storage layer");
     storage.init_coin_stats_at_startup().await.unwrap();
     storage
 }
 
+/// Connects to the storage and checks its health, without changing anything in it.
+pub async fn try_connect_storage(
+    config: &GasPoolStorageConfig,
+    sponsor_address: MysAddress,
+    coin_selection_strategy: CoinSelectionStrategy,
+    metrics: Arc<StorageMetrics>,
+) -> anyhow::Result<Arc<dyn Storage>> {
+    let storage: Arc<dyn Storage> = match config {
+        GasPoolStorageConfig::Redis { redis_url } => Arc::new(
+            RedisStorage::try_new(redis_url, sponsor_address, coin_selection_strategy, metrics)
+                .await?,
+        ),
+    };
+    storage.check_health().await?;
+    Ok(storage)
+}
+
 #[cfg(test)]
 pub async fn connect_storage_for_testing_with_config(
     config: &GasPoolStorageConfig,
     sponsor_address: MysAddress,
+    coin_selection_strategy: CoinSelectionStrategy,
 ) -> Arc<dyn Storage> {
     use std::sync::atomic::{AtomicBool, Ordering};
 
     static IS_FIRST_CALL: AtomicBool = AtomicBool::new(true);
     let is_first_call = IS_FIRST_CALL.fetch_and(false, Ordering::SeqCst);
 
-    let storage = connect_storage(config, sponsor_address, StorageMetrics::new_for_testing()).await;
+    let storage = connect_storage(
+        config,
+        sponsor_address,
+        coin_selection_strategy,
+        StorageMetrics::new_for_testing(),
+    )
+    .await;
     if is_first_call {
         // Make sure that we only flush the DB once at the beginning of each test run.
         storage.flush_db().await;
@@ -108,29 +452,69 @@ pub async fn connect_storage_for_testing_with_config(
     storage
 }
 
+/// Whether safe mode is on. It is assumed to be on if it cannot be read, so that background tasks
+/// never change the pool while its state is unknown.
+pub async fn safe_mode_is_on(storage: &dyn Storage) -> bool {
+    storage.is_safe_mode().await.unwrap_or_else(|err| {
+        error!("Failed to check whether safe mode is on: {:?}", err);
+        true
+    })
+}
+
 #[cfg(test)]
 pub async fn connect_storage_for_testing(sponsor_address: MysAddress) -> Arc<dyn Storage> {
-    connect_storage_for_testing_with_config(&GasPoolStorageConfig::default(), sponsor_address).await
+    connect_storage_for_testing_with_config(
+        &GasPoolStorageConfig::default(),
+        sponsor_address,
+        CoinSelectionStrategy::default(),
+    )
+    .await
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::storage::{connect_storage_for_testing, Storage, MAX_GAS_PER_QUERY};
-    use crate::types::GasCoin;
+    use crate::config::{CoinSelectionStrategy, GasPoolStorageConfig};
+    use crate::errors::GasStationError;
+    use crate::storage::{
+        conformance, connect_storage_for_testing, connect_storage_for_testing_with_config,
+        ExecutingReservation, Storage, MAX_GAS_PER_QUERY,
+    };
+    use crate::types::{AuditEvent, AuditRecord, CoinBalanceDistribution, GasCoin, ReservationID};
     use rand::random;
     use std::collections::BTreeSet;
     use std::sync::Arc;
     use std::time::Duration;
     use mys_types::base_types::{random_object_ref, ObjectID, SequenceNumber, MysAddress};
-    use mys_types::digests::ObjectDigest;
+    use mys_types::digests::{ObjectDigest, TransactionDigest};
 
     async fn assert_coin_count(storage: &Arc<dyn Storage>, available: usize, reserved: usize) {
         assert_eq!(storage.get_available_coin_count().await.unwrap(), available);
         assert_eq!(storage.get_reserved_coin_count().await, reserved);
     }
 
+    async fn expire_coin_ids(storage: &Arc<dyn Storage>) -> Vec<ObjectID> {
+        let expired_reservations = storage.expire_coins().await.unwrap();
+        expired_reservations
+            .into_iter()
+            .flat_map(|(_, coin_ids)| coin_ids)
+            .collect()
+    }
+
     async fn setup(sponsor: MysAddress, init_balances: Vec<u64>) -> Arc<dyn Storage> {
-        let storage = connect_storage_for_testing(sponsor).await;
+        setup_with_strategy(sponsor, init_balances, CoinSelectionStrategy::default()).await
+    }
+
+    async fn setup_with_strategy(
+        sponsor: MysAddress,
+        init_balances: Vec<u64>,
+        coin_selection_strategy: CoinSelectionStrategy,
+    ) -> Arc<dyn Storage> {
+        let storage = connect_storage_for_testing_with_config(
+            &GasPoolStorageConfig::default(),
+            sponsor,
+            coin_selection_strategy,
+        )
+        .await;
         let gas_coins = init_balances
             .into_iter()
             .map(|balance| GasCoin {
@@ -148,6 +532,11 @@ mod tests {
         storage
     }
 
+    #[tokio::test]
+    async fn test_storage_conformance() {
+        conformance::run_conformance_suite(connect_storage_for_testing).await;
+    }
+
     #[tokio::test]
     async fn test_gas_pool_init() {
         let sponsor = MysAddress::random_for_testing_only();
@@ -177,7 +566,7 @@ mod tests {
         for i in 1..=MAX_GAS_PER_QUERY {
             let (res_id, reserved_gas_coins) =
                 storage.reserve_gas_coins(i as u64, 1000).await.unwrap();
-            assert_eq!(expected_res_id, res_id);
+            assert_eq!(expected_res_id, res_id.value());
             assert_eq!(reserved_gas_coins.len(), i);
             expected_res_id += 1;
             cur_available -= i;
@@ -204,6 +593,139 @@ mod tests {
         assert_coin_count(&storage, 100, 0).await;
     }
 
+    #[tokio::test]
+    async fn test_preserve_large_coins() {
+        let balances = |coins: &[GasCoin]| coins.iter().map(|c| c.balance).collect::<Vec<_>>();
+        let strategy = CoinSelectionStrategy::PreserveLargeCoins {
+            max_single_coin_balance: 5,
+        };
+
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup_with_strategy(sponsor, vec![10, 1, 1, 1, 1, 20], strategy).await;
+        // The small coins cover the budget, so the large coin in front of them is kept.
+        let (_, coins) = storage.reserve_gas_coins(3, 1000).await.unwrap();
+        assert_eq!(balances(&coins), vec![1, 1, 1]);
+        assert_coin_count(&storage, 3, 3).await;
+        // The remaining small coin cannot cover the budget, so the oldest large coin is used alone.
+        let (_, coins) = storage.reserve_gas_coins(4, 1000).await.unwrap();
+        assert_eq!(balances(&coins), vec![10]);
+        assert_coin_count(&storage, 2, 4).await;
+        // Budgets above the threshold are reserved in FIFO order.
+        let (_, coins) = storage.reserve_gas_coins(21, 1000).await.unwrap();
+        assert_eq!(balances(&coins), vec![1, 20]);
+        assert_coin_count(&storage, 0, 6).await;
+
+        // Without the strategy, the large coin in front is reserved right away.
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![10, 1, 1, 1, 1, 20]).await;
+        let (_, coins) = storage.reserve_gas_coins(3, 1000).await.unwrap();
+        assert_eq!(balances(&coins), vec![10]);
+    }
+
+    #[tokio::test]
+    async fn test_prefer_stable_coins() {
+        let ids = |coins: &[GasCoin]| coins.iter().map(|c| c.object_ref.0).collect::<Vec<_>>();
+        let strategy = CoinSelectionStrategy::PreferStableCoins { min_stable_sec: 1 };
+
+        for strategy in [strategy, CoinSelectionStrategy::default()] {
+            let sponsor = MysAddress::random_for_testing_only();
+            let storage = setup_with_strategy(sponsor, vec![1; 3], strategy).await;
+            tokio::time::sleep(Duration::from_millis(1100)).await;
+            // The first coin pays for a transaction and comes back at a new version.
+            let (res_id, mut changed) = storage.reserve_gas_coins(1, 1000).await.unwrap();
+            let (id, version, digest) = changed[0].object_ref;
+            changed[0].object_ref = (id, SequenceNumber::from_u64(version.value() + 1), digest);
+            storage
+                .release_reserved_coins(res_id, ids(&changed), changed.clone())
+                .await
+                .unwrap();
+            // The second coin comes back unused, at the same version.
+            let (res_id, unchanged) = storage.reserve_gas_coins(1, 1000).await.unwrap();
+            storage
+                .release_reserved_coins(res_id, ids(&unchanged), unchanged.clone())
+                .await
+                .unwrap();
+
+            let (_, coins) = storage.reserve_gas_coins(2, 1000).await.unwrap();
+            let (_, rest) = storage.reserve_gas_coins(1, 1000).await.unwrap();
+            if strategy == CoinSelectionStrategy::default() {
+                // Coins are reserved in the order they were released.
+                assert_eq!(ids(&coins)[1], ids(&changed)[0]);
+                assert_eq!(ids(&rest), ids(&unchanged));
+            } else {
+                // The coin that changed is passed over while the stable coins cover the budget.
+                assert_eq!(ids(&coins)[1], ids(&unchanged)[0]);
+                assert_eq!(ids(&rest), ids(&changed));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_coin_version_times_pruned() {
+        let ids = |coins: &[GasCoin]| coins.iter().map(|c| c.object_ref.0).collect::<Vec<_>>();
+        let strategy = CoinSelectionStrategy::PreferStableCoins { min_stable_sec: 1 };
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup_with_strategy(sponsor, vec![1; 5], strategy).await;
+        assert_eq!(storage.get_coin_version_time_count().await, 5);
+        // Two of the reserved coins are merged away, so only one comes back.
+        let (res_id, coins) = storage.reserve_gas_coins(3, 1000).await.unwrap();
+        storage
+            .release_reserved_coins(res_id, ids(&coins), coins[..1].to_vec())
+            .await
+            .unwrap();
+        assert_eq!(storage.get_coin_version_time_count().await, 3);
+
+        // Nothing is recorded when the strategy does not need it.
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 5]).await;
+        let (res_id, coins) = storage.reserve_gas_coins(3, 1000).await.unwrap();
+        storage
+            .release_reserved_coins(res_id, ids(&coins), coins)
+            .await
+            .unwrap();
+        assert_eq!(storage.get_coin_version_time_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_partial_reservation() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 100]).await;
+        let (_, coins) = storage
+            .reserve_partial_gas_coins_with_callback(60, 1000, None)
+            .await
+            .unwrap();
+        assert_eq!(coins.len(), 60);
+        // Only 40 coins are left, so only those are reserved.
+        let (_, coins) = storage
+            .reserve_partial_gas_coins_with_callback(60, 1000, None)
+            .await
+            .unwrap();
+        assert_eq!(coins.len(), 40);
+        assert_coin_count(&storage, 0, 100).await;
+        assert!(storage
+            .reserve_partial_gas_coins_with_callback(1, 1000, None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_partial_reservation_with_budget_lease() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 100]).await;
+        storage.reserve_budget_lease(70, 900, None).await.unwrap();
+        // The leased budget is never handed out to partial reservations.
+        let (_, coins) = storage
+            .reserve_partial_gas_coins_with_callback(50, 1000, None)
+            .await
+            .unwrap();
+        assert_eq!(coins.len(), 30);
+        assert!(storage
+            .reserve_partial_gas_coins_with_callback(1, 1000, None)
+            .await
+            .is_err());
+        assert_coin_count(&storage, 70, 30).await;
+    }
+
     #[tokio::test]
     async fn test_coin_release() {
         let sponsor = MysAddress::random_for_testing_only();
@@ -258,6 +780,192 @@ mod tests {
         assert_coin_count(&storage, 50, 0).await;
     }
 
+    #[tokio::test]
+    async fn test_release_reserved_coins() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 10]).await;
+        let (res_id, coins) = storage.reserve_gas_coins(5, 900).await.unwrap();
+        let coin_ids: Vec<_> = coins.iter().map(|c| c.object_ref.0).collect();
+        storage.ready_for_execution(res_id).await.unwrap();
+
+        // Coins missing from the latest coins were deleted and are only forgotten.
+        let released = storage
+            .release_reserved_coins(res_id, coin_ids.clone(), coins[..3].to_vec())
+            .await
+            .unwrap();
+        assert_eq!(released, coins[..3].to_vec());
+        assert_coin_count(&storage, 8, 0).await;
+        assert_eq!(storage.get_available_coin_total_balance().await, 8);
+
+        // Releasing the same coins again, or under another reservation, adds nothing.
+        assert!(storage
+            .release_reserved_coins(res_id, coin_ids.clone(), coins.clone())
+            .await
+            .unwrap()
+            .is_empty());
+        storage.reserve_gas_coins(8, 900).await.unwrap();
+        assert!(storage
+            .release_reserved_coins(res_id, coin_ids, coins[..3].to_vec())
+            .await
+            .unwrap()
+            .is_empty());
+        assert_coin_count(&storage, 0, 8).await;
+    }
+
+    #[tokio::test]
+    async fn test_stranded_coins_after_crash() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 10]).await;
+        let (res_id1, coins1) = storage.reserve_gas_coins(3, 900).await.unwrap();
+        let (res_id2, coins2) = storage.reserve_gas_coins(3, 900).await.unwrap();
+        let (res_id3, coins3) = storage.reserve_gas_coins(3, 900).await.unwrap();
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        // The gas pool crashes after taking out the first reservation for execution, and after
+        // expiring the other ones, before releasing any coin.
+        storage.ready_for_execution(res_id1).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(expire_coin_ids(&storage).await.len(), 6);
+        assert_coin_count(&storage, 1, 0).await;
+
+        // Coins that were reserved recently may still be in use by another gas pool instance.
+        assert!(storage
+            .get_stranded_coins(now - 60_000)
+            .await
+            .unwrap()
+            .is_empty());
+        let coin_ids = |coins: &[GasCoin]| {
+            coins
+                .iter()
+                .map(|c| c.object_ref.0)
+                .collect::<BTreeSet<_>>()
+        };
+        let stranded_coins = storage.get_stranded_coins(now + 1).await.unwrap();
+        assert_eq!(
+            stranded_coins
+                .iter()
+                .map(|(res_id, ids)| (*res_id, ids.iter().cloned().collect()))
+                .collect::<Vec<_>>(),
+            vec![
+                (res_id1, coin_ids(&coins1)),
+                (res_id2, coin_ids(&coins2)),
+                (res_id3, coin_ids(&coins3)),
+            ]
+        );
+
+        let all_coins: Vec<_> = coins1.into_iter().chain(coins2).chain(coins3).collect();
+        for (res_id, ids) in stranded_coins {
+            storage
+                .release_reserved_coins(res_id, ids, all_coins.clone())
+                .await
+                .unwrap();
+        }
+        assert_coin_count(&storage, 10, 0).await;
+        assert!(storage
+            .get_stranded_coins(now + 1)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_executing_reservations() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 10]).await;
+        let (res_id, coins) = storage.reserve_gas_coins(3, 900).await.unwrap();
+        let coin_ids: Vec<_> = coins.iter().map(|c| c.object_ref.0).collect();
+        let digest = TransactionDigest::random();
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        storage.ready_for_execution(res_id).await.unwrap();
+        storage
+            .mark_executing(res_id, digest, coin_ids.clone())
+            .await
+            .unwrap();
+
+        // The transaction may still be executing on another gas pool instance.
+        assert!(storage
+            .get_executing_reservations(now - 60_000)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            storage.get_executing_reservations(now + 1000).await.unwrap(),
+            vec![ExecutingReservation {
+                reservation_id: res_id,
+                digest,
+                reserved_coin_ids: coin_ids.clone(),
+            }]
+        );
+
+        // Releasing the coins drops the record.
+        storage
+            .release_reserved_coins(res_id, coin_ids, coins)
+            .await
+            .unwrap();
+        assert_coin_count(&storage, 10, 0).await;
+        assert!(storage
+            .get_executing_reservations(now + 1000)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_release_unused_coins_of_executing_reservation() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 10]).await;
+        let (res_id, coins) = storage.reserve_gas_coins(3, 900).await.unwrap();
+        let (added, _) = storage
+            .add_client_reservation(res_id, "client", 3, 10, 10)
+            .await
+            .unwrap();
+        assert!(added);
+        let payment = vec![coins[0].object_ref.0];
+        let digest = TransactionDigest::random();
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        storage.ready_for_execution(res_id).await.unwrap();
+        storage
+            .mark_executing(res_id, digest, payment.clone())
+            .await
+            .unwrap();
+
+        // The coins the transaction does not pay with go back, e.g. after the submission timed out.
+        let unused_coins = coins[1..].to_vec();
+        let unused_coin_ids = unused_coins.iter().map(|c| c.object_ref.0).collect();
+        storage
+            .release_unused_coins(res_id, unused_coin_ids, unused_coins)
+            .await
+            .unwrap();
+        assert_coin_count(&storage, 9, 1).await;
+        // The reservation is still open, so that recovery can release the payment coins.
+        assert_eq!(
+            storage.get_executing_reservations(now + 1000).await.unwrap(),
+            vec![ExecutingReservation {
+                reservation_id: res_id,
+                digest,
+                reserved_coin_ids: payment.clone(),
+            }]
+        );
+        assert_eq!(
+            storage.get_client_usage("client").await.unwrap().active_reservations,
+            1
+        );
+
+        storage
+            .release_reserved_coins(res_id, payment, coins[..1].to_vec())
+            .await
+            .unwrap();
+        assert_coin_count(&storage, 10, 0).await;
+        assert!(storage
+            .get_executing_reservations(now + 1000)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            storage.get_client_usage("client").await.unwrap().active_reservations,
+            0
+        );
+    }
+
     #[tokio::test]
     async fn test_coin_expiration() {
         let sponsor = MysAddress::random_for_testing_only();
@@ -272,10 +980,10 @@ mod tests {
         assert_eq!(reserved_gas_coins3.len(), 50);
         assert_coin_count(&storage, 10, 90).await;
 
-        assert!(storage.expire_coins().await.unwrap().is_empty());
+        assert!(expire_coin_ids(&storage).await.is_empty());
         assert_coin_count(&storage, 10, 90).await;
         tokio::time::sleep(Duration::from_secs(1)).await;
-        let expired1 = storage.expire_coins().await.unwrap();
+        let expired1 = expire_coin_ids(&storage).await;
         assert_eq!(expired1.len(), 10);
         assert_eq!(
             expired1.iter().cloned().collect::<BTreeSet<_>>(),
@@ -286,11 +994,11 @@ mod tests {
         );
         assert_coin_count(&storage, 10, 80).await;
 
-        assert!(storage.expire_coins().await.unwrap().is_empty());
+        assert!(expire_coin_ids(&storage).await.is_empty());
         assert_coin_count(&storage, 10, 80).await;
         tokio::time::sleep(Duration::from_secs(1)).await;
 
-        let expired2 = storage.expire_coins().await.unwrap();
+        let expired2 = expire_coin_ids(&storage).await;
         assert_eq!(expired2.len(), 80);
         assert_eq!(
             expired2.iter().cloned().collect::<BTreeSet<_>>(),
@@ -303,6 +1011,316 @@ mod tests {
         assert_coin_count(&storage, 10, 0).await;
     }
 
+    #[tokio::test]
+    async fn test_reservation_callbacks() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 100]).await;
+        let (res_id1, _) = storage
+            .reserve_gas_coins_with_callback(10, 900, Some("https://a.com/x,y".to_string()))
+            .await
+            .unwrap();
+        let (res_id2, _) = storage
+            .reserve_gas_coins_with_callback(10, 900, Some("https://b.com".to_string()))
+            .await
+            .unwrap();
+        let (res_id3, _) = storage.reserve_gas_coins(10, 900).await.unwrap();
+        assert_eq!(
+            storage.ready_for_execution(res_id2).await.unwrap(),
+            Some("https://b.com".to_string())
+        );
+        assert_eq!(storage.ready_for_execution(res_id3).await.unwrap(), None);
+        assert!(storage
+            .take_expired_reservation_callbacks()
+            .await
+            .unwrap()
+            .is_empty());
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(expire_coin_ids(&storage).await.len(), 10);
+        assert_eq!(
+            storage.take_expired_reservation_callbacks().await.unwrap(),
+            vec![(res_id1, "https://a.com/x,y".to_string())]
+        );
+        // Callbacks are only handed out once.
+        assert!(storage
+            .take_expired_reservation_callbacks()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_budget_lease() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 100]).await;
+        let lease_id1 = storage.reserve_budget_lease(60, 900, None).await.unwrap();
+        // Leases do not take coins out of the pool, but their budget is no longer available.
+        assert_coin_count(&storage, 100, 0).await;
+        assert_eq!(storage.get_leased_budget().await.unwrap(), 60);
+        assert!(storage.reserve_budget_lease(41, 900, None).await.is_err());
+        assert!(storage.reserve_gas_coins(41, 900).await.is_err());
+        let (res_id, _) = storage.reserve_gas_coins(40, 900).await.unwrap();
+        assert_ne!(res_id, lease_id1);
+        assert!(storage.drain_available_coins().await.is_err());
+
+        let coins = storage.claim_budget_lease(lease_id1, 900).await.unwrap();
+        assert_eq!(coins.len(), 60);
+        assert_coin_count(&storage, 0, 100).await;
+        assert_eq!(storage.get_leased_budget().await.unwrap(), 0);
+        // A lease can only be claimed once.
+        assert!(storage.claim_budget_lease(lease_id1, 900).await.is_err());
+        // The claimed lease is a regular reservation.
+        storage.ready_for_execution(lease_id1).await.unwrap();
+        storage.add_new_coins(coins).await.unwrap();
+
+        // Unclaimed leases expire and give back their budget.
+        let lease_id2 = storage.reserve_budget_lease(60, 900, None).await.unwrap();
+        assert_eq!(storage.get_leased_budget().await.unwrap(), 60);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(expire_coin_ids(&storage).await.len(), 40);
+        assert_eq!(storage.get_leased_budget().await.unwrap(), 0);
+        assert!(storage.claim_budget_lease(lease_id2, 900).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ready_for_execution_expiry_margin() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 100]).await;
+        let (res_id1, _) = storage.reserve_gas_coins(10, 900).await.unwrap();
+        let (res_id2, _) = storage.reserve_gas_coins(10, 900).await.unwrap();
+        let err = storage
+            .ready_for_execution_with_expiry_margin(res_id1, 1000)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::ReservationTooCloseToExpiry(id, 1000)) if *id == res_id1
+        ));
+        storage
+            .ready_for_execution_with_expiry_margin(res_id2, 100)
+            .await
+            .unwrap();
+        // The rejected reservation is left untouched and expires as usual.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(expire_coin_ids(&storage).await.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_ready_for_execution_with_payment() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 100]).await;
+        let (res_id1, gas_coins1) = storage.reserve_gas_coins(3, 1000).await.unwrap();
+        let (_, gas_coins2) = storage.reserve_gas_coins(1, 1000).await.unwrap();
+        let coin_ids1: Vec<_> = gas_coins1.iter().map(|coin| coin.object_ref.0).collect();
+        // A payment coin of another reservation is rejected, leaving the reservation untouched.
+        let err = storage
+            .ready_for_execution_with_payment(
+                res_id1,
+                0,
+                vec![coin_ids1[0], gas_coins2[0].object_ref.0],
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::InvalidSponsoredTransaction(_))
+        ));
+        // Paying with some of the reserved coins returns all of them.
+        let (callback_url, reserved_coin_ids) = storage
+            .ready_for_execution_with_payment(res_id1, 0, vec![coin_ids1[0]])
+            .await
+            .unwrap();
+        assert_eq!(callback_url, None);
+        assert_eq!(
+            reserved_coin_ids.into_iter().collect::<BTreeSet<_>>(),
+            coin_ids1.into_iter().collect::<BTreeSet<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancel_reservation() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 100]).await;
+        let (res_id, reserved_gas_coins) = storage
+            .reserve_gas_coins_with_callback(10, 900, Some("https://a.com".to_string()))
+            .await
+            .unwrap();
+        let (coin_ids, callback_url) = storage.cancel_reservation(res_id).await.unwrap();
+        assert_eq!(
+            coin_ids.into_iter().collect::<BTreeSet<_>>(),
+            reserved_gas_coins
+                .iter()
+                .map(|coin| coin.object_ref.0)
+                .collect::<BTreeSet<_>>()
+        );
+        assert_eq!(callback_url, Some("https://a.com".to_string()));
+        // Cancelling again succeeds without returning the coins a second time.
+        assert_eq!(storage.cancel_reservation(res_id).await.unwrap(), (vec![], None));
+        let err = storage.ready_for_execution(res_id).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::ReservationNotFound(_))
+        ));
+        // The cancelled reservation does not expire again.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(expire_coin_ids(&storage).await.is_empty());
+        assert!(storage
+            .take_expired_reservation_callbacks()
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Unclaimed budget leases give back their budget.
+        let lease_id = storage.reserve_budget_lease(60, 900, None).await.unwrap();
+        assert_eq!(storage.cancel_reservation(lease_id).await.unwrap(), (vec![], None));
+        assert_eq!(storage.get_leased_budget().await.unwrap(), 0);
+        assert!(storage.claim_budget_lease(lease_id, 900).await.is_err());
+
+        // Executed reservations cannot be cancelled.
+        let (res_id, _) = storage.reserve_gas_coins(10, 900).await.unwrap();
+        storage.ready_for_execution(res_id).await.unwrap();
+        let err = storage.cancel_reservation(res_id).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::ReservationNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reserve_coins_for_rebalance() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let mut balances = vec![1; 5];
+        balances.extend(vec![10; 10]);
+        balances.extend(vec![100; 3]);
+        let storage = setup(sponsor, balances).await;
+        let (res_id, coins, distribution) = storage
+            .reserve_coins_for_rebalance(5, 50, 3, 1, 900)
+            .await
+            .unwrap();
+        assert_eq!(
+            distribution,
+            CoinBalanceDistribution {
+                coin_count: 18,
+                small_coin_count: 5,
+                large_coin_count: 3,
+            }
+        );
+        let mut balances: Vec<_> = coins.iter().map(|c| c.balance).collect();
+        balances.sort();
+        assert_eq!(balances, vec![1, 1, 1, 100]);
+        assert_coin_count(&storage, 14, 4).await;
+        assert_eq!(storage.get_available_coin_total_balance().await, 302);
+
+        // Nothing is reserved when all coins are within the thresholds.
+        let (res_id2, coins2, _) = storage
+            .reserve_coins_for_rebalance(0, u64::MAX, 10, 10, 900)
+            .await
+            .unwrap();
+        assert!(res_id2.is_none());
+        assert!(coins2.is_empty());
+
+        // The reserved coins are released like any other reservation.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(expire_coin_ids(&storage).await.len(), 4);
+        assert!(storage.ready_for_execution(res_id.unwrap()).await.is_err());
+
+        // Budget committed to leases stays in the pool.
+        storage.reserve_budget_lease(300, 900, None).await.unwrap();
+        let (_, coins, _) = storage
+            .reserve_coins_for_rebalance(5, 50, 10, 10, 900)
+            .await
+            .unwrap();
+        assert_eq!(coins.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reserve_requested_coins() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![]).await;
+        let coins: Vec<_> = [5, 10, 20]
+            .into_iter()
+            .map(|balance| GasCoin {
+                object_ref: random_object_ref(),
+                balance,
+            })
+            .collect();
+        storage.add_new_coins(coins.clone()).await.unwrap();
+        let coin_ids: Vec<_> = coins.iter().map(|c| c.object_ref.0).collect();
+
+        // Available coins are reserved, no matter which coins would be picked otherwise.
+        let (res_id, reserved) = storage
+            .reserve_requested_coins(vec![coin_ids[0], coin_ids[1]], 15, 900, None)
+            .await
+            .unwrap();
+        assert_eq!(reserved, coins[..2].to_vec());
+        assert_coin_count(&storage, 1, 2).await;
+        assert_eq!(storage.get_available_coin_total_balance().await, 20);
+
+        // Reserved coins cannot be requested, and nothing else is reserved in that case.
+        let err = storage
+            .reserve_requested_coins(vec![coin_ids[2], coin_ids[1]], 15, 900, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::RequestedCoinReserved(id)) if *id == coin_ids[1]
+        ));
+        assert_coin_count(&storage, 1, 2).await;
+
+        // Unknown coins cannot be requested.
+        let unknown_id = ObjectID::random();
+        let err = storage
+            .reserve_requested_coins(vec![coin_ids[2], unknown_id], 15, 900, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::RequestedCoinNotFound(id)) if *id == unknown_id
+        ));
+
+        // The requested coins must cover the budget.
+        let err = storage
+            .reserve_requested_coins(vec![coin_ids[2]], 21, 900, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GasStationError>(),
+            Some(GasStationError::InvalidRequest(_))
+        ));
+        assert_coin_count(&storage, 1, 2).await;
+
+        // The coins are released like any other reservation.
+        storage.ready_for_execution(res_id).await.unwrap();
+        storage
+            .release_reserved_coins(res_id, coin_ids[..2].to_vec(), reserved)
+            .await
+            .unwrap();
+        assert_coin_count(&storage, 3, 0).await;
+        storage
+            .reserve_requested_coins(vec![coin_ids[1]], 10, 900, None)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drain_available_coins() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 100]).await;
+        let (_, reserved_gas_coins) = storage.reserve_gas_coins(10, 900).await.unwrap();
+        // Cannot drain while there is an active reservation.
+        assert!(storage.drain_available_coins().await.is_err());
+        assert_coin_count(&storage, 90, 10).await;
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(expire_coin_ids(&storage).await.len(), 10);
+        storage.add_new_coins(reserved_gas_coins).await.unwrap();
+        let drained = storage.drain_available_coins().await.unwrap();
+        assert_eq!(drained.len(), 100);
+        assert_coin_count(&storage, 0, 0).await;
+        assert_eq!(storage.get_available_coin_total_balance().await, 0);
+        assert!(storage.reserve_gas_coins(1, 1000).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_multiple_sponsors() {
         let sponsors = (0..10)
@@ -357,6 +1375,58 @@ mod tests {
         assert!(storage.acquire_init_lock(5).await.unwrap());
     }
 
+    #[tokio::test]
+    async fn test_safe_mode() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 10]).await;
+        assert!(!storage.is_safe_mode().await.unwrap());
+        storage.set_safe_mode(true).await.unwrap();
+        assert!(storage.is_safe_mode().await.unwrap());
+        // Safe mode is per sponsor address.
+        let other_storage = setup(MysAddress::random_for_testing_only(), vec![]).await;
+        assert!(!other_storage.is_safe_mode().await.unwrap());
+        storage.set_safe_mode(false).await.unwrap();
+        assert!(!storage.is_safe_mode().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_reservation_tag() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 10]).await;
+        let (reservation_id, _) = storage.reserve_gas_coins(1, 1000).await.unwrap();
+        assert_eq!(storage.get_reservation_tag(reservation_id).await.unwrap(), None);
+        storage
+            .set_reservation_tag(reservation_id, "team-a:checkout")
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get_reservation_tag(reservation_id).await.unwrap(),
+            Some("team-a:checkout".to_string())
+        );
+        // The tag is still there once the coins are released.
+        storage.cancel_reservation(reservation_id).await.unwrap();
+        assert_eq!(
+            storage.get_reservation_tag(reservation_id).await.unwrap(),
+            Some("team-a:checkout".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reservation_owner() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = setup(sponsor, vec![1; 10]).await;
+        let (reservation_id, _) = storage.reserve_gas_coins(1, 1000).await.unwrap();
+        assert_eq!(storage.get_reservation_owner(reservation_id).await.unwrap(), None);
+        storage
+            .set_reservation_owner(reservation_id, "client-a")
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get_reservation_owner(reservation_id).await.unwrap(),
+            Some("client-a".to_string())
+        );
+    }
+
     #[tokio::test]
     async fn test_init_coin_stats_idempotent() {
         let sponsor = MysAddress::random_for_testing_only();
@@ -367,4 +1437,52 @@ mod tests {
         assert_eq!(coin_count, 100);
         assert_eq!(total_balance, 100);
     }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let sponsor = MysAddress::random_for_testing_only();
+        let storage = connect_storage_for_testing(sponsor).await;
+        let record = |timestamp_ms: u64, client_id: &str| AuditRecord {
+            timestamp_ms,
+            client_id: Some(client_id.to_string()),
+            reservation_id: ReservationID::new(timestamp_ms).unwrap(),
+            tag: None,
+            event: AuditEvent::Reserve {
+                gas_budget: 1,
+                gas_coins: vec![ObjectID::random()],
+            },
+        };
+        storage
+            .append_audit_records(vec![record(1, "a"), record(2, "b"), record(3, "a")])
+            .await
+            .unwrap();
+        storage
+            .append_audit_records(vec![record(4, "b")])
+            .await
+            .unwrap();
+
+        let timestamps = |records: Vec<AuditRecord>| -> Vec<u64> {
+            records.into_iter().map(|r| r.timestamp_ms).collect()
+        };
+        let records = storage.query_audit_records(0, 10, None, 100).await.unwrap();
+        assert_eq!(timestamps(records), vec![1, 2, 3, 4]);
+        let records = storage.query_audit_records(2, 4, None, 100).await.unwrap();
+        assert_eq!(timestamps(records), vec![2, 3]);
+        let records = storage.query_audit_records(0, 10, None, 3).await.unwrap();
+        assert_eq!(timestamps(records), vec![1, 2, 3]);
+        let records = storage
+            .query_audit_records(0, 10, Some("a".to_string()), 100)
+            .await
+            .unwrap();
+        assert_eq!(timestamps(records), vec![1, 3]);
+        let records = storage
+            .query_audit_records(0, 10, Some("b".to_string()), 1)
+            .await
+            .unwrap();
+        assert_eq!(timestamps(records), vec![2]);
+
+        assert_eq!(storage.trim_audit_records(3).await.unwrap(), 2);
+        let records = storage.query_audit_records(0, 10, None, 100).await.unwrap();
+        assert_eq!(timestamps(records), vec![3, 4]);
+    }
 }
@@ -0,0 +1,39 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to count a new reservation against the outstanding reserved budget and the active reservation
+-- count of the client that made it, unless that would push either of them over its cap.
+-- The reservation stays counted until its coins are released by release_reserved_coins.lua. A budget lease that is
+-- never claimed is uncounted when it is cancelled or expires instead.
+-- The first argument is the sponsor's address.
+-- The second argument is the reservation id.
+-- The third argument is the client id.
+-- The fourth argument is the budget of the reservation.
+-- The fifth argument is the cap on the outstanding reserved budget of the client.
+-- The sixth argument is the cap on the number of active reservations of the client.
+-- Returns a table with whether the reservation was counted (1 or 0), the outstanding reserved budget of the client, and
+-- the number of active reservations of the client.
+
+local sponsor_address = ARGV[1]
+local reservation_id = ARGV[2]
+local client_id = ARGV[3]
+local budget = ARGV[4]
+local max_outstanding_budget = tonumber(ARGV[5])
+local max_active_reservations = tonumber(ARGV[6])
+
+local t_client_reserved_budgets = sponsor_address .. ':client_reserved_budgets'
+local t_client_reservation_counts = sponsor_address .. ':client_reservation_counts'
+local t_reservation_clients = sponsor_address .. ':reservation_clients'
+
+local outstanding_budget = tonumber(redis.call('HGET', t_client_reserved_budgets, client_id) or 0)
+local active_reservations = tonumber(redis.call('HGET', t_client_reservation_counts, client_id) or 0)
+if outstanding_budget + tonumber(budget) > max_outstanding_budget or active_reservations >= max_active_reservations then
+    return {0, outstanding_budget, active_reservations}
+end
+
+local new_outstanding_budget = redis.call('HINCRBY', t_client_reserved_budgets, client_id, budget)
+local new_active_reservations = redis.call('HINCRBY', t_client_reservation_counts, client_id, 1)
+redis.call('HSET', t_reservation_clients, reservation_id, budget .. ',' .. client_id)
+
+return {1, new_outstanding_budget, new_active_reservations}
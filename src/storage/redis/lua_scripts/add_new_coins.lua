@@ -6,14 +6,21 @@
 -- The second argument is a JSON array of new coins.
 -- Each coin is just a string, using "," to separate these fields:
 --   balance, object id, object version, object digest.
--- In this script we don't care about the format, just push each to the queue.
+-- Each coin is pushed to the back of the queue.
 -- We also set the initialized flag to 1 if we added any coins.
+-- The third argument is the current time, in milliseconds.
+-- The fourth argument is '1' if the prefer-stable-coins strategy is used. In that case, the coin_version_times map
+-- records the time each coin was first added at its current version, which reserve_gas_coins.lua uses to tell how long
+-- its version has been stable. Otherwise the records of the coins are removed.
 -- Returns a table with the new total balance and new coin count.
 
 local sponsor_address = ARGV[1]
 local new_coins = ARGV[2]
+local current_time = ARGV[3]
+local track_version_times = ARGV[4] == '1'
 
 local t_available_gas_coins = sponsor_address .. ':available_gas_coins'
+local t_coin_version_times = sponsor_address .. ':coin_version_times'
 
 local decoded_new_coins = cjson.decode(new_coins)
 local count = #decoded_new_coins
@@ -22,9 +29,22 @@ local total_balance = 0
 for i = 1, count, 1 do
     local coin = decoded_new_coins[i]
     local idx1, _ = string.find(coin, ',', 1)
+    local idx2, _ = string.find(coin, ',', idx1 + 1)
+    local idx3, _ = string.find(coin, ',', idx2 + 1)
     local balance = string.sub(coin, 1, idx1 - 1)
     total_balance = total_balance + tonumber(balance)
 
+    local object_id = string.sub(coin, idx1 + 1, idx2 - 1)
+    local version = string.sub(coin, idx2 + 1, idx3 - 1)
+    if track_version_times then
+        local record = redis.call('HGET', t_coin_version_times, object_id)
+        if not record or string.sub(record, 1, string.find(record, ',', 1) - 1) ~= version then
+            redis.call('HSET', t_coin_version_times, object_id, version .. ',' .. current_time)
+        end
+    else
+        redis.call('HDEL', t_coin_version_times, object_id)
+    end
+
     redis.call('RPUSH', t_available_gas_coins, coin)
 end
 
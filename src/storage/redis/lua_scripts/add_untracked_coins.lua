@@ -0,0 +1,73 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to add coins discovered on chain that the gas pool does not know about yet.
+-- A coin is known if its object id is in the available_gas_coins list, in any active reservation, or in the
+-- coin_reservations map, which also covers coins that are being used in a transaction.
+-- Known coins are never touched, so reserved coins are not disturbed.
+-- This scans the whole list, so it should only be called on demand.
+-- The first argument is the sponsor's address.
+-- The second argument is a JSON array of candidate coins, in the same format as add_new_coins.lua.
+-- The third argument is "1" to only return the untracked coins without adding them.
+-- Returns the untracked coins, which were added unless this is a dry run.
+
+local sponsor_address = ARGV[1]
+local candidate_coins = cjson.decode(ARGV[2])
+local dry_run = ARGV[3] == '1'
+
+local t_available_gas_coins = sponsor_address .. ':available_gas_coins'
+local t_expiration_queue = sponsor_address .. ':expiration_queue'
+
+local function get_object_id(coin)
+    local idx1, _ = string.find(coin, ',', 1)
+    local idx2, _ = string.find(coin, ',', idx1 + 1)
+    return string.sub(coin, idx1 + 1, idx2 - 1)
+end
+
+local tracked = {}
+for _, coin in ipairs(redis.call('LRANGE', t_available_gas_coins, 0, -1)) do
+    tracked[get_object_id(coin)] = true
+end
+for _, reservation_id in ipairs(redis.call('ZRANGE', t_expiration_queue, 0, -1)) do
+    local object_ids = redis.call('GET', sponsor_address .. ':' .. reservation_id)
+    if object_ids then
+        for object_id in string.gmatch(object_ids, '[^,]+') do
+            tracked[object_id] = true
+        end
+    end
+end
+
+local t_coin_reservations = sponsor_address .. ':coin_reservations'
+
+local untracked_coins = {}
+local total_balance = 0
+for _, coin in ipairs(candidate_coins) do
+    local object_id = get_object_id(coin)
+    if not tracked[object_id] and redis.call('HEXISTS', t_coin_reservations, object_id) == 0 then
+        -- Guard against the same coin showing up twice in the candidates.
+        tracked[object_id] = true
+        table.insert(untracked_coins, coin)
+        local idx1, _ = string.find(coin, ',', 1)
+        total_balance = total_balance + tonumber(string.sub(coin, 1, idx1 - 1))
+    end
+end
+
+if dry_run or #untracked_coins == 0 then
+    return untracked_coins
+end
+
+for _, coin in ipairs(untracked_coins) do
+    redis.call('RPUSH', t_available_gas_coins, coin)
+end
+redis.call('SET', sponsor_address .. ':initialized', 1)
+
+local t_available_coin_total_balance = sponsor_address .. ':available_coin_total_balance'
+local cur_coin_total_balance = tonumber(redis.call('GET', t_available_coin_total_balance) or 0)
+redis.call('SET', t_available_coin_total_balance, cur_coin_total_balance + total_balance)
+
+local t_available_coin_count = sponsor_address .. ':available_coin_count'
+local cur_coin_count = tonumber(redis.call('GET', t_available_coin_count) or 0)
+redis.call('SET', t_available_coin_count, cur_coin_count + #untracked_coins)
+
+return untracked_coins
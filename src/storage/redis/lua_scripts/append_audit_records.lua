@@ -0,0 +1,15 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to append records to the audit log of a sponsor address.
+-- The first argument is the sponsor's address.
+-- The following arguments come in pairs of a timestamp in milliseconds and a JSON encoded record.
+-- The audit log is a sorted set of records ordered by timestamp.
+
+local sponsor_address = ARGV[1]
+
+local t_audit_log = sponsor_address .. ':audit_log'
+for i = 2, #ARGV, 2 do
+    redis.call('ZADD', t_audit_log, ARGV[i], ARGV[i + 1])
+end
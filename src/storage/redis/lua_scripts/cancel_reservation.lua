@@ -0,0 +1,76 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to cancel a reservation or an unclaimed budget lease before it expires.
+-- It takes out the reservation from the sponsor's reservation map and returns its coins, so that the caller can
+-- release them right away. The budget of a cancelled lease is returned to the pool, and the lease is uncounted against
+-- the usage of the client that made it, since it has no coins to be released by release_reserved_coins.lua.
+-- The reservation id is remembered as cancelled for a while, so that cancelling it again succeeds without
+-- returning any coins.
+-- The first argument is the sponsor's address.
+-- The second argument is the reservation id.
+-- The third argument is how long the cancellation is remembered, in seconds.
+-- Returns a table with 1 if the reservation exists or was already cancelled and 0 otherwise, followed by the comma
+-- separated object ids of the reserved coins and the callback URL of the reservation, or empty strings if there
+-- are none.
+
+local sponsor_address = ARGV[1]
+local reservation_id = ARGV[2]
+local cancelled_ttl_sec = tonumber(ARGV[3])
+
+local key = sponsor_address .. ':' .. reservation_id
+local t_cancelled = sponsor_address .. ':cancelled_reservation:' .. reservation_id
+local t_expiration_queue = sponsor_address .. ':expiration_queue'
+local t_reservation_callbacks = sponsor_address .. ':reservation_callbacks'
+local t_leases = sponsor_address .. ':budget_leases'
+local t_lease_expiration_queue = sponsor_address .. ':lease_expiration_queue'
+local t_leased_budget = sponsor_address .. ':leased_budget'
+local t_reservation_clients = sponsor_address .. ':reservation_clients'
+local t_client_reserved_budgets = sponsor_address .. ':client_reserved_budgets'
+local t_client_reservation_counts = sponsor_address .. ':client_reservation_counts'
+
+-- Uncounts a budget lease against the usage of the client that made it, if it was counted.
+local function uncount_lease(lease_id)
+    local client_record = redis.call('HGET', t_reservation_clients, lease_id)
+    if not client_record then
+        return
+    end
+    redis.call('HDEL', t_reservation_clients, lease_id)
+    local idx, _ = string.find(client_record, ',', 1)
+    local budget = string.sub(client_record, 1, idx - 1)
+    local client_id = string.sub(client_record, idx + 1)
+    if redis.call('HINCRBY', t_client_reserved_budgets, client_id, '-' .. budget) <= 0 then
+        redis.call('HDEL', t_client_reserved_budgets, client_id)
+    end
+    if redis.call('HINCRBY', t_client_reservation_counts, client_id, -1) <= 0 then
+        redis.call('HDEL', t_client_reservation_counts, client_id)
+    end
+end
+
+local object_ids = redis.call('GET', key)
+if object_ids then
+    redis.call('DEL', key)
+    redis.call('ZREM', t_expiration_queue, reservation_id)
+else
+    local budget = redis.call('HGET', t_leases, reservation_id)
+    if budget then
+        redis.call('HDEL', t_leases, reservation_id)
+        redis.call('ZREM', t_lease_expiration_queue, reservation_id)
+        local leased_budget = tonumber(redis.call('GET', t_leased_budget) or 0)
+        redis.call('SET', t_leased_budget, leased_budget - tonumber(budget))
+        uncount_lease(reservation_id)
+        object_ids = ''
+    elseif redis.call('EXISTS', t_cancelled) == 1 then
+        return {1, '', ''}
+    else
+        return {0, '', ''}
+    end
+end
+
+redis.call('SET', t_cancelled, '1', 'EX', cancelled_ttl_sec)
+local callback_url = redis.call('HGET', t_reservation_callbacks, reservation_id)
+if callback_url then
+    redis.call('HDEL', t_reservation_callbacks, reservation_id)
+end
+return {1, object_ids, callback_url or ''}
@@ -0,0 +1,34 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to take all available gas coins out of the pool, e.g. when decommissioning the pool.
+-- It refuses to drain the pool if there is any active reservation or budget lease, since those coins may still be used.
+-- The first argument is the sponsor's address.
+-- Returns all the coins that were taken out of the pool.
+
+local sponsor_address = ARGV[1]
+
+local t_expiration_queue = sponsor_address .. ':expiration_queue'
+local reservation_count = redis.call('ZCARD', t_expiration_queue)
+if reservation_count > 0 then
+    error('Unable to drain the pool while there are active reservations: ' .. reservation_count)
+end
+local t_lease_expiration_queue = sponsor_address .. ':lease_expiration_queue'
+local lease_count = redis.call('ZCARD', t_lease_expiration_queue)
+if lease_count > 0 then
+    error('Unable to drain the pool while there are active budget leases: ' .. lease_count)
+end
+
+local t_available_gas_coins = sponsor_address .. ':available_gas_coins'
+local coins = redis.call('LRANGE', t_available_gas_coins, 0, -1)
+redis.call('DEL', t_available_gas_coins)
+-- No coin is left in the pool, since there is no active reservation.
+redis.call('DEL', sponsor_address .. ':coin_version_times')
+
+local t_available_coin_total_balance = sponsor_address .. ':available_coin_total_balance'
+redis.call('SET', t_available_coin_total_balance, 0)
+local t_available_coin_count = sponsor_address .. ':available_coin_count'
+redis.call('SET', t_available_coin_count, 0)
+
+return coins
@@ -3,13 +3,21 @@
 
 -- This script is used to expire gas coins that have been reserved but not used after the expiration time.
 -- It takes out all gas coins from the expiration_queue that have expired and returns them to the caller.
+-- Each expired reservation is returned as "reservation_id,object_ids", where the object ids are comma separated.
+-- The coins stay in the coin_reservations map until the caller releases them with release_reserved_coins.lua.
 -- The first argument is the sponsor's address.
 -- The second argument is the current timestamp.
+-- Callback URLs of the expired reservations are moved to the expired_reservation_callbacks list,
+-- each as "reservation_id,callback_url", to be picked up by take_expired_reservation_callbacks.lua.
+-- Expired budget leases are removed as well, returning their budget to the pool, and they are uncounted against the
+-- usage of the clients that made them.
 
 local sponsor_address = ARGV[1]
 local current_time = tonumber(ARGV[2])
 
 local t_expiration_queue = sponsor_address .. ':expiration_queue'
+local t_reservation_callbacks = sponsor_address .. ':reservation_callbacks'
+local t_expired_reservation_callbacks = sponsor_address .. ':expired_reservation_callbacks'
 
 local elements = redis.call('ZRANGEBYSCORE', t_expiration_queue, 0, current_time)
 
@@ -20,10 +28,60 @@ if #elements > 0 then
         local object_ids = redis.call('GET', key)
         if object_ids then
             redis.call('DEL', key)
-            table.insert(expired_reservations, object_ids)
+            table.insert(expired_reservations, reservation_id .. ',' .. object_ids)
+            local callback_url = redis.call('HGET', t_reservation_callbacks, reservation_id)
+            if callback_url then
+                redis.call('HDEL', t_reservation_callbacks, reservation_id)
+                redis.call('RPUSH', t_expired_reservation_callbacks, reservation_id .. ',' .. callback_url)
+            end
         end
     end
     redis.call('ZREMRANGEBYSCORE', t_expiration_queue, 0, current_time)
 end
 
+local t_leases = sponsor_address .. ':budget_leases'
+local t_lease_expiration_queue = sponsor_address .. ':lease_expiration_queue'
+local t_leased_budget = sponsor_address .. ':leased_budget'
+local t_reservation_clients = sponsor_address .. ':reservation_clients'
+local t_client_reserved_budgets = sponsor_address .. ':client_reserved_budgets'
+local t_client_reservation_counts = sponsor_address .. ':client_reservation_counts'
+
+-- Uncounts a budget lease against the usage of the client that made it, if it was counted.
+local function uncount_lease(lease_id)
+    local client_record = redis.call('HGET', t_reservation_clients, lease_id)
+    if not client_record then
+        return
+    end
+    redis.call('HDEL', t_reservation_clients, lease_id)
+    local idx, _ = string.find(client_record, ',', 1)
+    local budget = string.sub(client_record, 1, idx - 1)
+    local client_id = string.sub(client_record, idx + 1)
+    if redis.call('HINCRBY', t_client_reserved_budgets, client_id, '-' .. budget) <= 0 then
+        redis.call('HDEL', t_client_reserved_budgets, client_id)
+    end
+    if redis.call('HINCRBY', t_client_reservation_counts, client_id, -1) <= 0 then
+        redis.call('HDEL', t_client_reservation_counts, client_id)
+    end
+end
+
+local expired_leases = redis.call('ZRANGEBYSCORE', t_lease_expiration_queue, 0, current_time)
+if #expired_leases > 0 then
+    local leased_budget = tonumber(redis.call('GET', t_leased_budget) or 0)
+    for _, lease_id in ipairs(expired_leases) do
+        local budget = redis.call('HGET', t_leases, lease_id)
+        if budget then
+            redis.call('HDEL', t_leases, lease_id)
+            leased_budget = leased_budget - tonumber(budget)
+            uncount_lease(lease_id)
+            local callback_url = redis.call('HGET', t_reservation_callbacks, lease_id)
+            if callback_url then
+                redis.call('HDEL', t_reservation_callbacks, lease_id)
+                redis.call('RPUSH', t_expired_reservation_callbacks, lease_id .. ',' .. callback_url)
+            end
+        end
+    end
+    redis.call('SET', t_leased_budget, leased_budget)
+    redis.call('ZREMRANGEBYSCORE', t_lease_expiration_queue, 0, current_time)
+end
+
 return expired_reservations
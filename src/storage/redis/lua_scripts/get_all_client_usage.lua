@@ -0,0 +1,19 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to get the outstanding reserved budget and the number of active reservations of every client
+-- that holds a reservation, as counted by add_client_reservation.lua.
+-- The first argument is the sponsor's address.
+-- Returns a table with the outstanding reserved budgets and the active reservation counts, each as a flat list of
+-- client ids and values.
+
+local sponsor_address = ARGV[1]
+
+local t_client_reserved_budgets = sponsor_address .. ':client_reserved_budgets'
+local t_client_reservation_counts = sponsor_address .. ':client_reservation_counts'
+
+return {
+    redis.call('HGETALL', t_client_reserved_budgets),
+    redis.call('HGETALL', t_client_reservation_counts),
+}
@@ -0,0 +1,21 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to read all available gas coins of a sponsor address along with the tracked total balance
+-- and coin count, so that they can be checked against each other.
+-- This is expensive with a large pool, and is only meant for periodic audits.
+-- The first argument is the sponsor's address.
+-- Returns a table with the available coins, the tracked total balance and the tracked coin count.
+
+local sponsor_address = ARGV[1]
+
+local t_available_gas_coins = sponsor_address .. ':available_gas_coins'
+local t_available_coin_total_balance = sponsor_address .. ':available_coin_total_balance'
+local t_available_coin_count = sponsor_address .. ':available_coin_count'
+
+local coins = redis.call('LRANGE', t_available_gas_coins, 0, -1)
+local total_balance = tonumber(redis.call('GET', t_available_coin_total_balance) or 0)
+local coin_count = tonumber(redis.call('GET', t_available_coin_count) or 0)
+
+return {coins, total_balance, coin_count}
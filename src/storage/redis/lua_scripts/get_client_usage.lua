@@ -0,0 +1,19 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to get the outstanding reserved budget and the number of active reservations of a client, as
+-- counted by add_client_reservation.lua.
+-- The first argument is the sponsor's address.
+-- The second argument is the client id.
+-- Returns a table with the outstanding reserved budget and the number of active reservations of the client.
+
+local sponsor_address = ARGV[1]
+local client_id = ARGV[2]
+
+local t_client_reserved_budgets = sponsor_address .. ':client_reserved_budgets'
+local t_client_reservation_counts = sponsor_address .. ':client_reservation_counts'
+local outstanding_budget = tonumber(redis.call('HGET', t_client_reserved_budgets, client_id) or 0)
+local active_reservations = tonumber(redis.call('HGET', t_client_reservation_counts, client_id) or 0)
+
+return {outstanding_budget, active_reservations}
@@ -0,0 +1,29 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to find reservations whose transaction was submitted for execution, as recorded by
+-- mark_executing.lua, but whose coins were never released. Only transactions submitted before the given time are
+-- returned, since more recent ones may still be executing on another gas pool instance.
+-- The first argument is the sponsor's address.
+-- The second argument is the time in milliseconds before which the transaction must have been submitted.
+-- Returns a list of "reservation_id,digest,object_id,..." strings.
+
+local sponsor_address = ARGV[1]
+local started_before = tonumber(ARGV[2])
+
+local t_executing_reservations = sponsor_address .. ':executing_reservations'
+
+local records = redis.call('HGETALL', t_executing_reservations)
+local executing_reservations = {}
+for i = 1, #records, 2 do
+    local reservation_id = records[i]
+    local record = records[i + 1]
+    local idx, _ = string.find(record, ',', 1)
+    local started_at = tonumber(string.sub(record, 1, idx - 1))
+    if started_at < started_before then
+        table.insert(executing_reservations, reservation_id .. ',' .. string.sub(record, idx + 1))
+    end
+end
+
+return executing_reservations
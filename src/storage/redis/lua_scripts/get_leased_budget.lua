@@ -0,0 +1,16 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to get the total budget committed to unclaimed budget leases for a sponsor address.
+-- The first argument is the sponsor's address.
+
+local sponsor_address = ARGV[1]
+
+local t_leased_budget = sponsor_address .. ':leased_budget'
+local leased_budget = redis.call('GET', t_leased_budget)
+if not leased_budget then
+    return 0
+end
+
+return tonumber(leased_budget)
@@ -0,0 +1,49 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to read the state of the pool of a sponsor address at once, for debugging.
+-- This is expensive with a large pool, and is only meant for occasional admin requests.
+-- The first argument is the sponsor's address.
+-- Returns a table with the available coins, the tracked total balance, the tracked coin count, the active
+-- reservations and the unclaimed budget leases.
+-- Each reservation is formatted as "reservation_id,expiration_time,object_ids", where the object ids are comma
+-- separated. Reservations that were taken out for execution, cancelled or expired are left out.
+-- Each budget lease is formatted as "lease_id,budget,expiration_time".
+
+local sponsor_address = ARGV[1]
+
+local t_available_gas_coins = sponsor_address .. ':available_gas_coins'
+local t_available_coin_total_balance = sponsor_address .. ':available_coin_total_balance'
+local t_available_coin_count = sponsor_address .. ':available_coin_count'
+local t_expiration_queue = sponsor_address .. ':expiration_queue'
+local t_leases = sponsor_address .. ':budget_leases'
+local t_lease_expiration_queue = sponsor_address .. ':lease_expiration_queue'
+
+local coins = redis.call('LRANGE', t_available_gas_coins, 0, -1)
+local total_balance = tonumber(redis.call('GET', t_available_coin_total_balance) or 0)
+local coin_count = tonumber(redis.call('GET', t_available_coin_count) or 0)
+
+local reservations = {}
+local queue = redis.call('ZRANGE', t_expiration_queue, 0, -1, 'WITHSCORES')
+for i = 1, #queue, 2 do
+    local reservation_id = queue[i]
+    local object_ids = redis.call('GET', sponsor_address .. ':' .. reservation_id)
+    if object_ids then
+        local expiration_time = string.format('%d', tonumber(queue[i + 1]))
+        table.insert(reservations, reservation_id .. ',' .. expiration_time .. ',' .. object_ids)
+    end
+end
+
+local leases = {}
+local lease_budgets = redis.call('HGETALL', t_leases)
+for i = 1, #lease_budgets, 2 do
+    local lease_id = lease_budgets[i]
+    local expiration_time = redis.call('ZSCORE', t_lease_expiration_queue, lease_id)
+    if expiration_time then
+        expiration_time = string.format('%d', tonumber(expiration_time))
+        table.insert(leases, lease_id .. ',' .. lease_budgets[i + 1] .. ',' .. expiration_time)
+    end
+end
+
+return {coins, total_balance, coin_count, reservations, leases}
@@ -0,0 +1,14 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to look up the id of the client that made a reservation.
+-- The first argument is the sponsor's address.
+-- The second argument is the reservation id.
+-- Returns the client id, or an empty string if no client is recorded for the reservation.
+
+local sponsor_address = ARGV[1]
+local reservation_id = ARGV[2]
+
+local t_reservation_owner = sponsor_address .. ':reservation_owner:' .. reservation_id
+return redis.call('GET', t_reservation_owner) or ''
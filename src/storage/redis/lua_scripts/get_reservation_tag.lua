@@ -0,0 +1,14 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to look up the tag of a reservation.
+-- The first argument is the sponsor's address.
+-- The second argument is the reservation id.
+-- Returns the tag, or an empty string if the reservation has no tag.
+
+local sponsor_address = ARGV[1]
+local reservation_id = ARGV[2]
+
+local t_reservation_tag = sponsor_address .. ':reservation_tag:' .. reservation_id
+return redis.call('GET', t_reservation_tag) or ''
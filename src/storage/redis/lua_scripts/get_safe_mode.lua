@@ -0,0 +1,11 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to check whether safe mode is on for a sponsor address.
+-- The first argument is the sponsor's address.
+
+local sponsor_address = ARGV[1]
+
+local t_safe_mode = sponsor_address .. ':safe_mode'
+return redis.call('EXISTS', t_safe_mode)
@@ -0,0 +1,32 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to find coins that are still recorded as reserved in the coin_reservations map, although their
+-- reservation is over and they were never released, e.g. because the gas pool crashed while executing a transaction.
+-- A coin is stranded if its reservation no longer exists and it was reserved before the given time. Coins that were
+-- reserved more recently may still be in use by another gas pool instance.
+-- This scans the whole map, so it should only be called on startup.
+-- The first argument is the sponsor's address.
+-- The second argument is the time in milliseconds before which a coin must have been reserved to be stranded.
+-- Returns a list of "reservation_id,object_id" strings.
+
+local sponsor_address = ARGV[1]
+local reserved_before = tonumber(ARGV[2])
+
+local t_coin_reservations = sponsor_address .. ':coin_reservations'
+
+local records = redis.call('HGETALL', t_coin_reservations)
+local stranded_coins = {}
+for i = 1, #records, 2 do
+    local object_id = records[i]
+    local record = records[i + 1]
+    local idx, _ = string.find(record, ',', 1)
+    local reservation_id = string.sub(record, 1, idx - 1)
+    local reserved_at = tonumber(string.sub(record, idx + 1))
+    if reserved_at < reserved_before and redis.call('EXISTS', sponsor_address .. ':' .. reservation_id) == 0 then
+        table.insert(stranded_coins, reservation_id .. ',' .. object_id)
+    end
+end
+
+return stranded_coins
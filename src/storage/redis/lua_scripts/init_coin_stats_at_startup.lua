@@ -3,6 +3,8 @@
 
 -- This script is used to initialize a few coin related statistics for a sponsor address at startup.
 -- Including the total balance and the total coin count.
+-- It also removes the records of the coin_version_times map for coins that are neither available nor reserved, in
+-- case any were left behind, e.g. by a crash.
 -- The first argument is the sponsor's address.
 -- Returns a table with the new coin count and new total balance.
 
@@ -31,4 +33,20 @@ if not total_balance then
     redis.call('SET', t_available_coin_total_balance, total_balance)
 end
 
+local t_coin_version_times = sponsor_address .. ':coin_version_times'
+if redis.call('HLEN', t_coin_version_times) > 0 then
+    local available = {}
+    for _, coin in ipairs(redis.call('LRANGE', t_available_gas_coins, 0, -1)) do
+        local idx1, _ = string.find(coin, ',', 1)
+        local idx2, _ = string.find(coin, ',', idx1 + 1)
+        available[string.sub(coin, idx1 + 1, idx2 - 1)] = true
+    end
+    local t_coin_reservations = sponsor_address .. ':coin_reservations'
+    for _, object_id in ipairs(redis.call('HKEYS', t_coin_version_times)) do
+        if not available[object_id] and redis.call('HEXISTS', t_coin_reservations, object_id) == 0 then
+            redis.call('HDEL', t_coin_version_times, object_id)
+        end
+    end
+end
+
 return {coin_count, total_balance}
@@ -0,0 +1,26 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to record that the transaction of a reservation is about to be submitted for execution, before
+-- it is submitted. The record is kept in the executing_reservations map until the coins of the reservation are
+-- released with release_reserved_coins.lua, so that a gas pool that crashed in between can look up the transaction
+-- and reconcile the coins.
+-- The first argument is the sponsor's address.
+-- The second argument is the reservation id.
+-- The third argument is the transaction digest.
+-- The fourth argument is a JSON array of the object ids of all coins of the reservation.
+-- The fifth argument is the current time, in milliseconds.
+
+local sponsor_address = ARGV[1]
+local reservation_id = ARGV[2]
+local digest = ARGV[3]
+local object_ids = cjson.decode(ARGV[4])
+local current_time = ARGV[5]
+
+local t_executing_reservations = sponsor_address .. ':executing_reservations'
+local record = current_time .. ',' .. digest
+for _, object_id in ipairs(object_ids) do
+    record = record .. ',' .. object_id
+end
+redis.call('HSET', t_executing_reservations, reservation_id, record)
@@ -0,0 +1,41 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to read records from the audit log of a sponsor address.
+-- The first argument is the sponsor's address.
+-- The second and third arguments are the start (inclusive) and end (exclusive) timestamps in milliseconds.
+-- The fourth argument is the client id to filter on, or an empty string to return records of all clients.
+-- The fifth argument is the maximum number of records to return.
+-- Returns a list of JSON encoded records, oldest first.
+
+local sponsor_address = ARGV[1]
+local from_ms = ARGV[2]
+local to_ms = ARGV[3]
+local client_id = ARGV[4]
+local limit = tonumber(ARGV[5])
+
+local t_audit_log = sponsor_address .. ':audit_log'
+local max_score = '(' .. to_ms
+if client_id == '' then
+    return redis.call('ZRANGEBYSCORE', t_audit_log, from_ms, max_score, 'LIMIT', 0, limit)
+end
+
+-- Records of other clients are skipped, so read the time range page by page until we have enough.
+local page_size = 1000
+local records = {}
+local offset = 0
+while #records < limit do
+    local page = redis.call('ZRANGEBYSCORE', t_audit_log, from_ms, max_score, 'LIMIT', offset, page_size)
+    for i = 1, #page, 1 do
+        if #records < limit and cjson.decode(page[i]).client_id == client_id then
+            table.insert(records, page[i])
+        end
+    end
+    if #page < page_size then
+        break
+    end
+    offset = offset + page_size
+end
+
+return records
@@ -7,14 +7,50 @@
 -- right before the transaction is executed.
 -- The first argument is the sponsor's address.
 -- The second argument is the reservation id.
+-- The third argument is the current time, in milliseconds.
+-- The fourth argument is the expiry margin, in milliseconds. A reservation that expires within the margin is left
+-- untouched, so that it expires as usual.
+-- The fifth argument is a comma separated list of the object ids of the coins the transaction pays with. Each of them
+-- must be a coin of the reservation, otherwise the reservation is left untouched. An empty list skips the check.
+-- Returns a table with a status, a value and the comma separated object ids of all coins of the reservation:
+--   {1, callback_url, object_ids} if the reservation was taken out, with an empty callback URL if there is none.
+--     The callback URL is removed at the same time.
+--   {0, '', ''} if the reservation does not exist,
+--   {2, '', ''} if it expires within the margin,
+--   {3, object_id, ''} if the given payment coin is not a coin of the reservation.
 
 local sponsor_address = ARGV[1]
 local reservation_id = ARGV[2]
+local current_time = tonumber(ARGV[3])
+local expiry_margin = tonumber(ARGV[4])
+local payment_ids = ARGV[5] or ''
 
 local key = sponsor_address .. ':' .. reservation_id
-local exists = redis.call('EXISTS', key)
-if exists == 1 then
-    redis.call('DEL', key)
-else
-    error('Reservation no longer exist: ' .. reservation_id)
-end
\ No newline at end of file
+local object_ids = redis.call('GET', key)
+if not object_ids then
+    return {0, '', ''}
+end
+
+local t_expiration_queue = sponsor_address .. ':expiration_queue'
+local expiration_time = redis.call('ZSCORE', t_expiration_queue, reservation_id)
+if expiration_time and tonumber(expiration_time) < current_time + expiry_margin then
+    return {2, '', ''}
+end
+
+local reserved = {}
+for object_id in string.gmatch(object_ids, '([^,]+)') do
+    reserved[object_id] = true
+end
+for object_id in string.gmatch(payment_ids, '([^,]+)') do
+    if not reserved[object_id] then
+        return {3, object_id, ''}
+    end
+end
+redis.call('DEL', key)
+
+local t_reservation_callbacks = sponsor_address .. ':reservation_callbacks'
+local callback_url = redis.call('HGET', t_reservation_callbacks, reservation_id)
+if callback_url then
+    redis.call('HDEL', t_reservation_callbacks, reservation_id)
+end
+return {1, callback_url or '', object_ids}
@@ -0,0 +1,109 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to put the coins of a reservation back into the available_gas_coins list once the reservation
+-- is over, whether it was executed, cancelled or expired.
+-- Only coins that the coin_reservations map records as held by the reservation are released, and their records are
+-- removed. Other coins, e.g. coins that were already released by a previous call, are skipped, so that a coin is
+-- never added to the pool twice.
+-- Unless the reservation is kept, the record of the reservation in the executing_reservations map, if any, is removed
+-- as well, and if the reservation is counted against the outstanding reserved budget and active reservation count of
+-- a client, it is uncounted.
+-- The first argument is the sponsor's address.
+-- The second argument is the reservation id.
+-- The third argument is a JSON array of the object ids of all coins of the reservation.
+-- The fourth argument is a JSON array of the latest state of the coins, in the same format as add_new_coins.lua.
+-- Coins of the reservation that are not in it no longer exist, and only their records are removed.
+-- The fifth argument is the current time, in milliseconds.
+-- The sixth argument is '1' if the prefer-stable-coins strategy is used. In that case, released coins whose version
+-- changed, e.g. because they paid for a transaction, are recorded in the coin_version_times map as changed at that
+-- time. Otherwise their records are removed. The records of coins that no longer exist are always removed.
+-- The seventh argument is '1' if the reservation is kept, because only some of its coins are released while the
+-- others may still be used by a transaction that is executing.
+-- Returns a table with the released coins, new total balance, and new coin count.
+
+local sponsor_address = ARGV[1]
+local reservation_id = ARGV[2]
+local reserved_object_ids = cjson.decode(ARGV[3])
+local latest_coins = cjson.decode(ARGV[4])
+local current_time = ARGV[5]
+local track_version_times = ARGV[6] == '1'
+local keep_reservation = ARGV[7] == '1'
+
+local t_available_gas_coins = sponsor_address .. ':available_gas_coins'
+local t_coin_reservations = sponsor_address .. ':coin_reservations'
+local t_executing_reservations = sponsor_address .. ':executing_reservations'
+local t_coin_version_times = sponsor_address .. ':coin_version_times'
+
+if not keep_reservation then
+    redis.call('HDEL', t_executing_reservations, reservation_id)
+
+    local t_reservation_clients = sponsor_address .. ':reservation_clients'
+    local client_record = redis.call('HGET', t_reservation_clients, reservation_id)
+    if client_record then
+        redis.call('HDEL', t_reservation_clients, reservation_id)
+        local idx, _ = string.find(client_record, ',', 1)
+        local budget = string.sub(client_record, 1, idx - 1)
+        local client_id = string.sub(client_record, idx + 1)
+        local t_client_reserved_budgets = sponsor_address .. ':client_reserved_budgets'
+        if redis.call('HINCRBY', t_client_reserved_budgets, client_id, '-' .. budget) <= 0 then
+            redis.call('HDEL', t_client_reserved_budgets, client_id)
+        end
+        local t_client_reservation_counts = sponsor_address .. ':client_reservation_counts'
+        if redis.call('HINCRBY', t_client_reservation_counts, client_id, -1) <= 0 then
+            redis.call('HDEL', t_client_reservation_counts, client_id)
+        end
+    end
+end
+
+local held = {}
+for _, object_id in ipairs(reserved_object_ids) do
+    local record = redis.call('HGET', t_coin_reservations, object_id)
+    if record then
+        local idx, _ = string.find(record, ',', 1)
+        if string.sub(record, 1, idx - 1) == reservation_id then
+            held[object_id] = true
+            redis.call('HDEL', t_coin_reservations, object_id)
+        end
+    end
+end
+
+local released_coins = {}
+local total_balance = 0
+for _, coin in ipairs(latest_coins) do
+    local idx1, _ = string.find(coin, ',', 1)
+    local idx2, _ = string.find(coin, ',', idx1 + 1)
+    local idx3, _ = string.find(coin, ',', idx2 + 1)
+    local object_id = string.sub(coin, idx1 + 1, idx2 - 1)
+    if held[object_id] then
+        -- Guard against the same coin showing up twice in the latest coins.
+        held[object_id] = nil
+        table.insert(released_coins, coin)
+        total_balance = total_balance + tonumber(string.sub(coin, 1, idx1 - 1))
+        if track_version_times then
+            local version = string.sub(coin, idx2 + 1, idx3 - 1)
+            local record = redis.call('HGET', t_coin_version_times, object_id)
+            if not record or string.sub(record, 1, string.find(record, ',', 1) - 1) ~= version then
+                redis.call('HSET', t_coin_version_times, object_id, version .. ',' .. current_time)
+            end
+        else
+            redis.call('HDEL', t_coin_version_times, object_id)
+        end
+        redis.call('RPUSH', t_available_gas_coins, coin)
+    end
+end
+-- The coins left no longer exist, e.g. because they were merged, or were retired, so they leave the pool for good.
+for object_id, _ in pairs(held) do
+    redis.call('HDEL', t_coin_version_times, object_id)
+end
+
+local t_available_coin_total_balance = sponsor_address .. ':available_coin_total_balance'
+local new_total_balance = tonumber(redis.call('GET', t_available_coin_total_balance) or 0) + total_balance
+redis.call('SET', t_available_coin_total_balance, new_total_balance)
+
+local t_available_coin_count = sponsor_address .. ':available_coin_count'
+local new_coin_count = tonumber(redis.call('GET', t_available_coin_count) or 0) + #released_coins
+redis.call('SET', t_available_coin_count, new_coin_count)
+
+return {released_coins, new_total_balance, new_coin_count}
@@ -0,0 +1,42 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to commit a budget against the gas pool of a sponsor address, without taking any coins.
+-- The lease is later claimed by reserve_gas_coins.lua, which then picks the actual coins.
+-- The lease shares the reservation id space, and is added to the lease_expiration_queue so that the committed
+-- budget is returned to the pool if the lease is never claimed.
+-- The first argument is the sponsor's address.
+-- The second argument is the budget.
+-- The third argument is the expiration time.
+-- The fourth argument is an optional callback URL to notify when the reservation expires or is consumed.
+-- Returns the lease id, or 0 if there is not enough uncommitted balance in the pool.
+
+local sponsor_address = ARGV[1]
+local budget = tonumber(ARGV[2])
+local expiration_time = tonumber(ARGV[3])
+local callback_url = ARGV[4]
+
+local t_next_reservation_id = sponsor_address .. ':next_reservation_id'
+local t_leases = sponsor_address .. ':budget_leases'
+local t_lease_expiration_queue = sponsor_address .. ':lease_expiration_queue'
+local t_leased_budget = sponsor_address .. ':leased_budget'
+local t_available_coin_total_balance = sponsor_address .. ':available_coin_total_balance'
+
+local available_balance = tonumber(redis.call('GET', t_available_coin_total_balance) or 0)
+local leased_budget = tonumber(redis.call('GET', t_leased_budget) or 0)
+if available_balance - leased_budget < budget then
+    return 0
+end
+
+redis.call('INCR', t_next_reservation_id)
+local lease_id = redis.call('GET', t_next_reservation_id)
+redis.call('HSET', t_leases, lease_id, budget)
+redis.call('ZADD', t_lease_expiration_queue, expiration_time, lease_id)
+redis.call('SET', t_leased_budget, leased_budget + budget)
+if callback_url and callback_url ~= '' then
+    local t_reservation_callbacks = sponsor_address .. ':reservation_callbacks'
+    redis.call('HSET', t_reservation_callbacks, lease_id, callback_url)
+end
+
+return lease_id
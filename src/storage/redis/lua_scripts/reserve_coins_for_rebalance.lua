@@ -0,0 +1,96 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to take coins that need rebalancing out of the available_gas_coins list.
+-- Coins with balance below the small threshold or above the large threshold are reserved just like in
+-- reserve_gas_coins.lua, so that they are released by expire_coins.lua if the rebalancing never finishes.
+-- They are recorded in the coin_reservations map just like in reserve_gas_coins.lua as well.
+-- Budget that is committed to budget leases is never taken out of the pool.
+-- This scans the whole list, so it should only be called periodically.
+-- The first argument is the sponsor's address.
+-- The second argument is the small balance threshold.
+-- The third argument is the large balance threshold.
+-- The fourth argument is the maximum number of small coins to reserve.
+-- The fifth argument is the maximum number of large coins to reserve.
+-- The sixth argument is the expiration time.
+-- The seventh argument is the current time, in milliseconds.
+-- Returns a table with the reservation id (0 if nothing was reserved), the reserved coins, and the number of
+-- available coins, small coins and large coins prior to the reservation.
+
+local sponsor_address = ARGV[1]
+local small_threshold = tonumber(ARGV[2])
+local large_threshold = tonumber(ARGV[3])
+local max_small_coins = tonumber(ARGV[4])
+local max_large_coins = tonumber(ARGV[5])
+local expiration_time = tonumber(ARGV[6])
+local current_time = ARGV[7]
+
+local t_available_gas_coins = sponsor_address .. ':available_gas_coins'
+local t_available_coin_total_balance = sponsor_address .. ':available_coin_total_balance'
+local t_available_coin_count = sponsor_address .. ':available_coin_count'
+local t_expiration_queue = sponsor_address .. ':expiration_queue'
+local t_next_reservation_id = sponsor_address .. ':next_reservation_id'
+local t_leased_budget = sponsor_address .. ':leased_budget'
+
+local available_balance = tonumber(redis.call('GET', t_available_coin_total_balance) or 0)
+local leased_budget = tonumber(redis.call('GET', t_leased_budget) or 0)
+
+local all_coins = redis.call('LRANGE', t_available_gas_coins, 0, -1)
+local small_count = 0
+local large_count = 0
+local reserved_balance = 0
+local coins = {}
+local object_ids = {}
+local kept_coins = {}
+
+for _, coin in ipairs(all_coins) do
+    local idx1, _ = string.find(coin, ',', 1)
+    local balance = tonumber(string.sub(coin, 1, idx1 - 1))
+    local take = false
+    if balance < small_threshold then
+        small_count = small_count + 1
+        take = small_count <= max_small_coins
+    elseif balance > large_threshold then
+        large_count = large_count + 1
+        take = large_count <= max_large_coins
+    end
+    if take and available_balance - reserved_balance - balance < leased_budget then
+        take = false
+    end
+    if take then
+        reserved_balance = reserved_balance + balance
+        local idx2, _ = string.find(coin, ',', idx1 + 1)
+        table.insert(coins, coin)
+        table.insert(object_ids, string.sub(coin, idx1 + 1, idx2 - 1))
+    else
+        table.insert(kept_coins, coin)
+    end
+end
+
+if #coins == 0 then
+    return {0, {}, #all_coins, small_count, large_count}
+end
+
+-- Rebuild the list without the reserved coins, keeping the original order.
+redis.call('DEL', t_available_gas_coins)
+local CHUNK_SIZE = 1000
+for i = 1, #kept_coins, CHUNK_SIZE do
+    redis.call('RPUSH', t_available_gas_coins, unpack(kept_coins, i, math.min(i + CHUNK_SIZE - 1, #kept_coins)))
+end
+
+redis.call('SET', t_available_coin_total_balance, available_balance - reserved_balance)
+local cur_coin_count = tonumber(redis.call('GET', t_available_coin_count) or 0)
+redis.call('SET', t_available_coin_count, cur_coin_count - #coins)
+
+redis.call('INCR', t_next_reservation_id)
+local reservation_id = redis.call('GET', t_next_reservation_id)
+local key = sponsor_address .. ':' .. reservation_id
+redis.call('SET', key, table.concat(object_ids, ','))
+redis.call('ZADD', t_expiration_queue, expiration_time, reservation_id)
+local t_coin_reservations = sponsor_address .. ':coin_reservations'
+for _, object_id in ipairs(object_ids) do
+    redis.call('HSET', t_coin_reservations, object_id, reservation_id .. ',' .. current_time)
+end
+
+return {reservation_id, coins, #all_coins, small_count, large_count}
@@ -6,46 +6,154 @@
 -- It also creates a unique reservation id and stores the reserved coins in a separate reservation map.
 -- The reservation id is used to track the reserved coins and to release them back to the available pool if not used.
 -- The reservation id is added to the expiration_queue to track the expiration time of the reserved coins.
+-- Each reserved coin is also recorded in the coin_reservations map, along with the reservation id and the time it was
+-- reserved, until the coin is released with release_reserved_coins.lua.
+-- Coins are taken from the front of the list, while new and released coins are pushed to the back, so coins are
+-- reserved in the order they returned to the pool.
 -- The first argument is the sponsor's address.
 -- The second argument is the target budget.
 -- The third argument is the expiration time.
--- Returns a table with the reservation id, reserved coins, new total balance, and new coin count.
+-- The fourth argument is an optional callback URL to notify when the reservation expires or is consumed.
+-- The fifth argument is an optional budget lease id created by reserve_budget_lease.lua. When specified, the lease
+-- is claimed: its budget is used as the target budget and its id becomes the reservation id.
+-- Budget that is committed to leases is not available to other reservations.
+-- The sixth argument is '1' if a partial reservation is allowed. In that case, when the target budget cannot be fully
+-- covered, as many coins as possible are reserved instead of reserving nothing.
+-- The seventh argument is the maximum single coin balance of the preserve-large-coins strategy, or 0 to reserve coins
+-- in FIFO order. For target budgets up to that balance, coins with a larger balance are set aside while looking for
+-- smaller coins to cover the budget. Since any of them covers the budget on its own, a single one is only reserved
+-- when the smaller coins cannot cover it. Coins that are not reserved go back to the front of the queue in their
+-- original order.
+-- The eighth argument is the current time, in milliseconds.
+-- The ninth argument is the minimum stable time of the prefer-stable-coins strategy in milliseconds, or 0 to ignore how
+-- recently coins changed. Coins whose version changed more recently than that, according to the coin_version_times map,
+-- are set aside and only reserved, oldest first, when the stable coins cannot cover the budget. Coins without a
+-- recorded version time count as stable.
+-- Returns a table with the reservation id, reserved coins, new total balance, new coin count, and the number of
+-- candidate coins that were taken out of the queue to select the reserved coins.
 
 local sponsor_address = ARGV[1]
 local target_budget = tonumber(ARGV[2])
 local expiration_time = tonumber(ARGV[3])
+local callback_url = ARGV[4]
+local lease_id = ARGV[5]
+local allow_partial = ARGV[6] == '1'
+local max_single_coin_balance = tonumber(ARGV[7]) or 0
+local current_time = ARGV[8]
+local min_stable_ms = tonumber(ARGV[9]) or 0
 
 local MAX_GAS_PER_QUERY = 256
+-- Bounds how many large coins are looked past in a single reservation.
+local MAX_SET_ASIDE_COINS = 256
 
 local t_available_gas_coins = sponsor_address .. ':available_gas_coins'
 local t_expiration_queue = sponsor_address .. ':expiration_queue'
 local t_next_reservation_id = sponsor_address .. ':next_reservation_id'
+local t_leases = sponsor_address .. ':budget_leases'
+local t_lease_expiration_queue = sponsor_address .. ':lease_expiration_queue'
+local t_leased_budget = sponsor_address .. ':leased_budget'
+
+local claiming_lease = lease_id and lease_id ~= ''
+local leased_budget = tonumber(redis.call('GET', t_leased_budget) or 0)
+if claiming_lease then
+    local lease_budget = redis.call('HGET', t_leases, lease_id)
+    if not lease_budget then
+        error('Lease no longer exist: ' .. lease_id)
+    end
+    target_budget = tonumber(lease_budget)
+    -- The budget of the lease being claimed is available to this reservation.
+    leased_budget = leased_budget - target_budget
+end
+if leased_budget > 0 then
+    local available_balance = tonumber(redis.call('GET', sponsor_address .. ':available_coin_total_balance') or 0)
+    local uncommitted_balance = available_balance - leased_budget
+    if uncommitted_balance < target_budget then
+        if not allow_partial or uncommitted_balance <= 0 then
+            return {0, {}, 0, 0, 0}
+        end
+        -- Only the balance that is not committed to leases can be partially reserved.
+        target_budget = uncommitted_balance
+    end
+end
+
+local preserve_large_coins = max_single_coin_balance > 0 and target_budget <= max_single_coin_balance
+local prefer_stable_coins = min_stable_ms > 0
+local stable_before = tonumber(current_time) - min_stable_ms
+local t_coin_version_times = sponsor_address .. ':coin_version_times'
+
+local function is_unstable(object_id)
+    local record = redis.call('HGET', t_coin_version_times, object_id)
+    if not record then
+        return false
+    end
+    local idx, _ = string.find(record, ',', 1)
+    return tonumber(string.sub(record, idx + 1)) > stable_before
+end
 
 local total_balance = 0
 local coins = {}
 local object_ids = {}
+-- All coins taken out of the queue, in order, and the ones among them that were set aside.
+local popped = {}
+local set_aside = {}
+local unstable = {}
+
+local function reserve(entry)
+    entry.reserved = true
+    total_balance = total_balance + entry.balance
+    table.insert(coins, entry.coin)
+    table.insert(object_ids, entry.object_id)
+end
 
 while total_balance < target_budget and #coins < MAX_GAS_PER_QUERY do
     local coin = redis.call('LPOP', t_available_gas_coins)
     if not coin then break end
 
     local idx1, _ = string.find(coin, ',', 1)
-    local balance = string.sub(coin, 1, idx1 - 1)
-    total_balance = total_balance + tonumber(balance)
-
     local idx2, _ = string.find(coin, ',', idx1 + 1)
-    local object_id = string.sub(coin, idx1 + 1, idx2 - 1)
+    local entry = {
+        coin = coin,
+        balance = tonumber(string.sub(coin, 1, idx1 - 1)),
+        object_id = string.sub(coin, idx1 + 1, idx2 - 1),
+        reserved = false,
+    }
+    table.insert(popped, entry)
+    if preserve_large_coins and entry.balance > max_single_coin_balance and #set_aside < MAX_SET_ASIDE_COINS then
+        table.insert(set_aside, entry)
+    elseif prefer_stable_coins and #unstable < MAX_SET_ASIDE_COINS and is_unstable(entry.object_id) then
+        table.insert(unstable, entry)
+    else
+        reserve(entry)
+    end
+end
 
-    table.insert(coins, coin)
-    table.insert(object_ids, object_id)
+-- The stable coins cannot cover the budget, so add the coins that changed recently, oldest first.
+for _, entry in ipairs(unstable) do
+    if total_balance >= target_budget or #coins >= MAX_GAS_PER_QUERY then break end
+    reserve(entry)
 end
 
-if total_balance < target_budget then
-    -- If the threshold is not reached, push the coins back to the front of the queue in the original order.
-    for i = #coins, 1, -1 do
-        redis.call('LPUSH', t_available_gas_coins, coins[i])
+if total_balance < target_budget and #set_aside > 0 then
+    -- The smaller coins cannot cover the budget, so use the oldest large coin alone instead.
+    for _, entry in ipairs(popped) do
+        entry.reserved = false
     end
-    return {0, {}, 0, 0}
+    total_balance = 0
+    coins = {}
+    object_ids = {}
+    reserve(set_aside[1])
+end
+
+local succeeded = total_balance >= target_budget or (allow_partial and #coins > 0)
+-- Push the coins that are not reserved back to the front of the queue in the original order.
+-- If the threshold is not reached, that is all of them.
+for i = #popped, 1, -1 do
+    if not succeeded or not popped[i].reserved then
+        redis.call('LPUSH', t_available_gas_coins, popped[i].coin)
+    end
+end
+if not succeeded then
+    return {0, {}, 0, 0, #popped}
 end
 
 local t_available_coin_total_balance = sponsor_address .. ':available_coin_total_balance'
@@ -69,11 +177,27 @@ end
 local new_coin_count = cur_coin_count - #coins
 redis.call('SET', t_available_coin_count, new_coin_count)
 
-redis.call('INCR', t_next_reservation_id)
-local reservation_id = redis.call('GET', t_next_reservation_id)
+local reservation_id
+if claiming_lease then
+    reservation_id = lease_id
+    redis.call('HDEL', t_leases, lease_id)
+    redis.call('ZREM', t_lease_expiration_queue, lease_id)
+    redis.call('SET', t_leased_budget, leased_budget)
+else
+    redis.call('INCR', t_next_reservation_id)
+    reservation_id = redis.call('GET', t_next_reservation_id)
+end
 local concated_object_ids = table.concat(object_ids, ',')
 local key = sponsor_address .. ':' .. reservation_id
 redis.call('SET', key, concated_object_ids)
 redis.call('ZADD', t_expiration_queue, expiration_time, reservation_id)
+local t_coin_reservations = sponsor_address .. ':coin_reservations'
+for _, object_id in ipairs(object_ids) do
+    redis.call('HSET', t_coin_reservations, object_id, reservation_id .. ',' .. current_time)
+end
+if callback_url and callback_url ~= '' then
+    local t_reservation_callbacks = sponsor_address .. ':reservation_callbacks'
+    redis.call('HSET', t_reservation_callbacks, reservation_id, callback_url)
+end
 
-return {reservation_id, coins, new_total_balance, new_coin_count}
+return {reservation_id, coins, new_total_balance, new_coin_count, #popped}
@@ -0,0 +1,107 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to reserve exactly the coins a client asked for, out of the available_gas_coins list.
+-- Either all of the requested coins are reserved, or none of them is.
+-- The reservation is recorded just like in reserve_gas_coins.lua, including the coin_reservations map.
+-- Budget that is committed to budget leases is never taken out of the pool.
+-- This scans the whole list, so its cost grows with the size of the pool.
+-- The first argument is the sponsor's address.
+-- The second argument is a comma separated list of the requested object ids.
+-- The third argument is the target budget that the requested coins must cover.
+-- The fourth argument is the expiration time.
+-- The fifth argument is an optional callback URL to notify when the reservation expires or is consumed.
+-- The sixth argument is the current time, in milliseconds.
+-- Returns a table with a status, a value (as a string) and the reserved coins:
+--   {1, reservation_id, coins} if the coins were reserved,
+--   {2, object_id, {}} if the given coin is held by a reservation,
+--   {3, object_id, {}} if the given coin is not in the pool,
+--   {4, total_balance, {}} if the requested coins do not cover the target budget,
+--   {5, 0, {}} if reserving the coins would take budget committed to budget leases.
+
+local sponsor_address = ARGV[1]
+local requested_ids = ARGV[2]
+local target_budget = tonumber(ARGV[3])
+local expiration_time = tonumber(ARGV[4])
+local callback_url = ARGV[5]
+local current_time = ARGV[6]
+
+local t_available_gas_coins = sponsor_address .. ':available_gas_coins'
+local t_available_coin_total_balance = sponsor_address .. ':available_coin_total_balance'
+local t_available_coin_count = sponsor_address .. ':available_coin_count'
+local t_expiration_queue = sponsor_address .. ':expiration_queue'
+local t_next_reservation_id = sponsor_address .. ':next_reservation_id'
+local t_leased_budget = sponsor_address .. ':leased_budget'
+local t_coin_reservations = sponsor_address .. ':coin_reservations'
+
+local requested = {}
+local requested_order = {}
+for object_id in string.gmatch(requested_ids, '([^,]+)') do
+    requested[object_id] = true
+    table.insert(requested_order, object_id)
+end
+
+local all_coins = redis.call('LRANGE', t_available_gas_coins, 0, -1)
+local found = {}
+local reserved_balance = 0
+local kept_coins = {}
+
+for _, coin in ipairs(all_coins) do
+    local idx1, _ = string.find(coin, ',', 1)
+    local idx2, _ = string.find(coin, ',', idx1 + 1)
+    local object_id = string.sub(coin, idx1 + 1, idx2 - 1)
+    if requested[object_id] then
+        found[object_id] = coin
+        reserved_balance = reserved_balance + tonumber(string.sub(coin, 1, idx1 - 1))
+    else
+        table.insert(kept_coins, coin)
+    end
+end
+
+local coins = {}
+for _, object_id in ipairs(requested_order) do
+    if not found[object_id] then
+        if redis.call('HEXISTS', t_coin_reservations, object_id) == 1 then
+            return {2, object_id, {}}
+        end
+        return {3, object_id, {}}
+    end
+    table.insert(coins, found[object_id])
+end
+
+if reserved_balance < target_budget then
+    return {4, tostring(reserved_balance), {}}
+end
+
+local available_balance = tonumber(redis.call('GET', t_available_coin_total_balance) or 0)
+local leased_budget = tonumber(redis.call('GET', t_leased_budget) or 0)
+if available_balance - reserved_balance < leased_budget then
+    return {5, '0', {}}
+end
+
+-- Rebuild the list without the reserved coins, keeping the original order.
+redis.call('DEL', t_available_gas_coins)
+local CHUNK_SIZE = 1000
+for i = 1, #kept_coins, CHUNK_SIZE do
+    redis.call('RPUSH', t_available_gas_coins, unpack(kept_coins, i, math.min(i + CHUNK_SIZE - 1, #kept_coins)))
+end
+
+redis.call('SET', t_available_coin_total_balance, available_balance - reserved_balance)
+local cur_coin_count = tonumber(redis.call('GET', t_available_coin_count) or 0)
+redis.call('SET', t_available_coin_count, cur_coin_count - #coins)
+
+redis.call('INCR', t_next_reservation_id)
+local reservation_id = redis.call('GET', t_next_reservation_id)
+local key = sponsor_address .. ':' .. reservation_id
+redis.call('SET', key, table.concat(requested_order, ','))
+redis.call('ZADD', t_expiration_queue, expiration_time, reservation_id)
+for _, object_id in ipairs(requested_order) do
+    redis.call('HSET', t_coin_reservations, object_id, reservation_id .. ',' .. current_time)
+end
+if callback_url and callback_url ~= '' then
+    local t_reservation_callbacks = sponsor_address .. ':reservation_callbacks'
+    redis.call('HSET', t_reservation_callbacks, reservation_id, callback_url)
+end
+
+return {1, reservation_id, coins}
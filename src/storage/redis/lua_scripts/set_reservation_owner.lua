@@ -0,0 +1,18 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to store the id of the client that made a reservation, so that other clients cannot act on it.
+-- The record expires on its own, so that it does not need to be cleaned up with the reservation.
+-- The first argument is the sponsor's address.
+-- The second argument is the reservation id.
+-- The third argument is the client id.
+-- The fourth argument is how long the record is kept, in seconds.
+
+local sponsor_address = ARGV[1]
+local reservation_id = ARGV[2]
+local client_id = ARGV[3]
+local ttl_sec = tonumber(ARGV[4])
+
+local t_reservation_owner = sponsor_address .. ':reservation_owner:' .. reservation_id
+redis.call('SET', t_reservation_owner, client_id, 'EX', ttl_sec)
@@ -0,0 +1,18 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to store the tag of a reservation, for downstream accounting.
+-- The tag expires on its own, so that it does not need to be cleaned up with the reservation.
+-- The first argument is the sponsor's address.
+-- The second argument is the reservation id.
+-- The third argument is the tag.
+-- The fourth argument is how long the tag is kept, in seconds.
+
+local sponsor_address = ARGV[1]
+local reservation_id = ARGV[2]
+local tag = ARGV[3]
+local ttl_sec = tonumber(ARGV[4])
+
+local t_reservation_tag = sponsor_address .. ':reservation_tag:' .. reservation_id
+redis.call('SET', t_reservation_tag, tag, 'EX', ttl_sec)
@@ -0,0 +1,17 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to turn safe mode on or off for a sponsor address.
+-- The first argument is the sponsor's address.
+-- The second argument is 1 to turn safe mode on, 0 to turn it off.
+
+local sponsor_address = ARGV[1]
+local enabled = tonumber(ARGV[2])
+
+local t_safe_mode = sponsor_address .. ':safe_mode'
+if enabled == 1 then
+    redis.call('SET', t_safe_mode, 1)
+else
+    redis.call('DEL', t_safe_mode)
+end
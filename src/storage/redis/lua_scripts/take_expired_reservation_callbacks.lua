@@ -0,0 +1,15 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to take out the callbacks of reservations that were expired by expire_coins.lua.
+-- The first argument is the sponsor's address.
+-- Returns a list of strings, each in the format of "reservation_id,callback_url".
+
+local sponsor_address = ARGV[1]
+
+local t_expired_reservation_callbacks = sponsor_address .. ':expired_reservation_callbacks'
+local callbacks = redis.call('LRANGE', t_expired_reservation_callbacks, 0, -1)
+redis.call('DEL', t_expired_reservation_callbacks)
+
+return callbacks
@@ -0,0 +1,14 @@
+-- Copyright (c) Mysten Labs, Inc.
+-- Copyright (c) The Social Proof Foundation, LLC.
+-- SPDX-License-Identifier: Apache-2.0
+
+-- This script is used to remove old records from the audit log of a sponsor address.
+-- The first argument is the sponsor's address.
+-- The second argument is a timestamp in milliseconds. All records older than it are removed.
+-- Returns the number of removed records.
+
+local sponsor_address = ARGV[1]
+local before_ms = ARGV[2]
+
+local t_audit_log = sponsor_address .. ':audit_log'
+return redis.call('ZREMRANGEBYSCORE', t_audit_log, '-inf', '(' .. before_ms)
@@ -4,23 +4,37 @@
 
 mod script_manager;
 
+use crate::config::CoinSelectionStrategy;
+use crate::errors::GasStationError;
 use crate::metrics::StorageMetrics;
 use crate::storage::redis::script_manager::ScriptManager;
-use crate::storage::Storage;
-use crate::types::{GasCoin, ReservationID};
+use crate::storage::{AvailableCoinsSnapshot, ExecutingReservation, PoolSnapshot, Storage};
+use crate::types::{
+    AuditRecord, BudgetLeaseSnapshot, ClientUsage, CoinBalanceDistribution, GasCoin,
+    ReservationID, ReservationSnapshot,
+};
 use chrono::Utc;
 use redis::aio::ConnectionManager;
+use redis::{RedisWrite, ToRedisArgs};
+use std::collections::BTreeMap;
 use std::ops::Add;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use mys_types::base_types::{ObjectDigest, ObjectID, SequenceNumber, MysAddress};
+use mys_types::digests::TransactionDigest;
 use tracing::{debug, info};
 
+/// How long a cancelled reservation is remembered, so that cancelling it again succeeds. Also how
+/// long the tag and the client of a reservation are kept.
+/// This covers the longest time a client may hold on to a reservation or budget lease.
+const CANCELLED_RESERVATION_TTL_SEC: u64 = 24 * 60 * 60;
+
 pub struct RedisStorage {
     conn_manager: ConnectionManager,
     // String format of the sponsor address to avoid converting it to string multiple times.
     sponsor_str: String,
+    coin_selection_strategy: CoinSelectionStrategy,
     metrics: Arc<StorageMetrics>,
 }
 
@@ -28,24 +42,94 @@ impl RedisStorage {
     pub async fn new(
         redis_url: &str,
         sponsor_address: MysAddress,
+        coin_selection_strategy: CoinSelectionStrategy,
         metrics: Arc<StorageMetrics>,
     ) -> Self {
-        let client = redis::Client::open(redis_url).unwrap();
-        let conn_manager = ConnectionManager::new(client).await.unwrap();
-        Self {
+        Self::try_new(redis_url, sponsor_address, coin_selection_strategy, metrics)
+            .await
+            .unwrap()
+    }
+
+    /// Same as `new`, but fails instead of panicking when the URL is invalid or Redis cannot be
+    /// reached.
+    pub async fn try_new(
+        redis_url: &str,
+        sponsor_address: MysAddress,
+        coin_selection_strategy: CoinSelectionStrategy,
+        metrics: Arc<StorageMetrics>,
+    ) -> anyhow::Result<Self> {
+        let client = redis::Client::open(redis_url)?;
+        let conn_manager = ConnectionManager::new(client).await?;
+        Ok(Self {
             conn_manager,
             sponsor_str: sponsor_address.to_string(),
+            coin_selection_strategy,
             metrics,
+        })
+    }
+
+    /// Releases the coins of a reservation with release_reserved_coins.lua, keeping the
+    /// reservation open if `keep_reservation` is set.
+    async fn release_coins(
+        &self,
+        reservation_id: ReservationID,
+        reserved_coin_ids: Vec<ObjectID>,
+        latest_coins: Vec<GasCoin>,
+        keep_reservation: bool,
+    ) -> anyhow::Result<Vec<GasCoin>> {
+        let reserved_coin_ids = reserved_coin_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<String>>();
+        let formatted_coins = latest_coins.iter().map(format_gas_coin).collect::<Vec<String>>();
+        let mut conn = self.conn_manager.clone();
+        let (released_coins, new_total_balance, new_coin_count): (Vec<String>, i64, i64) =
+            ScriptManager::release_reserved_coins_script()
+                .arg(self.sponsor_str.clone())
+                .arg(reservation_id)
+                .arg(serde_json::to_string(&reserved_coin_ids)?)
+                .arg(serde_json::to_string(&formatted_coins)?)
+                .arg(Utc::now().timestamp_millis() as u64)
+                .arg(self.track_version_times_arg())
+                .arg(if keep_reservation { "1" } else { "0" })
+                .invoke_async(&mut conn)
+                .await?;
+        debug!(
+            ?reservation_id,
+            "After release_reserved_coins. New total balance: {}, new coin count: {}",
+            new_total_balance,
+            new_coin_count
+        );
+        self.metrics
+            .gas_pool_available_gas_coin_count
+            .with_label_values(&[&self.sponsor_str])
+            .set(new_coin_count);
+        self.metrics
+            .gas_pool_available_gas_total_balance
+            .with_label_values(&[&self.sponsor_str])
+            .set(new_total_balance);
+        Ok(released_coins.iter().map(|s| parse_gas_coin(s)).collect())
+    }
+
+    /// Whether the scripts that return coins to the pool record when their versions changed,
+    /// which only the prefer-stable-coins strategy needs.
+    fn track_version_times_arg(&self) -> &'static str {
+        if self.coin_selection_strategy.min_stable_ms() > 0 {
+            "1"
+        } else {
+            "0"
         }
     }
-}
 
-#[async_trait::async_trait]
-impl Storage for RedisStorage {
-    async fn reserve_gas_coins(
+    /// Reserves gas coins, either for a new reservation or for a budget lease that is being
+    /// claimed, in which case the target budget is taken from the lease.
+    async fn reserve_gas_coins_impl(
         &self,
         target_budget: u64,
         reserved_duration_ms: u64,
+        callback_url: Option<String>,
+        lease_id: Option<ReservationID>,
+        allow_partial: bool,
     ) -> anyhow::Result<(ReservationID, Vec<GasCoin>)> {
         self.metrics.num_reserve_gas_coins_requests.inc();
 
@@ -53,40 +137,40 @@ impl Storage for RedisStorage {
             .add(Duration::from_millis(reserved_duration_ms))
             .timestamp_millis() as u64;
         let mut conn = self.conn_manager.clone();
-        let (reservation_id, coins, new_total_balance, new_coin_count): (
-            ReservationID,
+        let cur_time = std::time::Instant::now();
+        let (reservation_id, coins, new_total_balance, new_coin_count, candidate_count): (
+            u64,
             Vec<String>,
             i64,
             i64,
+            i64,
         ) = ScriptManager::reserve_gas_coins_script()
             .arg(self.sponsor_str.clone())
             .arg(target_budget)
             .arg(expiration_time)
+            .arg(callback_url.unwrap_or_default())
+            .arg(lease_id.map(|id| id.to_string()).unwrap_or_default())
+            .arg(if allow_partial { "1" } else { "0" })
+            .arg(self.coin_selection_strategy.max_single_coin_balance())
+            .arg(Utc::now().timestamp_millis() as u64)
+            .arg(self.coin_selection_strategy.min_stable_ms())
             .invoke_async(&mut conn)
             .await?;
+        self.metrics
+            .coin_selection_latency_ms
+            .observe(cur_time.elapsed().as_millis() as u64);
+        self.metrics
+            .coin_selection_candidate_count
+            .with_label_values(&[&self.sponsor_str])
+            .set(candidate_count);
         // The script returns (0, []) if it is unable to find enough coins to reserve.
         // We choose to handle the error here instead of inside the script so that we could
         // provide a more readable error message.
         if coins.is_empty() {
-            return Err(anyhow::anyhow!(
-                "Unable to reserve gas coins for the given budget."
-            ));
+            return Err(GasStationError::InsufficientGasCoins.into());
         }
-        let gas_coins: Vec<_> = coins
-            .into_iter()
-            .map(|s| {
-                // Each coin is in the form of: balance,object_id,version,digest
-                let mut splits = s.split(',');
-                let balance = splits.next().unwrap().parse::<u64>().unwrap();
-                let object_id = ObjectID::from_str(splits.next().unwrap()).unwrap();
-                let version = SequenceNumber::from(splits.next().unwrap().parse::<u64>().unwrap());
-                let digest = ObjectDigest::from_str(splits.next().unwrap()).unwrap();
-                GasCoin {
-                    balance,
-                    object_ref: (object_id, version, digest),
-                }
-            })
-            .collect();
+        let reservation_id = ReservationID::new(reservation_id)?;
+        let gas_coins: Vec<_> = coins.iter().map(|s| parse_gas_coin(s)).collect();
 
         self.metrics
             .gas_pool_available_gas_coin_count
@@ -100,44 +184,238 @@ impl Storage for RedisStorage {
         Ok((reservation_id, gas_coins))
     }
 
-    async fn ready_for_execution(&self, reservation_id: ReservationID) -> anyhow::Result<()> {
-        self.metrics.num_ready_for_execution_requests.inc();
+    async fn add_untracked_coins_impl(
+        &self,
+        coins: Vec<GasCoin>,
+        dry_run: bool,
+    ) -> anyhow::Result<Vec<GasCoin>> {
+        let formatted_coins = coins.iter().map(format_gas_coin).collect::<Vec<String>>();
+        let mut conn = self.conn_manager.clone();
+        let untracked_coins: Vec<String> = ScriptManager::add_untracked_coins_script()
+            .arg(self.sponsor_str.clone())
+            .arg(serde_json::to_string(&formatted_coins)?)
+            .arg(if dry_run { "1" } else { "0" })
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(untracked_coins.iter().map(|s| parse_gas_coin(s)).collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for RedisStorage {
+    async fn reserve_gas_coins_with_callback(
+        &self,
+        target_budget: u64,
+        reserved_duration_ms: u64,
+        callback_url: Option<String>,
+    ) -> anyhow::Result<(ReservationID, Vec<GasCoin>)> {
+        self.reserve_gas_coins_impl(
+            target_budget,
+            reserved_duration_ms,
+            callback_url,
+            None,
+            false,
+        )
+        .await
+    }
+
+    async fn reserve_partial_gas_coins_with_callback(
+        &self,
+        target_budget: u64,
+        reserved_duration_ms: u64,
+        callback_url: Option<String>,
+    ) -> anyhow::Result<(ReservationID, Vec<GasCoin>)> {
+        self.reserve_gas_coins_impl(
+            target_budget,
+            reserved_duration_ms,
+            callback_url,
+            None,
+            true,
+        )
+        .await
+    }
+
+    async fn reserve_requested_coins(
+        &self,
+        coin_ids: Vec<ObjectID>,
+        target_budget: u64,
+        reserved_duration_ms: u64,
+        callback_url: Option<String>,
+    ) -> anyhow::Result<(ReservationID, Vec<GasCoin>)> {
+        self.metrics.num_reserve_gas_coins_requests.inc();
 
+        let expiration_time = Utc::now()
+            .add(Duration::from_millis(reserved_duration_ms))
+            .timestamp_millis() as u64;
+        let requested_ids = coin_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
         let mut conn = self.conn_manager.clone();
-        ScriptManager::ready_for_execution_script()
+        let (status, value, coins): (u8, String, Vec<String>) =
+            ScriptManager::reserve_requested_coins_script()
+                .arg(self.sponsor_str.clone())
+                .arg(requested_ids)
+                .arg(target_budget)
+                .arg(expiration_time)
+                .arg(callback_url.unwrap_or_default())
+                .arg(Utc::now().timestamp_millis() as u64)
+                .invoke_async(&mut conn)
+                .await?;
+        match status {
+            1 => {}
+            2 => return Err(GasStationError::RequestedCoinReserved(value.parse()?).into()),
+            3 => return Err(GasStationError::RequestedCoinNotFound(value.parse()?).into()),
+            4 => {
+                return Err(GasStationError::InvalidRequest(format!(
+                    "Requested gas coins hold a total balance of {}, which does not cover the budget of {}",
+                    value, target_budget
+                ))
+                .into())
+            }
+            _ => return Err(GasStationError::InsufficientGasCoins.into()),
+        }
+        let gas_coins: Vec<_> = coins.iter().map(|s| parse_gas_coin(s)).collect();
+        let reserved_balance: u64 = gas_coins.iter().map(|c| c.balance).sum();
+        self.metrics
+            .gas_pool_available_gas_coin_count
+            .with_label_values(&[&self.sponsor_str])
+            .sub(gas_coins.len() as i64);
+        self.metrics
+            .gas_pool_available_gas_total_balance
+            .with_label_values(&[&self.sponsor_str])
+            .sub(reserved_balance as i64);
+        self.metrics.num_successful_reserve_gas_coins_requests.inc();
+        Ok((ReservationID::new(value.parse()?)?, gas_coins))
+    }
+
+    async fn reserve_budget_lease(
+        &self,
+        budget: u64,
+        lease_duration_ms: u64,
+        callback_url: Option<String>,
+    ) -> anyhow::Result<ReservationID> {
+        let expiration_time = Utc::now()
+            .add(Duration::from_millis(lease_duration_ms))
+            .timestamp_millis() as u64;
+        let mut conn = self.conn_manager.clone();
+        let lease_id: u64 = ScriptManager::reserve_budget_lease_script()
             .arg(self.sponsor_str.clone())
-            .arg(reservation_id)
-            .invoke_async::<_, ()>(&mut conn)
+            .arg(budget)
+            .arg(expiration_time)
+            .arg(callback_url.unwrap_or_default())
+            .invoke_async(&mut conn)
             .await?;
+        // The script returns 0 if there is not enough uncommitted balance in the pool.
+        if lease_id == 0 {
+            return Err(anyhow::anyhow!(
+                "Unable to lease the given budget from the pool."
+            ));
+        }
+        Ok(ReservationID::new(lease_id)?)
+    }
+
+    async fn claim_budget_lease(
+        &self,
+        lease_id: ReservationID,
+        reserved_duration_ms: u64,
+    ) -> anyhow::Result<Vec<GasCoin>> {
+        // The target budget is taken from the lease.
+        let (_, gas_coins) = self
+            .reserve_gas_coins_impl(0, reserved_duration_ms, None, Some(lease_id), false)
+            .await?;
+        Ok(gas_coins)
+    }
+
+    async fn ready_for_execution_with_payment(
+        &self,
+        reservation_id: ReservationID,
+        expiry_margin_ms: u64,
+        payment: Vec<ObjectID>,
+    ) -> anyhow::Result<(Option<String>, Vec<ObjectID>)> {
+        self.metrics.num_ready_for_execution_requests.inc();
+
+        let now = Utc::now().timestamp_millis() as u64;
+        let payment_ids = payment
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut conn = self.conn_manager.clone();
+        let (status, value, object_ids): (u8, String, String) =
+            ScriptManager::ready_for_execution_script()
+                .arg(self.sponsor_str.clone())
+                .arg(reservation_id)
+                .arg(now)
+                .arg(expiry_margin_ms)
+                .arg(payment_ids)
+                .invoke_async(&mut conn)
+                .await?;
+        match status {
+            0 => return Err(GasStationError::ReservationNotFound(reservation_id).into()),
+            2 => {
+                return Err(GasStationError::ReservationTooCloseToExpiry(
+                    reservation_id,
+                    expiry_margin_ms,
+                )
+                .into())
+            }
+            3 => {
+                return Err(GasStationError::InvalidSponsoredTransaction(format!(
+                    "Gas coin {} is not reserved by reservation {}",
+                    value, reservation_id
+                ))
+                .into())
+            }
+            _ => {}
+        }
 
         self.metrics
             .num_successful_ready_for_execution_requests
             .inc();
-        Ok(())
+        let object_ids = object_ids
+            .split(',')
+            .filter(|id| !id.is_empty())
+            .map(ObjectID::from_str)
+            .collect::<Result<_, _>>()?;
+        Ok((Some(value).filter(|url| !url.is_empty()), object_ids))
+    }
+
+    async fn cancel_reservation(
+        &self,
+        reservation_id: ReservationID,
+    ) -> anyhow::Result<(Vec<ObjectID>, Option<String>)> {
+        let mut conn = self.conn_manager.clone();
+        let (exists, object_ids, callback_url): (bool, String, String) =
+            ScriptManager::cancel_reservation_script()
+                .arg(self.sponsor_str.clone())
+                .arg(reservation_id)
+                .arg(CANCELLED_RESERVATION_TTL_SEC)
+                .invoke_async(&mut conn)
+                .await?;
+        if !exists {
+            return Err(GasStationError::ReservationNotFound(reservation_id).into());
+        }
+        // The script returns the reserved coins as comma separated object ids.
+        let object_ids = object_ids
+            .split(',')
+            .filter(|id| !id.is_empty())
+            .map(ObjectID::from_str)
+            .collect::<Result<_, _>>()?;
+        Ok((object_ids, Some(callback_url).filter(|url| !url.is_empty())))
     }
 
     async fn add_new_coins(&self, new_coins: Vec<GasCoin>) -> anyhow::Result<()> {
         self.metrics.num_add_new_coins_requests.inc();
-        let formatted_coins = new_coins
-            .iter()
-            .map(|c| {
-                // The format is: balance,object_id,version,digest
-                // The way we turn them into strings must be consistent with the way we parse them in
-                // reserve_gas_coins_script.
-                format!(
-                    "{},{},{},{}",
-                    c.balance,
-                    c.object_ref.0,
-                    c.object_ref.1.value(),
-                    c.object_ref.2
-                )
-            })
-            .collect::<Vec<String>>();
+        let formatted_coins = new_coins.iter().map(format_gas_coin).collect::<Vec<String>>();
 
         let mut conn = self.conn_manager.clone();
         let (new_total_balance, new_coin_count): (i64, i64) = ScriptManager::add_new_coins_script()
             .arg(self.sponsor_str.clone())
             .arg(serde_json::to_string(&formatted_coins)?)
+            .arg(Utc::now().timestamp_millis() as u64)
+            .arg(self.track_version_times_arg())
             .invoke_async(&mut conn)
             .await?;
 
@@ -157,24 +435,300 @@ impl Storage for RedisStorage {
         Ok(())
     }
 
-    async fn expire_coins(&self) -> anyhow::Result<Vec<ObjectID>> {
+    async fn expire_coins(&self) -> anyhow::Result<Vec<(ReservationID, Vec<ObjectID>)>> {
         self.metrics.num_expire_coins_requests.inc();
 
         let now = Utc::now().timestamp_millis() as u64;
         let mut conn = self.conn_manager.clone();
-        let expired_coin_strings: Vec<String> = ScriptManager::expire_coins_script()
+        let expired_reservation_strings: Vec<String> = ScriptManager::expire_coins_script()
             .arg(self.sponsor_str.clone())
             .arg(now)
             .invoke_async(&mut conn)
             .await?;
-        // The script returns a list of comma separated coin ids.
-        let expired_coin_ids = expired_coin_strings
+        // The script returns a list of reservation ids, each followed by comma separated coin ids.
+        let expired_reservations = expired_reservation_strings
             .iter()
-            .flat_map(|s| s.split(',').map(|id| ObjectID::from_str(id).unwrap()))
+            .map(|s| {
+                let mut splits = s.split(',');
+                let reservation_id = splits.next().unwrap().parse().unwrap();
+                let coin_ids = splits.map(|id| ObjectID::from_str(id).unwrap()).collect();
+                (reservation_id, coin_ids)
+            })
             .collect();
 
         self.metrics.num_successful_expire_coins_requests.inc();
-        Ok(expired_coin_ids)
+        Ok(expired_reservations)
+    }
+
+    async fn release_reserved_coins(
+        &self,
+        reservation_id: ReservationID,
+        reserved_coin_ids: Vec<ObjectID>,
+        latest_coins: Vec<GasCoin>,
+    ) -> anyhow::Result<Vec<GasCoin>> {
+        self.release_coins(reservation_id, reserved_coin_ids, latest_coins, false)
+            .await
+    }
+
+    async fn release_unused_coins(
+        &self,
+        reservation_id: ReservationID,
+        unused_coin_ids: Vec<ObjectID>,
+        latest_coins: Vec<GasCoin>,
+    ) -> anyhow::Result<Vec<GasCoin>> {
+        self.release_coins(reservation_id, unused_coin_ids, latest_coins, true)
+            .await
+    }
+
+    async fn get_stranded_coins(
+        &self,
+        reserved_before_ms: u64,
+    ) -> anyhow::Result<Vec<(ReservationID, Vec<ObjectID>)>> {
+        let mut conn = self.conn_manager.clone();
+        let stranded_coins: Vec<String> = ScriptManager::get_stranded_coins_script()
+            .arg(self.sponsor_str.clone())
+            .arg(reserved_before_ms)
+            .invoke_async(&mut conn)
+            .await?;
+        // Each coin is formatted as "reservation_id,object_id".
+        let mut coins_by_reservation: BTreeMap<ReservationID, Vec<ObjectID>> = BTreeMap::new();
+        for s in stranded_coins {
+            let (reservation_id, object_id) = s
+                .split_once(',')
+                .ok_or_else(|| anyhow::anyhow!("Invalid stranded coin: {}", s))?;
+            coins_by_reservation
+                .entry(reservation_id.parse()?)
+                .or_default()
+                .push(ObjectID::from_str(object_id)?);
+        }
+        Ok(coins_by_reservation.into_iter().collect())
+    }
+
+    async fn mark_executing(
+        &self,
+        reservation_id: ReservationID,
+        digest: TransactionDigest,
+        reserved_coin_ids: Vec<ObjectID>,
+    ) -> anyhow::Result<()> {
+        let reserved_coin_ids = reserved_coin_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<String>>();
+        let mut conn = self.conn_manager.clone();
+        ScriptManager::mark_executing_script()
+            .arg(self.sponsor_str.clone())
+            .arg(reservation_id)
+            .arg(digest.to_string())
+            .arg(serde_json::to_string(&reserved_coin_ids)?)
+            .arg(Utc::now().timestamp_millis() as u64)
+            .invoke_async::<_, ()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_executing_reservations(
+        &self,
+        started_before_ms: u64,
+    ) -> anyhow::Result<Vec<ExecutingReservation>> {
+        let mut conn = self.conn_manager.clone();
+        let records: Vec<String> = ScriptManager::get_executing_reservations_script()
+            .arg(self.sponsor_str.clone())
+            .arg(started_before_ms)
+            .invoke_async(&mut conn)
+            .await?;
+        // Each reservation is formatted as "reservation_id,digest,object_id,...".
+        records
+            .iter()
+            .map(|record| {
+                let mut splits = record.split(',');
+                let (Some(reservation_id), Some(digest)) = (splits.next(), splits.next()) else {
+                    anyhow::bail!("Invalid executing reservation: {}", record);
+                };
+                Ok(ExecutingReservation {
+                    reservation_id: reservation_id.parse()?,
+                    digest: TransactionDigest::from_str(digest)?,
+                    reserved_coin_ids: splits
+                        .map(ObjectID::from_str)
+                        .collect::<Result<_, _>>()?,
+                })
+            })
+            .collect()
+    }
+
+    async fn add_client_reservation(
+        &self,
+        reservation_id: ReservationID,
+        client_id: &str,
+        budget: u64,
+        max_outstanding_budget: u64,
+        max_active_reservations: u64,
+    ) -> anyhow::Result<(bool, ClientUsage)> {
+        let mut conn = self.conn_manager.clone();
+        let (added, reserved_budget, active_reservations) =
+            ScriptManager::add_client_reservation_script()
+                .arg(self.sponsor_str.clone())
+                .arg(reservation_id)
+                .arg(client_id)
+                .arg(budget)
+                .arg(max_outstanding_budget)
+                .arg(max_active_reservations)
+                .invoke_async::<_, (u8, u64, u64)>(&mut conn)
+                .await?;
+        Ok((
+            added == 1,
+            ClientUsage {
+                reserved_budget,
+                active_reservations,
+            },
+        ))
+    }
+
+    async fn get_client_usage(&self, client_id: &str) -> anyhow::Result<ClientUsage> {
+        let mut conn = self.conn_manager.clone();
+        let (reserved_budget, active_reservations) = ScriptManager::get_client_usage_script()
+            .arg(self.sponsor_str.clone())
+            .arg(client_id)
+            .invoke_async::<_, (u64, u64)>(&mut conn)
+            .await?;
+        Ok(ClientUsage {
+            reserved_budget,
+            active_reservations,
+        })
+    }
+
+    async fn get_all_client_usage(&self) -> anyhow::Result<BTreeMap<String, ClientUsage>> {
+        let mut conn = self.conn_manager.clone();
+        let (reserved_budgets, reservation_counts) = ScriptManager::get_all_client_usage_script()
+            .arg(self.sponsor_str.clone())
+            .invoke_async::<_, (BTreeMap<String, u64>, BTreeMap<String, u64>)>(&mut conn)
+            .await?;
+        let mut usage: BTreeMap<String, ClientUsage> = BTreeMap::new();
+        for (client_id, reserved_budget) in reserved_budgets {
+            usage.entry(client_id).or_default().reserved_budget = reserved_budget;
+        }
+        for (client_id, active_reservations) in reservation_counts {
+            usage.entry(client_id).or_default().active_reservations = active_reservations;
+        }
+        Ok(usage)
+    }
+
+    async fn take_expired_reservation_callbacks(
+        &self,
+    ) -> anyhow::Result<Vec<(ReservationID, String)>> {
+        let mut conn = self.conn_manager.clone();
+        let callbacks: Vec<String> = ScriptManager::take_expired_reservation_callbacks_script()
+            .arg(self.sponsor_str.clone())
+            .invoke_async(&mut conn)
+            .await?;
+        // Each callback is formatted as "reservation_id,callback_url". The URL may contain commas.
+        callbacks
+            .iter()
+            .map(|s| {
+                let (reservation_id, callback_url) = s
+                    .split_once(',')
+                    .ok_or_else(|| anyhow::anyhow!("Invalid reservation callback: {}", s))?;
+                Ok((reservation_id.parse()?, callback_url.to_string()))
+            })
+            .collect()
+    }
+
+    async fn reserve_coins_for_rebalance(
+        &self,
+        small_balance_threshold: u64,
+        large_balance_threshold: u64,
+        max_small_coins: usize,
+        max_large_coins: usize,
+        reserved_duration_ms: u64,
+    ) -> anyhow::Result<(Option<ReservationID>, Vec<GasCoin>, CoinBalanceDistribution)> {
+        let expiration_time = Utc::now()
+            .add(Duration::from_millis(reserved_duration_ms))
+            .timestamp_millis() as u64;
+        let mut conn = self.conn_manager.clone();
+        let (reservation_id, coins, coin_count, small_coin_count, large_coin_count): (
+            u64,
+            Vec<String>,
+            usize,
+            usize,
+            usize,
+        ) = ScriptManager::reserve_coins_for_rebalance_script()
+            .arg(self.sponsor_str.clone())
+            .arg(small_balance_threshold)
+            .arg(large_balance_threshold)
+            .arg(max_small_coins)
+            .arg(max_large_coins)
+            .arg(expiration_time)
+            .arg(Utc::now().timestamp_millis() as u64)
+            .invoke_async(&mut conn)
+            .await?;
+        let gas_coins: Vec<_> = coins.iter().map(|s| parse_gas_coin(s)).collect();
+        if !gas_coins.is_empty() {
+            let reserved_balance: u64 = gas_coins.iter().map(|c| c.balance).sum();
+            self.metrics
+                .gas_pool_available_gas_coin_count
+                .with_label_values(&[&self.sponsor_str])
+                .sub(gas_coins.len() as i64);
+            self.metrics
+                .gas_pool_available_gas_total_balance
+                .with_label_values(&[&self.sponsor_str])
+                .sub(reserved_balance as i64);
+        }
+        // The script returns 0 as the reservation id if no coin was reserved.
+        let reservation_id = if gas_coins.is_empty() {
+            None
+        } else {
+            Some(ReservationID::new(reservation_id)?)
+        };
+        Ok((
+            reservation_id,
+            gas_coins,
+            CoinBalanceDistribution {
+                coin_count,
+                small_coin_count,
+                large_coin_count,
+            },
+        ))
+    }
+
+    async fn filter_untracked_coins(&self, coins: Vec<GasCoin>) -> anyhow::Result<Vec<GasCoin>> {
+        self.add_untracked_coins_impl(coins, true).await
+    }
+
+    async fn add_untracked_coins(&self, coins: Vec<GasCoin>) -> anyhow::Result<Vec<GasCoin>> {
+        let added_coins = self.add_untracked_coins_impl(coins, false).await?;
+        if !added_coins.is_empty() {
+            let added_balance: u64 = added_coins.iter().map(|c| c.balance).sum();
+            self.metrics
+                .gas_pool_available_gas_coin_count
+                .with_label_values(&[&self.sponsor_str])
+                .add(added_coins.len() as i64);
+            self.metrics
+                .gas_pool_available_gas_total_balance
+                .with_label_values(&[&self.sponsor_str])
+                .add(added_balance as i64);
+        }
+        Ok(added_coins)
+    }
+
+    async fn drain_available_coins(&self) -> anyhow::Result<Vec<GasCoin>> {
+        let mut conn = self.conn_manager.clone();
+        let coins: Vec<String> = ScriptManager::drain_available_coins_script()
+            .arg(self.sponsor_str.clone())
+            .invoke_async(&mut conn)
+            .await?;
+        info!(
+            sponsor_address=?self.sponsor_str,
+            "Drained {} coins from the pool",
+            coins.len()
+        );
+        self.metrics
+            .gas_pool_available_gas_coin_count
+            .with_label_values(&[&self.sponsor_str])
+            .set(0);
+        self.metrics
+            .gas_pool_available_gas_total_balance
+            .with_label_values(&[&self.sponsor_str])
+            .set(0);
+        Ok(coins.iter().map(|s| parse_gas_coin(s)).collect())
     }
 
     async fn init_coin_stats_at_startup(&self) -> anyhow::Result<(u64, u64)> {
@@ -239,6 +793,87 @@ impl Storage for RedisStorage {
         Ok(())
     }
 
+    async fn set_safe_mode(&self, enabled: bool) -> anyhow::Result<()> {
+        let mut conn = self.conn_manager.clone();
+        ScriptManager::set_safe_mode_script()
+            .arg(self.sponsor_str.clone())
+            .arg(enabled as u8)
+            .invoke_async::<_, ()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn is_safe_mode(&self) -> anyhow::Result<bool> {
+        let mut conn = self.conn_manager.clone();
+        let result = ScriptManager::get_safe_mode_script()
+            .arg(self.sponsor_str.clone())
+            .invoke_async::<_, bool>(&mut conn)
+            .await?;
+        Ok(result)
+    }
+
+    async fn set_reservation_tag(
+        &self,
+        reservation_id: ReservationID,
+        tag: &str,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.conn_manager.clone();
+        ScriptManager::set_reservation_tag_script()
+            .arg(self.sponsor_str.clone())
+            .arg(reservation_id)
+            .arg(tag)
+            .arg(CANCELLED_RESERVATION_TTL_SEC)
+            .invoke_async::<_, ()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_reservation_tag(
+        &self,
+        reservation_id: ReservationID,
+    ) -> anyhow::Result<Option<String>> {
+        let mut conn = self.conn_manager.clone();
+        let tag: String = ScriptManager::get_reservation_tag_script()
+            .arg(self.sponsor_str.clone())
+            .arg(reservation_id)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(Some(tag).filter(|tag| !tag.is_empty()))
+    }
+
+    async fn set_reservation_owner(
+        &self,
+        reservation_id: ReservationID,
+        client_id: &str,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.conn_manager.clone();
+        ScriptManager::set_reservation_owner_script()
+            .arg(self.sponsor_str.clone())
+            .arg(reservation_id)
+            .arg(client_id)
+            .arg(CANCELLED_RESERVATION_TTL_SEC)
+            .invoke_async::<_, ()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_reservation_owner(
+        &self,
+        reservation_id: ReservationID,
+    ) -> anyhow::Result<Option<String>> {
+        let mut conn = self.conn_manager.clone();
+        let client_id: String = ScriptManager::get_reservation_owner_script()
+            .arg(self.sponsor_str.clone())
+            .arg(reservation_id)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(Some(client_id).filter(|client_id| !client_id.is_empty()))
+    }
+
+    fn backend_name(&self) -> &'static str {
+        "redis"
+    }
+
     async fn check_health(&self) -> anyhow::Result<()> {
         let mut conn = self.conn_manager.clone();
         redis::cmd("PING").query_async(&mut conn).await?;
@@ -262,6 +897,70 @@ impl Storage for RedisStorage {
         Ok(count)
     }
 
+    async fn get_available_coins_snapshot(&self) -> anyhow::Result<AvailableCoinsSnapshot> {
+        let mut conn = self.conn_manager.clone();
+        let (coins, tracked_total_balance, tracked_coin_count): (Vec<String>, i64, i64) =
+            ScriptManager::get_available_coins_snapshot_script()
+                .arg(self.sponsor_str.clone())
+                .invoke_async(&mut conn)
+                .await?;
+        Ok(AvailableCoinsSnapshot {
+            coins: coins.iter().map(|s| parse_gas_coin(s)).collect(),
+            tracked_total_balance,
+            tracked_coin_count,
+        })
+    }
+
+    async fn get_pool_snapshot(&self) -> anyhow::Result<PoolSnapshot> {
+        let mut conn = self.conn_manager.clone();
+        let (coins, tracked_total_balance, tracked_coin_count, reservations, leases): (
+            Vec<String>,
+            i64,
+            i64,
+            Vec<String>,
+            Vec<String>,
+        ) = ScriptManager::get_pool_snapshot_script()
+            .arg(self.sponsor_str.clone())
+            .invoke_async(&mut conn)
+            .await?;
+        // Each reservation is formatted as "reservation_id,expiration_time,object_ids", and each
+        // lease as "lease_id,budget,expiration_time".
+        let reservations = reservations
+            .iter()
+            .map(|s| -> anyhow::Result<_> {
+                let mut splits = s.split(',');
+                let reservation_id = splits.next().unwrap().parse()?;
+                let expiration_ms = splits.next().unwrap().parse()?;
+                let coin_ids = splits.map(ObjectID::from_str).collect::<Result<_, _>>()?;
+                Ok(ReservationSnapshot {
+                    reservation_id,
+                    expiration_ms,
+                    coin_ids,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let budget_leases = leases
+            .iter()
+            .map(|s| -> anyhow::Result<_> {
+                let mut splits = s.split(',');
+                Ok(BudgetLeaseSnapshot {
+                    lease_id: splits.next().unwrap().parse()?,
+                    budget: splits.next().unwrap().parse()?,
+                    expiration_ms: splits.next().unwrap().parse()?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(PoolSnapshot {
+            available_coins: AvailableCoinsSnapshot {
+                coins: coins.iter().map(|s| parse_gas_coin(s)).collect(),
+                tracked_total_balance,
+                tracked_coin_count,
+            },
+            reservations,
+            budget_leases,
+        })
+    }
+
     async fn get_available_coin_total_balance(&self) -> u64 {
         let mut conn = self.conn_manager.clone();
         ScriptManager::get_available_coin_total_balance_script()
@@ -271,6 +970,73 @@ impl Storage for RedisStorage {
             .unwrap()
     }
 
+    async fn get_leased_budget(&self) -> anyhow::Result<u64> {
+        let mut conn = self.conn_manager.clone();
+        let leased_budget = ScriptManager::get_leased_budget_script()
+            .arg(self.sponsor_str.clone())
+            .invoke_async::<_, u64>(&mut conn)
+            .await?;
+        Ok(leased_budget)
+    }
+
+    async fn append_audit_records(&self, records: Vec<AuditRecord>) -> anyhow::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut script = ScriptManager::append_audit_records_script().prepare_invoke();
+        script.arg(self.sponsor_str.clone());
+        for record in &records {
+            script
+                .arg(record.timestamp_ms)
+                .arg(serde_json::to_string(record)?);
+        }
+        let mut conn = self.conn_manager.clone();
+        script.invoke_async::<_, ()>(&mut conn).await?;
+        Ok(())
+    }
+
+    async fn query_audit_records(
+        &self,
+        from_ms: u64,
+        to_ms: u64,
+        client_id: Option<String>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<AuditRecord>> {
+        let mut conn = self.conn_manager.clone();
+        let records: Vec<String> = ScriptManager::query_audit_records_script()
+            .arg(self.sponsor_str.clone())
+            .arg(from_ms)
+            .arg(to_ms)
+            .arg(client_id.unwrap_or_default())
+            .arg(limit)
+            .invoke_async(&mut conn)
+            .await?;
+        records
+            .iter()
+            .map(|record| Ok(serde_json::from_str(record)?))
+            .collect()
+    }
+
+    async fn trim_audit_records(&self, before_ms: u64) -> anyhow::Result<u64> {
+        let mut conn = self.conn_manager.clone();
+        let removed = ScriptManager::trim_audit_records_script()
+            .arg(self.sponsor_str.clone())
+            .arg(before_ms)
+            .invoke_async::<_, u64>(&mut conn)
+            .await?;
+        Ok(removed)
+    }
+
+    #[cfg(test)]
+    async fn get_coin_version_time_count(&self) -> usize {
+        let mut conn = self.conn_manager.clone();
+        redis::cmd("HLEN")
+            .arg(format!("{}:coin_version_times", self.sponsor_str))
+            .query_async(&mut conn)
+            .await
+            .unwrap()
+    }
+
     #[cfg(test)]
     async fn get_reserved_coin_count(&self) -> usize {
         let mut conn = self.conn_manager.clone();
@@ -282,11 +1048,48 @@ impl Storage for RedisStorage {
     }
 }
 
+// Storage keys of reservations are built from the reservation id inside the scripts.
+impl ToRedisArgs for ReservationID {
+    fn write_redis_args<W>(&self, out: &mut W)
+    where
+        W: ?Sized + RedisWrite,
+    {
+        self.value().write_redis_args(out)
+    }
+}
+
+// The format is: balance,object_id,version,digest
+// The way we turn them into strings must be consistent with the way we parse them in
+// reserve_gas_coins_script.
+fn format_gas_coin(coin: &GasCoin) -> String {
+    format!(
+        "{},{},{},{}",
+        coin.balance,
+        coin.object_ref.0,
+        coin.object_ref.1.value(),
+        coin.object_ref.2
+    )
+}
+
+fn parse_gas_coin(s: &str) -> GasCoin {
+    // Each coin is in the form of: balance,object_id,version,digest
+    let mut splits = s.split(',');
+    let balance = splits.next().unwrap().parse::<u64>().unwrap();
+    let object_id = ObjectID::from_str(splits.next().unwrap()).unwrap();
+    let version = SequenceNumber::from(splits.next().unwrap().parse::<u64>().unwrap());
+    let digest = ObjectDigest::from_str(splits.next().unwrap()).unwrap();
+    GasCoin {
+        balance,
+        object_ref: (object_id, version, digest),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use mys_types::base_types::{random_object_ref, MysAddress};
 
     use crate::{
+        config::CoinSelectionStrategy,
         metrics::StorageMetrics,
         storage::{redis::RedisStorage, Storage},
         types::GasCoin,
@@ -352,10 +1155,71 @@ mod tests {
         assert_eq!(total_balance, 1000);
     }
 
+    #[tokio::test]
+    async fn test_add_untracked_coins() {
+        let storage = setup_storage().await;
+        let coins: Vec<_> = [100, 200, 300]
+            .into_iter()
+            .map(|balance| GasCoin {
+                balance,
+                object_ref: random_object_ref(),
+            })
+            .collect();
+        storage.add_new_coins(coins[..2].to_vec()).await.unwrap();
+        let (_, reserved_coins) = storage.reserve_gas_coins(100, 1000).await.unwrap();
+        assert_eq!(reserved_coins, coins[..1].to_vec());
+
+        // Neither the available coin nor the reserved coin is untracked.
+        assert_eq!(
+            storage.filter_untracked_coins(coins.clone()).await.unwrap(),
+            coins[2..].to_vec()
+        );
+        assert_eq!(storage.get_available_coin_count().await.unwrap(), 1);
+        let mut candidates = coins.clone();
+        candidates.push(coins[2].clone());
+        assert_eq!(
+            storage.add_untracked_coins(candidates).await.unwrap(),
+            coins[2..].to_vec()
+        );
+        assert_eq!(storage.get_available_coin_count().await.unwrap(), 2);
+        assert_eq!(storage.get_available_coin_total_balance().await, 500);
+        assert_eq!(storage.get_reserved_coin_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_coin_selection_metrics() {
+        let storage = setup_storage().await;
+        storage
+            .add_new_coins(
+                [1, 1, 1, 1]
+                    .into_iter()
+                    .map(|balance| GasCoin {
+                        balance,
+                        object_ref: random_object_ref(),
+                    })
+                    .collect(),
+            )
+            .await
+            .unwrap();
+        let candidate_count = || {
+            storage
+                .metrics
+                .coin_selection_candidate_count
+                .with_label_values(&[&storage.sponsor_str])
+                .get()
+        };
+        storage.reserve_gas_coins(3, 1000).await.unwrap();
+        assert_eq!(candidate_count(), 3);
+        // A failed selection still reports the coins it looked at.
+        assert!(storage.reserve_gas_coins(2, 1000).await.is_err());
+        assert_eq!(candidate_count(), 1);
+    }
+
     async fn setup_storage() -> RedisStorage {
         let storage = RedisStorage::new(
             "redis://127.0.0.1:6379",
             MysAddress::ZERO,
+            CoinSelectionStrategy::default(),
             StorageMetrics::new_for_testing(),
         )
         .await;
@@ -14,6 +14,38 @@ const GET_AVAILABLE_COIN_TOTAL_BALANCE_SCRIPT: &str =
     include_str!("lua_scripts/get_available_coin_total_balance.lua");
 const ACQUIRE_INIT_LOCK_SCRIPT: &str = include_str!("lua_scripts/acquire_init_lock.lua");
 const RELEASE_INIT_LOCK_SCRIPT: &str = include_str!("lua_scripts/release_init_lock.lua");
+const DRAIN_AVAILABLE_COINS_SCRIPT: &str = include_str!("lua_scripts/drain_available_coins.lua");
+const RESERVE_BUDGET_LEASE_SCRIPT: &str = include_str!("lua_scripts/reserve_budget_lease.lua");
+const GET_LEASED_BUDGET_SCRIPT: &str = include_str!("lua_scripts/get_leased_budget.lua");
+const TAKE_EXPIRED_RESERVATION_CALLBACKS_SCRIPT: &str =
+    include_str!("lua_scripts/take_expired_reservation_callbacks.lua");
+const RESERVE_COINS_FOR_REBALANCE_SCRIPT: &str =
+    include_str!("lua_scripts/reserve_coins_for_rebalance.lua");
+const RESERVE_REQUESTED_COINS_SCRIPT: &str =
+    include_str!("lua_scripts/reserve_requested_coins.lua");
+const ADD_UNTRACKED_COINS_SCRIPT: &str = include_str!("lua_scripts/add_untracked_coins.lua");
+const CANCEL_RESERVATION_SCRIPT: &str = include_str!("lua_scripts/cancel_reservation.lua");
+const GET_AVAILABLE_COINS_SNAPSHOT_SCRIPT: &str =
+    include_str!("lua_scripts/get_available_coins_snapshot.lua");
+const GET_POOL_SNAPSHOT_SCRIPT: &str = include_str!("lua_scripts/get_pool_snapshot.lua");
+const APPEND_AUDIT_RECORDS_SCRIPT: &str = include_str!("lua_scripts/append_audit_records.lua");
+const QUERY_AUDIT_RECORDS_SCRIPT: &str = include_str!("lua_scripts/query_audit_records.lua");
+const TRIM_AUDIT_RECORDS_SCRIPT: &str = include_str!("lua_scripts/trim_audit_records.lua");
+const RELEASE_RESERVED_COINS_SCRIPT: &str = include_str!("lua_scripts/release_reserved_coins.lua");
+const GET_STRANDED_COINS_SCRIPT: &str = include_str!("lua_scripts/get_stranded_coins.lua");
+const MARK_EXECUTING_SCRIPT: &str = include_str!("lua_scripts/mark_executing.lua");
+const GET_EXECUTING_RESERVATIONS_SCRIPT: &str =
+    include_str!("lua_scripts/get_executing_reservations.lua");
+const ADD_CLIENT_RESERVATION_SCRIPT: &str =
+    include_str!("lua_scripts/add_client_reservation.lua");
+const GET_CLIENT_USAGE_SCRIPT: &str = include_str!("lua_scripts/get_client_usage.lua");
+const GET_ALL_CLIENT_USAGE_SCRIPT: &str = include_str!("lua_scripts/get_all_client_usage.lua");
+const SET_SAFE_MODE_SCRIPT: &str = include_str!("lua_scripts/set_safe_mode.lua");
+const GET_SAFE_MODE_SCRIPT: &str = include_str!("lua_scripts/get_safe_mode.lua");
+const SET_RESERVATION_TAG_SCRIPT: &str = include_str!("lua_scripts/set_reservation_tag.lua");
+const GET_RESERVATION_TAG_SCRIPT: &str = include_str!("lua_scripts/get_reservation_tag.lua");
+const SET_RESERVATION_OWNER_SCRIPT: &str = include_str!("lua_scripts/set_reservation_owner.lua");
+const GET_RESERVATION_OWNER_SCRIPT: &str = include_str!("lua_scripts/get_reservation_owner.lua");
 
 #[cfg(test)]
 const GET_RESERVED_COIN_COUNT_SCRIPT: &str =
@@ -73,6 +105,140 @@ impl ScriptManager {
         Lazy::force(&SCRIPT)
     }
 
+    pub fn drain_available_coins_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(DRAIN_AVAILABLE_COINS_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn reserve_budget_lease_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(RESERVE_BUDGET_LEASE_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn get_leased_budget_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(GET_LEASED_BUDGET_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn take_expired_reservation_callbacks_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> =
+            Lazy::new(|| Script::new(TAKE_EXPIRED_RESERVATION_CALLBACKS_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn reserve_coins_for_rebalance_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> =
+            Lazy::new(|| Script::new(RESERVE_COINS_FOR_REBALANCE_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn reserve_requested_coins_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(RESERVE_REQUESTED_COINS_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn add_untracked_coins_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(ADD_UNTRACKED_COINS_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn cancel_reservation_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(CANCEL_RESERVATION_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn get_available_coins_snapshot_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> =
+            Lazy::new(|| Script::new(GET_AVAILABLE_COINS_SNAPSHOT_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn get_pool_snapshot_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(GET_POOL_SNAPSHOT_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn append_audit_records_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(APPEND_AUDIT_RECORDS_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn query_audit_records_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(QUERY_AUDIT_RECORDS_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn trim_audit_records_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(TRIM_AUDIT_RECORDS_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn release_reserved_coins_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(RELEASE_RESERVED_COINS_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn get_stranded_coins_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(GET_STRANDED_COINS_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn mark_executing_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(MARK_EXECUTING_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn get_executing_reservations_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> =
+            Lazy::new(|| Script::new(GET_EXECUTING_RESERVATIONS_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn add_client_reservation_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(ADD_CLIENT_RESERVATION_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn get_client_usage_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(GET_CLIENT_USAGE_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn get_all_client_usage_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(GET_ALL_CLIENT_USAGE_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn set_safe_mode_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(SET_SAFE_MODE_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn get_safe_mode_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(GET_SAFE_MODE_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn set_reservation_tag_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(SET_RESERVATION_TAG_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn get_reservation_tag_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(GET_RESERVATION_TAG_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn set_reservation_owner_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(SET_RESERVATION_OWNER_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
+    pub fn get_reservation_owner_script() -> &'static Script {
+        static SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(GET_RESERVATION_OWNER_SCRIPT));
+        Lazy::force(&SCRIPT)
+    }
+
     // This needs to be test only because it's really expensive to call in production.
     #[cfg(test)]
     pub fn get_reserved_coin_count_script() -> &'static Script {
@@ -2,7 +2,10 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::config::{CoinInitConfig, DEFAULT_DAILY_GAS_USAGE_CAP};
+use crate::config::{
+    AuthConfig, BatchExecuteConfig, CoinInitConfig, GasPoolCoreConfig, RequestDeadlineConfig,
+    ReservationLimitsConfig, DEFAULT_DAILY_GAS_USAGE_CAP,
+};
 use crate::gas_pool::gas_pool_core::GasPoolContainer;
 use crate::gas_pool_initializer::GasPoolInitializer;
 use crate::metrics::{GasPoolCoreMetrics, GasPoolRpcMetrics};
@@ -10,7 +13,7 @@ use crate::rpc::GasPoolServer;
 use crate::storage::connect_storage_for_testing;
 use crate::mys_client::MysClient;
 use crate::tx_signer::{TestTxSigner, TxSigner};
-use crate::AUTH_ENV_NAME;
+use crate::{ADMIN_AUTH_ENV_NAME, AUTH_ENV_NAME};
 use std::sync::Arc;
 use mys_config::local_ip_utils::{get_available_port, localhost_for_testing};
 use mys_swarm_config::genesis_config::AccountConfig;
@@ -44,6 +47,23 @@ pub async fn start_mys_cluster(init_gas_amounts: Vec<u64>) -> (TestCluster, Arc<
 pub async fn start_gas_station(
     init_gas_amounts: Vec<u64>,
     target_init_coin_balance: u64,
+) -> (TestCluster, GasPoolContainer) {
+    start_gas_station_with_config(
+        init_gas_amounts,
+        target_init_coin_balance,
+        GasPoolCoreConfig {
+            sign_execution_effects: true,
+            coin_cooldown_ms: 0,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+pub async fn start_gas_station_with_config(
+    init_gas_amounts: Vec<u64>,
+    target_init_coin_balance: u64,
+    gas_pool_core_config: GasPoolCoreConfig,
 ) -> (TestCluster, GasPoolContainer) {
     debug!("Starting MySo cluster..");
     let (test_cluster, signer) = start_mys_cluster(init_gas_amounts).await;
@@ -68,6 +88,7 @@ pub async fn start_gas_station(
         mys_client,
         DEFAULT_DAILY_GAS_USAGE_CAP,
         GasPoolCoreMetrics::new_for_testing(),
+        gas_pool_core_config,
     )
     .await;
     (test_cluster, station)
@@ -77,14 +98,60 @@ pub async fn start_rpc_server_for_testing(
     init_gas_amounts: Vec<u64>,
     target_init_balance: u64,
 ) -> (TestCluster, GasPoolContainer, GasPoolServer) {
-    let (test_cluster, container) = start_gas_station(init_gas_amounts, target_init_balance).await;
+    start_rpc_server_for_testing_with_config(
+        init_gas_amounts,
+        target_init_balance,
+        GasPoolCoreConfig {
+            sign_execution_effects: true,
+            coin_cooldown_ms: 0,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+pub async fn start_rpc_server_for_testing_with_config(
+    init_gas_amounts: Vec<u64>,
+    target_init_balance: u64,
+    gas_pool_core_config: GasPoolCoreConfig,
+) -> (TestCluster, GasPoolContainer, GasPoolServer) {
+    start_rpc_server_for_testing_with_auth_config(
+        init_gas_amounts,
+        target_init_balance,
+        gas_pool_core_config,
+        AuthConfig::default(),
+    )
+    .await
+}
+
+/// Same as `start_rpc_server_for_testing_with_config`, but authenticates requests with the given
+/// auth config. The secrets it needs must be set in the environment beforehand.
+pub async fn start_rpc_server_for_testing_with_auth_config(
+    init_gas_amounts: Vec<u64>,
+    target_init_balance: u64,
+    gas_pool_core_config: GasPoolCoreConfig,
+    auth_config: AuthConfig,
+) -> (TestCluster, GasPoolContainer, GasPoolServer) {
+    let (test_cluster, container) = start_gas_station_with_config(
+        init_gas_amounts,
+        target_init_balance,
+        gas_pool_core_config,
+    )
+    .await;
     let localhost = localhost_for_testing();
     std::env::set_var(AUTH_ENV_NAME, "some secret");
+    std::env::set_var(ADMIN_AUTH_ENV_NAME, "some admin secret");
     let server = GasPoolServer::new(
         container.get_gas_pool_arc(),
         localhost.parse().unwrap(),
         get_available_port(&localhost),
         GasPoolRpcMetrics::new_for_testing(),
+        BatchExecuteConfig::default(),
+        ReservationLimitsConfig::default(),
+        RequestDeadlineConfig::default(),
+        None,
+        auth_config,
+        None,
     )
     .await;
     (test_cluster, container, server)
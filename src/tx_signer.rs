@@ -2,27 +2,46 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::log_redaction::redacted;
 use anyhow::anyhow;
 use fastcrypto::encoding::{Base64, Encoding};
+use parking_lot::RwLock;
 use reqwest::Client;
 use serde::Deserialize;
 use serde_json::{self, json};
-use shared_crypto::intent::{Intent, IntentMessage};
+use shared_crypto::intent::{Intent, IntentMessage, PersonalMessage};
 use std::str::FromStr;
+#[cfg(test)]
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+#[cfg(test)]
+use std::time::Duration;
 use mys_types::base_types::MysAddress;
 use mys_types::crypto::{Signature, MysKeyPair};
 use mys_types::signature::GenericSignature;
 use mys_types::transaction::TransactionData;
+use tracing::{debug, info};
 
 #[async_trait::async_trait]
 pub trait TxSigner: Send + Sync {
     async fn sign_transaction(&self, tx_data: &TransactionData)
         -> anyhow::Result<GenericSignature>;
+    /// Sign an arbitrary message with the personal message intent.
+    /// Not every signer supports this, in which case an error is returned.
+    async fn sign_personal_message(&self, _message: &[u8]) -> anyhow::Result<GenericSignature> {
+        Err(anyhow!("Signing personal messages is not supported by this signer"))
+    }
     fn get_address(&self) -> MysAddress;
+    /// A short name of the kind of signer, e.g. for build info. Must not contain secrets.
+    fn signer_type(&self) -> &'static str;
     fn is_valid_address(&self, address: &MysAddress) -> bool {
         self.get_address() == *address
     }
+    /// Re-reads the address from the signing backend, so that get_address reflects a key that
+    /// was changed outside of the gas station. A no-op for signers that hold their own key.
+    async fn refresh_address(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 #[derive(Deserialize)]
@@ -47,59 +66,72 @@ struct MysAddressResponse {
 pub struct SidecarTxSigner {
     sidecar_url: String,
     client: Client,
-    mys_address: MysAddress,
+    mys_address: RwLock<MysAddress>,
 }
 
 impl SidecarTxSigner {
     pub async fn new(sidecar_url: String) -> Arc<Self> {
+        Self::try_new(sidecar_url)
+            .await
+            .unwrap_or_else(|err| panic!("{:?}", err))
+    }
+
+    /// Same as `new`, but fails instead of panicking when the sidecar cannot tell its address.
+    pub async fn try_new(sidecar_url: String) -> anyhow::Result<Arc<Self>> {
         let client = Client::builder()
             .timeout(std::time::Duration::from_secs(30))
-            .build()
-            .unwrap();
+            .build()?;
         let url = format!("{}/{}", sidecar_url, "get-pubkey-address");
-        println!("Requesting KMS sidecar address from: {}", url);
+        info!("Requesting KMS sidecar address from: {}", url);
 
         let resp = client
             .get(&url)
             .timeout(std::time::Duration::from_secs(15))
             .send()
             .await
-            .unwrap_or_else(|err| panic!("Failed to get pubkey address from {}: {}", url, err));
-            
+            .map_err(|err| anyhow!("Failed to get pubkey address from {}: {}", url, err))?;
+
         let status = resp.status();
         if !status.is_success() {
             let error_text = resp.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            panic!("KMS sidecar returned error status {}: {}", status, error_text);
+            return Err(anyhow!("KMS sidecar returned error status {}: {}", status, error_text));
         }
-        
-        let response_text = resp.text().await
-            .unwrap_or_else(|err| panic!("Failed to read response body from {}: {}", url, err));
-        println!("KMS sidecar response: {}", response_text);
-        
-        let mys_address: MysAddressResponse = serde_json::from_str(&response_text)
-            .unwrap_or_else(|err| panic!("Failed to parse address response from {}: {}. Response was: {}", url, err, response_text));
-            
-        Arc::new(Self {
+
+        let response_text = resp
+            .text()
+            .await
+            .map_err(|err| anyhow!("Failed to read response body from {}: {}", url, err))?;
+        debug!("KMS sidecar response: {}", redacted(&response_text));
+
+        let mys_address: MysAddressResponse =
+            serde_json::from_str(&response_text).map_err(|err| {
+                anyhow!(
+                    "Failed to parse address response from {}: {}. Response was: {}",
+                    url,
+                    err,
+                    redacted(&response_text)
+                )
+            })?;
+
+        Ok(Arc::new(Self {
             sidecar_url,
             client,
-            mys_address: mys_address.mys_pubkey_address,
-        })
+            mys_address: RwLock::new(mys_address.mys_pubkey_address),
+        }))
     }
-}
 
-#[async_trait::async_trait]
-impl TxSigner for SidecarTxSigner {
-    async fn sign_transaction(
+    /// Posts `body` to a signing endpoint of the sidecar and parses the returned signature.
+    async fn request_signature(
         &self,
-        tx_data: &TransactionData,
+        endpoint: &str,
+        body: serde_json::Value,
     ) -> anyhow::Result<GenericSignature> {
-        let bytes = Base64::encode(bcs::to_bytes(&tx_data)?);
         let resp = self
             .client
-            .post(format!("{}/{}", self.sidecar_url, "sign-transaction"))
+            .post(format!("{}/{}", self.sidecar_url, endpoint))
             .header("Content-Type", "application/json")
             .timeout(std::time::Duration::from_secs(20))
-            .json(&json!({"txBytes": bytes}))
+            .json(&body)
             .send()
             .await?;
 
@@ -107,7 +139,11 @@ impl TxSigner for SidecarTxSigner {
         let status = resp.status();
         if !status.is_success() {
             let error_text = resp.text().await?;
-            return Err(anyhow!("KMS sidecar returned error status {}: {}", status, error_text));
+            return Err(anyhow!(
+                "KMS sidecar returned error status {}: {}",
+                status,
+                redacted(&error_text)
+            ));
         }
 
         // Get the response text to check its structure
@@ -120,15 +156,70 @@ impl TxSigner for SidecarTxSigner {
 
         // Try to parse as success response
         let sig_bytes: SignatureResponse = serde_json::from_str(&response_text)
-            .map_err(|_| anyhow!("Failed to parse KMS sidecar response: {}", response_text))?;
+            .map_err(|_| {
+                anyhow!(
+                    "Failed to parse KMS sidecar response: {}",
+                    redacted(&response_text)
+                )
+            })?;
 
         let sig = GenericSignature::from_str(&sig_bytes.signature)
             .map_err(|err| anyhow!(err.to_string()))?;
         Ok(sig)
     }
+}
+
+#[async_trait::async_trait]
+impl TxSigner for SidecarTxSigner {
+    async fn sign_transaction(
+        &self,
+        tx_data: &TransactionData,
+    ) -> anyhow::Result<GenericSignature> {
+        let bytes = Base64::encode(bcs::to_bytes(&tx_data)?);
+        self.request_signature("sign-transaction", json!({"txBytes": bytes}))
+            .await
+    }
+
+    async fn sign_personal_message(&self, message: &[u8]) -> anyhow::Result<GenericSignature> {
+        let bytes = Base64::encode(message);
+        self.request_signature("sign-personal-message", json!({"messageBytes": bytes}))
+            .await
+    }
 
     fn get_address(&self) -> MysAddress {
-        self.mys_address
+        *self.mys_address.read()
+    }
+
+    fn signer_type(&self) -> &'static str {
+        "sidecar"
+    }
+
+    async fn refresh_address(&self) -> anyhow::Result<()> {
+        let resp = self
+            .client
+            .get(format!("{}/{}", self.sidecar_url, "get-pubkey-address"))
+            .timeout(std::time::Duration::from_secs(15))
+            .send()
+            .await?;
+        let status = resp.status();
+        if !status.is_success() {
+            let error_text = resp.text().await?;
+            return Err(anyhow!(
+                "KMS sidecar returned error status {}: {}",
+                status,
+                redacted(&error_text)
+            ));
+        }
+        let response_text = resp.text().await?;
+        let response: MysAddressResponse =
+            serde_json::from_str(&response_text).map_err(|_| {
+                anyhow!(
+                    "Failed to parse KMS sidecar address response: {}",
+                    redacted(&response_text)
+                )
+            })?;
+        *self.mys_address.write() = response.mys_pubkey_address;
+        Ok(())
     }
 }
 
@@ -153,7 +244,200 @@ impl TxSigner for TestTxSigner {
         Ok(sponsor_sig)
     }
 
+    async fn sign_personal_message(&self, message: &[u8]) -> anyhow::Result<GenericSignature> {
+        let intent_msg = IntentMessage::new(
+            Intent::personal_message(),
+            PersonalMessage {
+                message: message.to_vec(),
+            },
+        );
+        Ok(Signature::new_secure(&intent_msg, &self.keypair).into())
+    }
+
     fn get_address(&self) -> MysAddress {
         (&self.keypair.public()).into()
     }
+
+    fn signer_type(&self) -> &'static str {
+        "local"
+    }
+}
+
+/// Faults injected by `MockTxSigner`. They follow a fixed schedule of calls rather than chance,
+/// so that tests are deterministic.
+#[cfg(test)]
+#[derive(Clone, Debug, Default)]
+pub struct MockTxSignerConfig {
+    /// Added to every sign_transaction call.
+    pub latency: Duration,
+    /// When non-zero, every `fail_every`-th call fails after the latency.
+    pub fail_every: usize,
+    /// When non-zero, every `timeout_every`-th call hangs for `timeout` and then fails, as a
+    /// request to an unresponsive sidecar would.
+    pub timeout_every: usize,
+    pub timeout: Duration,
+}
+
+/// Signs like `TestTxSigner`, but simulates a slow and flaky signing backend, to exercise the
+/// handling of signing failures without a real sidecar.
+#[cfg(test)]
+pub struct MockTxSigner {
+    inner: Arc<TestTxSigner>,
+    config: MockTxSignerConfig,
+    num_calls: AtomicUsize,
+    failing: AtomicBool,
+}
+
+#[cfg(test)]
+impl MockTxSigner {
+    pub fn new(keypair: MysKeyPair, config: MockTxSignerConfig) -> Arc<Self> {
+        Arc::new(Self {
+            inner: TestTxSigner::new(keypair),
+            config,
+            num_calls: AtomicUsize::new(0),
+            failing: AtomicBool::new(false),
+        })
+    }
+
+    /// While failing, every sign_transaction call fails after the latency, as during an outage
+    /// of the signing backend.
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::Relaxed);
+    }
+
+    /// The number of sign_transaction calls so far, including the failed ones.
+    pub fn num_calls(&self) -> usize {
+        self.num_calls.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl TxSigner for MockTxSigner {
+    async fn sign_transaction(
+        &self,
+        tx_data: &TransactionData,
+    ) -> anyhow::Result<GenericSignature> {
+        let call = self.num_calls.fetch_add(1, Ordering::Relaxed) + 1;
+        let is_nth_call = |n: usize| n != 0 && call % n == 0;
+        if is_nth_call(self.config.timeout_every) {
+            tokio::time::sleep(self.config.timeout).await;
+            return Err(anyhow!("Mock signer timed out on call {}", call));
+        }
+        tokio::time::sleep(self.config.latency).await;
+        if self.failing.load(Ordering::Relaxed) || is_nth_call(self.config.fail_every) {
+            return Err(anyhow!("Mock signer failed on call {}", call));
+        }
+        self.inner.sign_transaction(tx_data).await
+    }
+
+    async fn sign_personal_message(&self, message: &[u8]) -> anyhow::Result<GenericSignature> {
+        self.inner.sign_personal_message(message).await
+    }
+
+    fn get_address(&self) -> MysAddress {
+        self.inner.get_address()
+    }
+
+    fn signer_type(&self) -> &'static str {
+        "mock"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastcrypto::traits::EncodeDecodeBase64;
+    use mys_types::base_types::random_object_ref;
+    use mys_types::crypto::get_account_key_pair;
+    use mys_types::programmable_transaction_builder::ProgrammableTransactionBuilder;
+    use mys_types::transaction::TransactionKind;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_mock_tx_signer() {
+        let (sender, keypair) = get_account_key_pair();
+        let test_signer = TestTxSigner::new(keypair.copy().into());
+        let mock_signer = MockTxSigner::new(
+            keypair.into(),
+            MockTxSignerConfig {
+                latency: Duration::from_millis(50),
+                fail_every: 3,
+                timeout_every: 4,
+                timeout: Duration::from_millis(200),
+            },
+        );
+        assert_eq!(mock_signer.get_address(), test_signer.get_address());
+        let tx_data = TransactionData::new_with_gas_coins(
+            TransactionKind::programmable(ProgrammableTransactionBuilder::new().finish()),
+            sender,
+            vec![random_object_ref()],
+            1,
+            1,
+        );
+        let expected_sig = test_signer.sign_transaction(&tx_data).await.unwrap();
+
+        for call in 1..=5 {
+            let start = Instant::now();
+            let result = mock_signer.sign_transaction(&tx_data).await;
+            let elapsed = start.elapsed();
+            match call {
+                3 => {
+                    assert!(result.is_err());
+                    assert!(elapsed >= Duration::from_millis(50));
+                }
+                4 => {
+                    assert!(result.is_err());
+                    assert!(elapsed >= Duration::from_millis(200));
+                }
+                _ => {
+                    assert_eq!(result.unwrap(), expected_sig);
+                    assert!(elapsed >= Duration::from_millis(50));
+                }
+            }
+        }
+
+        mock_signer.set_failing(true);
+        assert!(mock_signer.sign_transaction(&tx_data).await.is_err());
+        mock_signer.set_failing(false);
+        assert!(mock_signer.sign_transaction(&tx_data).await.is_ok());
+        assert_eq!(mock_signer.num_calls(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_sidecar_tx_signer_personal_message() {
+        let (sender, keypair) = get_account_key_pair();
+        let test_signer = TestTxSigner::new(keypair.into());
+        let sidecar_signer = test_signer.clone();
+        let app = axum::Router::new()
+            .route(
+                "/get-pubkey-address",
+                axum::routing::get(move || async move {
+                    axum::Json(json!({"mysPubkeyAddress": sender}))
+                }),
+            )
+            .route(
+                "/sign-personal-message",
+                axum::routing::post(move |axum::Json(body): axum::Json<serde_json::Value>| {
+                    let signer = sidecar_signer.clone();
+                    async move {
+                        let message =
+                            Base64::decode(body["messageBytes"].as_str().unwrap()).unwrap();
+                        let sig = signer.sign_personal_message(&message).await.unwrap();
+                        axum::Json(json!({"signature": sig.encode_base64()}))
+                    }
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sidecar_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let signer = SidecarTxSigner::try_new(sidecar_url).await.unwrap();
+        assert_eq!(signer.get_address(), sender);
+        let message = b"signed by the sidecar";
+        assert_eq!(
+            signer.sign_personal_message(message).await.unwrap(),
+            test_signer.sign_personal_message(message).await.unwrap()
+        );
+    }
 }
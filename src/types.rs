@@ -2,12 +2,17 @@
 // Copyright (c) The Social Proof Foundation, LLC.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::errors::GasStationError;
 use anyhow::bail;
+use fastcrypto::encoding::Base64;
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::BTreeSet;
-use mys_json_rpc_types::MysObjectRef;
-use mys_types::base_types::{ObjectID, ObjectRef};
+use std::fmt;
+use std::str::FromStr;
+use mys_json_rpc_types::{MysExecutionStatus, MysObjectRef};
+use mys_types::base_types::{MysAddress, ObjectID, ObjectRef};
+use mys_types::digests::TransactionDigest;
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct GasCoin {
@@ -15,6 +20,29 @@ pub struct GasCoin {
     pub balance: u64,
 }
 
+impl GasCoin {
+    /// The gas budget of a transaction that splits `object_count` new objects off this coin,
+    /// given the calibrated gas cost per object. Fails with `GasStationError::InvalidRequest` if
+    /// the budget does not fit in a u64, and with `GasStationError::InsufficientGasCoins` if it
+    /// would leave nothing of the balance of the coin to split.
+    pub fn split_budget(
+        &self,
+        gas_cost_per_object: u64,
+        object_count: u64,
+    ) -> Result<u64, GasStationError> {
+        let budget = gas_cost_per_object.checked_mul(object_count).ok_or_else(|| {
+            GasStationError::InvalidRequest(format!(
+                "Gas budget to create {} objects at {} each overflows",
+                object_count, gas_cost_per_object
+            ))
+        })?;
+        if budget >= self.balance {
+            return Err(GasStationError::InsufficientGasCoins);
+        }
+        Ok(budget)
+    }
+}
+
 #[derive(Debug, JsonSchema, Serialize, Deserialize)]
 pub struct MysGasCoin {
     pub object_ref: MysObjectRef,
@@ -39,7 +67,174 @@ impl From<MysGasCoin> for GasCoin {
     }
 }
 
-pub type ReservationID = u64;
+/// Effects of an executed transaction, signed by the sponsor so that clients can verify
+/// that the effects were indeed obtained by the gas pool.
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
+pub struct SignedEffects {
+    /// BCS serialized TransactionEffects, as returned by the fullnode.
+    pub raw_effects: Base64,
+    /// Sponsor signature over `raw_effects`, committed to the personal message intent.
+    pub signature: Base64,
+}
+
+/// What the gas pool committed to a reservation. The BCS encoding of this struct is what the
+/// sponsor signs in a `SignedReservationReceipt`, so its fields must never be reordered.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReservationReceipt {
+    pub reservation_id: ReservationID,
+    pub sponsor_address: MysAddress,
+    pub gas_coins: Vec<ObjectRef>,
+    /// The budget of the reservation, or the budget covered by the reserved coins if the pool
+    /// could only partially cover it.
+    pub budget: u64,
+    /// When the reservation expires, in milliseconds since the Unix epoch.
+    pub expiration_ms: u64,
+}
+
+/// A reservation receipt signed by the sponsor, so that clients can prove to third parties that
+/// the gas pool committed gas to them.
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
+pub struct SignedReservationReceipt {
+    /// BCS serialized `ReservationReceipt`.
+    pub raw_receipt: Base64,
+    /// Sponsor signature over `raw_receipt`, committed to the personal message intent.
+    pub signature: Base64,
+}
+
+/// How the available coins in the pool are distributed relative to the rebalancing thresholds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct CoinBalanceDistribution {
+    pub coin_count: usize,
+    /// Number of coins with balance below the small balance threshold.
+    pub small_coin_count: usize,
+    /// Number of coins with balance above the large balance threshold.
+    pub large_coin_count: usize,
+}
+
+/// Identifies a reservation or a budget lease. Ids are handed out by the storage starting from 1,
+/// so ids that could never have been handed out are rejected when they are constructed or
+/// deserialized, instead of failing later with a confusing error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, JsonSchema, Serialize)]
+#[serde(transparent)]
+pub struct ReservationID(u64);
+
+impl ReservationID {
+    /// The largest valid id. Ids are sent as JSON numbers, which many clients cannot represent
+    /// exactly above 2^53 - 1.
+    pub const MAX: u64 = (1 << 53) - 1;
+
+    pub fn new(id: u64) -> Result<Self, GasStationError> {
+        if id == 0 || id > Self::MAX {
+            return Err(GasStationError::InvalidRequest(format!(
+                "Reservation id must be between 1 and {}, got {}",
+                Self::MAX,
+                id
+            )));
+        }
+        Ok(Self(id))
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+impl TryFrom<u64> for ReservationID {
+    type Error = GasStationError;
+
+    fn try_from(id: u64) -> Result<Self, Self::Error> {
+        Self::new(id)
+    }
+}
+
+impl FromStr for ReservationID {
+    type Err = GasStationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let id = s.parse::<u64>().map_err(|err| {
+            GasStationError::InvalidRequest(format!("Invalid reservation id {:?}: {}", s, err))
+        })?;
+        Self::new(id)
+    }
+}
+
+impl fmt::Display for ReservationID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for ReservationID {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = u64::deserialize(deserializer)?;
+        Self::new(id).map_err(serde::de::Error::custom)
+    }
+}
+
+/// The reservations a client holds at once, as counted by the client budget limits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct ClientUsage {
+    /// The total budget of the reservations of the client whose coins were not released yet.
+    pub reserved_budget: u64,
+    /// The number of reservations of the client whose coins were not released yet.
+    pub active_reservations: u64,
+}
+
+/// A reservation whose coins were neither taken out for execution nor expired yet.
+#[derive(Clone, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct ReservationSnapshot {
+    pub reservation_id: ReservationID,
+    /// When the reservation expires, in milliseconds since the Unix epoch.
+    pub expiration_ms: u64,
+    pub coin_ids: Vec<ObjectID>,
+}
+
+/// A budget lease that was neither claimed nor expired yet.
+#[derive(Clone, Debug, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct BudgetLeaseSnapshot {
+    pub lease_id: ReservationID,
+    pub budget: u64,
+    /// When the lease expires, in milliseconds since the Unix epoch.
+    pub expiration_ms: u64,
+}
+
+/// An entry of the reservation audit log.
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp_ms: u64,
+    /// The client the request was authenticated as, or the client id in the request if the
+    /// authentication backend cannot tell clients apart. None if neither is known.
+    pub client_id: Option<String>,
+    pub reservation_id: ReservationID,
+    /// The tag the reservation was made with, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    pub event: AuditEvent,
+}
+
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// Gas coins were reserved, or budget was committed to a budget lease, in which case there
+    /// are no coins yet.
+    Reserve {
+        gas_budget: u64,
+        gas_coins: Vec<ObjectID>,
+    },
+    /// The coins of a budget lease were picked.
+    ClaimLease { gas_coins: Vec<ObjectID> },
+    /// The reservation was used to execute a transaction. The digest, status and net gas usage
+    /// are None if the execution failed before the transaction was executed, and the error
+    /// is None if it was executed.
+    Execute {
+        gas_coins: Vec<ObjectID>,
+        digest: Option<TransactionDigest>,
+        status: Option<MysExecutionStatus>,
+        net_gas_usage: Option<i64>,
+        error: Option<String>,
+    },
+}
+
 pub type ExpirationTimeMs = u64;
 pub type GasGroupKey = ObjectID;
 
@@ -85,3 +280,64 @@ impl ReservedGasGroup {
         *self.objects.iter().next().unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reservation_id_round_trip() {
+        let id = ReservationID::new(42).unwrap();
+        assert_eq!(serde_json::to_string(&id).unwrap(), "42");
+        assert_eq!(serde_json::from_str::<ReservationID>("42").unwrap(), id);
+        assert_eq!(id.to_string().parse::<ReservationID>().unwrap(), id);
+        let max = ReservationID::new(ReservationID::MAX).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ReservationID>(&serde_json::to_string(&max).unwrap()).unwrap(),
+            max
+        );
+    }
+
+    #[test]
+    fn test_split_budget() {
+        let coin = GasCoin {
+            object_ref: mys_types::base_types::random_object_ref(),
+            balance: 1000,
+        };
+        assert_eq!(coin.split_budget(0, 500).unwrap(), 0);
+        assert_eq!(coin.split_budget(999, 1).unwrap(), 999);
+        assert!(matches!(
+            coin.split_budget(1000, 1),
+            Err(GasStationError::InsufficientGasCoins)
+        ));
+        assert!(matches!(
+            coin.split_budget(u64::MAX / 2 + 1, 2),
+            Err(GasStationError::InvalidRequest(_))
+        ));
+        let coin = GasCoin {
+            balance: u64::MAX,
+            ..coin
+        };
+        assert_eq!(coin.split_budget(u64::MAX / 2, 2).unwrap(), u64::MAX - 1);
+        assert!(matches!(
+            coin.split_budget(u64::MAX, 1),
+            Err(GasStationError::InsufficientGasCoins)
+        ));
+    }
+
+    #[test]
+    fn test_reservation_id_rejects_invalid_values() {
+        assert!(ReservationID::new(0).is_err());
+        assert!(ReservationID::new(ReservationID::MAX + 1).is_err());
+        assert!(serde_json::from_str::<ReservationID>("0").is_err());
+        assert!(serde_json::from_str::<ReservationID>("-1").is_err());
+        assert!(serde_json::from_str::<ReservationID>("1.5").is_err());
+        assert!(serde_json::from_str::<ReservationID>("\"1\"").is_err());
+        assert!(serde_json::from_str::<ReservationID>("9007199254740992").is_err());
+        assert!("abc".parse::<ReservationID>().is_err());
+        assert!(matches!(
+            ReservationID::new(0),
+            Err(GasStationError::InvalidRequest(_))
+        ));
+    }
+}